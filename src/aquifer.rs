@@ -0,0 +1,558 @@
+//! What fills the empty space of the terrain: air, the default fluid below
+//! sea level, or lava deep down. With aquifers enabled, underground water
+//! and lava sit at their own levels in separate pockets, walled off from
+//! each other by barrier noise. A port of vanilla's `Aquifer`.
+
+use crate::chunk::{BlockState, SECTION_SIZE};
+use crate::noise::floor;
+use crate::noise_router::RandomState;
+use crate::surface::WAY_BELOW_MIN_Y;
+use std::collections::HashMap;
+
+/// Aquifer centres are placed one per grid cell of this size, jittered by
+/// up to the range in each direction.
+const X_SPACING: i32 = 16;
+const Y_SPACING: i32 = 12;
+const Z_SPACING: i32 = 16;
+const X_RANGE: i32 = 10;
+const Y_RANGE: i32 = 9;
+const Z_RANGE: i32 = 10;
+
+/// Chunk offsets of the columns whose surface caps an aquifer.
+const SURFACE_SAMPLING_OFFSETS_IN_CHUNKS: [(i32, i32); 13] = [
+    (0, 0),
+    (-2, -1),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-3, 0),
+    (-2, 0),
+    (-1, 0),
+    (1, 0),
+    (-2, 1),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+/// How alike two squared distances to aquifer centres are: 1 when equal,
+/// falling to 0 at a difference of 25.
+fn similarity(a: i32, b: i32) -> f64 {
+    1.0 - f64::from((b - a).abs()) / 25.0
+}
+
+/// Above this similarity of the nearest centres, fluids placed between
+/// differing aquifers are scheduled to flow.
+fn flowing_update_similarity() -> f64 {
+    similarity(10 * 10, 12 * 12)
+}
+
+fn is_lava(state: &BlockState) -> bool {
+    state.name == "minecraft:lava"
+}
+
+/// A fluid surface: `fluid_type` below `fluid_level`, air at and above it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FluidStatus {
+    pub fluid_level: i32,
+    pub fluid_type: BlockState,
+}
+
+impl FluidStatus {
+    pub fn new(fluid_level: i32, fluid_type: BlockState) -> Self {
+        Self {
+            fluid_level,
+            fluid_type,
+        }
+    }
+
+    pub fn at(&self, y: i32) -> BlockState {
+        if y < self.fluid_level {
+            self.fluid_type.clone()
+        } else {
+            BlockState::air()
+        }
+    }
+}
+
+/// The dimension-wide fluid levels: the default fluid up to sea level and
+/// lava up to y = -54 below it.
+#[derive(Clone, Debug)]
+pub struct FluidPicker {
+    lava: FluidStatus,
+    fluid: FluidStatus,
+}
+
+impl FluidPicker {
+    pub fn new(sea_level: i32, default_fluid: BlockState) -> Self {
+        Self {
+            lava: FluidStatus::new(-54, BlockState::new("minecraft:lava")),
+            fluid: FluidStatus::new(sea_level, default_fluid),
+        }
+    }
+
+    pub fn compute_fluid(&self, _x: i32, y: i32, _z: i32) -> &FluidStatus {
+        if y < self.lava.fluid_level.min(self.fluid.fluid_level) {
+            &self.lava
+        } else {
+            &self.fluid
+        }
+    }
+}
+
+/// Decides the block at a position the density leaves open.
+pub enum Aquifer<'a> {
+    /// No local fluid levels, only the dimension-wide ones.
+    Disabled(FluidPicker),
+    NoiseBased(Box<NoiseBasedAquifer<'a>>),
+}
+
+impl Aquifer<'_> {
+    /// The block at a position with the given density: `None` where the
+    /// terrain is solid, otherwise the fluid or air filling it.
+    pub fn compute_substance(
+        &mut self,
+        x: i32,
+        y: i32,
+        z: i32,
+        density: f64,
+    ) -> Option<BlockState> {
+        match self {
+            Self::Disabled(picker) => (density <= 0.0).then(|| picker.compute_fluid(x, y, z).at(y)),
+            Self::NoiseBased(aquifer) => aquifer.compute_substance(x, y, z, density),
+        }
+    }
+
+    /// Whether placed fluids may flow and must be ticked after generation.
+    pub fn should_schedule_fluid_update(&self) -> bool {
+        match self {
+            Self::Disabled(_) => false,
+            Self::NoiseBased(aquifer) => aquifer.should_schedule_fluid_update,
+        }
+    }
+}
+
+/// The aquifers of one chunk, with the centres and fluid levels of the
+/// grid cells around it cached.
+pub struct NoiseBasedAquifer<'a> {
+    state: &'a RandomState,
+    global: FluidPicker,
+    min_grid_x: i32,
+    min_grid_y: i32,
+    min_grid_z: i32,
+    grid_size_x: i32,
+    grid_size_z: i32,
+    /// Packed centre of each grid cell, as `BlockPos.asLong`.
+    locations: Vec<Option<i64>>,
+    statuses: Vec<Option<FluidStatus>>,
+    preliminary_surface: HashMap<(i32, i32), i32>,
+    should_schedule_fluid_update: bool,
+}
+
+impl<'a> NoiseBasedAquifer<'a> {
+    /// Aquifers for chunk `chunk_x`/`chunk_z` of a world spanning `height`
+    /// blocks from `min_y`, with `global` as the dimension-wide levels.
+    pub fn new(
+        state: &'a RandomState,
+        chunk_x: i32,
+        chunk_z: i32,
+        min_y: i32,
+        height: i32,
+        global: FluidPicker,
+    ) -> Self {
+        let size = SECTION_SIZE as i32;
+        let min_grid_x = (chunk_x * size).div_euclid(X_SPACING) - 1;
+        let max_grid_x = (chunk_x * size + size - 1).div_euclid(X_SPACING) + 1;
+        let min_grid_y = min_y.div_euclid(Y_SPACING) - 1;
+        let max_grid_y = (min_y + height).div_euclid(Y_SPACING) + 1;
+        let min_grid_z = (chunk_z * size).div_euclid(Z_SPACING) - 1;
+        let max_grid_z = (chunk_z * size + size - 1).div_euclid(Z_SPACING) + 1;
+        let grid_size_x = max_grid_x - min_grid_x + 1;
+        let grid_size_z = max_grid_z - min_grid_z + 1;
+        let cells = (grid_size_x * (max_grid_y - min_grid_y + 1) * grid_size_z) as usize;
+        Self {
+            state,
+            global,
+            min_grid_x,
+            min_grid_y,
+            min_grid_z,
+            grid_size_x,
+            grid_size_z,
+            locations: vec![None; cells],
+            statuses: vec![None; cells],
+            preliminary_surface: HashMap::new(),
+            should_schedule_fluid_update: false,
+        }
+    }
+
+    fn index(&self, grid_x: i32, grid_y: i32, grid_z: i32) -> usize {
+        let x = grid_x - self.min_grid_x;
+        let y = grid_y - self.min_grid_y;
+        let z = grid_z - self.min_grid_z;
+        ((y * self.grid_size_z + z) * self.grid_size_x + x) as usize
+    }
+
+    fn compute_substance(&mut self, x: i32, y: i32, z: i32, density: f64) -> Option<BlockState> {
+        if density > 0.0 {
+            self.should_schedule_fluid_update = false;
+            return None;
+        }
+        if is_lava(&self.global.compute_fluid(x, y, z).at(y)) {
+            self.should_schedule_fluid_update = false;
+            return Some(BlockState::new("minecraft:lava"));
+        }
+
+        // The four nearest aquifer centres and their squared distances.
+        let mut nearest = [(i32::MAX, 0i64); 4];
+        let (grid_x, grid_y, grid_z) = (
+            (x - 5).div_euclid(X_SPACING),
+            (y + 1).div_euclid(Y_SPACING),
+            (z - 5).div_euclid(Z_SPACING),
+        );
+        for dx in 0..=1 {
+            for dy in -1..=1 {
+                for dz in 0..=1 {
+                    let location = self.location(grid_x + dx, grid_y + dy, grid_z + dz);
+                    let (cx, cy, cz) = unpack(location);
+                    let distance = (cx - x).pow(2) + (cy - y).pow(2) + (cz - z).pow(2);
+                    if let Some(i) = nearest.iter().position(|&(d, _)| d >= distance) {
+                        nearest[i..].rotate_right(1);
+                        nearest[i] = (distance, location);
+                    }
+                }
+            }
+        }
+        let [(d0, l0), (d1, l1), (d2, l2), (d3, l3)] = nearest;
+
+        let first = self.status(l0);
+        let similar = similarity(d0, d1);
+        let state = first.at(y);
+        let flowing = flowing_update_similarity();
+        if similar <= 0.0 {
+            self.should_schedule_fluid_update = similar >= flowing && first != self.status(l1);
+            return Some(state);
+        }
+        if state.name == "minecraft:water"
+            && is_lava(&self.global.compute_fluid(x, y - 1, z).at(y - 1))
+        {
+            self.should_schedule_fluid_update = true;
+            return Some(state);
+        }
+
+        let mut barrier = None;
+        let second = self.status(l1);
+        if density + similar * self.pressure(x, y, z, &mut barrier, &first, &second) > 0.0 {
+            self.should_schedule_fluid_update = false;
+            return None;
+        }
+        let third = self.status(l2);
+        let similar_13 = similarity(d0, d2);
+        if similar_13 > 0.0 {
+            let pressure = self.pressure(x, y, z, &mut barrier, &first, &third);
+            if density + similar * similar_13 * pressure > 0.0 {
+                self.should_schedule_fluid_update = false;
+                return None;
+            }
+        }
+        let similar_23 = similarity(d1, d2);
+        if similar_23 > 0.0 {
+            let pressure = self.pressure(x, y, z, &mut barrier, &second, &third);
+            if density + similar * similar_23 * pressure > 0.0 {
+                self.should_schedule_fluid_update = false;
+                return None;
+            }
+        }
+
+        self.should_schedule_fluid_update = first != second
+            || (similar_23 >= flowing && second != third)
+            || (similar_13 >= flowing && first != third)
+            || (similar_13 >= flowing && similarity(d0, d3) >= flowing && first != self.status(l3));
+        Some(state)
+    }
+
+    /// The jittered centre of a grid cell, packed.
+    fn location(&mut self, grid_x: i32, grid_y: i32, grid_z: i32) -> i64 {
+        let index = self.index(grid_x, grid_y, grid_z);
+        if let Some(location) = self.locations[index] {
+            return location;
+        }
+        let mut random = self.state.aquifer_random().at(grid_x, grid_y, grid_z);
+        let location = pack(
+            grid_x * X_SPACING + random.next_int_bounded(X_RANGE),
+            grid_y * Y_SPACING + random.next_int_bounded(Y_RANGE),
+            grid_z * Z_SPACING + random.next_int_bounded(Z_RANGE),
+        );
+        self.locations[index] = Some(location);
+        location
+    }
+
+    /// How strongly the boundary between two aquifers resists opening up:
+    /// always where lava meets water, otherwise by how far the levels differ
+    /// around this height, roughened by the barrier noise.
+    fn pressure(
+        &self,
+        x: i32,
+        y: i32,
+        z: i32,
+        barrier: &mut Option<f64>,
+        first: &FluidStatus,
+        second: &FluidStatus,
+    ) -> f64 {
+        let (a, b) = (first.at(y), second.at(y));
+        if (is_lava(&a) && b.name == "minecraft:water")
+            || (a.name == "minecraft:water" && is_lava(&b))
+        {
+            return 2.0;
+        }
+        let difference = (first.fluid_level - second.fluid_level).abs();
+        if difference == 0 {
+            return 0.0;
+        }
+        let middle = 0.5 * f64::from(first.fluid_level + second.fluid_level);
+        let above = f64::from(y) + 0.5 - middle;
+        let half = f64::from(difference) / 2.0;
+        let distance = half - above.abs();
+        let gradient = if above > 0.0 {
+            if distance > 0.0 {
+                distance / 1.5
+            } else {
+                distance / 2.5
+            }
+        } else if 3.0 + distance > 0.0 {
+            (3.0 + distance) / 3.0
+        } else {
+            (3.0 + distance) / 10.0
+        };
+        let noise = if (-2.0..=2.0).contains(&gradient) {
+            *barrier.get_or_insert_with(|| self.state.router.barrier.compute(x, y, z))
+        } else {
+            0.0
+        };
+        2.0 * (noise + gradient)
+    }
+
+    /// The fluid of the aquifer centred at `location`.
+    fn status(&mut self, location: i64) -> FluidStatus {
+        let (x, y, z) = unpack(location);
+        let index = self.index(
+            x.div_euclid(X_SPACING),
+            y.div_euclid(Y_SPACING),
+            z.div_euclid(Z_SPACING),
+        );
+        if let Some(status) = &self.statuses[index] {
+            return status.clone();
+        }
+        let status = self.compute_fluid(x, y, z);
+        self.statuses[index] = Some(status.clone());
+        status
+    }
+
+    fn preliminary_surface_level(&mut self, x: i32, z: i32) -> i32 {
+        let (x, z) = (x & !3, z & !3);
+        let level = &self.state.router.preliminary_surface_level;
+        *self
+            .preliminary_surface
+            .entry((x, z))
+            .or_insert_with(|| floor(level.compute(x, 0, z)))
+    }
+
+    /// An aquifer near or above the surface takes the surface's fluid;
+    /// deeper ones get a level of their own.
+    fn compute_fluid(&mut self, x: i32, y: i32, z: i32) -> FluidStatus {
+        let global = self.global.compute_fluid(x, y, z).clone();
+        let mut lowest_surface = i32::MAX;
+        let mut fluid_present = false;
+        for (dx, dz) in SURFACE_SAMPLING_OFFSETS_IN_CHUNKS {
+            let (sx, sz) = (x + dx * 16, z + dz * 16);
+            let surface = self.preliminary_surface_level(sx, sz);
+            let top = surface + 8;
+            let centre = dx == 0 && dz == 0;
+            if centre && y - 12 > top {
+                return global;
+            }
+            let below_surface = y + 12 > top;
+            if below_surface || centre {
+                let status = self.global.compute_fluid(sx, top, sz);
+                if !status.at(top).is_air() {
+                    fluid_present |= centre;
+                    if below_surface {
+                        return status.clone();
+                    }
+                }
+            }
+            lowest_surface = lowest_surface.min(surface);
+        }
+        let level = self.surface_level(x, y, z, &global, lowest_surface, fluid_present);
+        let fluid_type = self.fluid_type(x, y, z, &global, level);
+        FluidStatus::new(level, fluid_type)
+    }
+
+    /// The level of a buried aquifer: the global one where flooded, a
+    /// random one, or none at all. Deep dark regions never flood.
+    fn surface_level(
+        &self,
+        x: i32,
+        y: i32,
+        z: i32,
+        global: &FluidStatus,
+        max_surface_level: i32,
+        fluid_present: bool,
+    ) -> i32 {
+        let router = &self.state.router;
+        let deep_dark = router.erosion.compute(x, y, z) < f64::from(-0.225f32)
+            && router.depth.compute(x, y, z) > f64::from(0.9f32);
+        let (partially_flooded, fully_flooded) = if deep_dark {
+            (-1.0, -1.0)
+        } else {
+            let depth = f64::from(max_surface_level + 8 - y);
+            let near_surface = if fluid_present {
+                1.0 - (depth / 64.0).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let floodedness = router
+                .fluid_level_floodedness
+                .compute(x, y, z)
+                .clamp(-1.0, 1.0);
+            let full = -0.3 + (near_surface - 1.0) / -1.0 * 1.1;
+            let partial = -0.8 + (near_surface - 1.0) / -1.0 * 1.2;
+            (floodedness - partial, floodedness - full)
+        };
+        if fully_flooded > 0.0 {
+            global.fluid_level
+        } else if partially_flooded > 0.0 {
+            self.randomized_surface_level(x, y, z, max_surface_level)
+        } else {
+            WAY_BELOW_MIN_Y
+        }
+    }
+
+    fn randomized_surface_level(&self, x: i32, y: i32, z: i32, max_surface_level: i32) -> i32 {
+        let (gx, gy, gz) = (x.div_euclid(16), y.div_euclid(40), z.div_euclid(16));
+        let spread = self.state.router.fluid_level_spread.compute(gx, gy, gz) * 10.0;
+        let quantized = floor(spread / 3.0) * 3;
+        max_surface_level.min(gy * 40 + 20 + quantized)
+    }
+
+    /// Deep aquifers turn to lava in patches of the lava noise.
+    fn fluid_type(&self, x: i32, y: i32, z: i32, global: &FluidStatus, level: i32) -> BlockState {
+        if level <= -10 && level != WAY_BELOW_MIN_Y && !is_lava(&global.fluid_type) {
+            let (gx, gy, gz) = (x.div_euclid(64), y.div_euclid(40), z.div_euclid(64));
+            if self.state.router.lava.compute(gx, gy, gz).abs() > 0.3 {
+                return BlockState::new("minecraft:lava");
+            }
+        }
+        global.fluid_type.clone()
+    }
+}
+
+/// `BlockPos.asLong`.
+fn pack(x: i32, y: i32, z: i32) -> i64 {
+    ((i64::from(x) & 0x3ff_ffff) << 38)
+        | ((i64::from(z) & 0x3ff_ffff) << 12)
+        | (i64::from(y) & 0xfff)
+}
+
+/// `BlockPos.getX/Y/Z`.
+fn unpack(packed: i64) -> (i32, i32, i32) {
+    (
+        (packed >> 38) as i32,
+        (packed << 52 >> 52) as i32,
+        (packed << 26 >> 38) as i32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::density::DensityFunction;
+    use crate::noise_router::NoiseRouter;
+
+    /// A world whose surface is at y = 100 everywhere and whose aquifers
+    /// have the given floodedness.
+    fn world(floodedness: f64) -> RandomState {
+        let router = NoiseRouter {
+            preliminary_surface_level: DensityFunction::constant(100.0),
+            fluid_level_floodedness: DensityFunction::constant(floodedness),
+            ..NoiseRouter::default()
+        };
+        RandomState::new(&router, 99, false)
+    }
+
+    /// The blocks an open cave gets from y = -64 up to 100 at `x`/`z`, with
+    /// stone for barriers between aquifers.
+    fn column(state: &RandomState, x: i32, z: i32) -> Vec<BlockState> {
+        let picker = FluidPicker::new(63, BlockState::new("minecraft:water"));
+        let aquifer = NoiseBasedAquifer::new(state, x >> 4, z >> 4, -64, 384, picker);
+        let mut aquifer = Aquifer::NoiseBased(Box::new(aquifer));
+        (-64..100)
+            .map(|y| {
+                aquifer
+                    .compute_substance(x, y, z, -1.0)
+                    .unwrap_or_else(|| BlockState::new("minecraft:stone"))
+            })
+            .collect()
+    }
+
+    fn names(blocks: &[BlockState]) -> Vec<&str> {
+        blocks.iter().map(|b| b.name.as_str()).collect()
+    }
+
+    #[test]
+    fn flooded_and_dry_caves() {
+        for (x, y, z) in [(0, 0, 0), (-5, -64, 77), (1000, 319, -1000)] {
+            assert_eq!(unpack(pack(x, y, z)), (x, y, z));
+        }
+
+        // Lava below y = -54, whose aquifers hold air above it, then sea
+        // water in flooded caves and nothing in dry ones.
+        let flooded = column(&world(1.0), 40, 40);
+        let dry = column(&world(-1.0), 40, 40);
+        for (blocks, fill) in [(&flooded, "minecraft:water"), (&dry, "minecraft:air")] {
+            let names = names(blocks);
+            for (y, &name) in (-64..).zip(&names) {
+                let expected = if y < -54 {
+                    "minecraft:lava"
+                } else if y < -40 && name == "minecraft:air" {
+                    name
+                } else if y < 63 {
+                    fill
+                } else {
+                    "minecraft:air"
+                };
+                assert!(
+                    name == expected || name == "minecraft:stone",
+                    "{y}: {names:?}"
+                );
+            }
+            assert!(names.contains(&"minecraft:lava") && names.contains(&fill));
+        }
+        let state = world(1.0);
+        let mut aquifer = Aquifer::NoiseBased(Box::new(NoiseBasedAquifer::new(
+            &state,
+            0,
+            0,
+            -64,
+            384,
+            FluidPicker::new(63, BlockState::new("minecraft:water")),
+        )));
+        assert_eq!(aquifer.compute_substance(3, 20, 3, 0.5), None);
+    }
+
+    #[test]
+    fn partly_flooded_caves_have_their_own_levels() {
+        let state = world(0.5);
+        let mut levels = 0;
+        for x in 0..8 {
+            let blocks = column(&state, x * 50, 7);
+            let names = names(&blocks);
+            // Somewhere water stands below air without reaching sea level.
+            levels += names
+                .windows(2)
+                .zip(-64..)
+                .filter(|(w, y)| *y < 62 && w[0] == "minecraft:water" && w[1] != "minecraft:water")
+                .count();
+        }
+        assert!(levels > 0);
+    }
+}
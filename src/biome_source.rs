@@ -0,0 +1,396 @@
+//! Biome sources: which biome sits at each 4×4×4 cell of a chunk.
+
+use crate::chunk::{BIOME_SIZE, Chunk, SECTION_SIZE};
+use crate::climate::{self, Parameter, ParameterList, ParameterPoint, quantize_coord};
+use crate::density::DensityFn;
+use crate::nbt_util::invalid_data;
+use crate::noise_router::NoiseRouter;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::io;
+use std::path::Path;
+
+/// Picks biomes by climate from a list of parameter points, as used by the
+/// overworld and the Nether.
+#[derive(Debug)]
+pub struct MultiNoiseBiomeSource {
+    parameters: ParameterList<String>,
+}
+
+impl MultiNoiseBiomeSource {
+    pub fn new(biomes: Vec<(ParameterPoint, String)>) -> Self {
+        Self {
+            parameters: ParameterList::new(biomes),
+        }
+    }
+
+    /// The `minecraft:nether` preset.
+    pub fn nether() -> Self {
+        let biome = |t, h, offset, name: &str| {
+            (
+                ParameterPoint::new(t, h, 0.0, 0.0, 0.0, 0.0, offset),
+                name.to_string(),
+            )
+        };
+        Self::new(vec![
+            biome(0.0, 0.0, 0.0, "minecraft:nether_wastes"),
+            biome(0.0, -0.5, 0.0, "minecraft:soul_sand_valley"),
+            biome(0.4, 0.0, 0.0, "minecraft:crimson_forest"),
+            biome(0.0, 0.5, 0.375, "minecraft:warped_forest"),
+            biome(-0.5, 0.0, 0.175, "minecraft:basalt_deltas"),
+        ])
+    }
+
+    /// Parse a `{"biomes": [{"biome": ..., "parameters": {...}}]}` list, the
+    /// shape of both an inline `multi_noise` biome source and the
+    /// `reports/biome_parameters` files of the data generator. The latter
+    /// are how the large overworld preset is obtained.
+    pub fn from_json(json: &Value) -> io::Result<Self> {
+        let biomes = json
+            .get("biomes")
+            .and_then(Value::as_array)
+            .filter(|b| !b.is_empty())
+            .ok_or_else(|| invalid_data("multi noise source has no `biomes`"))?;
+        let biomes = biomes
+            .iter()
+            .map(|entry| {
+                let biome = entry
+                    .get("biome")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| invalid_data("biome entry has no `biome`"))?;
+                let p = entry
+                    .get("parameters")
+                    .ok_or_else(|| invalid_data(format!("`{biome}` has no `parameters`")))?;
+                let param = |key: &str| {
+                    parse_parameter(p.get(key).ok_or_else(|| {
+                        invalid_data(format!("`{biome}` is missing parameter `{key}`"))
+                    })?)
+                    .map_err(|e| invalid_data(format!("`{biome}` parameter `{key}`: {e}")))
+                };
+                let offset = match p.get("offset") {
+                    Some(v) => quantize_coord(as_f32(v)?),
+                    None => 0,
+                };
+                let point = ParameterPoint {
+                    temperature: param("temperature")?,
+                    humidity: param("humidity")?,
+                    continentalness: param("continentalness")?,
+                    erosion: param("erosion")?,
+                    depth: param("depth")?,
+                    weirdness: param("weirdness")?,
+                    offset,
+                };
+                Ok((point, biome.to_string()))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(Self::new(biomes))
+    }
+
+    /// Load a biome parameter list from a JSON file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let json: Value = serde_json::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| invalid_data(format!("invalid biome parameters: {e}")))?;
+        Self::from_json(&json)
+    }
+
+    pub fn parameters(&self) -> &ParameterList<String> {
+        &self.parameters
+    }
+
+    /// Biome at quart coordinates, by the climate sampled from `router`.
+    pub fn get_noise_biome(&self, router: &NoiseRouter, x: i32, y: i32, z: i32) -> &str {
+        self.parameters
+            .find_value(&climate::sample(router, x, y, z))
+    }
+}
+
+/// JSON numbers are read as `f32` from their text, as the game's codecs do.
+fn as_f32(v: &Value) -> io::Result<f32> {
+    match v {
+        Value::Number(n) => n
+            .to_string()
+            .parse()
+            .map_err(|_| invalid_data(format!("bad number {n}"))),
+        _ => Err(invalid_data("not a number")),
+    }
+}
+
+/// A single value or a `[min, max]` pair.
+fn parse_parameter(v: &Value) -> io::Result<Parameter> {
+    match v {
+        Value::Array(pair) if pair.len() == 2 => {
+            let (min, max) = (as_f32(&pair[0])?, as_f32(&pair[1])?);
+            if min > max {
+                return Err(invalid_data(format!("min {min} above max {max}")));
+            }
+            Ok(Parameter::span(min, max))
+        }
+        v => Ok(Parameter::point(as_f32(v)?)),
+    }
+}
+
+/// Where a dimension's biomes come from.
+#[derive(Debug)]
+pub enum BiomeSource {
+    /// One biome everywhere, like superflat and single-biome worlds.
+    Fixed(String),
+    MultiNoise(MultiNoiseBiomeSource),
+    /// The End: the main island, then rings chosen by erosion.
+    TheEnd,
+}
+
+impl BiomeSource {
+    /// Biome at quart coordinates.
+    pub fn get_noise_biome(&self, router: &NoiseRouter, x: i32, y: i32, z: i32) -> &str {
+        match self {
+            Self::Fixed(biome) => biome,
+            Self::MultiNoise(source) => source.get_noise_biome(router, x, y, z),
+            Self::TheEnd => the_end_biome(&router.erosion, x, y, z),
+        }
+    }
+
+    /// Every biome the source can pick, in the order it lists them.
+    pub fn possible_biomes(&self) -> Vec<&str> {
+        match self {
+            Self::Fixed(biome) => vec![biome],
+            Self::MultiNoise(source) => {
+                let mut biomes: Vec<&str> = Vec::new();
+                for (_, biome) in source.parameters.values() {
+                    if !biomes.contains(&biome.as_str()) {
+                        biomes.push(biome);
+                    }
+                }
+                biomes
+            }
+            Self::TheEnd => vec![
+                "minecraft:the_end",
+                "minecraft:end_highlands",
+                "minecraft:end_midlands",
+                "minecraft:small_end_islands",
+                "minecraft:end_barrens",
+            ],
+        }
+    }
+
+    /// Fill the 4×4×4 biome cells of every section of `chunk`.
+    pub fn fill_biomes(&self, chunk: &mut Chunk, router: &NoiseRouter) {
+        let quarts = (SECTION_SIZE / BIOME_SIZE) as i32;
+        let (base_x, base_z) = (chunk.x * quarts, chunk.z * quarts);
+        let min_section_y = chunk.min_section_y();
+        for (i, section) in chunk.sections_mut().iter_mut().enumerate() {
+            let base_y = (min_section_y + i as i32) * quarts;
+            for y in 0..quarts {
+                for z in 0..quarts {
+                    for x in 0..quarts {
+                        let biome =
+                            self.get_noise_biome(router, base_x + x, base_y + y, base_z + z);
+                        section.set_biome(x as usize, y as usize, z as usize, biome);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Smooths biome borders: picks, for a block, one of the eight quart cells
+/// around it by a seeded jittered distance, like vanilla's `BiomeManager`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BiomeManager {
+    zoom_seed: i64,
+}
+
+impl BiomeManager {
+    pub fn new(seed: i64) -> Self {
+        Self {
+            zoom_seed: obfuscate_seed(seed),
+        }
+    }
+
+    /// Biome at block coordinates, given the biome of each quart cell.
+    pub fn get_biome<T>(
+        &self,
+        x: i32,
+        y: i32,
+        z: i32,
+        mut noise_biome: impl FnMut(i32, i32, i32) -> T,
+    ) -> T {
+        let (x, y, z) = (x - 2, y - 2, z - 2);
+        let (qx, qy, qz) = (x >> 2, y >> 2, z >> 2);
+        let fx = f64::from(x & 3) / 4.0;
+        let fy = f64::from(y & 3) / 4.0;
+        let fz = f64::from(z & 3) / 4.0;
+        let mut closest = 0;
+        let mut best = f64::INFINITY;
+        for corner in 0..8 {
+            let (dx, dy, dz) = ((corner >> 2) & 1, (corner >> 1) & 1, corner & 1);
+            let distance = fiddled_distance(
+                self.zoom_seed,
+                qx + dx,
+                qy + dy,
+                qz + dz,
+                fx - f64::from(dx),
+                fy - f64::from(dy),
+                fz - f64::from(dz),
+            );
+            if best > distance {
+                closest = corner;
+                best = distance;
+            }
+        }
+        noise_biome(
+            qx + ((closest >> 2) & 1),
+            qy + ((closest >> 1) & 1),
+            qz + (closest & 1),
+        )
+    }
+}
+
+/// `BiomeManager.obfuscateSeed`: the first eight bytes of the SHA-256 of
+/// the little-endian seed.
+pub fn obfuscate_seed(seed: i64) -> i64 {
+    let hash = Sha256::digest(seed.to_le_bytes());
+    i64::from_le_bytes(hash[..8].try_into().unwrap())
+}
+
+fn lcg_next(seed: i64, add: i64) -> i64 {
+    let next = seed
+        .wrapping_mul(6364136223846793005)
+        .wrapping_add(1442695040888963407);
+    seed.wrapping_mul(next).wrapping_add(add)
+}
+
+fn fiddled_distance(seed: i64, x: i32, y: i32, z: i32, dx: f64, dy: f64, dz: f64) -> f64 {
+    let mut m = seed;
+    for v in [x, y, z, x, y, z] {
+        m = lcg_next(m, i64::from(v));
+    }
+    let fiddle_x = fiddle(m);
+    m = lcg_next(m, seed);
+    let fiddle_y = fiddle(m);
+    m = lcg_next(m, seed);
+    let fiddle_z = fiddle(m);
+    (dz + fiddle_z).powi(2) + (dy + fiddle_y).powi(2) + (dx + fiddle_x).powi(2)
+}
+
+fn fiddle(seed: i64) -> f64 {
+    let d = (seed >> 24).rem_euclid(1024) as f64 / 1024.0;
+    (d - 0.5) * 0.9
+}
+
+fn the_end_biome(erosion: &DensityFn, x: i32, y: i32, z: i32) -> &'static str {
+    let (section_x, section_z) = ((x << 2) >> 4, (z << 2) >> 4);
+    if i64::from(section_x).pow(2) + i64::from(section_z).pow(2) <= 4096 {
+        return "minecraft:the_end";
+    }
+    let d = erosion.compute((section_x * 2 + 1) * 8, y << 2, (section_z * 2 + 1) * 8);
+    if d > 0.25 {
+        "minecraft:end_highlands"
+    } else if d >= -0.0625 {
+        "minecraft:end_midlands"
+    } else if d < -0.21875 {
+        "minecraft:small_end_islands"
+    } else {
+        "minecraft:end_barrens"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::density::DensityFunction;
+
+    #[test]
+    fn picks_the_closest_biome() {
+        let router = |t: f64, h: f64| NoiseRouter {
+            temperature: DensityFunction::constant(t),
+            vegetation: DensityFunction::constant(h),
+            ..Default::default()
+        };
+        let nether = MultiNoiseBiomeSource::nether();
+        let at = |t, h| nether.get_noise_biome(&router(t, h), 10, 5, -3).to_string();
+        assert_eq!(at(0.0, 0.0), "minecraft:nether_wastes");
+        assert_eq!(at(0.05, -0.4), "minecraft:soul_sand_valley");
+        assert_eq!(at(0.5, 0.1), "minecraft:crimson_forest");
+        assert_eq!(at(-0.6, 0.0), "minecraft:basalt_deltas");
+        // The offset makes warped forest lose to nether wastes close by.
+        assert_eq!(at(0.0, 0.3), "minecraft:nether_wastes");
+        assert_eq!(at(0.0, 0.9), "minecraft:warped_forest");
+    }
+
+    #[test]
+    fn parses_parameter_lists_and_fills_chunks() {
+        let json = serde_json::json!({ "biomes": [
+            {
+                "biome": "minecraft:desert",
+                "parameters": {
+                    "temperature": [0.55, 1.0], "humidity": [-1.0, 1.0],
+                    "continentalness": [-0.11, 1.0], "erosion": [-1.0, 1.0],
+                    "depth": 0.0, "weirdness": [-1.0, 1.0], "offset": 0.0
+                }
+            },
+            {
+                "biome": "minecraft:snowy_plains",
+                "parameters": {
+                    "temperature": [-1.0, -0.45], "humidity": [-1.0, 1.0],
+                    "continentalness": [-0.11, 1.0], "erosion": [-1.0, 1.0],
+                    "depth": 0.0, "weirdness": [-1.0, 1.0], "offset": 0.0
+                }
+            }
+        ]});
+        let source = BiomeSource::MultiNoise(MultiNoiseBiomeSource::from_json(&json).unwrap());
+        // Cold at the bottom of the world, warm at the top.
+        let router = NoiseRouter {
+            temperature: DensityFunction::y_clamped_gradient(0, 128, -1.0, 1.0),
+            ..Default::default()
+        };
+        let mut chunk = Chunk::new(0, 0);
+        source.fill_biomes(&mut chunk, &router);
+        assert_eq!(chunk.get_biome(0, -64, 0), "minecraft:snowy_plains");
+        assert_eq!(chunk.get_biome(15, 300, 15), "minecraft:desert");
+        // Quart y 16 (y = 64) samples exactly 0, closer to snowy plains.
+        assert_eq!(chunk.get_biome(3, 64, 3), "minecraft:snowy_plains");
+        assert_eq!(chunk.get_biome(3, 100, 3), "minecraft:desert");
+
+        let bad = serde_json::json!({ "biomes": [{ "biome": "x", "parameters": {} }] });
+        assert!(MultiNoiseBiomeSource::from_json(&bad).is_err());
+    }
+
+    #[test]
+    fn end_rings_follow_erosion() {
+        let router = NoiseRouter {
+            erosion: DensityFunction::constant(0.3),
+            ..Default::default()
+        };
+        let end = BiomeSource::TheEnd;
+        assert_eq!(end.get_noise_biome(&router, 0, 10, 0), "minecraft:the_end");
+        assert_eq!(
+            end.get_noise_biome(&router, 1000, 10, 0),
+            "minecraft:end_highlands"
+        );
+        assert_eq!(
+            BiomeSource::Fixed("minecraft:plains".into()).get_noise_biome(&router, 0, 0, 0),
+            "minecraft:plains"
+        );
+    }
+
+    #[test]
+    fn zooms_between_neighbouring_cells() {
+        assert_eq!(obfuscate_seed(0), 8794265229978523055);
+        assert_eq!(obfuscate_seed(1234), 7029374744722207258);
+
+        let manager = BiomeManager::new(1234);
+        let mut moved = false;
+        for x in -20..20 {
+            for z in -20..20 {
+                let (qx, qy, qz) = manager.get_biome(x, 70, z, |x, y, z| (x, y, z));
+                // The chosen cell is one of the eight around the block,
+                // offset by two so the block sits in the middle.
+                assert!((qx - ((x - 2) >> 2)) & !1 == 0);
+                assert!((qy - (68 >> 2)) & !1 == 0);
+                assert!((qz - ((z - 2) >> 2)) & !1 == 0);
+                moved |= (qx, qz) != (x >> 2, z >> 2);
+            }
+        }
+        assert!(moved);
+    }
+}
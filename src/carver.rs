@@ -0,0 +1,786 @@
+//! Carvers: the tunnels, caverns and ravines cut into terrain after the
+//! surface stage. A port of vanilla's `CaveWorldCarver`,
+//! `NetherWorldCarver` and `CanyonWorldCarver`, started per chunk from the
+//! world seed and spreading up to eight chunks away from where they start.
+
+use crate::aquifer::Aquifer;
+use crate::biome_source::BiomeSource;
+use crate::chunk::{BlockState, CarvingMask, Chunk, SECTION_SIZE};
+use crate::noise::{cos, floor, sin};
+use crate::noise_router::RandomState;
+use crate::provider::{FloatProvider, HeightProvider};
+use crate::random::{LegacyRandom, RandomAlgorithm, RandomSource, WorldgenRandom};
+use crate::surface::{SurfaceSystem, VerticalAnchor, has_fluid};
+use std::collections::{HashMap, HashSet};
+use std::f32::consts::PI;
+
+/// Chunks around a chunk whose carvers may reach into it.
+const RANGE: i32 = 8;
+/// Vanilla's `WorldCarver.getRange`, which sets how long tunnels get.
+const CARVER_RANGE: i32 = 4;
+/// Blocks below the top of the world that carvers leave alone.
+const TOP_MARGIN: i32 = 7;
+
+/// Settings shared by every carver.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CarverConfiguration {
+    /// Chance for each chunk to start the carver.
+    pub probability: f32,
+    pub y: HeightProvider,
+    pub y_scale: FloatProvider,
+    /// Carved blocks at or below this height become lava.
+    pub lava_level: VerticalAnchor,
+    /// Names of the blocks the carver may remove.
+    pub replaceable: HashSet<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CaveCarverConfiguration {
+    pub carver: CarverConfiguration,
+    pub horizontal_radius_multiplier: FloatProvider,
+    pub vertical_radius_multiplier: FloatProvider,
+    /// How far down, relative to the radius, tunnels get a flat floor.
+    pub floor_level: FloatProvider,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CanyonShape {
+    pub distance_factor: FloatProvider,
+    pub thickness: FloatProvider,
+    /// One in this many heights changes the width of the walls.
+    pub width_smoothness: i32,
+    pub horizontal_radius_factor: FloatProvider,
+    pub vertical_radius_default_factor: f32,
+    pub vertical_radius_center_factor: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CanyonCarverConfiguration {
+    pub carver: CarverConfiguration,
+    pub vertical_rotation: FloatProvider,
+    pub shape: CanyonShape,
+}
+
+/// A `worldgen/configured_carver` entry.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfiguredCarver {
+    Cave(CaveCarverConfiguration),
+    /// Wider, flatter caves that fill with lava near the bottom of the world
+    /// and never with the aquifer's fluids.
+    NetherCave(CaveCarverConfiguration),
+    Canyon(CanyonCarverConfiguration),
+}
+
+/// Vanilla's `#overworld_carver_replaceables` block tag, expanded.
+pub const OVERWORLD_CARVER_REPLACEABLES: &[&str] = &[
+    "minecraft:stone",
+    "minecraft:granite",
+    "minecraft:diorite",
+    "minecraft:andesite",
+    "minecraft:tuff",
+    "minecraft:deepslate",
+    "minecraft:dirt",
+    "minecraft:grass_block",
+    "minecraft:podzol",
+    "minecraft:coarse_dirt",
+    "minecraft:mycelium",
+    "minecraft:rooted_dirt",
+    "minecraft:moss_block",
+    "minecraft:mud",
+    "minecraft:muddy_mangrove_roots",
+    "minecraft:sand",
+    "minecraft:red_sand",
+    "minecraft:terracotta",
+    "minecraft:white_terracotta",
+    "minecraft:orange_terracotta",
+    "minecraft:yellow_terracotta",
+    "minecraft:brown_terracotta",
+    "minecraft:red_terracotta",
+    "minecraft:light_gray_terracotta",
+    "minecraft:iron_ore",
+    "minecraft:deepslate_iron_ore",
+    "minecraft:copper_ore",
+    "minecraft:deepslate_copper_ore",
+    "minecraft:water",
+    "minecraft:gravel",
+    "minecraft:sandstone",
+    "minecraft:red_sandstone",
+    "minecraft:calcite",
+    "minecraft:snow",
+    "minecraft:packed_ice",
+    "minecraft:raw_iron_block",
+    "minecraft:raw_copper_block",
+];
+
+/// Vanilla's `#nether_carver_replaceables` block tag, expanded.
+pub const NETHER_CARVER_REPLACEABLES: &[&str] = &[
+    "minecraft:netherrack",
+    "minecraft:basalt",
+    "minecraft:blackstone",
+    "minecraft:soul_sand",
+    "minecraft:soul_soil",
+    "minecraft:crimson_nylium",
+    "minecraft:warped_nylium",
+    "minecraft:nether_wart_block",
+    "minecraft:warped_wart_block",
+];
+
+fn uniform(min_inclusive: f32, max_exclusive: f32) -> FloatProvider {
+    FloatProvider::Uniform {
+        min_inclusive,
+        max_exclusive,
+    }
+}
+
+fn replaceable(blocks: &[&str]) -> HashSet<String> {
+    blocks.iter().map(|b| b.to_string()).collect()
+}
+
+impl ConfiguredCarver {
+    /// `minecraft:cave`.
+    pub fn cave() -> Self {
+        Self::overworld_cave(0.15, 180)
+    }
+
+    /// `minecraft:cave_extra_underground`: more caves below y = 47.
+    pub fn cave_extra_underground() -> Self {
+        Self::overworld_cave(0.07, 47)
+    }
+
+    fn overworld_cave(probability: f32, max_y: i32) -> Self {
+        Self::Cave(CaveCarverConfiguration {
+            carver: CarverConfiguration {
+                probability,
+                y: HeightProvider::Uniform {
+                    min_inclusive: VerticalAnchor::AboveBottom(8),
+                    max_inclusive: VerticalAnchor::Absolute(max_y),
+                },
+                y_scale: uniform(0.1, 0.9),
+                lava_level: VerticalAnchor::AboveBottom(8),
+                replaceable: replaceable(OVERWORLD_CARVER_REPLACEABLES),
+            },
+            horizontal_radius_multiplier: uniform(0.7, 1.4),
+            vertical_radius_multiplier: uniform(0.8, 1.3),
+            floor_level: uniform(-1.0, -0.4),
+        })
+    }
+
+    /// `minecraft:canyon`.
+    pub fn canyon() -> Self {
+        Self::Canyon(CanyonCarverConfiguration {
+            carver: CarverConfiguration {
+                probability: 0.01,
+                y: HeightProvider::Uniform {
+                    min_inclusive: VerticalAnchor::Absolute(10),
+                    max_inclusive: VerticalAnchor::Absolute(67),
+                },
+                y_scale: FloatProvider::Constant(3.0),
+                lava_level: VerticalAnchor::AboveBottom(8),
+                replaceable: replaceable(OVERWORLD_CARVER_REPLACEABLES),
+            },
+            vertical_rotation: uniform(-0.125, 0.125),
+            shape: CanyonShape {
+                distance_factor: uniform(0.75, 1.0),
+                thickness: FloatProvider::Trapezoid {
+                    min: 0.0,
+                    max: 6.0,
+                    plateau: 2.0,
+                },
+                width_smoothness: 3,
+                horizontal_radius_factor: uniform(0.75, 1.0),
+                vertical_radius_default_factor: 1.0,
+                vertical_radius_center_factor: 0.0,
+            },
+        })
+    }
+
+    /// `minecraft:nether_cave`.
+    pub fn nether_cave() -> Self {
+        Self::NetherCave(CaveCarverConfiguration {
+            carver: CarverConfiguration {
+                probability: 0.2,
+                y: HeightProvider::Uniform {
+                    min_inclusive: VerticalAnchor::Absolute(0),
+                    max_inclusive: VerticalAnchor::BelowTop(1),
+                },
+                y_scale: FloatProvider::Constant(0.5),
+                lava_level: VerticalAnchor::AboveBottom(10),
+                replaceable: replaceable(NETHER_CARVER_REPLACEABLES),
+            },
+            horizontal_radius_multiplier: FloatProvider::Constant(1.0),
+            vertical_radius_multiplier: FloatProvider::Constant(1.0),
+            floor_level: FloatProvider::Constant(-0.7),
+        })
+    }
+
+    pub fn config(&self) -> &CarverConfiguration {
+        match self {
+            Self::Cave(c) | Self::NetherCave(c) => &c.carver,
+            Self::Canyon(c) => &c.carver,
+        }
+    }
+
+    pub fn is_start_chunk(&self, random: &mut dyn RandomSource) -> bool {
+        random.next_float() <= self.config().probability
+    }
+
+    /// Carve the part of the carver started in chunk `start_x`/`start_z`
+    /// that falls inside `chunk`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn carve(
+        &self,
+        ctx: &CarvingContext,
+        chunk: &mut Chunk,
+        random: &mut dyn RandomSource,
+        aquifer: &mut Aquifer,
+        start_x: i32,
+        start_z: i32,
+        mask: &mut CarvingMask,
+    ) {
+        let mut carving = Carving {
+            ctx,
+            config: self.config(),
+            nether: matches!(self, Self::NetherCave(_)),
+            min_y: chunk.min_y(),
+            height: chunk.height(),
+            chunk,
+            aquifer,
+            mask,
+        };
+        match self {
+            Self::Cave(config) | Self::NetherCave(config) => {
+                carving.caves(config, random, start_x, start_z)
+            }
+            Self::Canyon(config) => carving.canyon(config, random, start_x, start_z),
+        }
+    }
+}
+
+/// What carvers consult besides the chunk: the seeded world state, the
+/// biomes and the surface rules that re-cover uncovered dirt.
+pub struct CarvingContext<'a> {
+    pub state: &'a RandomState,
+    pub biome_source: &'a BiomeSource,
+    pub surface: &'a SurfaceSystem,
+}
+
+/// Run the carvers of every chunk within range of `chunk` over it, each
+/// seeded from the world seed, its index and its start chunk. `carvers`
+/// lists them per biome, picked at the start chunk's corner at y = 0. The
+/// carved blocks are recorded in the chunk's carving mask.
+pub fn apply_carvers(
+    ctx: &CarvingContext,
+    carvers: &HashMap<String, Vec<ConfiguredCarver>>,
+    chunk: &mut Chunk,
+    aquifer: &mut Aquifer,
+) {
+    let mut mask = chunk
+        .carving_mask
+        .take()
+        .unwrap_or_else(|| CarvingMask::new(chunk.min_y(), chunk.height()));
+    let mut random = WorldgenRandom::new(RandomAlgorithm::Legacy, 0);
+    let seed = ctx.state.seed();
+    for start_x in chunk.x - RANGE..=chunk.x + RANGE {
+        for start_z in chunk.z - RANGE..=chunk.z + RANGE {
+            let biome =
+                ctx.biome_source
+                    .get_noise_biome(&ctx.state.router, start_x * 4, 0, start_z * 4);
+            let Some(list) = carvers.get(biome) else {
+                continue;
+            };
+            for (i, carver) in list.iter().enumerate() {
+                random.set_large_feature_seed(seed.wrapping_add(i as i64), start_x, start_z);
+                if carver.is_start_chunk(&mut random) {
+                    carver.carve(
+                        ctx,
+                        chunk,
+                        &mut random,
+                        aquifer,
+                        start_x,
+                        start_z,
+                        &mut mask,
+                    );
+                }
+            }
+        }
+    }
+    chunk.carving_mask = Some(mask);
+}
+
+/// Which points of an ellipsoid a carver leaves standing.
+#[derive(Clone, Copy)]
+enum Skip<'w> {
+    /// Outside the ellipsoid or below its flat floor.
+    Cave { floor_level: f64 },
+    /// Outside an ellipsoid whose width varies by height.
+    Canyon { width_factors: &'w [f32] },
+}
+
+impl Skip<'_> {
+    fn should_skip(self, dx: f64, dy: f64, dz: f64, y: i32, min_y: i32) -> bool {
+        match self {
+            Self::Cave { floor_level } => dy <= floor_level || dx * dx + dy * dy + dz * dz >= 1.0,
+            Self::Canyon { width_factors } => {
+                let factor = f64::from(width_factors[(y - min_y - 1) as usize]);
+                (dx * dx + dz * dz) * factor + dy * dy / 6.0 >= 1.0
+            }
+        }
+    }
+}
+
+/// One carver at work on one chunk.
+struct Carving<'a, 'w> {
+    ctx: &'a CarvingContext<'a>,
+    config: &'a CarverConfiguration,
+    nether: bool,
+    min_y: i32,
+    height: i32,
+    chunk: &'a mut Chunk,
+    aquifer: &'a mut Aquifer<'w>,
+    mask: &'a mut CarvingMask,
+}
+
+impl Carving<'_, '_> {
+    fn caves(
+        &mut self,
+        config: &CaveCarverConfiguration,
+        random: &mut dyn RandomSource,
+        start_x: i32,
+        start_z: i32,
+    ) {
+        let max_distance = (CARVER_RANGE * 2 - 1) * SECTION_SIZE as i32;
+        let bound = if self.nether { 10 } else { 15 };
+        let y_ratio = if self.nether { 5.0 } else { 1.0 };
+        let bound = random.next_int_bounded(bound) + 1;
+        let bound = random.next_int_bounded(bound) + 1;
+        let count = random.next_int_bounded(bound);
+        for _ in 0..count {
+            let x = f64::from(start_x * 16 + random.next_int_bounded(16));
+            let y = f64::from(config.carver.y.sample(random, self.min_y, self.height));
+            let z = f64::from(start_z * 16 + random.next_int_bounded(16));
+            let horizontal = f64::from(config.horizontal_radius_multiplier.sample(random));
+            let vertical = f64::from(config.vertical_radius_multiplier.sample(random));
+            let skip = Skip::Cave {
+                floor_level: f64::from(config.floor_level.sample(random)),
+            };
+            let mut tunnels = 1;
+            if random.next_int_bounded(4) == 0 {
+                let y_scale = f64::from(config.carver.y_scale.sample(random));
+                let radius = 1.0 + random.next_float() * 6.0;
+                self.room(x, y, z, radius, y_scale, skip);
+                tunnels += random.next_int_bounded(4);
+            }
+            for _ in 0..tunnels {
+                let yaw = random.next_float() * (PI * 2.0);
+                let pitch = (random.next_float() - 0.5) / 4.0;
+                let thickness = self.thickness(random);
+                let branch_count = max_distance - random.next_int_bounded(max_distance / 4);
+                self.tunnel(
+                    random.next_long(),
+                    [x, y, z],
+                    [horizontal, vertical, y_ratio],
+                    thickness,
+                    yaw,
+                    pitch,
+                    0,
+                    branch_count,
+                    skip,
+                );
+            }
+        }
+    }
+
+    fn thickness(&self, random: &mut dyn RandomSource) -> f32 {
+        let thickness = random.next_float() * 2.0 + random.next_float();
+        if self.nether {
+            return thickness * 2.0;
+        }
+        if random.next_int_bounded(10) == 0 {
+            thickness * (random.next_float() * random.next_float() * 3.0 + 1.0)
+        } else {
+            thickness
+        }
+    }
+
+    fn room(&mut self, x: f64, y: f64, z: f64, radius: f32, y_scale: f64, skip: Skip) {
+        let horizontal = 1.5 + f64::from(sin(f64::from(PI / 2.0)) * radius);
+        let vertical = horizontal * y_scale;
+        self.ellipsoid(x + 1.0, y, z, horizontal, vertical, skip);
+    }
+
+    /// A winding tunnel of `branch_count` steps from `pos`, splitting in two
+    /// once when thick enough. `radii` holds the horizontal and vertical
+    /// radius multipliers and the vertical to horizontal ratio.
+    #[allow(clippy::too_many_arguments)]
+    fn tunnel(
+        &mut self,
+        seed: i64,
+        pos: [f64; 3],
+        radii: [f64; 3],
+        thickness: f32,
+        mut yaw: f32,
+        mut pitch: f32,
+        branch_index: i32,
+        branch_count: i32,
+        skip: Skip,
+    ) {
+        let [mut x, mut y, mut z] = pos;
+        let [horizontal, vertical, ratio] = radii;
+        let mut random = LegacyRandom::new(seed);
+        let split = random.next_int_bounded(branch_count / 2) + branch_count / 4;
+        let steep = random.next_int_bounded(6) == 0;
+        let (mut yaw_change, mut pitch_change) = (0.0f32, 0.0f32);
+        for i in branch_index..branch_count {
+            let progress = PI * i as f32 / branch_count as f32;
+            let radius = 1.5 + f64::from(sin(f64::from(progress)) * thickness);
+            let height = radius * ratio;
+            let h = cos(f64::from(pitch));
+            x += f64::from(cos(f64::from(yaw)) * h);
+            y += f64::from(sin(f64::from(pitch)));
+            z += f64::from(sin(f64::from(yaw)) * h);
+            pitch *= if steep { 0.92 } else { 0.7 };
+            pitch += pitch_change * 0.1;
+            yaw += yaw_change * 0.1;
+            pitch_change *= 0.9;
+            yaw_change *= 0.75;
+            pitch_change += (random.next_float() - random.next_float()) * random.next_float() * 2.0;
+            yaw_change += (random.next_float() - random.next_float()) * random.next_float() * 4.0;
+
+            if i == split && thickness > 1.0 {
+                for turn in [-PI / 2.0, PI / 2.0] {
+                    self.tunnel(
+                        random.next_long(),
+                        [x, y, z],
+                        [horizontal, vertical, 1.0],
+                        random.next_float() * 0.5 + 0.5,
+                        yaw + turn,
+                        pitch / 3.0,
+                        i,
+                        branch_count,
+                        skip,
+                    );
+                }
+                return;
+            }
+            if random.next_int_bounded(4) != 0 {
+                if !self.can_reach(x, z, i, branch_count, thickness) {
+                    return;
+                }
+                self.ellipsoid(x, y, z, radius * horizontal, height * vertical, skip);
+            }
+        }
+    }
+
+    fn canyon(
+        &mut self,
+        config: &CanyonCarverConfiguration,
+        random: &mut dyn RandomSource,
+        start_x: i32,
+        start_z: i32,
+    ) {
+        let max_distance = (CARVER_RANGE * 2 - 1) * SECTION_SIZE as i32;
+        let x = f64::from(start_x * 16 + random.next_int_bounded(16));
+        let y = f64::from(config.carver.y.sample(random, self.min_y, self.height));
+        let z = f64::from(start_z * 16 + random.next_int_bounded(16));
+        let yaw = random.next_float() * (PI * 2.0);
+        let pitch = config.vertical_rotation.sample(random);
+        let y_scale = f64::from(config.carver.y_scale.sample(random));
+        let thickness = config.shape.thickness.sample(random);
+        let branch_count =
+            (max_distance as f32 * config.shape.distance_factor.sample(random)) as i32;
+
+        let mut random = LegacyRandom::new(random.next_long());
+        let width_factors = self.width_factors(&config.shape, &mut random);
+        let skip = Skip::Canyon {
+            width_factors: &width_factors,
+        };
+        let (mut x, mut y, mut z, mut yaw, mut pitch) = (x, y, z, yaw, pitch);
+        let (mut yaw_change, mut pitch_change) = (0.0f32, 0.0f32);
+        for i in 0..branch_count {
+            let progress = i as f32 * PI / branch_count as f32;
+            let mut radius = 1.5 + f64::from(sin(f64::from(progress)) * thickness);
+            let mut height = radius * y_scale;
+            radius *= f64::from(config.shape.horizontal_radius_factor.sample(&mut random));
+            height = vertical_radius(&config.shape, &mut random, height, branch_count, i);
+            let h = cos(f64::from(pitch));
+            let dy = sin(f64::from(pitch));
+            x += f64::from(cos(f64::from(yaw)) * h);
+            y += f64::from(dy);
+            z += f64::from(sin(f64::from(yaw)) * h);
+            pitch *= 0.7;
+            pitch += pitch_change * 0.05;
+            yaw += yaw_change * 0.05;
+            pitch_change *= 0.8;
+            yaw_change *= 0.5;
+            pitch_change += (random.next_float() - random.next_float()) * random.next_float() * 2.0;
+            yaw_change += (random.next_float() - random.next_float()) * random.next_float() * 4.0;
+            if random.next_int_bounded(4) != 0 {
+                if !self.can_reach(x, z, i, branch_count, thickness) {
+                    return;
+                }
+                self.ellipsoid(x, y, z, radius, height, skip);
+            }
+        }
+    }
+
+    /// Squared width multipliers of a canyon for each height of the world.
+    fn width_factors(&self, shape: &CanyonShape, random: &mut dyn RandomSource) -> Vec<f32> {
+        let mut factor = 1.0f32;
+        (0..self.height)
+            .map(|i| {
+                if i == 0 || random.next_int_bounded(shape.width_smoothness) == 0 {
+                    factor = 1.0 + random.next_float() * random.next_float();
+                }
+                factor * factor
+            })
+            .collect()
+    }
+
+    /// Whether a carver at step `branch` of `branch_count` could still
+    /// reach this chunk.
+    fn can_reach(&self, x: f64, z: f64, branch: i32, branch_count: i32, width: f32) -> bool {
+        let dx = x - f64::from(self.chunk.x * 16 + 8);
+        let dz = z - f64::from(self.chunk.z * 16 + 8);
+        let remaining = f64::from(branch_count - branch);
+        let reach = f64::from(width + 2.0 + 16.0);
+        dx * dx + dz * dz - remaining * remaining <= reach * reach
+    }
+
+    /// Carve the part of an ellipsoid inside the chunk. Returns whether any
+    /// block was removed.
+    fn ellipsoid(
+        &mut self,
+        x: f64,
+        y: f64,
+        z: f64,
+        horizontal: f64,
+        vertical: f64,
+        skip: Skip,
+    ) -> bool {
+        let (min_x, min_z) = (self.chunk.x * 16, self.chunk.z * 16);
+        let reach = 16.0 + horizontal * 2.0;
+        if (x - f64::from(min_x + 8)).abs() > reach || (z - f64::from(min_z + 8)).abs() > reach {
+            return false;
+        }
+        let x0 = (floor(x - horizontal) - min_x - 1).max(0);
+        let x1 = (floor(x + horizontal) - min_x).min(15);
+        let y0 = (floor(y - vertical) - 1).max(self.min_y + 1);
+        let y1 = (floor(y + vertical) + 1).min(self.min_y + self.height - 1 - TOP_MARGIN);
+        let z0 = (floor(z - horizontal) - min_z - 1).max(0);
+        let z1 = (floor(z + horizontal) - min_z).min(15);
+
+        let mut carved = false;
+        for lx in x0..=x1 {
+            let dx = (f64::from(min_x + lx) + 0.5 - x) / horizontal;
+            for lz in z0..=z1 {
+                let dz = (f64::from(min_z + lz) + 0.5 - z) / horizontal;
+                if dx * dx + dz * dz >= 1.0 {
+                    continue;
+                }
+                let (lx, lz) = (lx as usize, lz as usize);
+                let mut reached_surface = false;
+                for by in (y0 + 1..=y1).rev() {
+                    let dy = (f64::from(by) - 0.5 - y) / vertical;
+                    if skip.should_skip(dx, dy, dz, by, self.min_y) || self.mask.get(lx, by, lz) {
+                        continue;
+                    }
+                    self.mask.set(lx, by, lz);
+                    carved |= self.block(lx, by, lz, &mut reached_surface);
+                }
+            }
+        }
+        carved
+    }
+
+    /// Carve one block. Once a tunnel has cut through grass or mycelium,
+    /// dirt it uncovers gets the biome's top block back.
+    fn block(&mut self, x: usize, y: i32, z: usize, reached_surface: &mut bool) -> bool {
+        let block = self.chunk.get_block(x, y, z);
+        if self.nether {
+            if !self.config.replaceable.contains(&block.name) {
+                return false;
+            }
+            let state = if y <= self.min_y + 31 {
+                BlockState::new("minecraft:lava")
+            } else {
+                BlockState::new("minecraft:cave_air")
+            };
+            self.chunk.set_block(x, y, z, state);
+            return true;
+        }
+
+        if matches!(
+            block.name.as_str(),
+            "minecraft:grass_block" | "minecraft:mycelium"
+        ) {
+            *reached_surface = true;
+        }
+        if !self.config.replaceable.contains(&block.name) {
+            return false;
+        }
+        let (bx, bz) = (self.chunk.x * 16 + x as i32, self.chunk.z * 16 + z as i32);
+        let lava_level = self.config.lava_level.resolve_y(self.min_y, self.height);
+        let state = if y <= lava_level {
+            BlockState::new("minecraft:lava")
+        } else {
+            match self.aquifer.compute_substance(bx, y, bz, 0.0) {
+                Some(state) => state,
+                None => return false,
+            }
+        };
+        let fluid = has_fluid(&state);
+        self.chunk.set_block(x, y, z, state);
+        if fluid && self.aquifer.should_schedule_fluid_update() {
+            self.chunk.mark_for_post_processing(x, y, z);
+        }
+
+        if *reached_surface && self.chunk.get_block(x, y - 1, z).name == "minecraft:dirt" {
+            let top = self.ctx.surface.top_material(
+                self.ctx.state,
+                self.ctx.biome_source,
+                self.chunk,
+                bx,
+                y - 1,
+                bz,
+                fluid,
+            );
+            if let Some(top) = top {
+                let fluid = has_fluid(&top);
+                self.chunk.set_block(x, y - 1, z, top);
+                if fluid {
+                    self.chunk.mark_for_post_processing(x, y - 1, z);
+                }
+            }
+        }
+        true
+    }
+}
+
+/// Canyons are tallest halfway along.
+fn vertical_radius(
+    shape: &CanyonShape,
+    random: &mut dyn RandomSource,
+    radius: f64,
+    branch_count: i32,
+    branch: i32,
+) -> f64 {
+    let centre = 1.0 - (0.5 - branch as f32 / branch_count as f32).abs() * 2.0;
+    let factor =
+        shape.vertical_radius_default_factor + shape.vertical_radius_center_factor * centre;
+    f64::from(factor) * radius * f64::from(random.next_float() * 0.25 + 0.75)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aquifer::FluidPicker;
+    use crate::noise_router::NoiseRouter;
+    use crate::surface::SurfaceRule;
+    use crate::test::worldgen::find_blocks;
+
+    /// Carve a chunk filled with `block` with `carvers` in every biome and
+    /// no water.
+    fn carve(
+        carvers: Vec<ConfiguredCarver>,
+        seed: i64,
+        chunk_x: i32,
+        (min_y, height): (i32, i32),
+        block: &str,
+    ) -> Chunk {
+        let mut state = RandomState::new(&NoiseRouter::default(), seed, false);
+        let surface = SurfaceSystem::new(
+            &SurfaceRule::Sequence(Vec::new()),
+            BlockState::new(block),
+            -100,
+            &mut state,
+        );
+        let biomes = BiomeSource::Fixed("minecraft:plains".into());
+        let ctx = CarvingContext {
+            state: &state,
+            biome_source: &biomes,
+            surface: &surface,
+        };
+        let mut chunk = Chunk::with_height(chunk_x, 0, min_y, height);
+        for section in chunk.sections_mut() {
+            section.fill(BlockState::new(block));
+        }
+        let water = BlockState::new("minecraft:water");
+        let mut aquifer = Aquifer::Disabled(FluidPicker::new(-100, water));
+        let carvers = HashMap::from([("minecraft:plains".to_string(), carvers)]);
+        apply_carvers(&ctx, &carvers, &mut chunk, &mut aquifer);
+        chunk
+    }
+
+    /// Count the blocks of `chunk` matching `f`.
+    fn count(chunk: &Chunk, mut f: impl FnMut(i32, &BlockState) -> bool) -> usize {
+        find_blocks(std::slice::from_ref(chunk), |[_, y, _], block| f(y, block)).len()
+    }
+
+    const OVERWORLD: (i32, i32) = (-64, 384);
+
+    #[test]
+    fn caves_are_seeded_and_masked() {
+        let carvers = || {
+            vec![
+                ConfiguredCarver::cave(),
+                ConfiguredCarver::cave_extra_underground(),
+            ]
+        };
+        let chunk = carve(carvers(), 1234, 3, OVERWORLD, "minecraft:stone");
+        let mask = chunk.carving_mask.as_ref().unwrap();
+        let carved = count(&chunk, |y, block| {
+            if block.name == "minecraft:stone" {
+                return false;
+            }
+            let want = if y <= -56 {
+                "minecraft:lava"
+            } else {
+                "minecraft:air"
+            };
+            assert_eq!(block.name, want, "at y = {y}");
+            assert!(y < 320 - 7);
+            true
+        });
+        assert!(carved > 100, "only {carved} blocks carved");
+        let open = find_blocks(std::slice::from_ref(&chunk), |_, block| {
+            block.name != "minecraft:stone"
+        });
+        for ([x, y, z], _) in open {
+            let (x, z) = (x.rem_euclid(16) as usize, z.rem_euclid(16) as usize);
+            assert!(mask.get(x, y, z), "{x} {y} {z} not masked");
+        }
+
+        let again = carve(carvers(), 1234, 3, OVERWORLD, "minecraft:stone");
+        assert_eq!(again.sections(), chunk.sections());
+        let other = carve(carvers(), 4321, 3, OVERWORLD, "minecraft:stone");
+        assert_ne!(other.sections(), chunk.sections());
+    }
+
+    #[test]
+    fn canyons_cut_deep() {
+        let ConfiguredCarver::Canyon(mut config) = ConfiguredCarver::canyon() else {
+            unreachable!();
+        };
+        config.carver.probability = 0.5;
+        let carvers = vec![ConfiguredCarver::Canyon(config)];
+        let chunk = carve(carvers, 99, 0, OVERWORLD, "minecraft:stone");
+        let carved: Vec<i32> = (-64..320)
+            .filter(|&y| (0..16).any(|x| (0..16).any(|z| chunk.get_block(x, y, z).is_air())))
+            .collect();
+        assert!(carved.len() > 10, "canyon spans {carved:?}");
+    }
+
+    #[test]
+    fn nether_caves_fill_with_lava_low_down() {
+        let carvers = vec![ConfiguredCarver::nether_cave()];
+        let chunk = carve(carvers, 7, 0, (0, 128), "minecraft:netherrack");
+        let lava = count(&chunk, |y, block| {
+            assert!(block.name != "minecraft:lava" || y <= 31);
+            assert!(block.name != "minecraft:cave_air" || y > 31);
+            block.name == "minecraft:lava"
+        });
+        let air = count(&chunk, |_, block| block.name == "minecraft:cave_air");
+        assert!(lava + air > 100, "{lava} lava and {air} cave air");
+        assert_eq!(count(&chunk, |_, block| block.name == "minecraft:air"), 0);
+    }
+}
@@ -0,0 +1,410 @@
+//! Noise caves: the cheese, spaghetti, noodle and pillar parts of the
+//! overworld density, built as density function trees the way vanilla's
+//! `NoiseRouterData` builds `overworld/caves/*`. Unlike carvers, these
+//! depend on the position alone, so caves run on across chunk borders.
+
+use crate::density::{
+    BinaryOp, DensityFn, DensityFunction, MappedOp, NoiseHolder, RarityValueMapper,
+};
+use crate::noise::NoiseParameters;
+
+/// A `worldgen/noise` entry of vanilla, keyed so that it seeds as vanilla's.
+fn noise(name: &str, first_octave: i32, amplitudes: &[f64]) -> NoiseHolder {
+    NoiseHolder::new(
+        format!("minecraft:{name}"),
+        NoiseParameters::new(first_octave, amplitudes.to_vec()),
+    )
+}
+
+fn constant(value: f64) -> DensityFn {
+    DensityFunction::constant(value)
+}
+
+fn add(a: DensityFn, b: DensityFn) -> DensityFn {
+    DensityFunction::binary(BinaryOp::Add, a, b)
+}
+
+fn mul(a: DensityFn, b: DensityFn) -> DensityFn {
+    DensityFunction::binary(BinaryOp::Mul, a, b)
+}
+
+fn min(a: DensityFn, b: DensityFn) -> DensityFn {
+    DensityFunction::binary(BinaryOp::Min, a, b)
+}
+
+fn max(a: DensityFn, b: DensityFn) -> DensityFn {
+    DensityFunction::binary(BinaryOp::Max, a, b)
+}
+
+/// Noise rescaled from -1..1 to `from..to`.
+fn mapped_noise(noise: NoiseHolder, xz_scale: f64, y_scale: f64, from: f64, to: f64) -> DensityFn {
+    add(
+        constant((from + to) * 0.5),
+        mul(
+            constant((to - from) * 0.5),
+            DensityFunction::noise(noise, xz_scale, y_scale),
+        ),
+    )
+}
+
+/// `f` interpolated between `min_y` and `max_y`, `fallback` outside.
+fn y_limited(y: &DensityFn, f: DensityFn, min_y: i32, max_y: i32, fallback: f64) -> DensityFn {
+    DensityFunction::interpolated(DensityFunction::range_choice(
+        y.clone(),
+        f64::from(min_y),
+        f64::from(max_y + 1),
+        f,
+        constant(fallback),
+    ))
+}
+
+/// The shared cave functions. Each field is one node, referenced from
+/// everywhere it is used.
+#[derive(Clone, Debug)]
+pub struct NoiseCaves {
+    /// Wobble added to the walls of spaghetti caves.
+    pub spaghetti_roughness: DensityFn,
+    pub spaghetti_2d_thickness_modulator: DensityFn,
+    /// Long, flat tunnels winding at a noise-driven elevation.
+    pub spaghetti_2d: DensityFn,
+    /// 3D spaghetti tunnels, opening to the surface as cave entrances.
+    pub entrances: DensityFn,
+    /// Thin winding tunnels, negative inside; 64 outside `-60..=320`.
+    pub noodle: DensityFn,
+    /// Columns left standing in large cheese caves, positive inside.
+    pub pillars: DensityFn,
+}
+
+impl Default for NoiseCaves {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NoiseCaves {
+    pub fn new() -> Self {
+        let spaghetti_roughness = DensityFunction::cache_once(mul(
+            mapped_noise(
+                noise("spaghetti_roughness_modulator", -8, &[1.0]),
+                1.0,
+                1.0,
+                0.0,
+                -0.1,
+            ),
+            add(
+                DensityFunction::mapped(
+                    MappedOp::Abs,
+                    DensityFunction::noise(noise("spaghetti_roughness", -5, &[1.0]), 1.0, 1.0),
+                ),
+                constant(-0.4),
+            ),
+        ));
+        let spaghetti_2d_thickness_modulator = DensityFunction::cache_once(mapped_noise(
+            noise("spaghetti_2d_thickness", -11, &[1.0]),
+            2.0,
+            1.0,
+            -0.6,
+            -1.3,
+        ));
+        Self {
+            spaghetti_2d: spaghetti_2d(&spaghetti_2d_thickness_modulator),
+            entrances: entrances(&spaghetti_roughness),
+            noodle: noodle(),
+            pillars: pillars(),
+            spaghetti_roughness,
+            spaghetti_2d_thickness_modulator,
+        }
+    }
+
+    /// Big cheese caverns: the cave layer and cheese noises, closed off
+    /// near the surface where `sloped_cheese`, the terrain density before
+    /// caves, is low.
+    pub fn cheese(&self, sloped_cheese: &DensityFn) -> DensityFn {
+        let layer = DensityFunction::noise(noise("cave_layer", -8, &[1.0]), 1.0, 8.0);
+        let layer = mul(
+            constant(4.0),
+            DensityFunction::mapped(MappedOp::Square, layer),
+        );
+        let cheese = DensityFunction::noise(
+            noise(
+                "cave_cheese",
+                -8,
+                &[0.5, 1.0, 2.0, 1.0, 2.0, 1.0, 0.0, 2.0, 0.0],
+            ),
+            1.0,
+            0.6666666666666666,
+        );
+        let solid_near_surface = DensityFunction::clamp(
+            add(constant(1.5), mul(constant(-0.64), sloped_cheese.clone())),
+            0.0,
+            0.5,
+        );
+        add(
+            layer,
+            add(
+                DensityFunction::clamp(add(constant(0.27), cheese), -1.0, 1.0),
+                solid_near_surface,
+            ),
+        )
+    }
+
+    /// Terrain density deep underground: the least of cheese, entrances
+    /// and 2D spaghetti, with pillars put back.
+    pub fn underground(&self, sloped_cheese: &DensityFn) -> DensityFn {
+        let caves = min(
+            min(self.cheese(sloped_cheese), self.entrances.clone()),
+            add(self.spaghetti_2d.clone(), self.spaghetti_roughness.clone()),
+        );
+        let pillars = DensityFunction::range_choice(
+            self.pillars.clone(),
+            -1000000.0,
+            0.03,
+            constant(-1000000.0),
+            self.pillars.clone(),
+        );
+        max(caves, pillars)
+    }
+
+    /// Cut the caves into `sloped_cheese`: only entrances where it is
+    /// below 1.5625, close to the surface, and all caves below that.
+    /// Noodles are left out, as vanilla takes the minimum with them after
+    /// sliding and squeezing the result.
+    pub fn carve(&self, sloped_cheese: &DensityFn) -> DensityFn {
+        let near_surface = min(
+            sloped_cheese.clone(),
+            mul(constant(5.0), self.entrances.clone()),
+        );
+        DensityFunction::range_choice(
+            sloped_cheese.clone(),
+            -1000000.0,
+            1.5625,
+            near_surface,
+            self.underground(sloped_cheese),
+        )
+    }
+
+    /// The overworld's `final_density`: caves cut into `sloped_cheese`,
+    /// interpolated, squeezed, and the noodles taken out last. Vanilla's
+    /// `NoiseRouterData.overworld`, without blending or the slides at the
+    /// top and bottom of the world.
+    pub fn final_density(&self, sloped_cheese: &DensityFn) -> DensityFn {
+        let carved = mul(
+            constant(0.64),
+            DensityFunction::interpolated(self.carve(sloped_cheese)),
+        );
+        min(
+            DensityFunction::mapped(MappedOp::Squeeze, carved),
+            self.noodle.clone(),
+        )
+    }
+}
+
+fn spaghetti_2d(thickness_modulator: &DensityFn) -> DensityFn {
+    let modulator = DensityFunction::noise(noise("spaghetti_2d_modulator", -11, &[1.0]), 2.0, 1.0);
+    let tunnels = DensityFunction::weird_scaled_sampler(
+        modulator,
+        noise("spaghetti_2d", -7, &[1.0]),
+        RarityValueMapper::Type2,
+    );
+    let elevation = mapped_noise(
+        noise("spaghetti_2d_elevation", -8, &[1.0]),
+        1.0,
+        0.0,
+        f64::from((-64i32).div_euclid(8)),
+        8.0,
+    );
+    let height = DensityFunction::mapped(
+        MappedOp::Abs,
+        add(
+            elevation,
+            DensityFunction::y_clamped_gradient(-64, 320, 8.0, -40.0),
+        ),
+    );
+    let band = DensityFunction::mapped(MappedOp::Cube, add(height, thickness_modulator.clone()));
+    let tunnels = add(tunnels, mul(constant(0.083), thickness_modulator.clone()));
+    DensityFunction::clamp(max(tunnels, band), -1.0, 1.0)
+}
+
+fn entrances(roughness: &DensityFn) -> DensityFn {
+    let rarity = DensityFunction::cache_once(DensityFunction::noise(
+        noise("spaghetti_3d_rarity", -11, &[1.0]),
+        2.0,
+        1.0,
+    ));
+    let thickness = mapped_noise(
+        noise("spaghetti_3d_thickness", -8, &[1.0]),
+        1.0,
+        1.0,
+        -0.065,
+        -0.088,
+    );
+    let first = DensityFunction::weird_scaled_sampler(
+        rarity.clone(),
+        noise("spaghetti_3d_1", -7, &[1.0]),
+        RarityValueMapper::Type1,
+    );
+    let second = DensityFunction::weird_scaled_sampler(
+        rarity,
+        noise("spaghetti_3d_2", -7, &[1.0]),
+        RarityValueMapper::Type1,
+    );
+    let spaghetti = DensityFunction::clamp(add(max(first, second), thickness), -1.0, 1.0);
+    let entrance = DensityFunction::noise(noise("cave_entrance", -7, &[0.4, 0.5, 1.0]), 0.75, 0.5);
+    let entrance = add(
+        add(entrance, constant(0.37)),
+        DensityFunction::y_clamped_gradient(-10, 30, 0.3, 0.0),
+    );
+    DensityFunction::cache_once(min(entrance, add(roughness.clone(), spaghetti)))
+}
+
+fn noodle() -> DensityFn {
+    let y = DensityFunction::y_clamped_gradient(-4064, 4062, -4064.0, 4062.0);
+    let toggle = y_limited(
+        &y,
+        DensityFunction::noise(noise("noodle", -8, &[1.0]), 1.0, 1.0),
+        -60,
+        320,
+        -1.0,
+    );
+    let thickness = y_limited(
+        &y,
+        mapped_noise(noise("noodle_thickness", -8, &[1.0]), 1.0, 1.0, -0.05, -0.1),
+        -60,
+        320,
+        0.0,
+    );
+    let ridge = |name| {
+        let scale = 2.6666666666666665;
+        let ridge = DensityFunction::noise(noise(name, -7, &[1.0]), scale, scale);
+        DensityFunction::mapped(MappedOp::Abs, y_limited(&y, ridge, -60, 320, 0.0))
+    };
+    let ridges = mul(
+        constant(1.5),
+        max(ridge("noodle_ridge_a"), ridge("noodle_ridge_b")),
+    );
+    DensityFunction::range_choice(
+        toggle,
+        -1000000.0,
+        0.0,
+        constant(64.0),
+        add(thickness, ridges),
+    )
+}
+
+fn pillars() -> DensityFn {
+    let pillar = DensityFunction::noise(noise("pillar", -7, &[1.0, 1.0]), 25.0, 0.3);
+    let rareness = mapped_noise(noise("pillar_rareness", -8, &[1.0]), 1.0, 1.0, 0.0, -2.0);
+    let thickness = mapped_noise(noise("pillar_thickness", -8, &[1.0]), 1.0, 1.0, 0.0, 1.1);
+    DensityFunction::cache_once(mul(
+        add(mul(pillar, constant(2.0)), rareness),
+        DensityFunction::mapped(MappedOp::Cube, thickness),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aquifer::{Aquifer, FluidPicker};
+    use crate::chunk::{BlockState, Chunk};
+    use crate::noise_router::{NoiseRouter, NoiseSettings, RandomState, fill_from_noise};
+
+    /// Seed `final_density` for a world.
+    fn seeded(final_density: DensityFn, seed: i64) -> RandomState {
+        let router = NoiseRouter {
+            final_density,
+            ..NoiseRouter::default()
+        };
+        RandomState::new(&router, seed, false)
+    }
+
+    #[test]
+    fn noodles_stay_inside_the_world() {
+        let caves = NoiseCaves::new();
+        let state = seeded(caves.noodle.clone(), 42);
+        let noodle = &state.router.final_density;
+        assert_eq!(noodle.compute(5, -62, 5), 64.0);
+        assert_eq!(noodle.compute(5, 400, 5), 64.0);
+        let points =
+            (0..64).flat_map(|x| (0..32).flat_map(move |y| (0..64).map(move |z| (x, y, z))));
+        let lowest = points
+            .map(|(x, y, z)| noodle.compute(x * 2, y * 2, z * 2))
+            .fold(f64::INFINITY, f64::min);
+        assert!(lowest < 0.0, "no noodle, lowest {lowest}");
+    }
+
+    #[test]
+    fn routers_get_caves_below_the_surface() {
+        // Terrain reaching up to y = 128, before caves.
+        let terrain = DensityFunction::y_clamped_gradient(-64, 320, 3.0, -5.0);
+        let router = NoiseRouter {
+            final_density: terrain,
+            ..NoiseRouter::default()
+        }
+        .with_noise_caves();
+        let state = RandomState::new(&router, 99, false);
+        let stone = BlockState::new("minecraft:stone");
+        let mut chunk = Chunk::with_height(4, -2, -64, 384);
+        let water = BlockState::new("minecraft:water");
+        let mut aquifer = Aquifer::Disabled(FluidPicker::new(-64, water));
+        let settings = NoiseSettings::OVERWORLD;
+        fill_from_noise(
+            &mut chunk,
+            &state.router,
+            &settings,
+            &stone,
+            &mut aquifer,
+            None,
+        );
+
+        let air = |ys: std::ops::Range<i32>| {
+            ys.flat_map(|y| (0..16).flat_map(move |x| (0..16).map(move |z| (x, y, z))))
+                .filter(|&(x, y, z)| chunk.get_block(x, y, z).is_air())
+                .count()
+        };
+        let caves = air(-60..64);
+        assert!(caves > 0 && caves < 16 * 16 * 124 / 2, "{caves} air");
+        assert_eq!(air(140..320), 16 * 16 * 180);
+    }
+
+    #[test]
+    fn caves_connect_across_chunk_borders() {
+        let caves = NoiseCaves::new();
+        // Solid terrain deep below the surface everywhere.
+        let density = DensityFunction::interpolated(caves.carve(&constant(2.5)));
+        let state = seeded(min(density, caves.noodle.clone()), 1234);
+        let stone = BlockState::new("minecraft:stone");
+        let chunks: Vec<Chunk> = (0..2)
+            .map(|x| {
+                let mut chunk = Chunk::with_height(x, 0, -64, 384);
+                let water = BlockState::new("minecraft:water");
+                let mut aquifer = Aquifer::Disabled(FluidPicker::new(-64, water));
+                let settings = NoiseSettings::OVERWORLD;
+                fill_from_noise(
+                    &mut chunk,
+                    &state.router,
+                    &settings,
+                    &stone,
+                    &mut aquifer,
+                    None,
+                );
+                chunk
+            })
+            .collect();
+
+        let (mut air, mut border, mut joined) = (0, 0, 0);
+        for y in -60..256 {
+            for z in 0..16 {
+                air += (0..16)
+                    .filter(|&x| chunks[0].get_block(x, y, z).is_air())
+                    .count();
+                if chunks[0].get_block(15, y, z).is_air() {
+                    border += 1;
+                    joined += usize::from(chunks[1].get_block(0, y, z).is_air());
+                }
+            }
+        }
+        let total = 16 * 16 * 316;
+        assert!(air > total / 100 && air < total / 2, "{air} of {total} air");
+        assert!(joined * 10 > border * 8, "{joined} of {border} continue");
+    }
+}
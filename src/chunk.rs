@@ -0,0 +1,977 @@
+use crate::nbt_util::{self, get, get_compound, invalid_data, opt, opt_compound};
+use crate::palette::{BitStorage, PaletteKind, PalettedContainer, ceil_log2};
+use crate::perlin::{MAX_Y, MIN_Y};
+use crate::region::{Compression, RegionFile};
+use nbt::Tag;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+/// Blocks along each axis of a section.
+pub const SECTION_SIZE: usize = 16;
+/// Blocks in one 16³ section.
+pub const SECTION_VOLUME: usize = SECTION_SIZE * SECTION_SIZE * SECTION_SIZE;
+/// Biome cells along each axis of a section (one cell per 4³ blocks).
+pub const BIOME_SIZE: usize = 4;
+/// Biome cells in one section.
+pub const BIOME_VOLUME: usize = BIOME_SIZE * BIOME_SIZE * BIOME_SIZE;
+
+/// Bytes in a section light array (4 bits per block).
+pub const LIGHT_ARRAY_SIZE: usize = SECTION_VOLUME / 2;
+
+pub const DEFAULT_BIOME: &str = "minecraft:plains";
+/// Data version written into chunks, matching `level::VersionInfo::default()`.
+pub const DATA_VERSION: i32 = 4440;
+
+/// A block state: the namespaced block name plus its property values,
+/// kept sorted by property name so equal states always compare equal.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockState {
+    pub name: String,
+    pub properties: Vec<(String, String)>,
+}
+
+impl BlockState {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            properties: Vec::new(),
+        }
+    }
+
+    pub fn air() -> Self {
+        Self::new("minecraft:air")
+    }
+
+    /// Builder-style property setter.
+    pub fn with(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.set_property(key, value);
+        self
+    }
+
+    pub fn set_property(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        let key = key.into();
+        let value = value.into();
+        match self.properties.binary_search_by(|(k, _)| k.cmp(&key)) {
+            Ok(i) => self.properties[i].1 = value,
+            Err(i) => self.properties.insert(i, (key, value)),
+        }
+    }
+
+    pub fn property(&self, key: &str) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn is_air(&self) -> bool {
+        matches!(
+            self.name.as_str(),
+            "minecraft:air" | "minecraft:cave_air" | "minecraft:void_air"
+        )
+    }
+
+    /// Whether entities collide with the block: false for air, fluids and
+    /// the plants placed by worldgen features.
+    pub fn blocks_motion(&self) -> bool {
+        let name = self.name.as_str();
+        !(self.is_air() || name.ends_with("_sapling") || NON_COLLIDING_BLOCKS.contains(&name))
+    }
+}
+
+/// Blocks without collision besides air and saplings.
+const NON_COLLIDING_BLOCKS: &[&str] = &[
+    "minecraft:water",
+    "minecraft:lava",
+    "minecraft:bubble_column",
+    "minecraft:short_grass",
+    "minecraft:fern",
+    "minecraft:tall_grass",
+    "minecraft:large_fern",
+    "minecraft:dead_bush",
+    "minecraft:dandelion",
+    "minecraft:poppy",
+    "minecraft:blue_orchid",
+    "minecraft:allium",
+    "minecraft:azure_bluet",
+    "minecraft:red_tulip",
+    "minecraft:orange_tulip",
+    "minecraft:white_tulip",
+    "minecraft:pink_tulip",
+    "minecraft:oxeye_daisy",
+    "minecraft:cornflower",
+    "minecraft:lily_of_the_valley",
+    "minecraft:sunflower",
+    "minecraft:lilac",
+    "minecraft:rose_bush",
+    "minecraft:peony",
+    "minecraft:pink_petals",
+    "minecraft:seagrass",
+    "minecraft:tall_seagrass",
+    "minecraft:kelp",
+    "minecraft:kelp_plant",
+    "minecraft:vine",
+    "minecraft:snow",
+];
+
+/// Formats as `name[key=value,...]`, the syntax of commands and presets.
+impl fmt::Display for BlockState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)?;
+        if !self.properties.is_empty() {
+            let props: Vec<String> = self
+                .properties
+                .iter()
+                .map(|(k, v)| format!("{k}={v}"))
+                .collect();
+            write!(f, "[{}]", props.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses `name[key=value,...]`. Bare names get the `minecraft:` namespace.
+impl FromStr for BlockState {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Self> {
+        let s = s.trim();
+        let (name, props) = match s.split_once('[') {
+            Some((name, rest)) => {
+                let props = rest
+                    .strip_suffix(']')
+                    .ok_or_else(|| invalid_data(format!("unclosed `[` in block state `{s}`")))?;
+                (name, Some(props))
+            }
+            None => (s, None),
+        };
+        if name.is_empty() {
+            return Err(invalid_data(format!("missing block name in `{s}`")));
+        }
+        let name = if name.contains(':') {
+            name.to_string()
+        } else {
+            format!("minecraft:{name}")
+        };
+        let mut state = BlockState::new(name);
+        for prop in props.into_iter().flat_map(|p| p.split(',')) {
+            if prop.trim().is_empty() {
+                continue;
+            }
+            let (k, v) = prop
+                .split_once('=')
+                .ok_or_else(|| invalid_data(format!("bad property `{prop}` in `{s}`")))?;
+            state.set_property(k.trim(), v.trim());
+        }
+        Ok(state)
+    }
+}
+
+/// A 16³ slice of a chunk holding block states and a 4³ grid of biomes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChunkSection {
+    /// Section index along Y (`world_y >> 4`).
+    pub y: i32,
+    block_states: PalettedContainer<BlockState>,
+    biomes: PalettedContainer<String>,
+    non_air_blocks: u16,
+    /// Block light nibbles, `None` when not computed.
+    pub block_light: Option<Vec<u8>>,
+    /// Sky light nibbles, `None` when not computed.
+    pub sky_light: Option<Vec<u8>>,
+}
+
+#[inline]
+fn block_index(x: usize, y: usize, z: usize) -> usize {
+    (y * SECTION_SIZE + z) * SECTION_SIZE + x
+}
+
+#[inline]
+fn biome_index(x: usize, y: usize, z: usize) -> usize {
+    (y * BIOME_SIZE + z) * BIOME_SIZE + x
+}
+
+impl ChunkSection {
+    /// An all-air section using the default biome.
+    pub fn new(y: i32) -> Self {
+        Self {
+            y,
+            block_states: PalettedContainer::new(SECTION_VOLUME, BlockState::air()),
+            biomes: PalettedContainer::new(BIOME_VOLUME, DEFAULT_BIOME.to_string()),
+            non_air_blocks: 0,
+            block_light: None,
+            sky_light: None,
+        }
+    }
+
+    /// Block at local coordinates, each in `0..16`.
+    pub fn get_block(&self, x: usize, y: usize, z: usize) -> &BlockState {
+        self.block_states.get(block_index(x, y, z))
+    }
+
+    /// Set the block at local coordinates and return the previous state.
+    pub fn set_block(&mut self, x: usize, y: usize, z: usize, state: BlockState) -> BlockState {
+        let was_air = self.get_block(x, y, z).is_air();
+        let is_air = state.is_air();
+        let old = self.block_states.set(block_index(x, y, z), state);
+        match (was_air, is_air) {
+            (true, false) => self.non_air_blocks += 1,
+            (false, true) => self.non_air_blocks -= 1,
+            _ => {}
+        }
+        old
+    }
+
+    pub fn fill(&mut self, state: BlockState) {
+        self.non_air_blocks = if state.is_air() {
+            0
+        } else {
+            SECTION_VOLUME as u16
+        };
+        self.block_states.fill(state);
+    }
+
+    /// Biome at local biome-cell coordinates, each in `0..4`.
+    pub fn get_biome(&self, x: usize, y: usize, z: usize) -> &str {
+        self.biomes.get(biome_index(x, y, z))
+    }
+
+    pub fn set_biome(&mut self, x: usize, y: usize, z: usize, biome: impl Into<String>) {
+        self.biomes.set(biome_index(x, y, z), biome.into());
+    }
+
+    pub fn fill_biome(&mut self, biome: impl Into<String>) {
+        self.biomes.fill(biome.into());
+    }
+
+    pub fn block_states(&self) -> &PalettedContainer<BlockState> {
+        &self.block_states
+    }
+
+    pub fn biomes(&self) -> &PalettedContainer<String> {
+        &self.biomes
+    }
+
+    pub fn non_air_blocks(&self) -> u16 {
+        self.non_air_blocks
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.non_air_blocks == 0
+    }
+
+    fn to_nbt(&self) -> Tag {
+        let mut section = Tag::new_compound("");
+        section.insert("Y".into(), Tag::new_byte("Y", self.y as i8));
+
+        let (palette, data) = self.block_states.to_packed(PaletteKind::BlockStates);
+        let mut block_states = Tag::new_compound("block_states");
+        let palette: Vec<Tag> = palette.iter().map(block_state_to_nbt).collect();
+        block_states.insert("palette".into(), Tag::new_list("palette", 10, palette));
+        if !data.is_empty() {
+            block_states.insert("data".into(), Tag::new_long_array("data", data));
+        }
+        section.insert("block_states".into(), block_states);
+
+        let (palette, data) = self.biomes.to_packed(PaletteKind::Biomes);
+        let mut biomes = Tag::new_compound("biomes");
+        let palette: Vec<Tag> = palette
+            .into_iter()
+            .map(|b| Tag::new_string("", b))
+            .collect();
+        biomes.insert("palette".into(), Tag::new_list("palette", 8, palette));
+        if !data.is_empty() {
+            biomes.insert("data".into(), Tag::new_long_array("data", data));
+        }
+        section.insert("biomes".into(), biomes);
+
+        for (key, light) in [
+            ("BlockLight", &self.block_light),
+            ("SkyLight", &self.sky_light),
+        ] {
+            if let Some(light) = light {
+                let bytes = light.iter().map(|b| *b as i8).collect();
+                section.insert(key.into(), Tag::new_byte_array(key, bytes));
+            }
+        }
+        section
+    }
+
+    fn from_nbt(y: i32, tag: &Tag) -> io::Result<Self> {
+        let mut section = ChunkSection::new(y);
+
+        let states = get_compound(tag, "block_states")?;
+        let palette = get::<&[Tag]>(states, "palette")?
+            .iter()
+            .map(block_state_from_nbt)
+            .collect::<io::Result<Vec<_>>>()?;
+        let data = opt::<&[i64]>(states, "data")?.unwrap_or_default().to_vec();
+        section.block_states =
+            PalettedContainer::from_packed(PaletteKind::BlockStates, palette, data)?;
+        section.non_air_blocks = section.block_states.count(|s| !s.is_air()) as u16;
+
+        if let Some(biomes) = opt_compound(tag, "biomes")? {
+            let palette = get::<&[Tag]>(biomes, "palette")?
+                .iter()
+                .map(|t| {
+                    t.as_string()
+                        .map(str::to_string)
+                        .ok_or_else(|| invalid_data("biome palette entry is not a string"))
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+            let data = opt::<&[i64]>(biomes, "data")?.unwrap_or_default().to_vec();
+            section.biomes = PalettedContainer::from_packed(PaletteKind::Biomes, palette, data)?;
+        }
+
+        section.block_light = read_light(tag, "BlockLight")?;
+        section.sky_light = read_light(tag, "SkyLight")?;
+        Ok(section)
+    }
+}
+
+fn read_light(tag: &Tag, key: &str) -> io::Result<Option<Vec<u8>>> {
+    if tag.get(key).is_none() {
+        return Ok(None);
+    }
+    let bytes = get::<&[i8]>(tag, key)?;
+    if bytes.len() != LIGHT_ARRAY_SIZE {
+        return Err(invalid_data(format!(
+            "`{key}` has {} bytes, expected {LIGHT_ARRAY_SIZE}",
+            bytes.len()
+        )));
+    }
+    Ok(Some(bytes.iter().map(|b| *b as u8).collect()))
+}
+
+fn block_state_to_nbt(state: &BlockState) -> Tag {
+    let mut tag = Tag::new_compound("");
+    tag.insert("Name".into(), Tag::new_string("Name", state.name.clone()));
+    if !state.properties.is_empty() {
+        let mut props = Tag::new_compound("Properties");
+        for (k, v) in &state.properties {
+            props.insert(k.clone(), Tag::new_string(k, v.clone()));
+        }
+        tag.insert("Properties".into(), props);
+    }
+    tag
+}
+
+fn block_state_from_nbt(tag: &Tag) -> io::Result<BlockState> {
+    let mut state = BlockState::new(get::<&str>(tag, "Name")?);
+    if let Some(props) = opt_compound(tag, "Properties")? {
+        for (k, v) in props.as_compound().into_iter().flatten() {
+            let v = v
+                .as_string()
+                .ok_or_else(|| invalid_data(format!("block property `{k}` is not a string")))?;
+            state.set_property(k.clone(), v);
+        }
+    }
+    Ok(state)
+}
+
+/// The heightmap types stored in chunks, in protocol id order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum HeightmapKind {
+    WorldSurfaceWg,
+    WorldSurface,
+    OceanFloorWg,
+    OceanFloor,
+    MotionBlocking,
+    MotionBlockingNoLeaves,
+}
+
+impl HeightmapKind {
+    pub const ALL: [HeightmapKind; 6] = [
+        HeightmapKind::WorldSurfaceWg,
+        HeightmapKind::WorldSurface,
+        HeightmapKind::OceanFloorWg,
+        HeightmapKind::OceanFloor,
+        HeightmapKind::MotionBlocking,
+        HeightmapKind::MotionBlockingNoLeaves,
+    ];
+
+    /// Heightmaps kept by a fully generated chunk.
+    pub const FINAL: [HeightmapKind; 4] = [
+        HeightmapKind::WorldSurface,
+        HeightmapKind::OceanFloor,
+        HeightmapKind::MotionBlocking,
+        HeightmapKind::MotionBlockingNoLeaves,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HeightmapKind::WorldSurfaceWg => "WORLD_SURFACE_WG",
+            HeightmapKind::WorldSurface => "WORLD_SURFACE",
+            HeightmapKind::OceanFloorWg => "OCEAN_FLOOR_WG",
+            HeightmapKind::OceanFloor => "OCEAN_FLOOR",
+            HeightmapKind::MotionBlocking => "MOTION_BLOCKING",
+            HeightmapKind::MotionBlockingNoLeaves => "MOTION_BLOCKING_NO_LEAVES",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|k| k.name() == name)
+    }
+
+    /// Whether `state` counts as the surface for this heightmap. Collision
+    /// data is not available here, so fluids, plants and leaves are told
+    /// apart by name.
+    pub fn is_opaque(self, state: &BlockState) -> bool {
+        if state.is_air() {
+            return false;
+        }
+        let fluid = matches!(
+            state.name.as_str(),
+            "minecraft:water"
+                | "minecraft:lava"
+                | "minecraft:bubble_column"
+                | "minecraft:kelp"
+                | "minecraft:kelp_plant"
+                | "minecraft:seagrass"
+                | "minecraft:tall_seagrass"
+        ) || state.property("waterlogged") == Some("true");
+        let motion = state.blocks_motion() || fluid;
+        match self {
+            HeightmapKind::WorldSurfaceWg | HeightmapKind::WorldSurface => true,
+            HeightmapKind::OceanFloorWg | HeightmapKind::OceanFloor => state.blocks_motion(),
+            HeightmapKind::MotionBlocking => motion,
+            HeightmapKind::MotionBlockingNoLeaves => motion && !state.name.ends_with("_leaves"),
+        }
+    }
+}
+
+/// Blocks of a chunk already carved, so overlapping carvers skip them. Bits
+/// are indexed `x | z << 4 | (y - min_y) << 8`, as in vanilla's
+/// `CarvingMask`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CarvingMask {
+    min_y: i32,
+    bits: Vec<u64>,
+}
+
+impl CarvingMask {
+    pub fn new(min_y: i32, height: i32) -> Self {
+        Self {
+            min_y,
+            bits: vec![0; (height as usize * 256).div_ceil(64)],
+        }
+    }
+
+    fn index(&self, x: usize, y: i32, z: usize) -> usize {
+        x & 15 | (z & 15) << 4 | ((y - self.min_y) as usize) << 8
+    }
+
+    pub fn get(&self, x: usize, y: i32, z: usize) -> bool {
+        let i = self.index(x, y, z);
+        self.bits[i / 64] & 1 << (i % 64) != 0
+    }
+
+    pub fn set(&mut self, x: usize, y: i32, z: usize) {
+        let i = self.index(x, y, z);
+        self.bits[i / 64] |= 1 << (i % 64);
+    }
+
+    /// The bits as `BitSet.toLongArray` stores them, without trailing
+    /// zero words.
+    pub fn to_longs(&self) -> Vec<i64> {
+        let len = self.bits.iter().rposition(|&w| w != 0).map_or(0, |i| i + 1);
+        self.bits[..len].iter().map(|&w| w as i64).collect()
+    }
+
+    pub fn from_longs(min_y: i32, height: i32, longs: &[i64]) -> io::Result<Self> {
+        let mut mask = Self::new(min_y, height);
+        if longs.len() > mask.bits.len() {
+            return Err(invalid_data("carving mask is larger than the chunk"));
+        }
+        for (w, &l) in mask.bits.iter_mut().zip(longs) {
+            *w = l as u64;
+        }
+        Ok(mask)
+    }
+}
+
+/// A full column of sections at chunk coordinates `(x, z)`, plus the
+/// metadata vanilla keeps alongside it on disk.
+#[derive(Clone, Debug)]
+pub struct Chunk {
+    pub x: i32,
+    pub z: i32,
+    sections: Vec<ChunkSection>,
+    pub data_version: i32,
+    /// Generation status, `minecraft:full` once the chunk is complete.
+    pub status: String,
+    /// Per column `(z * 16 + x)`, one above the highest matching block,
+    /// relative to [`Chunk::min_y`]; zero for an empty column.
+    pub heightmaps: BTreeMap<HeightmapKind, [u16; 256]>,
+    /// Blocks removed by carvers, kept while the chunk is generating.
+    pub carving_mask: Option<CarvingMask>,
+    /// Whether light arrays are valid. When false vanilla relights the chunk.
+    pub light_on: bool,
+    pub block_entities: Vec<Tag>,
+    pub block_ticks: Vec<Tag>,
+    pub fluid_ticks: Vec<Tag>,
+    /// Packed positions awaiting post-processing, one list per section.
+    pub post_processing: Vec<Vec<i16>>,
+    pub inhabited_time: i64,
+    pub last_update: i64,
+}
+
+impl Chunk {
+    /// An empty overworld-height chunk (`MIN_Y..MAX_Y`).
+    pub fn new(x: i32, z: i32) -> Self {
+        Self::with_height(x, z, MIN_Y, MAX_Y - MIN_Y)
+    }
+
+    /// An empty chunk spanning `min_y..min_y + height`. Both must be multiples of 16.
+    pub fn with_height(x: i32, z: i32, min_y: i32, height: i32) -> Self {
+        assert!(
+            min_y % SECTION_SIZE as i32 == 0 && height % SECTION_SIZE as i32 == 0 && height > 0,
+            "chunk bounds must be aligned to sections"
+        );
+        let first = min_y >> 4;
+        let count = height >> 4;
+        Self {
+            x,
+            z,
+            sections: (first..first + count).map(ChunkSection::new).collect(),
+            data_version: DATA_VERSION,
+            status: "minecraft:full".into(),
+            heightmaps: BTreeMap::new(),
+            carving_mask: None,
+            light_on: false,
+            block_entities: Vec::new(),
+            block_ticks: Vec::new(),
+            fluid_ticks: Vec::new(),
+            post_processing: vec![Vec::new(); count as usize],
+            inhabited_time: 0,
+            last_update: 0,
+        }
+    }
+
+    pub fn min_y(&self) -> i32 {
+        self.min_section_y() * SECTION_SIZE as i32
+    }
+
+    /// Exclusive upper bound of the chunk.
+    pub fn max_y(&self) -> i32 {
+        self.min_y() + self.height()
+    }
+
+    pub fn height(&self) -> i32 {
+        (self.sections.len() * SECTION_SIZE) as i32
+    }
+
+    pub fn min_section_y(&self) -> i32 {
+        self.sections[0].y
+    }
+
+    pub fn sections(&self) -> &[ChunkSection] {
+        &self.sections
+    }
+
+    pub fn sections_mut(&mut self) -> &mut [ChunkSection] {
+        &mut self.sections
+    }
+
+    /// Section containing world height `y`, if inside the chunk.
+    pub fn section_at(&self, y: i32) -> Option<&ChunkSection> {
+        let i = (y >> 4) - self.min_section_y();
+        usize::try_from(i).ok().and_then(|i| self.sections.get(i))
+    }
+
+    pub fn section_at_mut(&mut self, y: i32) -> Option<&mut ChunkSection> {
+        let i = (y >> 4) - self.min_section_y();
+        usize::try_from(i)
+            .ok()
+            .and_then(move |i| self.sections.get_mut(i))
+    }
+
+    /// Block at local x/z (`0..16`) and world y. Outside the chunk is air.
+    pub fn get_block(&self, x: usize, y: i32, z: usize) -> BlockState {
+        self.section_at(y)
+            .map(|s| s.get_block(x, (y & 15) as usize, z).clone())
+            .unwrap_or_else(BlockState::air)
+    }
+
+    /// Set a block at local x/z and world y. Writes outside the chunk are ignored.
+    pub fn set_block(&mut self, x: usize, y: i32, z: usize, state: BlockState) {
+        if let Some(s) = self.section_at_mut(y) {
+            s.set_block(x, (y & 15) as usize, z, state);
+        }
+    }
+
+    /// Queue a block for the post-processing vanilla runs once the chunk is
+    /// loaded, like letting generated fluids flow.
+    pub fn mark_for_post_processing(&mut self, x: usize, y: i32, z: usize) {
+        let i = (y >> 4) - self.min_section_y();
+        if let Some(list) = usize::try_from(i)
+            .ok()
+            .and_then(|i| self.post_processing.get_mut(i))
+        {
+            list.push((x & 15 | (z & 15) << 4 | ((y & 15) as usize) << 8) as i16);
+        }
+    }
+
+    /// Biome at local block coordinates, sampled from its 4³ cell.
+    pub fn get_biome(&self, x: usize, y: i32, z: usize) -> &str {
+        let y = y.clamp(self.min_y(), self.max_y() - 1);
+        let section = self.section_at(y).expect("y clamped into the chunk");
+        section.get_biome(x >> 2, ((y & 15) >> 2) as usize, z >> 2)
+    }
+
+    /// Set the biome of the 4³ cell containing the given local block coordinates.
+    pub fn set_biome(&mut self, x: usize, y: i32, z: usize, biome: impl Into<String>) {
+        if let Some(s) = self.section_at_mut(y) {
+            s.set_biome(x >> 2, ((y & 15) >> 2) as usize, z >> 2, biome);
+        }
+    }
+}
+
+impl Chunk {
+    /// Recompute the [`HeightmapKind::FINAL`] heightmaps from the blocks.
+    pub fn compute_heightmaps(&mut self) {
+        for kind in HeightmapKind::FINAL {
+            let mut map = [0u16; 256];
+            for z in 0..SECTION_SIZE {
+                for x in 0..SECTION_SIZE {
+                    map[z * SECTION_SIZE + x] = self.column_height(kind, x, z);
+                }
+            }
+            self.heightmaps.insert(kind, map);
+        }
+    }
+
+    /// The y above the highest block of a column that counts for `kind`,
+    /// or the bottom of the world in an empty column. Computed from the
+    /// blocks, so it also serves the worldgen heightmaps not stored here.
+    pub fn surface_height(&self, kind: HeightmapKind, x: usize, z: usize) -> i32 {
+        self.min_y() + i32::from(self.column_height(kind, x, z))
+    }
+
+    fn column_height(&self, kind: HeightmapKind, x: usize, z: usize) -> u16 {
+        for (i, section) in self.sections.iter().enumerate().rev() {
+            if section.is_empty() {
+                continue;
+            }
+            for y in (0..SECTION_SIZE).rev() {
+                if kind.is_opaque(section.get_block(x, y, z)) {
+                    return (i * SECTION_SIZE + y + 1) as u16;
+                }
+            }
+        }
+        0
+    }
+
+    /// Bits per entry of a packed heightmap for this chunk's height.
+    pub fn heightmap_bits(&self) -> u8 {
+        ceil_log2(self.height() as usize + 1)
+    }
+
+    /// Pack a heightmap into longs, as stored on disk and sent to clients.
+    pub fn pack_heightmap(&self, map: &[u16; 256]) -> Vec<i64> {
+        let mut storage = BitStorage::new(self.heightmap_bits(), 256);
+        for (i, h) in map.iter().enumerate() {
+            storage.set(i, *h as u64);
+        }
+        storage.into_raw()
+    }
+
+    /// Serialize into the 1.21 chunk NBT layout.
+    pub fn to_nbt(&self) -> Tag {
+        let mut root = Tag::new_compound("");
+        root.insert(
+            "DataVersion".into(),
+            Tag::new_int("DataVersion", self.data_version),
+        );
+        root.insert("xPos".into(), Tag::new_int("xPos", self.x));
+        root.insert("yPos".into(), Tag::new_int("yPos", self.min_section_y()));
+        root.insert("zPos".into(), Tag::new_int("zPos", self.z));
+        root.insert(
+            "Status".into(),
+            Tag::new_string("Status", self.status.clone()),
+        );
+        root.insert(
+            "LastUpdate".into(),
+            Tag::new_long("LastUpdate", self.last_update),
+        );
+        root.insert(
+            "InhabitedTime".into(),
+            Tag::new_long("InhabitedTime", self.inhabited_time),
+        );
+        root.insert(
+            "isLightOn".into(),
+            Tag::new_byte("isLightOn", i8::from(self.light_on)),
+        );
+
+        let sections: Vec<Tag> = self.sections.iter().map(ChunkSection::to_nbt).collect();
+        root.insert("sections".into(), Tag::new_list("sections", 10, sections));
+
+        let mut heightmaps = Tag::new_compound("Heightmaps");
+        for (kind, map) in &self.heightmaps {
+            heightmaps.insert(
+                kind.name().into(),
+                Tag::new_long_array(kind.name(), self.pack_heightmap(map)),
+            );
+        }
+        root.insert("Heightmaps".into(), heightmaps);
+        if let Some(mask) = &self.carving_mask {
+            root.insert(
+                "carving_mask".into(),
+                Tag::new_long_array("carving_mask", mask.to_longs()),
+            );
+        }
+
+        for (key, list) in [
+            ("block_entities", &self.block_entities),
+            ("block_ticks", &self.block_ticks),
+            ("fluid_ticks", &self.fluid_ticks),
+        ] {
+            let kind = if list.is_empty() { 0 } else { 10 };
+            root.insert(key.into(), Tag::new_list(key, kind, list.clone()));
+        }
+
+        let post: Vec<Tag> = self
+            .post_processing
+            .iter()
+            .map(|l| {
+                let shorts = l.iter().map(|p| Tag::new_short("", *p)).collect::<Vec<_>>();
+                Tag::new_list("", if shorts.is_empty() { 0 } else { 2 }, shorts)
+            })
+            .collect();
+        root.insert(
+            "PostProcessing".into(),
+            Tag::new_list("PostProcessing", 9, post),
+        );
+
+        let mut structures = Tag::new_compound("structures");
+        structures.insert("starts".into(), Tag::new_compound("starts"));
+        structures.insert("References".into(), Tag::new_compound("References"));
+        root.insert("structures".into(), structures);
+
+        root
+    }
+
+    /// Deserialize the 1.21 chunk NBT layout. Sections that only carry light
+    /// (one below and one above the world) are skipped.
+    pub fn from_nbt(tag: &Tag) -> io::Result<Self> {
+        let x = get(tag, "xPos")?;
+        let z = get(tag, "zPos")?;
+        let min_section = get(tag, "yPos")?;
+
+        let mut sections = BTreeMap::new();
+        for section in get::<&[Tag]>(tag, "sections")? {
+            let y = opt::<i8>(section, "Y")?
+                .ok_or_else(|| invalid_data("chunk section without `Y`"))?
+                as i32;
+            if y < min_section || section.get("block_states").is_none() {
+                continue;
+            }
+            sections.insert(y, ChunkSection::from_nbt(y, section)?);
+        }
+        let top = sections.keys().next_back().copied().unwrap_or(min_section);
+        let height = (top - min_section + 1) * SECTION_SIZE as i32;
+
+        let mut chunk = Chunk::with_height(x, z, min_section * SECTION_SIZE as i32, height);
+        for section in chunk.sections.iter_mut() {
+            if let Some(loaded) = sections.remove(&section.y) {
+                *section = loaded;
+            }
+        }
+
+        chunk.data_version = opt(tag, "DataVersion")?.unwrap_or(DATA_VERSION);
+        chunk.status = opt(tag, "Status")?.unwrap_or("minecraft:empty").to_string();
+        chunk.last_update = opt(tag, "LastUpdate")?.unwrap_or(0);
+        chunk.inhabited_time = opt(tag, "InhabitedTime")?.unwrap_or(0);
+        chunk.light_on = opt(tag, "isLightOn")?.unwrap_or(false);
+
+        let heightmaps = get_compound(tag, "Heightmaps")?;
+        let bits = chunk.heightmap_bits();
+        for (name, data) in heightmaps.as_compound().into_iter().flatten() {
+            let Some(kind) = HeightmapKind::from_name(name) else {
+                continue;
+            };
+            let data = data
+                .as_long_array()
+                .ok_or_else(|| invalid_data(format!("heightmap `{name}` is not a long array")))?;
+            let storage = BitStorage::from_raw(bits, 256, data.to_vec())?;
+            let mut map = [0u16; 256];
+            for (i, h) in map.iter_mut().enumerate() {
+                *h = storage.get(i) as u16;
+            }
+            chunk.heightmaps.insert(kind, map);
+        }
+
+        if let Some(mask) = opt::<&[i64]>(tag, "carving_mask")? {
+            chunk.carving_mask = Some(CarvingMask::from_longs(
+                chunk.min_y(),
+                chunk.height(),
+                mask,
+            )?);
+        }
+
+        chunk.block_entities = opt::<&[Tag]>(tag, "block_entities")?
+            .unwrap_or_default()
+            .to_vec();
+        chunk.block_ticks = opt::<&[Tag]>(tag, "block_ticks")?
+            .unwrap_or_default()
+            .to_vec();
+        chunk.fluid_ticks = opt::<&[Tag]>(tag, "fluid_ticks")?
+            .unwrap_or_default()
+            .to_vec();
+
+        if let Some(post) = opt::<&[Tag]>(tag, "PostProcessing")? {
+            for (i, list) in post.iter().enumerate().take(chunk.sections.len()) {
+                let list = list
+                    .as_list()
+                    .ok_or_else(|| invalid_data("`PostProcessing` entry is not a list"))?;
+                chunk.post_processing[i] = list.iter().filter_map(Tag::as_short).collect();
+            }
+        }
+
+        Ok(chunk)
+    }
+
+    /// Load chunk `(x, z)` from a region file, `Ok(None)` if it was never saved.
+    pub fn load(region: &mut RegionFile, x: i32, z: i32) -> io::Result<Option<Self>> {
+        match region.read_chunk(x, z)? {
+            Some(bytes) => Self::from_nbt(&nbt_util::from_bytes(&bytes)?).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Save this chunk into a region file using zlib, vanilla's default.
+    pub fn save(&self, region: &mut RegionFile) -> io::Result<()> {
+        let bytes = nbt_util::to_bytes(&self.to_nbt())?;
+        region.write_chunk(self.x, self.z, &bytes, Compression::Zlib)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_round_trip_and_counts() {
+        let mut chunk = Chunk::new(3, -2);
+        assert_eq!(chunk.min_y(), -64);
+        assert_eq!(chunk.sections().len(), 24);
+
+        let stairs = BlockState::new("minecraft:oak_stairs")
+            .with("half", "bottom")
+            .with("facing", "north");
+        chunk.set_block(1, -64, 2, BlockState::new("minecraft:bedrock"));
+        chunk.set_block(15, 319, 15, stairs.clone());
+        chunk.set_block(0, 400, 0, BlockState::new("minecraft:stone"));
+
+        assert_eq!(chunk.get_block(1, -64, 2).name, "minecraft:bedrock");
+        assert_eq!(chunk.get_block(15, 319, 15), stairs);
+        assert_eq!(stairs.property("facing"), Some("north"));
+        assert!(chunk.get_block(0, 400, 0).is_air());
+
+        assert_eq!(chunk.section_at(-64).unwrap().non_air_blocks(), 1);
+        chunk.set_block(1, -64, 2, BlockState::air());
+        assert!(chunk.section_at(-64).unwrap().is_empty());
+    }
+
+    #[test]
+    fn biomes_use_four_block_cells() {
+        let mut chunk = Chunk::new(0, 0);
+        chunk.set_biome(5, 70, 9, "minecraft:desert");
+        assert_eq!(chunk.get_biome(4, 68, 8), "minecraft:desert");
+        assert_eq!(chunk.get_biome(7, 71, 11), "minecraft:desert");
+        assert_eq!(chunk.get_biome(8, 70, 9), DEFAULT_BIOME);
+    }
+
+    #[test]
+    fn properties_are_order_independent() {
+        let a = BlockState::new("minecraft:oak_log")
+            .with("axis", "y")
+            .with("b", "1");
+        let b = BlockState::new("minecraft:oak_log")
+            .with("b", "1")
+            .with("axis", "y");
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn nbt_round_trip() {
+        let mut chunk = Chunk::new(-7, 12);
+        chunk.set_block(0, -64, 0, BlockState::new("minecraft:bedrock"));
+        for x in 0..16 {
+            for z in 0..16 {
+                chunk.set_block(
+                    x,
+                    60 + (x as i32 % 5),
+                    z,
+                    BlockState::new("minecraft:stone"),
+                );
+            }
+        }
+        chunk.set_block(
+            3,
+            100,
+            4,
+            BlockState::new("minecraft:oak_leaves").with("distance", "1"),
+        );
+        chunk.set_biome(0, 64, 0, "minecraft:forest");
+        chunk.sections_mut()[5].sky_light = Some(vec![0xFF; LIGHT_ARRAY_SIZE]);
+        chunk.post_processing[2] = vec![17, 300];
+        chunk.inhabited_time = 1234;
+        chunk.compute_heightmaps();
+        let mut mask = CarvingMask::new(chunk.min_y(), chunk.height());
+        mask.set(3, -20, 15);
+        mask.set(0, 319, 0);
+        chunk.carving_mask = Some(mask);
+
+        let tag = chunk.to_nbt();
+        assert_eq!(tag.get("yPos").and_then(Tag::as_int), Some(-4));
+        assert_eq!(
+            tag.get("Status").and_then(Tag::as_string),
+            Some("minecraft:full")
+        );
+
+        let back = Chunk::from_nbt(&tag).unwrap();
+        assert_eq!((back.x, back.z), (-7, 12));
+        assert_eq!(back.sections(), chunk.sections());
+        assert_eq!(back.heightmaps, chunk.heightmaps);
+        assert_eq!(back.post_processing, chunk.post_processing);
+        assert_eq!(back.carving_mask, chunk.carving_mask);
+        let mask = back.carving_mask.unwrap();
+        assert!(mask.get(3, -20, 15) && mask.get(0, 319, 0) && !mask.get(3, -19, 15));
+        assert_eq!(back.inhabited_time, 1234);
+
+        let mut no_heightmaps = Tag::new_compound("");
+        for (key, value) in tag.as_compound().unwrap() {
+            if key != "Heightmaps" {
+                no_heightmaps.insert(key.clone(), value.clone());
+            }
+        }
+        let err = Chunk::from_nbt(&no_heightmaps).err().unwrap();
+        assert_eq!(err.to_string(), "missing tag `Heightmaps`");
+    }
+
+    #[test]
+    fn heightmaps_skip_leaves_and_fluids() {
+        let mut chunk = Chunk::new(0, 0);
+        chunk.set_block(0, 62, 0, BlockState::new("minecraft:sand"));
+        chunk.set_block(
+            0,
+            63,
+            0,
+            BlockState::new("minecraft:water").with("level", "0"),
+        );
+        chunk.set_block(1, 70, 0, BlockState::new("minecraft:stone"));
+        chunk.set_block(1, 71, 0, BlockState::new("minecraft:oak_leaves"));
+        chunk.compute_heightmaps();
+
+        let h = |kind: HeightmapKind, i: usize| chunk.heightmaps[&kind][i] as i32 + chunk.min_y();
+        assert_eq!(h(HeightmapKind::WorldSurface, 0), 64);
+        assert_eq!(h(HeightmapKind::OceanFloor, 0), 63);
+        assert_eq!(h(HeightmapKind::MotionBlocking, 1), 72);
+        assert_eq!(h(HeightmapKind::MotionBlockingNoLeaves, 1), 71);
+        assert_eq!(chunk.heightmaps[&HeightmapKind::WorldSurface][2], 0);
+        assert_eq!(chunk.pack_heightmap(&[0; 256]).len(), 37);
+    }
+}
@@ -0,0 +1,452 @@
+//! Vanilla's climate space: biomes are points (or boxes) in a seven
+//! dimensional parameter space, and the biome at a position is the one
+//! closest to the climate sampled there. Lookups go through the same R-tree
+//! as `Climate.RTree`, so ties resolve exactly like the game.
+
+use crate::noise_router::NoiseRouter;
+use std::cmp::Ordering as CmpOrdering;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Parameters are stored as fixed point with four decimals.
+pub fn quantize_coord(f: f32) -> i64 {
+    (f * 10000.0) as i64
+}
+
+pub fn unquantize_coord(l: i64) -> f32 {
+    l as f32 / 10000.0
+}
+
+/// A closed range of one climate parameter, quantized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Parameter {
+    pub min: i64,
+    pub max: i64,
+}
+
+impl Parameter {
+    pub fn point(f: f32) -> Self {
+        Self::span(f, f)
+    }
+
+    pub fn span(min: f32, max: f32) -> Self {
+        assert!(min <= max, "min {min} > max {max}");
+        Self {
+            min: quantize_coord(min),
+            max: quantize_coord(max),
+        }
+    }
+
+    /// Distance from a value to the range, zero inside it.
+    pub fn distance(&self, value: i64) -> i64 {
+        let above = value - self.max;
+        let below = self.min - value;
+        if above > 0 { above } else { below.max(0) }
+    }
+
+    /// Gap between two ranges, zero when they overlap.
+    pub fn distance_to(&self, other: &Self) -> i64 {
+        let above = other.min - self.max;
+        let below = self.min - other.max;
+        if above > 0 { above } else { below.max(0) }
+    }
+
+    /// Smallest range covering both.
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+}
+
+/// The climate of one biome entry. `offset` pushes the entry away from
+/// every target, making it lose ties to entries without one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ParameterPoint {
+    pub temperature: Parameter,
+    pub humidity: Parameter,
+    pub continentalness: Parameter,
+    pub erosion: Parameter,
+    pub depth: Parameter,
+    pub weirdness: Parameter,
+    pub offset: i64,
+}
+
+impl ParameterPoint {
+    /// `Climate.parameters` with every dimension a single value.
+    pub fn new(
+        temperature: f32,
+        humidity: f32,
+        continentalness: f32,
+        erosion: f32,
+        depth: f32,
+        weirdness: f32,
+        offset: f32,
+    ) -> Self {
+        Self {
+            temperature: Parameter::point(temperature),
+            humidity: Parameter::point(humidity),
+            continentalness: Parameter::point(continentalness),
+            erosion: Parameter::point(erosion),
+            depth: Parameter::point(depth),
+            weirdness: Parameter::point(weirdness),
+            offset: quantize_coord(offset),
+        }
+    }
+
+    /// The seven dimensions searched by the R-tree, offset last.
+    pub fn parameter_space(&self) -> [Parameter; 7] {
+        [
+            self.temperature,
+            self.humidity,
+            self.continentalness,
+            self.erosion,
+            self.depth,
+            self.weirdness,
+            Parameter {
+                min: self.offset,
+                max: self.offset,
+            },
+        ]
+    }
+
+    /// Squared distance to a target; lower is a better match.
+    pub fn fitness(&self, target: &TargetPoint) -> i64 {
+        distance(&self.parameter_space(), &target.to_parameter_array())
+    }
+}
+
+/// The quantized climate sampled at one position.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct TargetPoint {
+    pub temperature: i64,
+    pub humidity: i64,
+    pub continentalness: i64,
+    pub erosion: i64,
+    pub depth: i64,
+    pub weirdness: i64,
+}
+
+impl TargetPoint {
+    pub fn new(
+        temperature: f32,
+        humidity: f32,
+        continentalness: f32,
+        erosion: f32,
+        depth: f32,
+        weirdness: f32,
+    ) -> Self {
+        Self {
+            temperature: quantize_coord(temperature),
+            humidity: quantize_coord(humidity),
+            continentalness: quantize_coord(continentalness),
+            erosion: quantize_coord(erosion),
+            depth: quantize_coord(depth),
+            weirdness: quantize_coord(weirdness),
+        }
+    }
+
+    /// Parameter values with a zero offset.
+    pub fn to_parameter_array(&self) -> [i64; 7] {
+        [
+            self.temperature,
+            self.humidity,
+            self.continentalness,
+            self.erosion,
+            self.depth,
+            self.weirdness,
+            0,
+        ]
+    }
+}
+
+/// Sample the climate at quart (4-block) coordinates from a seeded router;
+/// humidity is the router's `vegetation`.
+pub fn sample(router: &NoiseRouter, quart_x: i32, quart_y: i32, quart_z: i32) -> TargetPoint {
+    let (x, y, z) = (quart_x << 2, quart_y << 2, quart_z << 2);
+    let f = |f: &crate::density::DensityFn| f.compute(x, y, z) as f32;
+    TargetPoint::new(
+        f(&router.temperature),
+        f(&router.vegetation),
+        f(&router.continents),
+        f(&router.erosion),
+        f(&router.depth),
+        f(&router.ridges),
+    )
+}
+
+fn distance(space: &[Parameter; 7], target: &[i64; 7]) -> i64 {
+    space
+        .iter()
+        .zip(target)
+        .map(|(p, &t)| {
+            let d = p.distance(t);
+            d * d
+        })
+        .sum()
+}
+
+/// Biome entries by climate, searched through an R-tree.
+#[derive(Debug)]
+pub struct ParameterList<T> {
+    values: Vec<(ParameterPoint, T)>,
+    tree: RTree,
+}
+
+impl<T> ParameterList<T> {
+    pub fn new(values: Vec<(ParameterPoint, T)>) -> Self {
+        assert!(!values.is_empty(), "need at least one parameter point");
+        let tree = RTree::new(values.iter().map(|(p, _)| p.parameter_space()).collect());
+        Self { values, tree }
+    }
+
+    pub fn values(&self) -> &[(ParameterPoint, T)] {
+        &self.values
+    }
+
+    /// The value whose parameters are closest to `target`.
+    pub fn find_value(&self, target: &TargetPoint) -> &T {
+        &self.values[self.tree.search(target)].1
+    }
+
+    /// Linear scan for the closest value, first entry winning ties. Used to
+    /// check the tree; generation uses [`find_value`](Self::find_value).
+    pub fn find_value_brute_force(&self, target: &TargetPoint) -> &T {
+        let mut best = (i64::MAX, 0);
+        for (i, (point, _)) in self.values.iter().enumerate() {
+            let fitness = point.fitness(target);
+            if fitness < best.0 {
+                best = (fitness, i);
+            }
+        }
+        &self.values[best.1].1
+    }
+}
+
+const CHILDREN_PER_NODE: usize = 6;
+
+#[derive(Debug)]
+enum Node {
+    Leaf {
+        space: [Parameter; 7],
+        index: usize,
+    },
+    SubTree {
+        space: [Parameter; 7],
+        children: Vec<Node>,
+    },
+}
+
+impl Node {
+    fn space(&self) -> &[Parameter; 7] {
+        match self {
+            Self::Leaf { space, .. } | Self::SubTree { space, .. } => space,
+        }
+    }
+
+    fn subtree(children: Vec<Node>) -> Self {
+        let space = union_of(children.iter().map(Node::space));
+        Self::SubTree { space, children }
+    }
+
+    fn search(&self, target: &[i64; 7], best: Option<usize>, leaves: &[[Parameter; 7]]) -> usize {
+        let children = match self {
+            Self::Leaf { index, .. } => return *index,
+            Self::SubTree { children, .. } => children,
+        };
+        let mut best = best;
+        let mut best_distance = best.map_or(i64::MAX, |i| distance(&leaves[i], target));
+        for child in children {
+            if best_distance > distance(child.space(), target) {
+                let leaf = child.search(target, best, leaves);
+                let d = distance(&leaves[leaf], target);
+                if best_distance > d {
+                    best_distance = d;
+                    best = Some(leaf);
+                }
+            }
+        }
+        best.expect("subtrees are never empty")
+    }
+}
+
+/// `Climate.RTree`: nodes of up to six children, split along the dimension
+/// that gives the tightest buckets.
+#[derive(Debug)]
+struct RTree {
+    root: Node,
+    leaves: Vec<[Parameter; 7]>,
+    /// Previous result, which seeds the next search like vanilla's
+    /// thread-local cache. It only affects which of several equally close
+    /// entries is returned.
+    last: AtomicUsize,
+}
+
+impl RTree {
+    fn new(leaves: Vec<[Parameter; 7]>) -> Self {
+        let nodes = leaves
+            .iter()
+            .enumerate()
+            .map(|(index, space)| Node::Leaf {
+                space: *space,
+                index,
+            })
+            .collect();
+        Self {
+            root: build(nodes),
+            leaves,
+            last: AtomicUsize::new(usize::MAX),
+        }
+    }
+
+    fn search(&self, target: &TargetPoint) -> usize {
+        let last = self.last.load(Ordering::Relaxed);
+        let last = (last != usize::MAX).then_some(last);
+        let found = self
+            .root
+            .search(&target.to_parameter_array(), last, &self.leaves);
+        self.last.store(found, Ordering::Relaxed);
+        found
+    }
+}
+
+fn midpoint(p: &Parameter) -> i64 {
+    (p.min + p.max) / 2
+}
+
+/// Order by dimension `first`, then the following dimensions cyclically.
+fn compare(a: &[Parameter; 7], b: &[Parameter; 7], first: usize, absolute: bool) -> CmpOrdering {
+    (0..7)
+        .map(|k| (first + k) % 7)
+        .map(|d| {
+            let (x, y) = (midpoint(&a[d]), midpoint(&b[d]));
+            if absolute {
+                x.abs().cmp(&y.abs())
+            } else {
+                x.cmp(&y)
+            }
+        })
+        .find(|o| o.is_ne())
+        .unwrap_or(CmpOrdering::Equal)
+}
+
+/// Bucket size for `len` nodes: the largest power of six below it.
+fn bucket_size(len: usize) -> usize {
+    6f64.powf(((len as f64 - 0.01).ln() / 6f64.ln()).floor()) as usize
+}
+
+fn union_of<'a>(mut spaces: impl Iterator<Item = &'a [Parameter; 7]>) -> [Parameter; 7] {
+    let mut space = *spaces.next().expect("empty bucket");
+    for other in spaces {
+        for (s, p) in space.iter_mut().zip(other) {
+            *s = s.union(p);
+        }
+    }
+    space
+}
+
+fn cost(space: &[Parameter; 7]) -> i64 {
+    let mut f = 0f32;
+    for p in space {
+        f += (p.max - p.min).abs() as f32;
+    }
+    f as i64
+}
+
+fn build(mut nodes: Vec<Node>) -> Node {
+    if nodes.len() == 1 {
+        return nodes.pop().unwrap();
+    }
+    if nodes.len() <= CHILDREN_PER_NODE {
+        nodes.sort_by_key(|n| n.space().iter().map(|p| midpoint(p).abs()).sum::<i64>());
+        return Node::subtree(nodes);
+    }
+
+    // Sort along each dimension in turn (each sort starting from the
+    // previous order, as vanilla does) and keep the order whose buckets
+    // have the least total extent.
+    let size = bucket_size(nodes.len());
+    let mut order: Vec<usize> = (0..nodes.len()).collect();
+    let mut best: Option<(i64, usize, Vec<usize>)> = None;
+    for dimension in 0..7 {
+        order.sort_by(|&a, &b| compare(nodes[a].space(), nodes[b].space(), dimension, false));
+        let total: i64 = order
+            .chunks(size)
+            .map(|bucket| cost(&union_of(bucket.iter().map(|&i| nodes[i].space()))))
+            .sum();
+        if best.as_ref().is_none_or(|(c, ..)| *c > total) {
+            best = Some((total, dimension, order.clone()));
+        }
+    }
+    let (_, dimension, order) = best.unwrap();
+
+    let mut slots: Vec<Option<Node>> = nodes.into_iter().map(Some).collect();
+    let mut buckets: Vec<Node> = order
+        .chunks(size)
+        .map(|bucket| Node::subtree(bucket.iter().map(|&i| slots[i].take().unwrap()).collect()))
+        .collect();
+    buckets.sort_by(|a, b| compare(a.space(), b.space(), dimension, true));
+    Node::subtree(
+        buckets
+            .into_iter()
+            .map(|bucket| match bucket {
+                Node::SubTree { children, .. } => build(children),
+                leaf => leaf,
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::{RandomSource, XoroshiroRandomSource};
+
+    #[test]
+    fn quantizes_like_vanilla() {
+        assert_eq!(quantize_coord(0.55), 5500);
+        assert_eq!(quantize_coord(-0.19), -1900);
+        assert_eq!(quantize_coord(-1.05), -10500);
+        let p = Parameter::span(-0.45, -0.15);
+        assert_eq!((p.min, p.max), (-4500, -1500));
+        assert_eq!(p.distance(-2000), 0);
+        assert_eq!(p.distance(1000), 2500);
+        assert_eq!(p.distance(-5000), 500);
+        assert_eq!(p.distance_to(&Parameter::point(0.5)), 6500);
+    }
+
+    #[test]
+    fn tree_finds_the_nearest_point() {
+        let mut random = XoroshiroRandomSource::new(19);
+        let span = |random: &mut XoroshiroRandomSource| {
+            let a = random.next_float() * 4.0 - 2.0;
+            let b = a + random.next_float() * 0.5;
+            Parameter::span(a, b.min(2.0))
+        };
+        let values: Vec<(ParameterPoint, usize)> = (0..500)
+            .map(|i| {
+                let point = ParameterPoint {
+                    temperature: span(&mut random),
+                    humidity: span(&mut random),
+                    continentalness: span(&mut random),
+                    erosion: span(&mut random),
+                    depth: span(&mut random),
+                    weirdness: span(&mut random),
+                    offset: quantize_coord(random.next_float() * 0.2),
+                };
+                (point, i)
+            })
+            .collect();
+        let list = ParameterList::new(values);
+        for _ in 0..2000 {
+            let mut f = || random.next_float() * 4.0 - 2.0;
+            let target = TargetPoint::new(f(), f(), f(), f(), f(), f());
+            let tree = list.find_value(&target);
+            let brute = list.find_value_brute_force(&target);
+            assert_eq!(
+                list.values()[*tree].0.fitness(&target),
+                list.values()[*brute].0.fitness(&target)
+            );
+        }
+    }
+}
@@ -0,0 +1,832 @@
+//! Worldgen data from datapacks: `worldgen/noise`, `worldgen/density_function`
+//! and `worldgen/noise_settings` JSON, merged across the enabled packs and
+//! turned into the structures of [`crate::density`] and [`crate::generator`].
+
+use crate::chunk::BlockState;
+use crate::density::{
+    BinaryOp, BlendedNoise, DensityFn, DensityFunction, EndIslands, MappedOp, Marker, NoiseHolder,
+    RarityValueMapper, Spline, SplinePoint,
+};
+use crate::generator::NoiseGeneratorSettings;
+use crate::level::DataPacks;
+use crate::nbt_util::invalid_data;
+use crate::noise::NoiseParameters;
+use crate::noise_router::{NoiseRouter, NoiseSettings};
+use crate::surface::{CaveSurface, SurfaceCondition, SurfaceRule, VerticalAnchor};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Registries read by [`WorldgenData`], as directories under
+/// `data/<namespace>/worldgen/`.
+const NOISE: &str = "noise";
+const DENSITY_FUNCTION: &str = "density_function";
+const NOISE_SETTINGS: &str = "noise_settings";
+
+/// Add the `minecraft:` namespace to bare ids, as resource locations do.
+fn namespaced(id: &str) -> String {
+    if id.contains(':') {
+        id.to_string()
+    } else {
+        format!("minecraft:{id}")
+    }
+}
+
+/// Raw worldgen JSON of the loaded packs, keyed by registry and id. Later
+/// packs replace entries of earlier ones.
+#[derive(Clone, Debug, Default)]
+pub struct WorldgenData {
+    entries: HashMap<&'static str, HashMap<String, Value>>,
+}
+
+impl WorldgenData {
+    /// Load the packs listed in `packs.enabled`, lowest priority first as
+    /// vanilla orders them. `vanilla` is the extracted vanilla data (the
+    /// directory holding `data/minecraft`), `file/<name>` a pack directory in
+    /// `<world_dir>/datapacks`, and any other name a built-in feature pack
+    /// under `data/minecraft/datapacks` of the vanilla data. Built-in packs
+    /// that are not present are skipped.
+    pub fn load(
+        packs: &DataPacks,
+        vanilla_dir: impl AsRef<Path>,
+        world_dir: impl AsRef<Path>,
+    ) -> io::Result<Self> {
+        let (vanilla_dir, world_dir) = (vanilla_dir.as_ref(), world_dir.as_ref());
+        let mut data = Self::default();
+        for name in &packs.enabled {
+            let root = if name == "vanilla" {
+                vanilla_dir.to_path_buf()
+            } else if let Some(file) = name.strip_prefix("file/") {
+                world_dir.join("datapacks").join(file)
+            } else {
+                let builtin = vanilla_dir.join("data/minecraft/datapacks").join(name);
+                if !builtin.is_dir() {
+                    continue;
+                }
+                builtin
+            };
+            if root.is_file() {
+                return Err(invalid_data(format!(
+                    "datapack `{name}` is an archive; only directories are supported"
+                )));
+            }
+            if !root.is_dir() {
+                return Err(invalid_data(format!(
+                    "datapack `{name}` not found at {}",
+                    root.display()
+                )));
+            }
+            data.add_pack(&root)?;
+        }
+        Ok(data)
+    }
+
+    /// Merge one pack directory (the one holding `data/`) over the entries
+    /// loaded so far.
+    pub fn add_pack(&mut self, root: impl AsRef<Path>) -> io::Result<()> {
+        let data = root.as_ref().join("data");
+        if !data.is_dir() {
+            return Ok(());
+        }
+        for namespace in std::fs::read_dir(&data)? {
+            let namespace = namespace?;
+            if !namespace.file_type()?.is_dir() {
+                continue;
+            }
+            let ns = namespace.file_name().to_string_lossy().into_owned();
+            for registry in [NOISE, DENSITY_FUNCTION, NOISE_SETTINGS] {
+                let dir = namespace.path().join("worldgen").join(registry);
+                let mut files = Vec::new();
+                collect_json(&dir, &mut files)?;
+                for file in files {
+                    let path = file.strip_prefix(&dir).unwrap_or(&file).with_extension("");
+                    let path = path
+                        .iter()
+                        .map(|p| p.to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/");
+                    let value: Value = serde_json::from_str(&std::fs::read_to_string(&file)?)
+                        .map_err(|e| invalid_data(format!("{}: {e}", file.display())))?;
+                    self.insert(registry, format!("{ns}:{path}"), value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, registry: &'static str, id: String, value: Value) {
+        self.entries.entry(registry).or_default().insert(id, value);
+    }
+
+    fn get(&self, registry: &str, id: &str) -> io::Result<&Value> {
+        self.entries
+            .get(registry)
+            .and_then(|e| e.get(&namespaced(id)))
+            .ok_or_else(|| invalid_data(format!("unknown {registry} `{id}`")))
+    }
+
+    fn ids(&self, registry: &str) -> Vec<&str> {
+        let mut ids: Vec<&str> = self
+            .entries
+            .get(registry)
+            .into_iter()
+            .flat_map(|e| e.keys().map(String::as_str))
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Ids of all noise settings, sorted.
+    pub fn noise_settings_ids(&self) -> Vec<&str> {
+        self.ids(NOISE_SETTINGS)
+    }
+
+    /// Raw JSON of a noise settings entry, for the parts not modelled here.
+    pub fn noise_settings_json(&self, id: &str) -> io::Result<&Value> {
+        self.get(NOISE_SETTINGS, id)
+    }
+
+    /// A `worldgen/noise` entry.
+    pub fn noise(&self, id: &str) -> io::Result<NoiseParameters> {
+        parse_noise_parameters(self.get(NOISE, id)?)
+            .map_err(|e| invalid_data(format!("noise `{id}`: {e}")))
+    }
+
+    /// A `worldgen/density_function` entry with its references resolved.
+    pub fn density_function(&self, id: &str) -> io::Result<DensityFn> {
+        Resolver::new(self).function_ref(id)
+    }
+
+    /// A `worldgen/noise_settings` entry, e.g. `minecraft:overworld`.
+    pub fn noise_settings(&self, id: &str) -> io::Result<NoiseGeneratorSettings> {
+        let json = self.get(NOISE_SETTINGS, id)?;
+        parse_noise_settings(self, json)
+            .map_err(|e| invalid_data(format!("noise settings `{id}`: {e}")))
+    }
+}
+
+fn collect_json(dir: &Path, out: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_json(&path, out)?;
+        } else if path.extension().is_some_and(|e| e == "json") {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn field<'a>(obj: &'a Map<String, Value>, key: &str) -> io::Result<&'a Value> {
+    obj.get(key)
+        .ok_or_else(|| invalid_data(format!("missing field `{key}`")))
+}
+
+fn f64_field(obj: &Map<String, Value>, key: &str) -> io::Result<f64> {
+    field(obj, key)?
+        .as_f64()
+        .ok_or_else(|| invalid_data(format!("field `{key}` is not a number")))
+}
+
+fn i32_field(obj: &Map<String, Value>, key: &str) -> io::Result<i32> {
+    field(obj, key)?
+        .as_i64()
+        .and_then(|v| i32::try_from(v).ok())
+        .ok_or_else(|| invalid_data(format!("field `{key}` is not an int")))
+}
+
+fn opt_bool(obj: &Map<String, Value>, key: &str, default: bool) -> io::Result<bool> {
+    match obj.get(key) {
+        None => Ok(default),
+        Some(v) => v
+            .as_bool()
+            .ok_or_else(|| invalid_data(format!("field `{key}` is not a boolean"))),
+    }
+}
+
+fn object(value: &Value) -> io::Result<&Map<String, Value>> {
+    value
+        .as_object()
+        .ok_or_else(|| invalid_data("expected an object"))
+}
+
+/// `{"firstOctave": -7, "amplitudes": [1.0, 1.0]}`.
+fn parse_noise_parameters(value: &Value) -> io::Result<NoiseParameters> {
+    let obj = object(value)?;
+    let amplitudes = field(obj, "amplitudes")?
+        .as_array()
+        .and_then(|a| a.iter().map(Value::as_f64).collect::<Option<Vec<_>>>())
+        .ok_or_else(|| invalid_data("`amplitudes` is not a list of numbers"))?;
+    Ok(NoiseParameters::new(
+        i32_field(obj, "firstOctave")?,
+        amplitudes,
+    ))
+}
+
+/// `{"Name": "minecraft:water", "Properties": {"level": "0"}}`.
+fn parse_block_state(value: &Value) -> io::Result<BlockState> {
+    let obj = object(value)?;
+    let name = field(obj, "Name")?
+        .as_str()
+        .ok_or_else(|| invalid_data("block `Name` is not a string"))?;
+    let mut state = BlockState::new(namespaced(name));
+    if let Some(props) = obj.get("Properties").and_then(Value::as_object) {
+        for (k, v) in props {
+            let v = v
+                .as_str()
+                .ok_or_else(|| invalid_data(format!("property `{k}` is not a string")))?;
+            state.set_property(k.clone(), v);
+        }
+    }
+    Ok(state)
+}
+
+fn parse_noise_settings(data: &WorldgenData, json: &Value) -> io::Result<NoiseGeneratorSettings> {
+    let obj = object(json)?;
+    let d = NoiseGeneratorSettings::default();
+    let noise = object(field(obj, "noise")?)?;
+    let noise = NoiseSettings {
+        min_y: i32_field(noise, "min_y")?,
+        height: i32_field(noise, "height")?,
+        size_horizontal: i32_field(noise, "size_horizontal")?,
+        size_vertical: i32_field(noise, "size_vertical")?,
+    };
+
+    let mut resolver = Resolver::new(data);
+    let router = object(field(obj, "noise_router")?)?;
+    let mut f = |key: &str| match router.get(key) {
+        Some(v) => resolver.function(v),
+        None => Ok(DensityFunction::constant(0.0)),
+    };
+    let preliminary_surface_level = match router.get("preliminary_surface_level") {
+        Some(_) => f("preliminary_surface_level")?,
+        // Before 1.21.4 the router held the initial density instead, and
+        // the surface was its topmost cell above a fixed threshold.
+        None => Arc::new(DensityFunction::FindTopSurface {
+            density: DensityFunction::binary(
+                BinaryOp::Add,
+                f("initial_density_without_jaggedness")?,
+                DensityFunction::constant(-0.390625),
+            ),
+            upper_bound: DensityFunction::constant(f64::from(noise.min_y + noise.height)),
+            lower_bound: noise.min_y,
+            cell_height: noise.cell_height(),
+        }),
+    };
+    let noise_router = NoiseRouter {
+        barrier: f("barrier")?,
+        fluid_level_floodedness: f("fluid_level_floodedness")?,
+        fluid_level_spread: f("fluid_level_spread")?,
+        lava: f("lava")?,
+        temperature: f("temperature")?,
+        vegetation: f("vegetation")?,
+        continents: f("continents")?,
+        erosion: f("erosion")?,
+        depth: f("depth")?,
+        ridges: f("ridges")?,
+        preliminary_surface_level,
+        final_density: f("final_density")?,
+        vein_toggle: f("vein_toggle")?,
+        vein_ridged: f("vein_ridged")?,
+        vein_gap: f("vein_gap")?,
+    };
+
+    Ok(NoiseGeneratorSettings {
+        noise,
+        default_block: match obj.get("default_block") {
+            Some(v) => parse_block_state(v)?,
+            None => d.default_block,
+        },
+        default_fluid: match obj.get("default_fluid") {
+            Some(v) => parse_block_state(v)?,
+            None => d.default_fluid,
+        },
+        noise_router,
+        surface_rule: match obj.get("surface_rule") {
+            Some(v) => parse_surface_rule(data, v)
+                .map_err(|e| invalid_data(format!("surface rule: {e}")))?,
+            None => d.surface_rule,
+        },
+        sea_level: match obj.get("sea_level") {
+            Some(_) => i32_field(obj, "sea_level")?,
+            None => d.sea_level,
+        },
+        disable_mob_generation: opt_bool(obj, "disable_mob_generation", d.disable_mob_generation)?,
+        aquifers_enabled: opt_bool(obj, "aquifers_enabled", d.aquifers_enabled)?,
+        ore_veins_enabled: opt_bool(obj, "ore_veins_enabled", d.ore_veins_enabled)?,
+        legacy_random_source: opt_bool(obj, "legacy_random_source", d.legacy_random_source)?,
+    })
+}
+
+/// The `type` of a rule or condition, without the `minecraft:` namespace.
+fn type_name(obj: &Map<String, Value>) -> io::Result<String> {
+    let kind = field(obj, "type")?
+        .as_str()
+        .ok_or_else(|| invalid_data("`type` is not a string"))?;
+    let kind = namespaced(kind);
+    Ok(kind.strip_prefix("minecraft:").unwrap_or(&kind).to_string())
+}
+
+fn parse_surface_rule(data: &WorldgenData, value: &Value) -> io::Result<SurfaceRule> {
+    let obj = object(value)?;
+    Ok(match type_name(obj)?.as_str() {
+        "block" => SurfaceRule::Block(parse_block_state(field(obj, "result_state")?)?),
+        "sequence" => SurfaceRule::Sequence(
+            field(obj, "sequence")?
+                .as_array()
+                .ok_or_else(|| invalid_data("`sequence` is not a list"))?
+                .iter()
+                .map(|rule| parse_surface_rule(data, rule))
+                .collect::<io::Result<_>>()?,
+        ),
+        "condition" => SurfaceRule::Condition {
+            if_true: parse_surface_condition(data, field(obj, "if_true")?)?,
+            then_run: Box::new(parse_surface_rule(data, field(obj, "then_run")?)?),
+        },
+        "bandlands" => SurfaceRule::Bandlands,
+        other => {
+            return Err(invalid_data(format!("unknown surface rule type `{other}`")));
+        }
+    })
+}
+
+fn parse_surface_condition(data: &WorldgenData, value: &Value) -> io::Result<SurfaceCondition> {
+    let obj = object(value)?;
+    let opt_i32 = |key: &str| match obj.get(key) {
+        Some(_) => i32_field(obj, key),
+        None => Ok(0),
+    };
+    Ok(match type_name(obj)?.as_str() {
+        "biome" => SurfaceCondition::Biome(
+            field(obj, "biome_is")?
+                .as_array()
+                .and_then(|b| {
+                    b.iter()
+                        .map(|b| b.as_str().map(namespaced))
+                        .collect::<Option<Vec<_>>>()
+                })
+                .ok_or_else(|| invalid_data("`biome_is` is not a list of ids"))?,
+        ),
+        "noise_threshold" => {
+            let id = field(obj, "noise")?
+                .as_str()
+                .ok_or_else(|| invalid_data("inline noise parameters are not supported"))?;
+            let id = namespaced(id);
+            SurfaceCondition::NoiseThreshold {
+                noise: NoiseHolder::new(id.clone(), data.noise(&id)?),
+                min_threshold: f64_field(obj, "min_threshold")?,
+                max_threshold: f64_field(obj, "max_threshold")?,
+            }
+        }
+        "vertical_gradient" => SurfaceCondition::VerticalGradient {
+            random_name: namespaced(
+                field(obj, "random_name")?
+                    .as_str()
+                    .ok_or_else(|| invalid_data("`random_name` is not a string"))?,
+            ),
+            true_at_and_below: parse_anchor(field(obj, "true_at_and_below")?)?,
+            false_at_and_above: parse_anchor(field(obj, "false_at_and_above")?)?,
+        },
+        "y_above" => SurfaceCondition::YAbove {
+            anchor: parse_anchor(field(obj, "anchor")?)?,
+            surface_depth_multiplier: i32_field(obj, "surface_depth_multiplier")?,
+            add_stone_depth: opt_bool(obj, "add_stone_depth", false)?,
+        },
+        "water" => SurfaceCondition::Water {
+            offset: i32_field(obj, "offset")?,
+            surface_depth_multiplier: i32_field(obj, "surface_depth_multiplier")?,
+            add_stone_depth: opt_bool(obj, "add_stone_depth", false)?,
+        },
+        "stone_depth" => SurfaceCondition::StoneDepth {
+            offset: i32_field(obj, "offset")?,
+            add_surface_depth: opt_bool(obj, "add_surface_depth", false)?,
+            secondary_depth_range: opt_i32("secondary_depth_range")?,
+            surface_type: match field(obj, "surface_type")?.as_str() {
+                Some("floor") => CaveSurface::Floor,
+                Some("ceiling") => CaveSurface::Ceiling,
+                _ => return Err(invalid_data("`surface_type` is not floor or ceiling")),
+            },
+        },
+        "temperature" => SurfaceCondition::Temperature,
+        "steep" => SurfaceCondition::Steep,
+        "hole" => SurfaceCondition::Hole,
+        "above_preliminary_surface" => SurfaceCondition::AbovePreliminarySurface,
+        "not" => SurfaceCondition::Not(Box::new(parse_surface_condition(
+            data,
+            field(obj, "invert")?,
+        )?)),
+        other => {
+            return Err(invalid_data(format!(
+                "unknown surface condition type `{other}`"
+            )));
+        }
+    })
+}
+
+/// `{"absolute": 63}`, `{"above_bottom": 5}` or `{"below_top": 0}`.
+fn parse_anchor(value: &Value) -> io::Result<VerticalAnchor> {
+    let obj = object(value)?;
+    if obj.contains_key("absolute") {
+        Ok(VerticalAnchor::Absolute(i32_field(obj, "absolute")?))
+    } else if obj.contains_key("above_bottom") {
+        Ok(VerticalAnchor::AboveBottom(i32_field(obj, "above_bottom")?))
+    } else if obj.contains_key("below_top") {
+        Ok(VerticalAnchor::BelowTop(i32_field(obj, "below_top")?))
+    } else {
+        Err(invalid_data(
+            "vertical anchor is not absolute, above_bottom or below_top",
+        ))
+    }
+}
+
+/// Builds density functions from JSON, resolving each named function once
+/// so every reference to it shares the node.
+struct Resolver<'a> {
+    data: &'a WorldgenData,
+    functions: HashMap<String, DensityFn>,
+    /// Named functions being resolved, to report reference cycles.
+    resolving: Vec<String>,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(data: &'a WorldgenData) -> Self {
+        Self {
+            data,
+            functions: HashMap::new(),
+            resolving: Vec::new(),
+        }
+    }
+
+    fn function_ref(&mut self, id: &str) -> io::Result<DensityFn> {
+        let id = namespaced(id);
+        if let Some(f) = self.functions.get(&id) {
+            return Ok(f.clone());
+        }
+        if self.resolving.contains(&id) {
+            return Err(invalid_data(format!(
+                "density function `{id}` references itself"
+            )));
+        }
+        let json = self.data.get(DENSITY_FUNCTION, &id)?;
+        self.resolving.push(id.clone());
+        let f = self
+            .function(json)
+            .map_err(|e| invalid_data(format!("density function `{id}`: {e}")));
+        self.resolving.pop();
+        let f = f?;
+        self.functions.insert(id, f.clone());
+        Ok(f)
+    }
+
+    fn noise(&self, value: &Value) -> io::Result<NoiseHolder> {
+        let id = value
+            .as_str()
+            .ok_or_else(|| invalid_data("inline noise parameters are not supported"))?;
+        let id = namespaced(id);
+        Ok(NoiseHolder::new(id.clone(), self.data.noise(&id)?))
+    }
+
+    fn arg(&mut self, obj: &Map<String, Value>, key: &str) -> io::Result<DensityFn> {
+        self.function(field(obj, key)?)
+    }
+
+    /// A density function: a number, the id of a named function, or an
+    /// object with a `type`.
+    fn function(&mut self, value: &Value) -> io::Result<DensityFn> {
+        type F = DensityFunction;
+        if let Some(v) = value.as_f64() {
+            return Ok(F::constant(v));
+        }
+        if let Some(id) = value.as_str() {
+            return self.function_ref(id);
+        }
+        let obj = object(value)?;
+        let kind = field(obj, "type")?
+            .as_str()
+            .ok_or_else(|| invalid_data("`type` is not a string"))?;
+        let kind = namespaced(kind);
+        let name = kind.strip_prefix("minecraft:").unwrap_or(&kind);
+
+        if let Some(op) = match name {
+            "add" => Some(BinaryOp::Add),
+            "mul" => Some(BinaryOp::Mul),
+            "min" => Some(BinaryOp::Min),
+            "max" => Some(BinaryOp::Max),
+            _ => None,
+        } {
+            let a = self.arg(obj, "argument1")?;
+            return Ok(F::binary(op, a, self.arg(obj, "argument2")?));
+        }
+        if let Some(op) = match name {
+            "abs" => Some(MappedOp::Abs),
+            "square" => Some(MappedOp::Square),
+            "cube" => Some(MappedOp::Cube),
+            "half_negative" => Some(MappedOp::HalfNegative),
+            "quarter_negative" => Some(MappedOp::QuarterNegative),
+            "invert" => Some(MappedOp::Invert),
+            "squeeze" => Some(MappedOp::Squeeze),
+            _ => None,
+        } {
+            return Ok(F::mapped(op, self.arg(obj, "argument")?));
+        }
+        if let Some(m) = match name {
+            "interpolated" => Some(Marker::Interpolated),
+            "flat_cache" => Some(Marker::FlatCache),
+            "cache_2d" => Some(Marker::Cache2d),
+            "cache_once" => Some(Marker::CacheOnce),
+            "cache_all_in_cell" => Some(Marker::CacheAllInCell),
+            _ => None,
+        } {
+            return Ok(F::marker(m, self.arg(obj, "argument")?));
+        }
+
+        let node = match name {
+            "constant" => F::Constant(f64_field(obj, "argument")?),
+            "clamp" => F::Clamp {
+                input: self.arg(obj, "input")?,
+                min: f64_field(obj, "min")?,
+                max: f64_field(obj, "max")?,
+            },
+            "y_clamped_gradient" => F::YClampedGradient {
+                from_y: i32_field(obj, "from_y")?,
+                to_y: i32_field(obj, "to_y")?,
+                from_value: f64_field(obj, "from_value")?,
+                to_value: f64_field(obj, "to_value")?,
+            },
+            "noise" => F::Noise {
+                noise: self.noise(field(obj, "noise")?)?,
+                xz_scale: f64_field(obj, "xz_scale")?,
+                y_scale: f64_field(obj, "y_scale")?,
+            },
+            "shifted_noise" => F::ShiftedNoise {
+                shift_x: self.arg(obj, "shift_x")?,
+                shift_y: self.arg(obj, "shift_y")?,
+                shift_z: self.arg(obj, "shift_z")?,
+                xz_scale: f64_field(obj, "xz_scale")?,
+                y_scale: f64_field(obj, "y_scale")?,
+                noise: self.noise(field(obj, "noise")?)?,
+            },
+            "shift_a" => F::ShiftA(self.noise(field(obj, "argument")?)?),
+            "shift_b" => F::ShiftB(self.noise(field(obj, "argument")?)?),
+            "shift" => F::Shift(self.noise(field(obj, "argument")?)?),
+            "range_choice" => F::RangeChoice {
+                input: self.arg(obj, "input")?,
+                min_inclusive: f64_field(obj, "min_inclusive")?,
+                max_exclusive: f64_field(obj, "max_exclusive")?,
+                when_in_range: self.arg(obj, "when_in_range")?,
+                when_out_of_range: self.arg(obj, "when_out_of_range")?,
+            },
+            "spline" => F::Spline(self.spline(field(obj, "spline")?)?),
+            "blend_alpha" => F::BlendAlpha,
+            "blend_offset" => F::BlendOffset,
+            "blend_density" => F::BlendDensity(self.arg(obj, "argument")?),
+            "beardifier" => F::Beardifier,
+            "end_islands" => F::EndIslands(EndIslands::new(0)),
+            "weird_scaled_sampler" => F::WeirdScaledSampler {
+                input: self.arg(obj, "input")?,
+                noise: self.noise(field(obj, "noise")?)?,
+                rarity: match field(obj, "rarity_value_mapper")?.as_str() {
+                    Some("type_1") => RarityValueMapper::Type1,
+                    Some("type_2") => RarityValueMapper::Type2,
+                    _ => return Err(invalid_data("unknown `rarity_value_mapper`")),
+                },
+            },
+            "old_blended_noise" => F::OldBlendedNoise(BlendedNoise::unseeded(
+                f64_field(obj, "xz_scale")?,
+                f64_field(obj, "y_scale")?,
+                f64_field(obj, "xz_factor")?,
+                f64_field(obj, "y_factor")?,
+                f64_field(obj, "smear_scale_multiplier")?,
+            )),
+            "find_top_surface" => F::FindTopSurface {
+                density: self.arg(obj, "density")?,
+                upper_bound: self.arg(obj, "upper_bound")?,
+                lower_bound: i32_field(obj, "lower_bound")?,
+                cell_height: i32_field(obj, "cell_height")?,
+            },
+            _ => {
+                return Err(invalid_data(format!(
+                    "unknown density function type `{kind}`"
+                )));
+            }
+        };
+        Ok(Arc::new(node))
+    }
+
+    /// A spline: a number, or `{coordinate, points: [{location, value,
+    /// derivative}]}` with nested splines as values.
+    fn spline(&mut self, value: &Value) -> io::Result<Spline> {
+        if let Some(v) = value.as_f64() {
+            return Ok(Spline::Constant(v as f32));
+        }
+        let obj = object(value)?;
+        let coordinate = self.arg(obj, "coordinate")?;
+        let points = field(obj, "points")?
+            .as_array()
+            .filter(|p| !p.is_empty())
+            .ok_or_else(|| invalid_data("spline has no points"))?
+            .iter()
+            .map(|p| {
+                let p = object(p)?;
+                Ok(SplinePoint {
+                    location: f64_field(p, "location")? as f32,
+                    value: self.spline(field(p, "value")?)?,
+                    derivative: f64_field(p, "derivative")? as f32,
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        if points.windows(2).any(|w| w[0].location >= w[1].location) {
+            return Err(invalid_data("spline locations are not increasing"));
+        }
+        Ok(Spline::Multipoint { coordinate, points })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::biome_source::BiomeSource;
+    use crate::generator::NoiseBasedGenerator;
+    use std::fs;
+
+    fn write(root: &Path, path: &str, json: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, json).unwrap();
+    }
+
+    /// A tiny "vanilla" pack plus a world pack overriding one function.
+    fn packs(dir: &str) -> (PathBuf, PathBuf) {
+        let root = Path::new("target").join(dir);
+        let _ = fs::remove_dir_all(&root);
+        let vanilla = root.join("vanilla");
+        let world = root.join("world");
+        write(
+            &vanilla,
+            "data/minecraft/worldgen/noise/ridge.json",
+            r#"{ "firstOctave": -7, "amplitudes": [1.0, 2.0, 1.0, 0.0, 0.0, 0.0] }"#,
+        );
+        write(
+            &vanilla,
+            "data/minecraft/worldgen/density_function/test/base.json",
+            r#"{
+                "type": "minecraft:y_clamped_gradient",
+                "from_y": -64, "to_y": 320, "from_value": 1.0, "to_value": -1.0
+            }"#,
+        );
+        write(
+            &vanilla,
+            "data/minecraft/worldgen/density_function/test/offset.json",
+            "0.25",
+        );
+        write(
+            &vanilla,
+            "data/minecraft/worldgen/noise_settings/test.json",
+            r#"{
+                "sea_level": 32,
+                "disable_mob_generation": false,
+                "aquifers_enabled": false,
+                "ore_veins_enabled": false,
+                "legacy_random_source": false,
+                "default_block": { "Name": "minecraft:deepslate", "Properties": { "axis": "y" } },
+                "default_fluid": { "Name": "minecraft:water", "Properties": { "level": "0" } },
+                "noise": { "min_y": -64, "height": 384, "size_horizontal": 1, "size_vertical": 2 },
+                "noise_router": {
+                    "ridges": { "type": "flat_cache", "argument": {
+                        "type": "minecraft:noise", "noise": "ridge", "xz_scale": 0.25, "y_scale": 0.0
+                    } },
+                    "final_density": {
+                        "type": "minecraft:interpolated",
+                        "argument": {
+                            "type": "minecraft:add",
+                            "argument1": "minecraft:test/base",
+                            "argument2": {
+                                "type": "minecraft:spline",
+                                "spline": {
+                                    "coordinate": "minecraft:test/base",
+                                    "points": [
+                                        { "location": -1.0, "value": 0.0, "derivative": 0.0 },
+                                        { "location": 1.0, "value": 0.25, "derivative": 0.0 }
+                                    ]
+                                }
+                            }
+                        }
+                    },
+                    "initial_density_without_jaggedness": "test/base"
+                },
+                "spawn_target": [],
+                "surface_rule": { "type": "minecraft:sequence", "sequence": [
+                    {
+                        "type": "minecraft:condition",
+                        "if_true": {
+                            "type": "minecraft:y_above", "anchor": { "absolute": 120 },
+                            "surface_depth_multiplier": 0, "add_stone_depth": false
+                        },
+                        "then_run": { "type": "block", "result_state": { "Name": "tuff" } }
+                    }
+                ] }
+            }"#,
+        );
+        write(
+            &world,
+            "datapacks/tall/data/minecraft/worldgen/density_function/test/offset.json",
+            "0.5",
+        );
+        (vanilla, world)
+    }
+
+    #[test]
+    fn loads_enabled_packs_in_order() {
+        let (vanilla, world) = packs("datapack_order");
+        let data = WorldgenData::load(&DataPacks::default(), &vanilla, &world).unwrap();
+        assert_eq!(data.noise_settings_ids(), ["minecraft:test"]);
+        assert_eq!(
+            data.noise("minecraft:ridge").unwrap(),
+            NoiseParameters::new(-7, vec![1.0, 2.0, 1.0, 0.0, 0.0, 0.0])
+        );
+        let offset = data.density_function("test/offset").unwrap();
+        assert_eq!(offset.compute(0, 0, 0), 0.25);
+
+        let packs = DataPacks {
+            enabled: vec!["vanilla".into(), "bundle".into(), "file/tall".into()],
+            ..Default::default()
+        };
+        let data = WorldgenData::load(&packs, &vanilla, &world).unwrap();
+        let offset = data.density_function("minecraft:test/offset").unwrap();
+        assert_eq!(offset.compute(0, 0, 0), 0.5);
+
+        let missing = DataPacks {
+            enabled: vec!["vanilla".into(), "file/nope".into()],
+            ..Default::default()
+        };
+        assert!(WorldgenData::load(&missing, &vanilla, &world).is_err());
+    }
+
+    #[test]
+    fn builds_a_generator_from_noise_settings() {
+        let (vanilla, world) = packs("datapack_settings");
+        let data = WorldgenData::load(&DataPacks::default(), &vanilla, &world).unwrap();
+        let settings = data.noise_settings("test").unwrap();
+        assert_eq!(settings.sea_level, 32);
+        assert!(!settings.aquifers_enabled);
+        assert_eq!(
+            settings.default_block,
+            BlockState::new("minecraft:deepslate").with("axis", "y")
+        );
+        assert_eq!(settings.noise, NoiseSettings::OVERWORLD);
+
+        // Both references to `test/base` are the same node.
+        let DensityFunction::Marker(_, sum) = &*settings.noise_router.final_density else {
+            panic!("not interpolated");
+        };
+        let DensityFunction::Binary(_, base, spline) = &**sum else {
+            panic!("not an add");
+        };
+        let DensityFunction::Spline(Spline::Multipoint { coordinate, .. }) = &**spline else {
+            panic!("not a spline");
+        };
+        assert!(Arc::ptr_eq(base, coordinate));
+        // The gradient is 0 at y = 128 and the spline halfway to 0.25.
+        assert_eq!(
+            settings.noise_router.final_density.compute(0, 128, 0),
+            0.125
+        );
+        // Converted from the old initial density: above 0.390625 up to y = 52.
+        assert_eq!(
+            settings
+                .noise_router
+                .preliminary_surface_level
+                .compute(0, 0, 0),
+            48.0
+        );
+
+        let biomes = BiomeSource::Fixed("minecraft:plains".into());
+        let generator = NoiseBasedGenerator::new(settings, biomes, 1234);
+        assert_ne!(generator.random_state.router.ridges.compute(5, 0, 5), 0.0);
+        let chunk = generator.generate_chunk(0, 0);
+        assert_eq!(chunk.get_block(3, 100, 3).name, "minecraft:deepslate");
+        assert_eq!(chunk.get_block(3, 125, 3).name, "minecraft:tuff");
+        assert!(chunk.get_block(3, 250, 3).is_air());
+    }
+
+    #[test]
+    fn reports_bad_functions() {
+        let mut data = WorldgenData::default();
+        let bad = serde_json::json!({ "type": "minecraft:nope" });
+        data.insert(DENSITY_FUNCTION, "minecraft:bad".into(), bad);
+        let cycle = serde_json::json!({ "type": "abs", "argument": "minecraft:cycle" });
+        data.insert(DENSITY_FUNCTION, "minecraft:cycle".into(), cycle);
+        let err = data.density_function("bad").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "density function `minecraft:bad`: unknown density function type `minecraft:nope`"
+        );
+        assert!(data.density_function("cycle").is_err());
+        assert!(data.density_function("missing").is_err());
+    }
+}
@@ -0,0 +1,366 @@
+//! The decoration stage: the placed features of the biomes in and around a
+//! chunk, run step by step in one order shared by every biome, each seeded
+//! from the chunk position and its index in that order.
+
+use crate::feature::WorldGenRegion;
+use crate::nbt_util::invalid_data;
+use crate::placement::PlacedFeature;
+use crate::random::{RandomAlgorithm, WorldgenRandom};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io;
+
+/// The steps of decoration, in the order they run. Vanilla's
+/// `GenerationStep.Decoration`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Decoration {
+    RawGeneration,
+    Lakes,
+    LocalModifications,
+    UndergroundStructures,
+    SurfaceStructures,
+    Strongholds,
+    UndergroundOres,
+    UndergroundDecoration,
+    FluidSprings,
+    VegetalDecoration,
+    TopLayerModification,
+}
+
+impl Decoration {
+    pub const ALL: [Decoration; 11] = [
+        Decoration::RawGeneration,
+        Decoration::Lakes,
+        Decoration::LocalModifications,
+        Decoration::UndergroundStructures,
+        Decoration::SurfaceStructures,
+        Decoration::Strongholds,
+        Decoration::UndergroundOres,
+        Decoration::UndergroundDecoration,
+        Decoration::FluidSprings,
+        Decoration::VegetalDecoration,
+        Decoration::TopLayerModification,
+    ];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// The placed features of a biome, one list per [`Decoration`] step.
+pub type BiomeFeatures = Vec<Vec<PlacedFeature>>;
+
+/// A biome's features under `step`, growing its step lists as needed.
+pub fn step_features(features: &mut BiomeFeatures, step: Decoration) -> &mut Vec<PlacedFeature> {
+    if features.len() <= step.index() {
+        features.resize_with(step.index() + 1, Vec::new);
+    }
+    &mut features[step.index()]
+}
+
+struct StepFeatures<'a> {
+    features: Vec<&'a PlacedFeature>,
+    /// For each feature, the biomes listing it, for the `biome` filter.
+    biomes: Vec<HashSet<String>>,
+}
+
+/// The features of every biome merged into one order per step, keeping
+/// the order within each biome's lists. Vanilla's `FeatureSorter`.
+pub struct FeaturesPerStep<'a> {
+    steps: Vec<StepFeatures<'a>>,
+    /// Each biome's features, as indices into their step's order.
+    biome_indices: HashMap<String, Vec<Vec<usize>>>,
+}
+
+impl<'a> FeaturesPerStep<'a> {
+    /// Merge the features of `biomes`, in the biome source's order. Fails
+    /// if two biomes list features in contradicting orders.
+    pub fn new(biomes: &[(&str, &'a BiomeFeatures)]) -> io::Result<Self> {
+        // Features are told apart by value, and numbered by first use.
+        let mut ids: Vec<&'a PlacedFeature> = Vec::new();
+        let mut id_of = |feature: &'a PlacedFeature| match ids.iter().position(|f| *f == feature) {
+            Some(id) => id,
+            None => {
+                ids.push(feature);
+                ids.len() - 1
+            }
+        };
+        // Each biome's features in order, as (step, id), each pointing at
+        // the next one.
+        let mut edges: BTreeMap<(usize, usize), BTreeSet<(usize, usize)>> = BTreeMap::new();
+        let mut step_count = 0;
+        for (_, steps) in biomes {
+            step_count = step_count.max(steps.len());
+            let mut nodes = Vec::new();
+            for (step, features) in steps.iter().enumerate() {
+                nodes.extend(features.iter().map(|feature| (step, id_of(feature))));
+            }
+            for (i, node) in nodes.iter().enumerate() {
+                let next = edges.entry(*node).or_default();
+                if let Some(following) = nodes.get(i + 1) {
+                    next.insert(*following);
+                }
+            }
+        }
+
+        let mut visited = HashSet::new();
+        let mut in_progress = HashSet::new();
+        let mut order = Vec::new();
+        for node in edges.keys() {
+            if visited.contains(node) {
+                continue;
+            }
+            if depth_first_search(&edges, &mut visited, &mut in_progress, &mut order, *node) {
+                return Err(invalid_data(
+                    "feature order cycle: biomes list features in contradicting orders",
+                ));
+            }
+        }
+        order.reverse();
+
+        let steps: Vec<StepFeatures> = (0..step_count)
+            .map(|step| {
+                let features: Vec<&PlacedFeature> = order
+                    .iter()
+                    .filter(|(s, _)| *s == step)
+                    .map(|(_, id)| ids[*id])
+                    .collect();
+                let biomes = features
+                    .iter()
+                    .map(|feature| {
+                        biomes
+                            .iter()
+                            .filter(|(_, steps)| steps.iter().flatten().any(|f| f == *feature))
+                            .map(|(biome, _)| biome.to_string())
+                            .collect()
+                    })
+                    .collect();
+                StepFeatures { features, biomes }
+            })
+            .collect();
+        let biome_indices = biomes
+            .iter()
+            .map(|(biome, biome_steps)| {
+                let indices = biome_steps
+                    .iter()
+                    .zip(&steps)
+                    .map(|(features, step)| {
+                        features
+                            .iter()
+                            .filter_map(|f| step.features.iter().position(|g| *g == f))
+                            .collect()
+                    })
+                    .collect();
+                (biome.to_string(), indices)
+            })
+            .collect();
+        Ok(Self {
+            steps,
+            biome_indices,
+        })
+    }
+
+    /// Decorate chunk `chunk_x`/`chunk_z` of `level`, with the features of
+    /// the biomes in it and its neighbours.
+    pub fn apply_biome_decoration(
+        &self,
+        level: &mut WorldGenRegion,
+        level_seed: i64,
+        chunk_x: i32,
+        chunk_z: i32,
+    ) {
+        let mut present = HashSet::new();
+        for dx in -1..=1 {
+            for dz in -1..=1 {
+                let Some(chunk) = level.chunk(chunk_x + dx, chunk_z + dz) else {
+                    continue;
+                };
+                for section in chunk.sections() {
+                    present.extend(section.biomes().palette().iter().cloned());
+                }
+            }
+        }
+        let origin = [chunk_x << 4, level.min_y(), chunk_z << 4];
+        let mut random = WorldgenRandom::new(RandomAlgorithm::Xoroshiro, 0);
+        let decoration_seed = random.set_decoration_seed(level_seed, origin[0], origin[2]);
+        for (step, features) in self.steps.iter().enumerate() {
+            let indices: BTreeSet<usize> = present
+                .iter()
+                .filter_map(|biome: &String| self.biome_indices.get(biome))
+                .filter_map(|steps| steps.get(step))
+                .flatten()
+                .copied()
+                .collect();
+            for index in indices {
+                random.set_feature_seed(decoration_seed, index as i32, step as i32);
+                features.features[index].place_with_biome_check(
+                    level,
+                    &features.biomes[index],
+                    &mut random,
+                    origin,
+                );
+            }
+        }
+    }
+}
+
+/// Add the nodes reachable from `node` to `order`, each after the nodes it
+/// points at. Returns whether it ran into a cycle. Vanilla's
+/// `Graph.depthFirstSearch`.
+fn depth_first_search(
+    edges: &BTreeMap<(usize, usize), BTreeSet<(usize, usize)>>,
+    visited: &mut HashSet<(usize, usize)>,
+    in_progress: &mut HashSet<(usize, usize)>,
+    order: &mut Vec<(usize, usize)>,
+    node: (usize, usize),
+) -> bool {
+    if visited.contains(&node) {
+        return false;
+    }
+    if !in_progress.insert(node) {
+        return true;
+    }
+    for next in edges.get(&node).into_iter().flatten() {
+        if depth_first_search(edges, visited, in_progress, order, *next) {
+            return true;
+        }
+    }
+    in_progress.remove(&node);
+    visited.insert(node);
+    order.push(node);
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::biome_source::BiomeSource;
+    use crate::chunk::{BlockState, Chunk};
+    use crate::density::DensityFunction;
+    use crate::feature::ConfiguredFeature;
+    use crate::generator::{NoiseBasedGenerator, NoiseGeneratorSettings};
+    use crate::noise_router::NoiseRouter;
+    use crate::placement::PlacementModifier;
+    use crate::provider::IntProvider;
+    use crate::surface::{CaveSurface, SurfaceCondition, SurfaceRule};
+    use crate::test::worldgen::{find_blocks, region};
+    use crate::tree::TreeConfiguration;
+
+    fn vegetation(features: Vec<PlacedFeature>) -> BiomeFeatures {
+        let mut steps = BiomeFeatures::new();
+        *step_features(&mut steps, Decoration::VegetalDecoration) = features;
+        steps
+    }
+
+    fn oaks(count: i32) -> PlacedFeature {
+        PlacedFeature::trees(
+            TreeConfiguration::oak(),
+            IntProvider::Constant(count),
+            "minecraft:oak_sapling",
+        )
+    }
+
+    fn logs(chunks: &[Chunk]) -> Vec<[i32; 3]> {
+        find_blocks(chunks, |_, state| state.name == "minecraft:oak_log")
+            .into_iter()
+            .map(|(pos, _)| pos)
+            .collect()
+    }
+
+    #[test]
+    fn merges_the_feature_orders_of_biomes() {
+        let [a, b, c] = [2, 3, 4].map(|chance| PlacedFeature {
+            feature: ConfiguredFeature::Kelp,
+            placement: vec![PlacementModifier::RarityFilter(chance)],
+        });
+        let first = vegetation(vec![a.clone(), c.clone()]);
+        let second = vegetation(vec![b.clone(), c.clone()]);
+        let features = FeaturesPerStep::new(&[("first", &first), ("second", &second)]).unwrap();
+        let step = &features.steps[Decoration::VegetalDecoration.index()];
+        assert_eq!(step.features, [&b, &a, &c]);
+        assert_eq!(features.biome_indices["first"][9], [1, 2]);
+        let both: HashSet<String> = ["first".into(), "second".into()].into();
+        assert_eq!(step.biomes[2], both);
+        assert!(features.steps[0].features.is_empty());
+
+        let reversed = vegetation(vec![c, a]);
+        assert!(FeaturesPerStep::new(&[("first", &first), ("reversed", &reversed)]).is_err());
+    }
+
+    #[test]
+    fn features_stay_in_biomes_listing_them() {
+        // Plains in the west half of chunk 0/0, desert everywhere else.
+        let chunks = region(-16, 64, |chunk| {
+            for section in chunk.sections_mut() {
+                section.fill_biome("minecraft:desert");
+            }
+            let origin = chunk.x == 0 && chunk.z == 0;
+            for x in 0..16 {
+                for z in 0..16 {
+                    chunk.set_block(x, 0, z, BlockState::new("minecraft:grass_block"));
+                    if origin && x < 8 {
+                        for y in (-16..64).step_by(4) {
+                            chunk.set_biome(x, y, z, "minecraft:plains");
+                        }
+                    }
+                }
+            }
+        });
+        let plains = vegetation(vec![oaks(12)]);
+        let desert = BiomeFeatures::new();
+        let features =
+            FeaturesPerStep::new(&[("minecraft:plains", &plains), ("minecraft:desert", &desert)])
+                .unwrap();
+        let decorated = |mut chunks: Vec<Chunk>, chunk_x| {
+            let mut level = WorldGenRegion::new(&mut chunks);
+            features.apply_biome_decoration(&mut level, 8, chunk_x, 0);
+            logs(&chunks)
+        };
+
+        let found = decorated(chunks.clone(), 0);
+        let trunks: Vec<_> = found.iter().filter(|[_, y, _]| *y == 1).collect();
+        assert!(trunks.len() > 3, "{}", trunks.len());
+        assert!(
+            trunks
+                .iter()
+                .all(|[x, _, z]| (0..8).contains(x) && (0..16).contains(z))
+        );
+        assert_eq!(decorated(chunks.clone(), 0), found);
+        // Chunks with no plains around them get no trees.
+        assert!(decorated(chunks, 2).is_empty());
+    }
+
+    #[test]
+    fn generates_decorated_areas() {
+        let grass = SurfaceRule::Condition {
+            if_true: SurfaceCondition::StoneDepth {
+                offset: 0,
+                add_surface_depth: false,
+                secondary_depth_range: 0,
+                surface_type: CaveSurface::Floor,
+            },
+            then_run: Box::new(SurfaceRule::Block(BlockState::new("minecraft:grass_block"))),
+        };
+        let settings = NoiseGeneratorSettings {
+            noise_router: NoiseRouter {
+                final_density: DensityFunction::y_clamped_gradient(0, 140, 1.0, -1.0),
+                ..Default::default()
+            },
+            surface_rule: grass,
+            sea_level: -64,
+            aquifers_enabled: false,
+            ..Default::default()
+        };
+        let biomes = BiomeSource::Fixed("minecraft:plains".into());
+        let mut generator = NoiseBasedGenerator::new(settings, biomes, 3);
+        generator
+            .features
+            .insert("minecraft:plains".into(), vegetation(vec![oaks(4)]));
+
+        let bare = generator.generate_area((0, 0), (0, 0), false).unwrap();
+        assert_eq!(bare.len(), 1);
+        assert!(logs(&bare).is_empty());
+        let decorated = generator.generate_area((0, 0), (0, 0), true).unwrap();
+        assert_eq!((decorated[0].x, decorated[0].z), (0, 0));
+        assert!(!logs(&decorated).is_empty());
+    }
+}
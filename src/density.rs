@@ -0,0 +1,1054 @@
+//! Density functions: the expression trees of `worldgen/density_function`
+//! and the noise router that describe terrain as data. A function maps a
+//! block position to a number; for `final_density`, positive means solid.
+//!
+//! Trees are built from [`DensityFn`] handles so that a function referenced
+//! from several places is one shared node, as in vanilla. Noises start out
+//! unseeded (sampling as zero) and get their generators when the tree is
+//! mapped through a seeding [`DensityVisitor`].
+
+use crate::noise::{NoiseParameters, NormalNoise, PerlinNoise, SimplexNoise, lerp};
+use crate::random::{LegacyRandom, RandomSource, XoroshiroRandomSource};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Shared handle to a node of a density function tree.
+pub type DensityFn = Arc<DensityFunction>;
+
+/// A named noise referenced by a density function, with its generator once
+/// the tree has been seeded.
+#[derive(Clone)]
+pub struct NoiseHolder {
+    pub key: String,
+    pub parameters: NoiseParameters,
+    noise: Option<Arc<NormalNoise>>,
+}
+
+impl NoiseHolder {
+    pub fn new(key: impl Into<String>, parameters: NoiseParameters) -> Self {
+        Self {
+            key: key.into(),
+            parameters,
+            noise: None,
+        }
+    }
+
+    /// The same holder backed by a seeded generator.
+    pub fn with_noise(&self, noise: Arc<NormalNoise>) -> Self {
+        Self {
+            noise: Some(noise),
+            ..self.clone()
+        }
+    }
+
+    pub fn is_seeded(&self) -> bool {
+        self.noise.is_some()
+    }
+
+    /// Sample the noise; unseeded holders give zero like vanilla.
+    pub fn get_value(&self, x: f64, y: f64, z: f64) -> f64 {
+        self.noise.as_ref().map_or(0.0, |n| n.get_value(x, y, z))
+    }
+}
+
+impl fmt::Debug for NoiseHolder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NoiseHolder")
+            .field("key", &self.key)
+            .field("seeded", &self.is_seeded())
+            .finish()
+    }
+}
+
+/// The pre-1.18 terrain noise (`old_blended_noise`): a main noise choosing
+/// between two limit noises, all legacy-seeded [`PerlinNoise`]s.
+#[derive(Clone)]
+pub struct BlendedNoise {
+    pub xz_scale: f64,
+    pub y_scale: f64,
+    pub xz_factor: f64,
+    pub y_factor: f64,
+    pub smear_scale_multiplier: f64,
+    min_limit: Arc<PerlinNoise>,
+    max_limit: Arc<PerlinNoise>,
+    main: Arc<PerlinNoise>,
+}
+
+impl BlendedNoise {
+    pub fn new(
+        random: &mut dyn RandomSource,
+        xz_scale: f64,
+        y_scale: f64,
+        xz_factor: f64,
+        y_factor: f64,
+        smear_scale_multiplier: f64,
+    ) -> Self {
+        Self {
+            xz_scale,
+            y_scale,
+            xz_factor,
+            y_factor,
+            smear_scale_multiplier,
+            min_limit: Arc::new(PerlinNoise::create_legacy_for_blended_noise(
+                random,
+                -15..=0,
+            )),
+            max_limit: Arc::new(PerlinNoise::create_legacy_for_blended_noise(
+                random,
+                -15..=0,
+            )),
+            main: Arc::new(PerlinNoise::create_legacy_for_blended_noise(random, -7..=0)),
+        }
+    }
+
+    /// As read from data: seeded from a zero source until the tree is seeded.
+    pub fn unseeded(
+        xz_scale: f64,
+        y_scale: f64,
+        xz_factor: f64,
+        y_factor: f64,
+        smear_scale_multiplier: f64,
+    ) -> Self {
+        let mut random = XoroshiroRandomSource::new(0);
+        Self::new(
+            &mut random,
+            xz_scale,
+            y_scale,
+            xz_factor,
+            y_factor,
+            smear_scale_multiplier,
+        )
+    }
+
+    /// The same parameters with noises drawn from `random`.
+    pub fn with_random(&self, random: &mut dyn RandomSource) -> Self {
+        Self::new(
+            random,
+            self.xz_scale,
+            self.y_scale,
+            self.xz_factor,
+            self.y_factor,
+            self.smear_scale_multiplier,
+        )
+    }
+
+    fn compute(&self, x: i32, y: i32, z: i32) -> f64 {
+        use crate::noise::wrap;
+
+        let xz_multiplier = 684.412 * self.xz_scale;
+        let y_multiplier = 684.412 * self.y_scale;
+        let d = f64::from(x) * xz_multiplier;
+        let e = f64::from(y) * y_multiplier;
+        let f = f64::from(z) * xz_multiplier;
+        let g = d / self.xz_factor;
+        let h = e / self.y_factor;
+        let i = f / self.xz_factor;
+        let smear = y_multiplier * self.smear_scale_multiplier;
+        let k = smear / self.y_factor;
+
+        let mut main = 0.0;
+        let mut o = 1.0;
+        for p in 0..8 {
+            if let Some(noise) = self.main.get_octave_noise(p) {
+                main += noise.noise_scaled(wrap(g * o), wrap(h * o), wrap(i * o), k * o, h * o) / o;
+            }
+            o /= 2.0;
+        }
+
+        let q = (main / 10.0 + 1.0) / 2.0;
+        let (only_max, only_min) = (q >= 1.0, q <= 0.0);
+        let (mut min, mut max) = (0.0, 0.0);
+        o = 1.0;
+        for r in 0..16 {
+            let (s, t, u) = (wrap(d * o), wrap(e * o), wrap(f * o));
+            let v = smear * o;
+            if !only_max && let Some(noise) = self.min_limit.get_octave_noise(r) {
+                min += noise.noise_scaled(s, t, u, v, e * o) / o;
+            }
+            if !only_min && let Some(noise) = self.max_limit.get_octave_noise(r) {
+                max += noise.noise_scaled(s, t, u, v, e * o) / o;
+            }
+            o /= 2.0;
+        }
+        clamped_lerp(min / 512.0, max / 512.0, q) / 128.0
+    }
+}
+
+impl fmt::Debug for BlendedNoise {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlendedNoise")
+            .field("xz_scale", &self.xz_scale)
+            .field("y_scale", &self.y_scale)
+            .field("xz_factor", &self.xz_factor)
+            .field("y_factor", &self.y_factor)
+            .field("smear_scale_multiplier", &self.smear_scale_multiplier)
+            .finish()
+    }
+}
+
+/// The End's island shape: a central island plus scattered small ones
+/// placed by a simplex noise.
+#[derive(Clone)]
+pub struct EndIslands {
+    noise: Arc<SimplexNoise>,
+}
+
+impl EndIslands {
+    pub fn new(seed: i64) -> Self {
+        let mut random = LegacyRandom::new(seed);
+        random.consume_count(17292);
+        Self {
+            noise: Arc::new(SimplexNoise::new(&mut random)),
+        }
+    }
+
+    fn height_value(&self, x: i32, z: i32) -> f32 {
+        let (k, l) = (x / 2, z / 2);
+        let (m, n) = (x % 2, z % 2);
+        let mut value = (100.0
+            - (x.wrapping_mul(x).wrapping_add(z.wrapping_mul(z)) as f32).sqrt() * 8.0)
+            .clamp(-100.0, 80.0);
+        for o in -12..=12 {
+            for p in -12..=12 {
+                let q = i64::from(k + o);
+                let r = i64::from(l + p);
+                if q * q + r * r > 4096 && self.noise.get_value(q as f64, r as f64) < -0.9f32 as f64
+                {
+                    let g = ((q as f32).abs() * 3439.0 + (r as f32).abs() * 147.0) % 13.0 + 9.0;
+                    let h = (m - o * 2) as f32;
+                    let s = (n - p * 2) as f32;
+                    let t = (100.0 - (h * h + s * s).sqrt() * g).clamp(-100.0, 80.0);
+                    value = value.max(t);
+                }
+            }
+        }
+        value
+    }
+}
+
+impl fmt::Debug for EndIslands {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EndIslands")
+    }
+}
+
+/// `add`, `mul`, `min` and `max`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Mul,
+    Min,
+    Max,
+}
+
+/// Single-argument functions applied to their input's value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MappedOp {
+    Abs,
+    Square,
+    Cube,
+    HalfNegative,
+    QuarterNegative,
+    Invert,
+    Squeeze,
+}
+
+impl MappedOp {
+    fn apply(self, d: f64) -> f64 {
+        match self {
+            Self::Abs => d.abs(),
+            Self::Square => d * d,
+            Self::Cube => d * d * d,
+            Self::HalfNegative if d > 0.0 => d,
+            Self::HalfNegative => d * 0.5,
+            Self::QuarterNegative if d > 0.0 => d,
+            Self::QuarterNegative => d * 0.25,
+            Self::Invert => 1.0 / d,
+            Self::Squeeze => {
+                let e = d.clamp(-1.0, 1.0);
+                e / 2.0 - e * e * e / 24.0
+            }
+        }
+    }
+}
+
+/// Caching hints. They never change a function's value, except that
+/// `flat_cache` samples at the corner of the 4×4 column at y = 0 and
+/// `interpolated` is smoothed over noise cells when evaluated with a
+/// [`NoiseCache`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Marker {
+    Interpolated,
+    FlatCache,
+    Cache2d,
+    CacheOnce,
+    CacheAllInCell,
+}
+
+/// How `weird_scaled_sampler` turns its input into a sampling scale.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RarityValueMapper {
+    /// `type_1`: 3D spaghetti caves.
+    Type1,
+    /// `type_2`: 2D spaghetti caves.
+    Type2,
+}
+
+impl RarityValueMapper {
+    fn map(self, d: f64) -> f64 {
+        match self {
+            Self::Type1 if d < -0.5 => 0.75,
+            Self::Type1 if d < 0.0 => 1.0,
+            Self::Type1 if d < 0.5 => 1.5,
+            Self::Type1 => 2.0,
+            Self::Type2 if d < -0.75 => 0.5,
+            Self::Type2 if d < -0.5 => 0.75,
+            Self::Type2 if d < 0.5 => 1.0,
+            Self::Type2 if d < 0.75 => 2.0,
+            Self::Type2 => 3.0,
+        }
+    }
+}
+
+/// A cubic spline over the value of a density function. Values are
+/// `f32`, as in vanilla.
+#[derive(Clone, Debug)]
+pub enum Spline {
+    Constant(f32),
+    Multipoint {
+        coordinate: DensityFn,
+        points: Vec<SplinePoint>,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct SplinePoint {
+    pub location: f32,
+    pub value: Spline,
+    pub derivative: f32,
+}
+
+impl Spline {
+    fn apply(&self, x: i32, y: i32, z: i32, cache: &mut Option<&mut NoiseCache>) -> f32 {
+        let (coordinate, points) = match self {
+            Self::Constant(c) => return *c,
+            Self::Multipoint { coordinate, points } => (coordinate, points),
+        };
+        let f = coordinate.eval(x, y, z, cache) as f32;
+        let last = points.len() - 1;
+        // Index of the last location not above `f`, or -1.
+        let i = points.partition_point(|p| f >= p.location) as isize - 1;
+        let extend = |point: &SplinePoint, value: f32| {
+            if point.derivative == 0.0 {
+                value
+            } else {
+                value + point.derivative * (f - point.location)
+            }
+        };
+        if i < 0 {
+            let v = points[0].value.apply(x, y, z, cache);
+            return extend(&points[0], v);
+        }
+        let i = i as usize;
+        if i == last {
+            let v = points[last].value.apply(x, y, z, cache);
+            return extend(&points[last], v);
+        }
+        let (a, b) = (&points[i], &points[i + 1]);
+        let (g, h) = (a.location, b.location);
+        let k = (f - g) / (h - g);
+        let n = a.value.apply(x, y, z, cache);
+        let o = b.value.apply(x, y, z, cache);
+        let p = a.derivative * (h - g) - (o - n);
+        let q = -b.derivative * (h - g) + (o - n);
+        lerp_f32(k, n, o) + k * (1.0 - k) * lerp_f32(k, p, q)
+    }
+
+    fn map(&self, visitor: &mut dyn DensityVisitor, memo: &mut Memo) -> Self {
+        match self {
+            Self::Constant(c) => Self::Constant(*c),
+            Self::Multipoint { coordinate, points } => Self::Multipoint {
+                coordinate: coordinate.map_with(visitor, memo),
+                points: points
+                    .iter()
+                    .map(|p| SplinePoint {
+                        location: p.location,
+                        value: p.value.map(visitor, memo),
+                        derivative: p.derivative,
+                    })
+                    .collect(),
+            },
+        }
+    }
+}
+
+fn lerp_f32(t: f32, a: f32, b: f32) -> f32 {
+    a + t * (b - a)
+}
+
+/// `Mth.clampedLerp`.
+fn clamped_lerp(a: f64, b: f64, t: f64) -> f64 {
+    if t < 0.0 {
+        a
+    } else if t > 1.0 {
+        b
+    } else {
+        lerp(t, a, b)
+    }
+}
+
+/// One node of a density function tree; the variants mirror the
+/// `minecraft:*` types of `worldgen/density_function`.
+#[derive(Clone, Debug)]
+pub enum DensityFunction {
+    Constant(f64),
+    Binary(BinaryOp, DensityFn, DensityFn),
+    Mapped(MappedOp, DensityFn),
+    Clamp {
+        input: DensityFn,
+        min: f64,
+        max: f64,
+    },
+    /// Linear from `from_value` at `from_y` to `to_value` at `to_y`,
+    /// constant beyond.
+    YClampedGradient {
+        from_y: i32,
+        to_y: i32,
+        from_value: f64,
+        to_value: f64,
+    },
+    Noise {
+        noise: NoiseHolder,
+        xz_scale: f64,
+        y_scale: f64,
+    },
+    ShiftedNoise {
+        shift_x: DensityFn,
+        shift_y: DensityFn,
+        shift_z: DensityFn,
+        xz_scale: f64,
+        y_scale: f64,
+        noise: NoiseHolder,
+    },
+    /// Offset noise sampled at `(x, 0, z)`.
+    ShiftA(NoiseHolder),
+    /// Offset noise sampled at `(z, x, 0)`.
+    ShiftB(NoiseHolder),
+    Shift(NoiseHolder),
+    RangeChoice {
+        input: DensityFn,
+        min_inclusive: f64,
+        max_exclusive: f64,
+        when_in_range: DensityFn,
+        when_out_of_range: DensityFn,
+    },
+    Spline(Spline),
+    Marker(Marker, DensityFn),
+    /// Weight of old-chunk blending; 1 as this server never blends.
+    BlendAlpha,
+    /// Offset of old-chunk blending; 0 here.
+    BlendOffset,
+    BlendDensity(DensityFn),
+    /// Structure terrain adaptation; 0 until structures exist.
+    Beardifier,
+    EndIslands(EndIslands),
+    WeirdScaledSampler {
+        input: DensityFn,
+        noise: NoiseHolder,
+        rarity: RarityValueMapper,
+    },
+    OldBlendedNoise(BlendedNoise),
+    /// Highest multiple of `cell_height` at or below `upper_bound` where
+    /// `density` is positive, scanning down to `lower_bound`.
+    FindTopSurface {
+        density: DensityFn,
+        upper_bound: DensityFn,
+        lower_bound: i32,
+        cell_height: i32,
+    },
+}
+
+/// Rewrites applied when mapping a tree, most importantly seeding noises.
+pub trait DensityVisitor {
+    fn visit_noise(&mut self, noise: &NoiseHolder) -> NoiseHolder {
+        noise.clone()
+    }
+
+    /// Called on every node after its children have been mapped.
+    fn apply(&mut self, function: DensityFunction) -> DensityFunction {
+        function
+    }
+}
+
+/// Already mapped nodes, keyed by address, so shared nodes stay shared.
+pub type Memo = HashMap<usize, DensityFn>;
+
+impl DensityFunction {
+    pub fn constant(value: f64) -> DensityFn {
+        Arc::new(Self::Constant(value))
+    }
+
+    pub fn binary(op: BinaryOp, a: DensityFn, b: DensityFn) -> DensityFn {
+        Arc::new(Self::Binary(op, a, b))
+    }
+
+    pub fn mapped(op: MappedOp, input: DensityFn) -> DensityFn {
+        Arc::new(Self::Mapped(op, input))
+    }
+
+    pub fn clamp(input: DensityFn, min: f64, max: f64) -> DensityFn {
+        Arc::new(Self::Clamp { input, min, max })
+    }
+
+    pub fn y_clamped_gradient(from_y: i32, to_y: i32, from_value: f64, to_value: f64) -> DensityFn {
+        Arc::new(Self::YClampedGradient {
+            from_y,
+            to_y,
+            from_value,
+            to_value,
+        })
+    }
+
+    pub fn noise(noise: NoiseHolder, xz_scale: f64, y_scale: f64) -> DensityFn {
+        Arc::new(Self::Noise {
+            noise,
+            xz_scale,
+            y_scale,
+        })
+    }
+
+    pub fn marker(marker: Marker, input: DensityFn) -> DensityFn {
+        Arc::new(Self::Marker(marker, input))
+    }
+
+    pub fn interpolated(input: DensityFn) -> DensityFn {
+        Self::marker(Marker::Interpolated, input)
+    }
+
+    pub fn flat_cache(input: DensityFn) -> DensityFn {
+        Self::marker(Marker::FlatCache, input)
+    }
+
+    pub fn cache_2d(input: DensityFn) -> DensityFn {
+        Self::marker(Marker::Cache2d, input)
+    }
+
+    pub fn cache_once(input: DensityFn) -> DensityFn {
+        Self::marker(Marker::CacheOnce, input)
+    }
+
+    pub fn spline(spline: Spline) -> DensityFn {
+        Arc::new(Self::Spline(spline))
+    }
+
+    /// `when_in_range` where `input` is in `min_inclusive..max_exclusive`,
+    /// `when_out_of_range` elsewhere.
+    pub fn range_choice(
+        input: DensityFn,
+        min_inclusive: f64,
+        max_exclusive: f64,
+        when_in_range: DensityFn,
+        when_out_of_range: DensityFn,
+    ) -> DensityFn {
+        Arc::new(Self::RangeChoice {
+            input,
+            min_inclusive,
+            max_exclusive,
+            when_in_range,
+            when_out_of_range,
+        })
+    }
+
+    pub fn weird_scaled_sampler(
+        input: DensityFn,
+        noise: NoiseHolder,
+        rarity: RarityValueMapper,
+    ) -> DensityFn {
+        Arc::new(Self::WeirdScaledSampler {
+            input,
+            noise,
+            rarity,
+        })
+    }
+
+    /// Value at a block position, sampling every point directly.
+    pub fn compute(&self, x: i32, y: i32, z: i32) -> f64 {
+        self.eval(x, y, z, &mut None)
+    }
+
+    /// Value at a block position within the chunk `cache` belongs to, with
+    /// `interpolated` parts smoothed over noise cells as in generation.
+    pub fn compute_cached(&self, x: i32, y: i32, z: i32, cache: &mut NoiseCache) -> f64 {
+        self.eval(x, y, z, &mut Some(cache))
+    }
+
+    fn key(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    fn eval(&self, x: i32, y: i32, z: i32, cache: &mut Option<&mut NoiseCache>) -> f64 {
+        match self {
+            Self::Constant(v) => *v,
+            Self::Binary(op, a, b) => {
+                let d = a.eval(x, y, z, cache);
+                match op {
+                    BinaryOp::Add => d + b.eval(x, y, z, cache),
+                    BinaryOp::Mul if d == 0.0 => 0.0,
+                    BinaryOp::Mul => d * b.eval(x, y, z, cache),
+                    BinaryOp::Min => d.min(b.eval(x, y, z, cache)),
+                    BinaryOp::Max => d.max(b.eval(x, y, z, cache)),
+                }
+            }
+            Self::Mapped(op, input) => op.apply(input.eval(x, y, z, cache)),
+            Self::Clamp { input, min, max } => {
+                let d = input.eval(x, y, z, cache);
+                if d < *min { *min } else { d.min(*max) }
+            }
+            Self::YClampedGradient {
+                from_y,
+                to_y,
+                from_value,
+                to_value,
+            } => {
+                let t = f64::from(y - from_y) / f64::from(to_y - from_y);
+                clamped_lerp(*from_value, *to_value, t)
+            }
+            Self::Noise {
+                noise,
+                xz_scale,
+                y_scale,
+            } => noise.get_value(
+                f64::from(x) * xz_scale,
+                f64::from(y) * y_scale,
+                f64::from(z) * xz_scale,
+            ),
+            Self::ShiftedNoise {
+                shift_x,
+                shift_y,
+                shift_z,
+                xz_scale,
+                y_scale,
+                noise,
+            } => {
+                let d = f64::from(x) * xz_scale + shift_x.eval(x, y, z, cache);
+                let e = f64::from(y) * y_scale + shift_y.eval(x, y, z, cache);
+                let f = f64::from(z) * xz_scale + shift_z.eval(x, y, z, cache);
+                noise.get_value(d, e, f)
+            }
+            Self::ShiftA(noise) => shift(noise, x, 0, z),
+            Self::ShiftB(noise) => shift(noise, z, x, 0),
+            Self::Shift(noise) => shift(noise, x, y, z),
+            Self::RangeChoice {
+                input,
+                min_inclusive,
+                max_exclusive,
+                when_in_range,
+                when_out_of_range,
+            } => {
+                let d = input.eval(x, y, z, cache);
+                if d >= *min_inclusive && d < *max_exclusive {
+                    when_in_range.eval(x, y, z, cache)
+                } else {
+                    when_out_of_range.eval(x, y, z, cache)
+                }
+            }
+            Self::Spline(spline) => f64::from(spline.apply(x, y, z, cache)),
+            Self::Marker(Marker::Interpolated, input) => match cache {
+                Some(c) if !c.filling => c.interpolate(self.key(), input, x, y, z),
+                _ => input.eval(x, y, z, cache),
+            },
+            Self::Marker(Marker::FlatCache, input) => {
+                let (qx, qz) = (x >> 2, z >> 2);
+                match cache {
+                    Some(c) => c.flat(self.key(), input, qx, qz),
+                    None => input.eval(qx << 2, 0, qz << 2, cache),
+                }
+            }
+            Self::Marker(_, input) => input.eval(x, y, z, cache),
+            Self::BlendAlpha => 1.0,
+            Self::BlendOffset | Self::Beardifier => 0.0,
+            Self::BlendDensity(input) => input.eval(x, y, z, cache),
+            Self::EndIslands(islands) => {
+                (f64::from(islands.height_value(x / 8, z / 8)) - 8.0) / 128.0
+            }
+            Self::WeirdScaledSampler {
+                input,
+                noise,
+                rarity,
+            } => {
+                let e = rarity.map(input.eval(x, y, z, cache));
+                e * noise
+                    .get_value(f64::from(x) / e, f64::from(y) / e, f64::from(z) / e)
+                    .abs()
+            }
+            Self::OldBlendedNoise(noise) => noise.compute(x, y, z),
+            Self::FindTopSurface {
+                density,
+                upper_bound,
+                lower_bound,
+                cell_height,
+            } => {
+                let upper = upper_bound.eval(x, y, z, cache) / f64::from(*cell_height);
+                let top = crate::noise::floor(upper) * cell_height;
+                if top <= *lower_bound {
+                    return f64::from(*lower_bound);
+                }
+                let mut y = top;
+                while y >= *lower_bound {
+                    if density.eval(x, y, z, cache) > 0.0 {
+                        return f64::from(y);
+                    }
+                    y -= cell_height;
+                }
+                f64::from(*lower_bound)
+            }
+        }
+    }
+
+    /// Rebuild the tree through `visitor`.
+    pub fn map_all(self: &Arc<Self>, visitor: &mut dyn DensityVisitor) -> DensityFn {
+        self.map_with(visitor, &mut Memo::new())
+    }
+
+    /// [`map_all`](Self::map_all) sharing `memo` with other trees, so a
+    /// node referenced from several trees is mapped once.
+    pub fn map_with(
+        self: &Arc<Self>,
+        visitor: &mut dyn DensityVisitor,
+        memo: &mut Memo,
+    ) -> DensityFn {
+        if let Some(mapped) = memo.get(&self.key()) {
+            return mapped.clone();
+        }
+        let mut map = |f: &DensityFn| f.map_with(visitor, memo);
+        let node = match &**self {
+            Self::Binary(op, a, b) => Self::Binary(*op, map(a), map(b)),
+            Self::Mapped(op, input) => Self::Mapped(*op, map(input)),
+            Self::Clamp { input, min, max } => Self::Clamp {
+                input: map(input),
+                min: *min,
+                max: *max,
+            },
+            Self::ShiftedNoise {
+                shift_x,
+                shift_y,
+                shift_z,
+                xz_scale,
+                y_scale,
+                noise,
+            } => {
+                let (shift_x, shift_y, shift_z) = (map(shift_x), map(shift_y), map(shift_z));
+                Self::ShiftedNoise {
+                    shift_x,
+                    shift_y,
+                    shift_z,
+                    xz_scale: *xz_scale,
+                    y_scale: *y_scale,
+                    noise: visitor.visit_noise(noise),
+                }
+            }
+            Self::RangeChoice {
+                input,
+                min_inclusive,
+                max_exclusive,
+                when_in_range,
+                when_out_of_range,
+            } => Self::RangeChoice {
+                input: map(input),
+                min_inclusive: *min_inclusive,
+                max_exclusive: *max_exclusive,
+                when_in_range: map(when_in_range),
+                when_out_of_range: map(when_out_of_range),
+            },
+            Self::Marker(marker, input) => Self::Marker(*marker, map(input)),
+            Self::BlendDensity(input) => Self::BlendDensity(map(input)),
+            Self::WeirdScaledSampler {
+                input,
+                noise,
+                rarity,
+            } => {
+                let input = map(input);
+                Self::WeirdScaledSampler {
+                    input,
+                    noise: visitor.visit_noise(noise),
+                    rarity: *rarity,
+                }
+            }
+            Self::FindTopSurface {
+                density,
+                upper_bound,
+                lower_bound,
+                cell_height,
+            } => Self::FindTopSurface {
+                density: map(density),
+                upper_bound: map(upper_bound),
+                lower_bound: *lower_bound,
+                cell_height: *cell_height,
+            },
+            Self::Spline(spline) => Self::Spline(spline.map(visitor, memo)),
+            Self::Noise {
+                noise,
+                xz_scale,
+                y_scale,
+            } => Self::Noise {
+                noise: visitor.visit_noise(noise),
+                xz_scale: *xz_scale,
+                y_scale: *y_scale,
+            },
+            Self::ShiftA(noise) => Self::ShiftA(visitor.visit_noise(noise)),
+            Self::ShiftB(noise) => Self::ShiftB(visitor.visit_noise(noise)),
+            Self::Shift(noise) => Self::Shift(visitor.visit_noise(noise)),
+            leaf => leaf.clone(),
+        };
+        let mapped = Arc::new(visitor.apply(node));
+        memo.insert(self.key(), mapped.clone());
+        mapped
+    }
+}
+
+/// `ShiftNoise.compute`: the offset noise at a quarter of the position,
+/// times four.
+fn shift(noise: &NoiseHolder, x: i32, y: i32, z: i32) -> f64 {
+    noise.get_value(
+        f64::from(x) * 0.25,
+        f64::from(y) * 0.25,
+        f64::from(z) * 0.25,
+    ) * 4.0
+}
+
+/// Per-chunk state for evaluating density functions the way chunk
+/// generation does: `interpolated` nodes are sampled once per noise cell
+/// corner and blended trilinearly inside the cell, and `flat_cache` nodes
+/// once per 4×4 column. Use one cache per chunk.
+#[derive(Debug)]
+pub struct NoiseCache {
+    cell_width: i32,
+    cell_height: i32,
+    corners: HashMap<(usize, i32, i32, i32), f64>,
+    flat: HashMap<(usize, i32, i32), f64>,
+    cell: Option<(i32, i32, i32)>,
+    cell_values: Vec<(usize, [f64; 8])>,
+    filling: bool,
+}
+
+impl NoiseCache {
+    /// Cells are `cell_width` blocks wide and `cell_height` tall, aligned to
+    /// multiples of their size; the world's `min_y` must be aligned too.
+    pub fn new(cell_width: i32, cell_height: i32) -> Self {
+        Self {
+            cell_width,
+            cell_height,
+            corners: HashMap::new(),
+            flat: HashMap::new(),
+            cell: None,
+            cell_values: Vec::new(),
+            filling: false,
+        }
+    }
+
+    pub fn cell_width(&self) -> i32 {
+        self.cell_width
+    }
+
+    pub fn cell_height(&self) -> i32 {
+        self.cell_height
+    }
+
+    fn flat(&mut self, key: usize, input: &DensityFn, qx: i32, qz: i32) -> f64 {
+        if let Some(&v) = self.flat.get(&(key, qx, qz)) {
+            return v;
+        }
+        let v = input.eval(qx << 2, 0, qz << 2, &mut Some(&mut *self));
+        self.flat.insert((key, qx, qz), v);
+        v
+    }
+
+    fn corner(&mut self, key: usize, input: &DensityFn, x: i32, y: i32, z: i32) -> f64 {
+        if let Some(&v) = self.corners.get(&(key, x, y, z)) {
+            return v;
+        }
+        let filling = std::mem::replace(&mut self.filling, true);
+        let v = input.eval(x, y, z, &mut Some(&mut *self));
+        self.filling = filling;
+        self.corners.insert((key, x, y, z), v);
+        v
+    }
+
+    fn interpolate(&mut self, key: usize, input: &DensityFn, x: i32, y: i32, z: i32) -> f64 {
+        let (w, h) = (self.cell_width, self.cell_height);
+        let cell = (x.div_euclid(w), y.div_euclid(h), z.div_euclid(w));
+        if self.cell != Some(cell) {
+            self.cell = Some(cell);
+            self.cell_values.clear();
+        }
+        let values = match self.cell_values.iter().find(|(k, _)| *k == key) {
+            Some((_, values)) => *values,
+            None => {
+                let (x0, y0, z0) = (cell.0 * w, cell.1 * h, cell.2 * w);
+                // Ordered noise000, noise100, noise010, ... with x fastest.
+                let mut values = [0.0; 8];
+                for (i, v) in values.iter_mut().enumerate() {
+                    let (dx, dy, dz) = ((i & 1) as i32, (i >> 1 & 1) as i32, (i >> 2) as i32);
+                    *v = self.corner(key, input, x0 + dx * w, y0 + dy * h, z0 + dz * w);
+                }
+                self.cell_values.push((key, values));
+                values
+            }
+        };
+        let tx = f64::from(x - cell.0 * w) / f64::from(w);
+        let ty = f64::from(y - cell.1 * h) / f64::from(h);
+        let tz = f64::from(z - cell.2 * w) / f64::from(w);
+        let [n000, n100, n010, n110, n001, n101, n011, n111] = values;
+        // Same order as vanilla's NoiseInterpolator: y, then x, then z.
+        let n00 = lerp(ty, n000, n010);
+        let n10 = lerp(ty, n100, n110);
+        let n01 = lerp(ty, n001, n011);
+        let n11 = lerp(ty, n101, n111);
+        lerp(tz, lerp(tx, n00, n10), lerp(tx, n01, n11))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::{PositionalRandomFactory, XoroshiroRandomSource};
+
+    struct Seed(Box<dyn PositionalRandomFactory>);
+
+    impl DensityVisitor for Seed {
+        fn visit_noise(&mut self, noise: &NoiseHolder) -> NoiseHolder {
+            let mut random = self.0.with_hash_of(&noise.key);
+            noise.with_noise(Arc::new(NormalNoise::create(
+                &mut *random,
+                &noise.parameters,
+            )))
+        }
+    }
+
+    fn seeded(f: &DensityFn) -> DensityFn {
+        let factory = XoroshiroRandomSource::new(42).fork_positional();
+        f.map_all(&mut Seed(factory))
+    }
+
+    #[test]
+    fn evaluates_arithmetic_and_gradients() {
+        type F = DensityFunction;
+        let gradient = F::y_clamped_gradient(-64, 320, 1.5, -1.5);
+        assert_eq!(gradient.compute(0, -100, 0), 1.5);
+        assert_eq!(gradient.compute(0, 128, 0), 0.0);
+        assert_eq!(gradient.compute(0, 400, 0), -1.5);
+
+        let f = F::clamp(
+            F::binary(
+                BinaryOp::Add,
+                F::binary(BinaryOp::Mul, gradient.clone(), F::constant(2.0)),
+                F::constant(0.5),
+            ),
+            -1.0,
+            1.0,
+        );
+        assert_eq!(f.compute(0, -64, 0), 1.0);
+        assert_eq!(f.compute(0, 128, 0), 0.5);
+        assert_eq!(f.compute(0, 320, 0), -1.0);
+
+        let choice = Arc::new(F::RangeChoice {
+            input: gradient,
+            min_inclusive: 0.0,
+            max_exclusive: 1.0,
+            when_in_range: F::constant(1.0),
+            when_out_of_range: F::mapped(MappedOp::QuarterNegative, F::constant(-2.0)),
+        });
+        assert_eq!(choice.compute(0, 128, 0), 1.0);
+        assert_eq!(choice.compute(0, 320, 0), -0.5);
+
+        let squeeze = F::mapped(MappedOp::Squeeze, F::constant(3.0));
+        assert_eq!(squeeze.compute(0, 0, 0), 0.5 - 1.0 / 24.0);
+        assert_eq!(
+            F::binary(BinaryOp::Min, F::constant(1.0), F::constant(-2.0)).compute(0, 0, 0),
+            -2.0
+        );
+    }
+
+    #[test]
+    fn evaluates_splines() {
+        let coordinate = DensityFunction::y_clamped_gradient(0, 100, -1.0, 1.0);
+        let point = |location, value, derivative| SplinePoint {
+            location,
+            value: Spline::Constant(value),
+            derivative,
+        };
+        let spline = DensityFunction::spline(Spline::Multipoint {
+            coordinate,
+            points: vec![point(-0.5, 0.0, 1.0), point(0.5, 1.0, 0.0)],
+        });
+        // Linear extension below the first point uses its derivative.
+        assert_eq!(spline.compute(0, 0, 0), -0.5);
+        assert_eq!(spline.compute(0, 100, 0), 1.0);
+        assert_eq!(spline.compute(0, 25, 0), 0.0);
+        // Hermite segment in between: lerp plus the derivative correction.
+        assert_eq!(spline.compute(0, 50, 0), 0.625);
+    }
+
+    #[test]
+    fn seeding_keeps_shared_nodes_shared() {
+        let noise = NoiseHolder::new("minecraft:test", NoiseParameters::new(-4, vec![1.0, 1.0]));
+        let shared = DensityFunction::noise(noise, 1.0, 1.0);
+        let sum = DensityFunction::binary(BinaryOp::Add, shared.clone(), shared);
+        assert_eq!(sum.compute(7, 8, 9), 0.0);
+
+        let seeded = seeded(&sum);
+        let DensityFunction::Binary(_, a, b) = &*seeded else {
+            panic!("not an add");
+        };
+        assert!(Arc::ptr_eq(a, b));
+        let single = a.compute(7, 8, 9);
+        assert_ne!(single, 0.0);
+        assert_eq!(seeded.compute(7, 8, 9), single * 2.0);
+    }
+
+    #[test]
+    fn interpolates_over_cells() {
+        let noise = NoiseHolder::new("minecraft:test", NoiseParameters::new(-3, vec![1.0]));
+        let raw = seeded(&DensityFunction::noise(noise, 1.0, 1.0));
+        let f = DensityFunction::interpolated(raw.clone());
+        let mut cache = NoiseCache::new(4, 8);
+
+        // Cell corners are exact.
+        assert_eq!(
+            f.compute_cached(4, 8, -4, &mut cache),
+            raw.compute(4, 8, -4)
+        );
+        assert_eq!(
+            f.compute_cached(0, -64, 12, &mut cache),
+            raw.compute(0, -64, 12)
+        );
+
+        // Inside a cell the value is the trilinear blend of the corners.
+        let corner = |x, y, z| raw.compute(x, y, z);
+        let (tx, ty, tz) = (0.25, 0.375, 0.5);
+        let want = lerp(
+            tz,
+            lerp(
+                tx,
+                lerp(ty, corner(4, 8, 8), corner(4, 16, 8)),
+                lerp(ty, corner(8, 8, 8), corner(8, 16, 8)),
+            ),
+            lerp(
+                tx,
+                lerp(ty, corner(4, 8, 12), corner(4, 16, 12)),
+                lerp(ty, corner(8, 8, 12), corner(8, 16, 12)),
+            ),
+        );
+        assert_eq!(f.compute_cached(5, 11, 10, &mut cache), want);
+        // Without a cache the marker is transparent.
+        assert_eq!(f.compute(5, 11, 10), raw.compute(5, 11, 10));
+
+        let flat = DensityFunction::flat_cache(raw.clone());
+        assert_eq!(flat.compute(-3, 70, 6), raw.compute(-4, 0, 4));
+        assert_eq!(
+            flat.compute_cached(-3, 70, 6, &mut cache),
+            raw.compute(-4, 0, 4)
+        );
+    }
+}
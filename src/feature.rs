@@ -0,0 +1,863 @@
+//! Features: the ores, trees and plants placed into generated terrain by
+//! the decoration stage. Features write into a [`WorldGenRegion`], the
+//! chunk being decorated and its neighbours, as a feature started near a
+//! border spills over it.
+
+use crate::biome_source::BiomeManager;
+use crate::chunk::{BlockState, Chunk, HeightmapKind};
+use crate::noise::{self, floor};
+use crate::placement::{PlacedFeature, PlacementModifier};
+use crate::provider::weighted_pick;
+use crate::random::RandomSource;
+use crate::tree::TreeConfiguration;
+use std::collections::{HashMap, HashSet};
+use std::f32::consts::PI;
+
+/// Vanilla's `#stone_ore_replaceables` block tag.
+pub const STONE_ORE_REPLACEABLES: &[&str] = &[
+    "minecraft:stone",
+    "minecraft:granite",
+    "minecraft:diorite",
+    "minecraft:andesite",
+];
+
+/// Vanilla's `#deepslate_ore_replaceables` block tag.
+pub const DEEPSLATE_ORE_REPLACEABLES: &[&str] = &["minecraft:deepslate", "minecraft:tuff"];
+
+/// Six directions, for neighbour checks.
+const DIRECTIONS: [[i32; 3]; 6] = [
+    [0, -1, 0],
+    [0, 1, 0],
+    [0, 0, -1],
+    [0, 0, 1],
+    [-1, 0, 0],
+    [1, 0, 0],
+];
+
+/// The chunks a feature may touch. Blocks outside them read as air and
+/// writes to them are dropped.
+pub struct WorldGenRegion<'a> {
+    chunks: &'a mut [Chunk],
+    index: HashMap<(i32, i32), usize>,
+    biome_manager: Option<BiomeManager>,
+}
+
+impl<'a> WorldGenRegion<'a> {
+    /// A region over `chunks`, which must all span the same heights.
+    pub fn new(chunks: &'a mut [Chunk]) -> Self {
+        assert!(!chunks.is_empty(), "a region needs at least one chunk");
+        let index = chunks
+            .iter()
+            .enumerate()
+            .map(|(i, c)| ((c.x, c.z), i))
+            .collect();
+        Self {
+            chunks,
+            index,
+            biome_manager: None,
+        }
+    }
+
+    /// Smooth biome borders with the zoom of world seed `seed`, as the game
+    /// does; otherwise a block has the biome of its 4³ cell.
+    pub fn with_biome_zoom(mut self, seed: i64) -> Self {
+        self.biome_manager = Some(BiomeManager::new(seed));
+        self
+    }
+
+    pub fn min_y(&self) -> i32 {
+        self.chunks[0].min_y()
+    }
+
+    pub fn max_y(&self) -> i32 {
+        self.chunks[0].max_y()
+    }
+
+    pub fn is_outside_build_height(&self, y: i32) -> bool {
+        y < self.min_y() || y >= self.max_y()
+    }
+
+    pub fn chunk(&self, chunk_x: i32, chunk_z: i32) -> Option<&Chunk> {
+        self.index
+            .get(&(chunk_x, chunk_z))
+            .map(|&i| &self.chunks[i])
+    }
+
+    pub fn get_block(&self, [x, y, z]: [i32; 3]) -> BlockState {
+        match self.chunk(x >> 4, z >> 4) {
+            Some(chunk) if !self.is_outside_build_height(y) => {
+                chunk.get_block((x & 15) as usize, y, (z & 15) as usize)
+            }
+            _ => BlockState::air(),
+        }
+    }
+
+    /// Set a block, returning whether it is inside the region.
+    pub fn set_block(&mut self, [x, y, z]: [i32; 3], state: BlockState) -> bool {
+        if self.is_outside_build_height(y) {
+            return false;
+        }
+        match self.index.get(&(x >> 4, z >> 4)) {
+            Some(&i) => {
+                let chunk = &mut self.chunks[i];
+                chunk.set_block((x & 15) as usize, y, (z & 15) as usize, state);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The y above the highest block of a column counting for `kind`.
+    pub fn height(&self, kind: HeightmapKind, x: i32, z: i32) -> i32 {
+        match self.chunk(x >> 4, z >> 4) {
+            Some(chunk) => chunk.surface_height(kind, (x & 15) as usize, (z & 15) as usize),
+            None => self.min_y(),
+        }
+    }
+
+    /// The biome at a block, or `None` when its cells are outside the
+    /// region.
+    pub fn get_biome(&self, [x, y, z]: [i32; 3]) -> Option<&str> {
+        let (min_qy, max_qy) = (self.min_y() >> 2, (self.max_y() >> 2) - 1);
+        let noise_biome = |qx: i32, qy: i32, qz: i32| {
+            let qy = qy.clamp(min_qy, max_qy);
+            let (cx, cz) = ((qx & 3) as usize * 4, (qz & 3) as usize * 4);
+            self.chunk(qx >> 2, qz >> 2)
+                .map(|chunk| chunk.get_biome(cx, qy << 2, cz))
+        };
+        match self.biome_manager {
+            Some(manager) => manager.get_biome(x, y, z, noise_biome),
+            None => noise_biome(x >> 2, y >> 2, z >> 2),
+        }
+    }
+
+    pub fn is_adjacent_to_air(&self, [x, y, z]: [i32; 3]) -> bool {
+        DIRECTIONS
+            .iter()
+            .any(|[dx, dy, dz]| self.get_block([x + dx, y + dy, z + dz]).is_air())
+    }
+}
+
+/// Which blocks a feature may replace.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RuleTest {
+    AlwaysTrue,
+    BlockMatch(String),
+    /// Any block of an expanded block tag.
+    TagMatch(HashSet<String>),
+}
+
+impl RuleTest {
+    pub fn tag(blocks: &[&str]) -> Self {
+        Self::TagMatch(blocks.iter().map(|b| b.to_string()).collect())
+    }
+
+    pub fn test(&self, state: &BlockState) -> bool {
+        match self {
+            Self::AlwaysTrue => true,
+            Self::BlockMatch(name) => state.name == *name,
+            Self::TagMatch(blocks) => blocks.contains(&state.name),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TargetBlockState {
+    pub target: RuleTest,
+    pub state: BlockState,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OreConfiguration {
+    /// The first matching target decides the ore of each block.
+    pub targets: Vec<TargetBlockState>,
+    /// Roughly the number of blocks in a blob.
+    pub size: i32,
+    /// Chance for an ore touching air to be left out.
+    pub discard_chance_on_air_exposure: f32,
+}
+
+impl OreConfiguration {
+    /// The usual pair of targets: `ore` in stone, `deepslate_ore` in
+    /// deepslate.
+    pub fn stone_and_deepslate(
+        ore: &str,
+        deepslate_ore: &str,
+        size: i32,
+        discard_chance_on_air_exposure: f32,
+    ) -> Self {
+        Self {
+            targets: vec![
+                TargetBlockState {
+                    target: RuleTest::tag(STONE_ORE_REPLACEABLES),
+                    state: BlockState::new(ore),
+                },
+                TargetBlockState {
+                    target: RuleTest::tag(DEEPSLATE_ORE_REPLACEABLES),
+                    state: BlockState::new(deepslate_ore),
+                },
+            ],
+            size,
+            discard_chance_on_air_exposure,
+        }
+    }
+}
+
+/// A `worldgen/configured_feature` entry.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfiguredFeature {
+    Ore(OreConfiguration),
+    Tree(Box<TreeConfiguration>),
+    /// One block, if it can survive where it is put.
+    SimpleBlock(BlockStateProvider),
+    RandomPatch(RandomPatchConfiguration),
+    /// A column of kelp growing up from the ocean floor.
+    Kelp,
+    /// Seagrass on the ocean floor, tall with chance `probability`.
+    Seagrass {
+        probability: f32,
+    },
+}
+
+impl ConfiguredFeature {
+    /// Place the feature at `origin`, returning whether anything was placed.
+    pub fn place(
+        &self,
+        level: &mut WorldGenRegion,
+        random: &mut dyn RandomSource,
+        origin: [i32; 3],
+    ) -> bool {
+        match self {
+            Self::Ore(config) => place_ore(config, level, random, origin),
+            Self::Tree(config) => config.place(level, random, origin),
+            Self::SimpleBlock(provider) => place_simple_block(provider, level, random, origin),
+            Self::RandomPatch(config) => place_random_patch(config, level, random, origin),
+            Self::Kelp => place_kelp(level, random, origin),
+            Self::Seagrass { probability } => place_seagrass(*probability, level, random, origin),
+        }
+    }
+
+    /// `minecraft:patch_grass`: short grass in 32 tries.
+    pub fn patch_grass() -> Self {
+        Self::grass_patch(BlockState::new("minecraft:short_grass"), 32)
+    }
+
+    /// `minecraft:patch_tall_grass`: 96 tries of tall grass.
+    pub fn patch_tall_grass() -> Self {
+        Self::grass_patch(BlockState::new("minecraft:tall_grass"), 96)
+    }
+
+    /// `minecraft:patch_taiga_grass`: ferns with some short grass.
+    pub fn patch_taiga_grass() -> Self {
+        Self::RandomPatch(RandomPatchConfiguration::only_when_empty(
+            32,
+            BlockStateProvider::Weighted(vec![
+                (BlockState::new("minecraft:short_grass"), 1),
+                (BlockState::new("minecraft:fern"), 4),
+            ]),
+        ))
+    }
+
+    /// `minecraft:flower_default`: poppies and dandelions.
+    pub fn flower_default() -> Self {
+        Self::RandomPatch(RandomPatchConfiguration::only_when_empty(
+            64,
+            BlockStateProvider::Weighted(vec![
+                (BlockState::new("minecraft:poppy"), 2),
+                (BlockState::new("minecraft:dandelion"), 1),
+            ]),
+        ))
+    }
+
+    fn grass_patch(state: BlockState, tries: i32) -> Self {
+        Self::RandomPatch(RandomPatchConfiguration::only_when_empty(
+            tries,
+            BlockStateProvider::Simple(state),
+        ))
+    }
+}
+
+/// The block states a feature picks from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BlockStateProvider {
+    Simple(BlockState),
+    /// One of several states, picked by weight.
+    Weighted(Vec<(BlockState, i32)>),
+}
+
+impl BlockStateProvider {
+    pub fn state(&self, random: &mut dyn RandomSource) -> BlockState {
+        match self {
+            Self::Simple(state) => state.clone(),
+            Self::Weighted(entries) => weighted_pick(entries, random).clone(),
+        }
+    }
+}
+
+/// Where a placed feature may go, tested by the `block_predicate_filter`
+/// placement modifier.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BlockPredicate {
+    /// The block at `offset` is one of `blocks`.
+    MatchingBlocks {
+        offset: [i32; 3],
+        blocks: Vec<String>,
+    },
+    /// `state` could stay at `offset`, like a sapling on dirt.
+    WouldSurvive { offset: [i32; 3], state: BlockState },
+}
+
+impl BlockPredicate {
+    /// Vanilla's `ONLY_IN_AIR_PREDICATE`.
+    pub fn only_in_air() -> Self {
+        Self::MatchingBlocks {
+            offset: [0, 0, 0],
+            blocks: vec!["minecraft:air".into()],
+        }
+    }
+
+    pub fn would_survive(state: BlockState) -> Self {
+        Self::WouldSurvive {
+            offset: [0, 0, 0],
+            state,
+        }
+    }
+
+    pub fn test(&self, level: &WorldGenRegion, [x, y, z]: [i32; 3]) -> bool {
+        match self {
+            Self::MatchingBlocks {
+                offset: [dx, dy, dz],
+                blocks,
+            } => {
+                let state = level.get_block([x + dx, y + dy, z + dz]);
+                blocks.contains(&state.name)
+            }
+            Self::WouldSurvive {
+                offset: [dx, dy, dz],
+                state,
+            } => can_survive(state, level, [x + dx, y + dy, z + dz]),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RandomPatchConfiguration {
+    pub tries: i32,
+    pub xz_spread: i32,
+    pub y_spread: i32,
+    /// Placed at each try, from a position spread around the origin.
+    pub feature: Box<PlacedFeature>,
+}
+
+impl RandomPatchConfiguration {
+    /// Single blocks of `provider` put where there is air, spread 7 blocks
+    /// sideways and 3 up and down: vanilla's usual plant patch.
+    pub fn only_when_empty(tries: i32, provider: BlockStateProvider) -> Self {
+        Self {
+            tries,
+            xz_spread: 7,
+            y_spread: 3,
+            feature: Box::new(PlacedFeature {
+                feature: ConfiguredFeature::SimpleBlock(provider),
+                placement: vec![PlacementModifier::BlockPredicateFilter(
+                    BlockPredicate::only_in_air(),
+                )],
+            }),
+        }
+    }
+}
+
+/// A blob of ore along a random line through `origin`, if any column it
+/// covers reaches down to it. Vanilla's `OreFeature`.
+fn place_ore(
+    config: &OreConfiguration,
+    level: &mut WorldGenRegion,
+    random: &mut dyn RandomSource,
+    [x, y, z]: [i32; 3],
+) -> bool {
+    let angle = random.next_float() * PI;
+    let spread = config.size as f32 / 8.0;
+    let margin = ((config.size as f32 / 16.0 * 2.0 + 1.0) / 2.0).ceil() as i32;
+    let (sin, cos) = (f64::from(angle).sin(), f64::from(angle).cos());
+    let spread_f64 = f64::from(spread);
+    let line = [
+        f64::from(x) + sin * spread_f64,
+        f64::from(x) - sin * spread_f64,
+        f64::from(z) + cos * spread_f64,
+        f64::from(z) - cos * spread_f64,
+        f64::from(y + random.next_int_bounded(3) - 2),
+        f64::from(y + random.next_int_bounded(3) - 2),
+    ];
+    let reach = spread.ceil() as i32 + margin;
+    let min = [x - reach, y - 2 - margin, z - reach];
+    let width = 2 * reach;
+    let height = 2 * (2 + margin);
+    for sx in min[0]..=min[0] + width {
+        for sz in min[2]..=min[2] + width {
+            if min[1] <= level.height(HeightmapKind::OceanFloorWg, sx, sz) {
+                return place_ore_blob(config, level, random, line, min, width, height);
+            }
+        }
+    }
+    false
+}
+
+fn place_ore_blob(
+    config: &OreConfiguration,
+    level: &mut WorldGenRegion,
+    random: &mut dyn RandomSource,
+    [min_x, max_x, min_z, max_z, min_y, max_y]: [f64; 6],
+    [x, y, z]: [i32; 3],
+    width: i32,
+    height: i32,
+) -> bool {
+    let size = config.size.max(0) as usize;
+    // Spheres along the line, as centre and radius.
+    let mut spheres: Vec<[f64; 4]> = (0..size)
+        .map(|k| {
+            let t = k as f32 / size as f32;
+            let lerp = |a: f64, b: f64| a + f64::from(t) * (b - a);
+            let scale = random.next_double() * size as f64 / 16.0;
+            let radius = (f64::from(noise::sin(f64::from(PI * t)) + 1.0) * scale + 1.0) / 2.0;
+            [
+                lerp(min_x, max_x),
+                lerp(min_y, max_y),
+                lerp(min_z, max_z),
+                radius,
+            ]
+        })
+        .collect();
+    // Drop spheres inside others.
+    for k in 0..size.saturating_sub(1) {
+        if spheres[k][3] <= 0.0 {
+            continue;
+        }
+        for m in k + 1..size {
+            if spheres[m][3] <= 0.0 {
+                continue;
+            }
+            let [dx, dy, dz, dr] = [0, 1, 2, 3].map(|i| spheres[k][i] - spheres[m][i]);
+            if dr * dr > dx * dx + dy * dy + dz * dz {
+                if dr > 0.0 {
+                    spheres[m][3] = -1.0;
+                } else {
+                    spheres[k][3] = -1.0;
+                }
+            }
+        }
+    }
+
+    let mut done = vec![false; (width * height * width).max(0) as usize];
+    let mut placed = 0;
+    for [cx, cy, cz, radius] in spheres {
+        if radius < 0.0 {
+            continue;
+        }
+        let x0 = floor(cx - radius).max(x);
+        let y0 = floor(cy - radius).max(y);
+        let z0 = floor(cz - radius).max(z);
+        let x1 = floor(cx + radius).max(x0);
+        let y1 = floor(cy + radius).max(y0);
+        let z1 = floor(cz + radius).max(z0);
+        for bx in x0..=x1 {
+            let dx = (f64::from(bx) + 0.5 - cx) / radius;
+            if dx * dx >= 1.0 {
+                continue;
+            }
+            for by in y0..=y1 {
+                let dy = (f64::from(by) + 0.5 - cy) / radius;
+                if dx * dx + dy * dy >= 1.0 {
+                    continue;
+                }
+                for bz in z0..=z1 {
+                    let dz = (f64::from(bz) + 0.5 - cz) / radius;
+                    if dx * dx + dy * dy + dz * dz >= 1.0 || level.is_outside_build_height(by) {
+                        continue;
+                    }
+                    let index = (bx - x + (by - y) * width + (bz - z) * width * height) as usize;
+                    // Vanilla's bit set grows as needed; spheres can reach
+                    // past the box it was sized for.
+                    if index >= done.len() {
+                        done.resize(index + 1, false);
+                    }
+                    if done[index] {
+                        continue;
+                    }
+                    done[index] = true;
+                    let pos = [bx, by, bz];
+                    let state = level.get_block(pos);
+                    for target in &config.targets {
+                        if can_place_ore(&state, level, random, config, target, pos) {
+                            if level.set_block(pos, target.state.clone()) {
+                                placed += 1;
+                            }
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    placed > 0
+}
+
+fn can_place_ore(
+    state: &BlockState,
+    level: &WorldGenRegion,
+    random: &mut dyn RandomSource,
+    config: &OreConfiguration,
+    target: &TargetBlockState,
+    pos: [i32; 3],
+) -> bool {
+    if !target.target.test(state) {
+        return false;
+    }
+    let chance = config.discard_chance_on_air_exposure;
+    let skip_air_check = chance <= 0.0 || (chance < 1.0 && random.next_float() >= chance);
+    skip_air_check || !level.is_adjacent_to_air(pos)
+}
+
+/// Vanilla's `#dirt` block tag.
+pub const DIRT: &[&str] = &[
+    "minecraft:dirt",
+    "minecraft:grass_block",
+    "minecraft:podzol",
+    "minecraft:coarse_dirt",
+    "minecraft:mycelium",
+    "minecraft:rooted_dirt",
+    "minecraft:moss_block",
+    "minecraft:mud",
+    "minecraft:muddy_mangrove_roots",
+    "minecraft:pale_moss_block",
+];
+
+/// Plants two blocks tall, placed as a lower and an upper half.
+const DOUBLE_PLANTS: &[&str] = &[
+    "minecraft:tall_grass",
+    "minecraft:large_fern",
+    "minecraft:sunflower",
+    "minecraft:lilac",
+    "minecraft:rose_bush",
+    "minecraft:peony",
+];
+
+pub fn is_dirt(state: &BlockState) -> bool {
+    DIRT.contains(&state.name.as_str())
+}
+
+/// Whether `state` could stay at `pos`, for the blocks features place.
+/// Full blocks can stay anywhere; sturdy faces are approximated by
+/// [`BlockState::blocks_motion`].
+pub fn can_survive(state: &BlockState, level: &WorldGenRegion, [x, y, z]: [i32; 3]) -> bool {
+    let below = level.get_block([x, y - 1, z]);
+    let on_sturdy = below.blocks_motion() && below.name != "minecraft:magma_block";
+    match state.name.as_str() {
+        "minecraft:kelp" | "minecraft:kelp_plant" => {
+            on_sturdy
+                || matches!(
+                    below.name.as_str(),
+                    "minecraft:kelp" | "minecraft:kelp_plant"
+                )
+        }
+        "minecraft:seagrass" => on_sturdy,
+        "minecraft:tall_seagrass" if state.property("half") == Some("upper") => {
+            below.name == "minecraft:tall_seagrass"
+        }
+        "minecraft:tall_seagrass" => on_sturdy,
+        name if DOUBLE_PLANTS.contains(&name) && state.property("half") == Some("upper") => {
+            below.name == name
+        }
+        name if name.ends_with("_sapling") || !state.blocks_motion() => {
+            is_dirt(&below) || below.name == "minecraft:farmland"
+        }
+        _ => true,
+    }
+}
+
+fn place_simple_block(
+    provider: &BlockStateProvider,
+    level: &mut WorldGenRegion,
+    random: &mut dyn RandomSource,
+    [x, y, z]: [i32; 3],
+) -> bool {
+    let state = provider.state(random);
+    if !can_survive(&state, level, [x, y, z]) {
+        return false;
+    }
+    if DOUBLE_PLANTS.contains(&state.name.as_str()) {
+        if !level.get_block([x, y + 1, z]).is_air() {
+            return false;
+        }
+        level.set_block([x, y, z], state.clone().with("half", "lower"));
+        level.set_block([x, y + 1, z], state.with("half", "upper"));
+    } else {
+        level.set_block([x, y, z], state);
+    }
+    true
+}
+
+fn place_random_patch(
+    config: &RandomPatchConfiguration,
+    level: &mut WorldGenRegion,
+    random: &mut dyn RandomSource,
+    [x, y, z]: [i32; 3],
+) -> bool {
+    let (xz, y_spread) = (config.xz_spread + 1, config.y_spread + 1);
+    let mut placed = 0;
+    for _ in 0..config.tries {
+        let dx = random.next_int_bounded(xz) - random.next_int_bounded(xz);
+        let dy = random.next_int_bounded(y_spread) - random.next_int_bounded(y_spread);
+        let dz = random.next_int_bounded(xz) - random.next_int_bounded(xz);
+        if config
+            .feature
+            .place(level, random, [x + dx, y + dy, z + dz])
+        {
+            placed += 1;
+        }
+    }
+    placed > 0
+}
+
+fn is_water(state: &BlockState) -> bool {
+    state.name == "minecraft:water"
+}
+
+/// Vanilla's `KelpFeature`.
+fn place_kelp(
+    level: &mut WorldGenRegion,
+    random: &mut dyn RandomSource,
+    [x, _, z]: [i32; 3],
+) -> bool {
+    let floor = level.height(HeightmapKind::OceanFloor, x, z);
+    if !is_water(&level.get_block([x, floor, z])) {
+        return false;
+    }
+    let kelp = BlockState::new("minecraft:kelp");
+    let plant = BlockState::new("minecraft:kelp_plant");
+    let mut placed = 0;
+    let length = 1 + random.next_int_bounded(10);
+    for (i, y) in (0..=length).zip(floor..) {
+        if is_water(&level.get_block([x, y, z]))
+            && is_water(&level.get_block([x, y + 1, z]))
+            && can_survive(&plant, level, [x, y, z])
+        {
+            if i == length {
+                let age = random.next_int_bounded(4) + 20;
+                level.set_block([x, y, z], kelp.clone().with("age", age.to_string()));
+                placed += 1;
+            } else {
+                level.set_block([x, y, z], plant.clone());
+            }
+        } else if i > 0 {
+            if can_survive(&kelp, level, [x, y - 1, z])
+                && level.get_block([x, y - 2, z]).name != "minecraft:magma_block"
+            {
+                let age = random.next_int_bounded(4) + 20;
+                level.set_block([x, y - 1, z], kelp.clone().with("age", age.to_string()));
+                placed += 1;
+            }
+            break;
+        }
+    }
+    placed > 0
+}
+
+/// Vanilla's `SeagrassFeature`.
+fn place_seagrass(
+    probability: f32,
+    level: &mut WorldGenRegion,
+    random: &mut dyn RandomSource,
+    [x, _, z]: [i32; 3],
+) -> bool {
+    let x = x + random.next_int_bounded(8) - random.next_int_bounded(8);
+    let z = z + random.next_int_bounded(8) - random.next_int_bounded(8);
+    let y = level.height(HeightmapKind::OceanFloor, x, z);
+    if !is_water(&level.get_block([x, y, z])) {
+        return false;
+    }
+    let tall = random.next_double() < f64::from(probability);
+    let state = BlockState::new(if tall {
+        "minecraft:tall_seagrass"
+    } else {
+        "minecraft:seagrass"
+    });
+    if !can_survive(&state, level, [x, y, z]) {
+        return false;
+    }
+    if !tall {
+        level.set_block([x, y, z], state);
+    } else if is_water(&level.get_block([x, y + 1, z])) {
+        level.set_block([x, y, z], state.clone().with("half", "lower"));
+        level.set_block([x, y + 1, z], state.with("half", "upper"));
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::XoroshiroRandomSource;
+    use crate::test::worldgen::{find_blocks, region};
+
+    /// 3x3 chunks around the origin: deepslate below y = 0, stone up to
+    /// y = 16 and air above.
+    fn terrain() -> Vec<Chunk> {
+        region(-32, 64, |chunk| {
+            for section in chunk.sections_mut() {
+                match section.y {
+                    ..0 => section.fill(BlockState::new("minecraft:deepslate")),
+                    0 => section.fill(BlockState::new("minecraft:stone")),
+                    _ => {}
+                }
+            }
+        })
+    }
+
+    fn ores(chunks: &[Chunk]) -> Vec<([i32; 3], String)> {
+        find_blocks(chunks, |_, state| state.name.ends_with("_ore"))
+            .into_iter()
+            .map(|(pos, state)| (pos, state.name))
+            .collect()
+    }
+
+    #[test]
+    fn ores_replace_stone_and_deepslate() {
+        let mut chunks = terrain();
+        let mut level = WorldGenRegion::new(&mut chunks);
+        let ore = ConfiguredFeature::Ore(OreConfiguration::stone_and_deepslate(
+            "minecraft:iron_ore",
+            "minecraft:deepslate_iron_ore",
+            20,
+            0.0,
+        ));
+        let mut random = XoroshiroRandomSource::new(7);
+        assert!(ore.place(&mut level, &mut random, [15, 0, 15]));
+
+        let found = ores(&chunks);
+        assert!(found.len() > 5, "{}", found.len());
+        // The blob straddles the deepslate border and spills into the
+        // neighbouring chunks.
+        assert!(found.iter().any(|(_, n)| n == "minecraft:iron_ore"));
+        assert!(
+            found
+                .iter()
+                .any(|(_, n)| n == "minecraft:deepslate_iron_ore")
+        );
+        assert!(found.iter().any(|([x, _, _], _)| *x >= 16));
+        for ([_, y, _], name) in &found {
+            assert!(*y < 16, "ore in the air at {y}");
+            assert_eq!(
+                *y < 0,
+                name.starts_with("minecraft:deepslate_"),
+                "{name} at {y}"
+            );
+        }
+    }
+
+    #[test]
+    fn ores_exposed_to_air_can_be_discarded() {
+        let place = |discard: f32| {
+            let mut chunks = terrain();
+            let mut level = WorldGenRegion::new(&mut chunks);
+            let ore = ConfiguredFeature::Ore(OreConfiguration::stone_and_deepslate(
+                "minecraft:coal_ore",
+                "minecraft:deepslate_coal_ore",
+                17,
+                discard,
+            ));
+            let mut random = XoroshiroRandomSource::new(3);
+            ore.place(&mut level, &mut random, [8, 15, 8]);
+            ores(&chunks)
+        };
+        assert!(place(0.0).iter().any(|([_, y, _], _)| *y == 15));
+        let found = place(1.0);
+        assert!(!found.is_empty());
+        assert!(found.iter().all(|([_, y, _], _)| *y < 15));
+    }
+
+    /// A 3x3 chunk region: grass at y = 0 where `x` < 16, sand under water
+    /// up to y = 10 elsewhere.
+    fn shore() -> Vec<Chunk> {
+        region(-16, 32, |chunk| {
+            for x in 0..16 {
+                for z in 0..16 {
+                    if chunk.x < 1 {
+                        chunk.set_block(x, 0, z, BlockState::new("minecraft:grass_block"));
+                    } else {
+                        chunk.set_block(x, 0, z, BlockState::new("minecraft:sand"));
+                        for y in 1..=10 {
+                            chunk.set_block(x, y, z, BlockState::new("minecraft:water"));
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    fn count(chunks: &[Chunk], name: &str) -> usize {
+        find_blocks(chunks, |_, state| state.name == name).len()
+    }
+
+    #[test]
+    fn plants_grow_only_where_they_survive() {
+        let mut chunks = shore();
+        let mut level = WorldGenRegion::new(&mut chunks);
+        let mut random = XoroshiroRandomSource::new(11);
+        let grass = ConfiguredFeature::patch_grass();
+        assert!(grass.place(&mut level, &mut random, [0, 1, 0]));
+        // Not on sand, nor in water.
+        assert!(!grass.place(&mut level, &mut random, [24, 1, 0]));
+        assert!(!grass.place(&mut level, &mut random, [24, 11, 0]));
+        let tall = ConfiguredFeature::patch_tall_grass();
+        assert!(tall.place(&mut level, &mut random, [-8, 1, 0]));
+
+        let short = count(&chunks, "minecraft:short_grass");
+        assert!(short > 5, "{short}");
+        let tall = count(&chunks, "minecraft:tall_grass");
+        assert!(tall > 0 && tall.is_multiple_of(2), "{tall}");
+        // Every plant sits on grass.
+        let ground: HashSet<[i32; 3]> =
+            find_blocks(&chunks, |_, state| state.name == "minecraft:grass_block")
+                .into_iter()
+                .map(|(pos, _)| pos)
+                .collect();
+        for ([x, y, z], _) in find_blocks(&chunks, |_, state| state.name == "minecraft:short_grass")
+        {
+            assert!(ground.contains(&[x, y - 1, z]), "{x} {y} {z}");
+        }
+    }
+
+    #[test]
+    fn kelp_and_seagrass_grow_under_water() {
+        let mut chunks = shore();
+        let mut level = WorldGenRegion::new(&mut chunks);
+        let mut random = XoroshiroRandomSource::new(5);
+        let mut placed = 0;
+        for z in -8..8 {
+            placed +=
+                usize::from(ConfiguredFeature::Kelp.place(&mut level, &mut random, [24, 0, z]));
+        }
+        assert!(!ConfiguredFeature::Kelp.place(&mut level, &mut random, [0, 0, 0]));
+        let seagrass = ConfiguredFeature::Seagrass { probability: 0.3 };
+        for _ in 0..16 {
+            seagrass.place(&mut level, &mut random, [24, 0, 0]);
+        }
+
+        assert_eq!(count(&chunks, "minecraft:kelp"), placed);
+        // Kelp tops out below the surface.
+        let top = find_blocks(&chunks, |[_, y, _], state| {
+            y == 10 && state.name == "minecraft:kelp_plant"
+        });
+        assert!(top.is_empty());
+        let grass =
+            count(&chunks, "minecraft:seagrass") + count(&chunks, "minecraft:tall_seagrass");
+        assert!(grass > 0);
+        assert_eq!(
+            count(&chunks, "minecraft:water")
+                + grass
+                + count(&chunks, "minecraft:kelp")
+                + count(&chunks, "minecraft:kelp_plant"),
+            3 * 16 * 16 * 10
+        );
+    }
+}
@@ -0,0 +1,174 @@
+//! The noise-based chunk generator of the overworld, Nether and End, built
+//! from a `worldgen/noise_settings` entry.
+
+use crate::aquifer::{Aquifer, FluidPicker, NoiseBasedAquifer};
+use crate::biome_source::BiomeSource;
+use crate::carver::{CarvingContext, ConfiguredCarver, apply_carvers};
+use crate::chunk::{BlockState, Chunk};
+use crate::decoration::{BiomeFeatures, FeaturesPerStep};
+use crate::feature::WorldGenRegion;
+use crate::noise_router::{NoiseRouter, NoiseSettings, RandomState, fill_from_noise};
+use crate::ore_vein::OreVeinifier;
+use crate::surface::{SurfaceRule, SurfaceSystem};
+use std::collections::HashMap;
+use std::io;
+
+/// A `worldgen/noise_settings` entry.
+#[derive(Clone, Debug)]
+pub struct NoiseGeneratorSettings {
+    pub noise: NoiseSettings,
+    pub default_block: BlockState,
+    pub default_fluid: BlockState,
+    pub noise_router: NoiseRouter,
+    pub surface_rule: SurfaceRule,
+    pub sea_level: i32,
+    pub disable_mob_generation: bool,
+    pub aquifers_enabled: bool,
+    pub ore_veins_enabled: bool,
+    pub legacy_random_source: bool,
+}
+
+impl Default for NoiseGeneratorSettings {
+    /// Overworld bounds and blocks with an empty router and no surface rules.
+    fn default() -> Self {
+        Self {
+            noise: NoiseSettings::OVERWORLD,
+            default_block: BlockState::new("minecraft:stone"),
+            default_fluid: BlockState::new("minecraft:water"),
+            noise_router: NoiseRouter::default(),
+            surface_rule: SurfaceRule::Sequence(Vec::new()),
+            sea_level: 63,
+            disable_mob_generation: false,
+            aquifers_enabled: true,
+            ore_veins_enabled: true,
+            legacy_random_source: false,
+        }
+    }
+}
+
+/// Generates chunks for one world seed from noise settings.
+pub struct NoiseBasedGenerator {
+    pub settings: NoiseGeneratorSettings,
+    pub biome_source: BiomeSource,
+    pub random_state: RandomState,
+    pub surface_system: SurfaceSystem,
+    /// Carvers of each biome, in the order they run. Empty by default.
+    pub carvers: HashMap<String, Vec<ConfiguredCarver>>,
+    /// Placed features of each biome, by decoration step. Empty by default.
+    pub features: HashMap<String, BiomeFeatures>,
+}
+
+impl NoiseBasedGenerator {
+    pub fn new(settings: NoiseGeneratorSettings, biome_source: BiomeSource, seed: i64) -> Self {
+        let mut random_state =
+            RandomState::new(&settings.noise_router, seed, settings.legacy_random_source);
+        let surface_system = SurfaceSystem::new(
+            &settings.surface_rule,
+            settings.default_block.clone(),
+            settings.sea_level,
+            &mut random_state,
+        );
+        Self {
+            settings,
+            biome_source,
+            random_state,
+            surface_system,
+            carvers: HashMap::new(),
+            features: HashMap::new(),
+        }
+    }
+
+    /// Run the biome, noise, surface and carver stages for a chunk spanning
+    /// the settings' height.
+    pub fn generate_chunk(&self, chunk_x: i32, chunk_z: i32) -> Chunk {
+        let noise = &self.settings.noise;
+        let mut chunk = Chunk::with_height(chunk_x, chunk_z, noise.min_y, noise.height);
+        self.biome_source
+            .fill_biomes(&mut chunk, &self.random_state.router);
+        let picker = FluidPicker::new(self.settings.sea_level, self.settings.default_fluid.clone());
+        let mut aquifer = if self.settings.aquifers_enabled {
+            Aquifer::NoiseBased(Box::new(NoiseBasedAquifer::new(
+                &self.random_state,
+                chunk_x,
+                chunk_z,
+                noise.min_y,
+                noise.height,
+                picker,
+            )))
+        } else {
+            Aquifer::Disabled(picker)
+        };
+        let ore_veins = self
+            .settings
+            .ore_veins_enabled
+            .then(|| OreVeinifier::new(&self.random_state));
+        fill_from_noise(
+            &mut chunk,
+            &self.random_state.router,
+            noise,
+            &self.settings.default_block,
+            &mut aquifer,
+            ore_veins.as_ref(),
+        );
+        self.surface_system.build_surface(
+            &self.random_state,
+            &self.biome_source,
+            &mut chunk,
+            self.settings.legacy_random_source,
+        );
+        let ctx = CarvingContext {
+            state: &self.random_state,
+            biome_source: &self.biome_source,
+            surface: &self.surface_system,
+        };
+        apply_carvers(&ctx, &self.carvers, &mut chunk, &mut aquifer);
+        chunk.compute_heightmaps();
+        chunk
+    }
+
+    /// The biome features merged into one order per step, for the biomes
+    /// the biome source can pick.
+    pub fn features_per_step(&self) -> io::Result<FeaturesPerStep<'_>> {
+        let biomes: Vec<(&str, &BiomeFeatures)> = self
+            .biome_source
+            .possible_biomes()
+            .into_iter()
+            .filter_map(|biome| self.features.get(biome).map(|f| (biome, f)))
+            .collect();
+        FeaturesPerStep::new(&biomes)
+    }
+
+    /// Generate the chunks from `min_x`/`min_z` to `max_x`/`max_z`
+    /// inclusive, decorated when `generate_features` is set. As features
+    /// spill into neighbouring chunks, decorating takes generating two
+    /// chunks more on every side.
+    pub fn generate_area(
+        &self,
+        (min_x, min_z): (i32, i32),
+        (max_x, max_z): (i32, i32),
+        generate_features: bool,
+    ) -> io::Result<Vec<Chunk>> {
+        let margin = if generate_features { 2 } else { 0 };
+        let mut chunks = Vec::new();
+        for x in min_x - margin..=max_x + margin {
+            for z in min_z - margin..=max_z + margin {
+                chunks.push(self.generate_chunk(x, z));
+            }
+        }
+        if generate_features {
+            let features = self.features_per_step()?;
+            let seed = self.random_state.seed();
+            let mut level = WorldGenRegion::new(&mut chunks).with_biome_zoom(seed);
+            for x in min_x - 1..=max_x + 1 {
+                for z in min_z - 1..=max_z + 1 {
+                    features.apply_biome_decoration(&mut level, seed, x, z);
+                }
+            }
+        }
+        chunks.retain(|c| (min_x..=max_x).contains(&c.x) && (min_z..=max_z).contains(&c.z));
+        for chunk in &mut chunks {
+            chunk.compute_heightmaps();
+        }
+        Ok(chunks)
+    }
+}
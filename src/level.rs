@@ -1,17 +1,40 @@
+use crate::nbt_util::{self, get, get_compound, invalid_data, opt, opt_compound};
 use nbt::{Tag, Writer};
 use std::collections::HashMap;
 use std::fs::File;
+use std::io;
 
+/// A boss bar made with `/bossbar`.
+pub struct CustomBossEvent {
+    /// The title, a text component as stored in NBT.
+    pub name: Tag,
+    pub color: String,
+    pub overlay: String,
+    pub value: i32,
+    pub max: i32,
+    pub visible: bool,
+    /// UUIDs of the players seeing the bar.
+    pub players: Vec<[i32; 4]>,
+    pub create_world_fog: bool,
+    pub darken_screen: bool,
+    pub play_boss_music: bool,
+}
+
+/// Custom boss bars, by id.
+#[derive(Default)]
 pub struct CustomBossEvents {
-    boss: Vec<String>,
+    pub events: HashMap<String, CustomBossEvent>,
 }
 
-impl Default for CustomBossEvents {
-    fn default() -> Self {
-        Self {
-            boss: ["".into()].into(),
-        }
-    }
+/// A function scheduled with `/schedule`.
+pub struct ScheduledEvent {
+    /// The event's key, the function or tag id it runs.
+    pub name: String,
+    /// Game time at which it runs.
+    pub trigger_time: i64,
+    /// `function` or `function_tag`.
+    pub callback_type: String,
+    pub callback_name: String,
 }
 
 pub struct DataPacks {
@@ -72,6 +95,8 @@ pub enum Dimension {
     Overworld,
     End,
     Nether,
+    /// A datapack dimension type, by id.
+    Custom(String),
 }
 
 pub struct WorldGenSettings {
@@ -106,7 +131,7 @@ pub struct LevelDat {
     pub game_rules: GameRules,
     pub version: VersionInfo,
     pub world_gen_settings: WorldGenSettings,
-    pub scheduled_events: Vec<String>,
+    pub scheduled_events: Vec<ScheduledEvent>,
     pub server_brands: ServerBrands,
 
     pub allow_commands: bool,
@@ -154,7 +179,7 @@ impl Default for LevelDat {
             game_rules: GameRules::default(),
             version: VersionInfo::default(),
             world_gen_settings: WorldGenSettings::default(),
-            scheduled_events: ["".into()].into(),
+            scheduled_events: Vec::new(),
             server_brands: ServerBrands::default(),
             allow_commands: false,
             border_center_x: 0.0,
@@ -192,15 +217,33 @@ impl Default for LevelDat {
         }
     }
 }
-fn dim_to_str(d: &Dimension) -> &'static str {
+fn dim_to_str(d: &Dimension) -> &str {
     match d {
         Dimension::Overworld => "overworld",
         Dimension::End => "the_end",
         Dimension::Nether => "the_nether",
+        Dimension::Custom(id) => id,
+    }
+}
+
+pub(crate) fn dim_from_str(s: &str) -> Option<Dimension> {
+    match s.strip_prefix("minecraft:").unwrap_or(s) {
+        "overworld" => Some(Dimension::Overworld),
+        "the_end" => Some(Dimension::End),
+        "the_nether" => Some(Dimension::Nether),
+        _ => None,
     }
 }
 
 pub fn create_nbt(level: &LevelDat, path: &str) -> std::io::Result<()> {
+    let root = to_nbt(level);
+    let file = File::create(path)?;
+    let mut w = Writer::to_gzip(file);
+    w.write_tag(&root)?;
+    Ok(())
+}
+
+pub fn to_nbt(level: &LevelDat) -> Tag {
     let mut root = Tag::new_compound("Data");
 
     // --- primitives (root) ---
@@ -472,19 +515,292 @@ pub fn create_nbt(level: &LevelDat, path: &str) -> std::io::Result<()> {
         Tag::new_list("ServerBrands", 8, brands_list),
     );
 
-    // --- CustomBossEvents / ScheduledEvents (empty compounds for now) ---
-    root.insert(
-        "CustomBossEvents".to_string(),
-        Tag::new_compound("CustomBossEvents"),
-    );
+    // --- CustomBossEvents ---
+    let mut bosses = Tag::new_compound("CustomBossEvents");
+    for (id, event) in &level.custom_boss_events.events {
+        bosses.insert(id.clone(), boss_event_to_nbt(id, event));
+    }
+    root.insert("CustomBossEvents".to_string(), bosses);
+
+    // --- ScheduledEvents ---
+    let events: Vec<Tag> = level
+        .scheduled_events
+        .iter()
+        .map(scheduled_event_to_nbt)
+        .collect();
+    let kind = if events.is_empty() { 0 } else { 10 };
     root.insert(
         "ScheduledEvents".to_string(),
-        Tag::new_compound("ScheduledEvents"),
+        Tag::new_list("ScheduledEvents", kind, events),
     );
 
-    // --- write ---
-    let file = File::create(path)?;
-    let mut w = Writer::to_gzip(file);
-    w.write_tag(&root)?;
-    Ok(())
+    root
+}
+
+fn boss_event_to_nbt(id: &str, event: &CustomBossEvent) -> Tag {
+    let mut tag = Tag::new_compound(id);
+    tag.insert("Name".into(), event.name.clone());
+    tag.insert(
+        "Color".into(),
+        Tag::new_string("Color", event.color.clone()),
+    );
+    tag.insert(
+        "Overlay".into(),
+        Tag::new_string("Overlay", event.overlay.clone()),
+    );
+    tag.insert("Value".into(), Tag::new_int("Value", event.value));
+    tag.insert("Max".into(), Tag::new_int("Max", event.max));
+    tag.insert(
+        "Visible".into(),
+        Tag::new_byte("Visible", i8::from(event.visible)),
+    );
+    let players = event
+        .players
+        .iter()
+        .map(|uuid| Tag::new_int_array("", uuid.to_vec()))
+        .collect();
+    tag.insert("Players".into(), Tag::new_list("Players", 11, players));
+    tag.insert(
+        "CreateWorldFog".into(),
+        Tag::new_byte("CreateWorldFog", i8::from(event.create_world_fog)),
+    );
+    tag.insert(
+        "DarkenScreen".into(),
+        Tag::new_byte("DarkenScreen", i8::from(event.darken_screen)),
+    );
+    tag.insert(
+        "PlayBossMusic".into(),
+        Tag::new_byte("PlayBossMusic", i8::from(event.play_boss_music)),
+    );
+    tag
+}
+
+fn boss_event_from_nbt(id: &str, tag: &Tag) -> io::Result<CustomBossEvent> {
+    let name = tag
+        .get("Name")
+        .cloned()
+        .unwrap_or_else(|| Tag::new_string("Name", id));
+    let players = opt::<&[Tag]>(tag, "Players")?
+        .unwrap_or_default()
+        .iter()
+        .map(|uuid| {
+            uuid.as_int_array()
+                .and_then(|uuid| uuid.try_into().ok())
+                .ok_or_else(|| invalid_data(format!("boss bar `{id}` has a bad player UUID")))
+        })
+        .collect::<io::Result<_>>()?;
+    Ok(CustomBossEvent {
+        name,
+        color: opt::<&str>(tag, "Color")?.unwrap_or("white").to_string(),
+        overlay: opt::<&str>(tag, "Overlay")?
+            .unwrap_or("progress")
+            .to_string(),
+        value: opt(tag, "Value")?.unwrap_or(0),
+        max: opt(tag, "Max")?.unwrap_or(100),
+        visible: opt(tag, "Visible")?.unwrap_or(true),
+        players,
+        create_world_fog: opt(tag, "CreateWorldFog")?.unwrap_or(false),
+        darken_screen: opt(tag, "DarkenScreen")?.unwrap_or(false),
+        play_boss_music: opt(tag, "PlayBossMusic")?.unwrap_or(false),
+    })
+}
+
+fn scheduled_event_to_nbt(event: &ScheduledEvent) -> Tag {
+    let mut callback = Tag::new_compound("Callback");
+    callback.insert(
+        "Type".into(),
+        Tag::new_string("Type", event.callback_type.clone()),
+    );
+    callback.insert(
+        "Name".into(),
+        Tag::new_string("Name", event.callback_name.clone()),
+    );
+    let mut tag = Tag::new_compound("");
+    tag.insert("Name".into(), Tag::new_string("Name", event.name.clone()));
+    tag.insert(
+        "TriggerTime".into(),
+        Tag::new_long("TriggerTime", event.trigger_time),
+    );
+    tag.insert("Callback".into(), callback);
+    tag
+}
+
+fn scheduled_event_from_nbt(tag: &Tag) -> io::Result<ScheduledEvent> {
+    let callback = get_compound(tag, "Callback")?;
+    Ok(ScheduledEvent {
+        name: get::<&str>(tag, "Name")?.to_string(),
+        trigger_time: get(tag, "TriggerTime")?,
+        callback_type: get::<&str>(callback, "Type")?.to_string(),
+        callback_name: get::<&str>(callback, "Name")?.to_string(),
+    })
+}
+
+fn string_list(tag: &Tag, key: &str) -> io::Result<Option<Vec<String>>> {
+    let Some(list) = opt::<&[Tag]>(tag, key)? else {
+        return Ok(None);
+    };
+    list.iter()
+        .map(|t| {
+            t.as_string()
+                .map(str::to_string)
+                .ok_or_else(|| invalid_data(format!("`{key}` entry is not a string")))
+        })
+        .collect::<io::Result<Vec<_>>>()
+        .map(Some)
+}
+
+impl LevelDat {
+    /// Read a gzip-compressed `level.dat`.
+    pub fn read(path: &str) -> io::Result<Self> {
+        Self::from_nbt(&nbt_util::read_gzip_file(path)?)
+    }
+
+    /// Decode a `level.dat` tree. Accepts both the vanilla layout, where the
+    /// fields sit in a `Data` child, and the flat layout written by
+    /// [`create_nbt`]. Missing fields keep their [`Default`] value.
+    pub fn from_nbt(tag: &Tag) -> io::Result<Self> {
+        let data = opt_compound(tag, "Data")?.unwrap_or(tag);
+        let d = LevelDat::default();
+
+        let mut game_rules = d.game_rules;
+        if let Some(rules) = opt_compound(data, "GameRules")? {
+            for (k, v) in rules.as_compound().into_iter().flatten() {
+                let v = v
+                    .as_string()
+                    .ok_or_else(|| invalid_data(format!("game rule `{k}` is not a string")))?;
+                game_rules.insert(k.clone(), v.to_string());
+            }
+        }
+
+        let mut data_packs = d.data_packs;
+        if let Some(dp) = opt_compound(data, "DataPacks")? {
+            if let Some(enabled) = string_list(dp, "Enabled")? {
+                data_packs.enabled = enabled;
+            }
+            if let Some(disabled) = string_list(dp, "Disabled")? {
+                data_packs.disabled = disabled;
+            }
+        }
+
+        let mut dragon_fight = d.dragon_fight;
+        if let Some(df) = opt_compound(data, "DragonFight")? {
+            if let Some(gateways) = opt::<&[Tag]>(df, "Gateways")? {
+                dragon_fight.gateways = gateways.iter().filter_map(Tag::as_int).collect();
+            }
+            let f = &mut dragon_fight;
+            f.dragon_killed = opt(df, "DragonKilled")?.unwrap_or(f.dragon_killed);
+            f.needs_state_scanning =
+                opt(df, "NeedsStateScanning")?.unwrap_or(f.needs_state_scanning);
+            f.previously_killed = opt(df, "PreviouslyKilled")?.unwrap_or(f.previously_killed);
+        }
+
+        let mut version = d.version;
+        if let Some(v) = opt_compound(data, "Version")? {
+            version.id = opt(v, "Id")?.unwrap_or(version.id);
+            if let Some(name) = opt::<&str>(v, "Name")? {
+                version.name = name.to_string();
+            }
+            if let Some(series) = opt::<&str>(v, "Series")? {
+                version.series = series.to_string();
+            }
+            version.snapshot = opt(v, "Snapshot")?.unwrap_or(version.snapshot);
+        }
+
+        let mut world_gen_settings = d.world_gen_settings;
+        if let Some(wgs) = opt_compound(data, "WorldGenSettings")? {
+            let w = &mut world_gen_settings;
+            w.seed = opt(wgs, "seed")?.unwrap_or(w.seed);
+            w.generate_features = opt(wgs, "generate_features")?.unwrap_or(w.generate_features);
+            w.bonus_chest = opt(wgs, "bonus_chest")?.unwrap_or(w.bonus_chest);
+            if let Some(dims) = opt_compound(wgs, "dimensions")? {
+                w.dimensions.clear();
+                for (name, dim) in dims.as_compound().into_iter().flatten() {
+                    // Cactus writes the type as a string, vanilla as a
+                    // compound with a `type` entry, which is either an id or
+                    // the dimension type itself.
+                    let kind = match dim.as_string() {
+                        Some(kind) => kind,
+                        None => match dim.get("type") {
+                            Some(kind) => kind.as_string().unwrap_or(name),
+                            None => name,
+                        },
+                    };
+                    let dim = dim_from_str(kind).unwrap_or_else(|| Dimension::Custom(kind.into()));
+                    w.dimensions.insert(name.clone(), dim);
+                }
+            }
+        } else {
+            // Pre-1.16 worlds only carry the legacy keys.
+            let w = &mut world_gen_settings;
+            w.seed = opt(data, "RandomSeed")?.unwrap_or(w.seed);
+            w.generate_features = opt(data, "MapFeatures")?.unwrap_or(w.generate_features);
+        }
+
+        let mut custom_boss_events = d.custom_boss_events;
+        if let Some(bosses) = opt_compound(data, "CustomBossEvents")? {
+            for (id, boss) in bosses.as_compound().into_iter().flatten() {
+                let event = boss_event_from_nbt(id, boss)?;
+                custom_boss_events.events.insert(id.clone(), event);
+            }
+        }
+        let scheduled_events = match opt::<&[Tag]>(data, "ScheduledEvents")? {
+            Some(events) => events
+                .iter()
+                .map(scheduled_event_from_nbt)
+                .collect::<io::Result<_>>()?,
+            None => d.scheduled_events,
+        };
+
+        Ok(Self {
+            custom_boss_events,
+            data_packs,
+            dragon_fight,
+            game_rules,
+            version,
+            world_gen_settings,
+            scheduled_events,
+            server_brands: string_list(data, "ServerBrands")?.unwrap_or(d.server_brands),
+            allow_commands: opt(data, "allowCommands")?.unwrap_or(d.allow_commands),
+            border_center_x: opt(data, "BorderCenterX")?.unwrap_or(d.border_center_x),
+            border_center_z: opt(data, "BorderCenterZ")?.unwrap_or(d.border_center_z),
+            border_damage_per_block: opt(data, "BorderDamagePerBlock")?
+                .unwrap_or(d.border_damage_per_block),
+            border_safe_zone: opt(data, "BorderSafeZone")?.unwrap_or(d.border_safe_zone),
+            border_size: opt(data, "BorderSize")?.unwrap_or(d.border_size),
+            border_size_lerp_target: opt(data, "BorderSizeLerpTarget")?
+                .unwrap_or(d.border_size_lerp_target),
+            border_size_lerp_time: opt(data, "BorderSizeLerpTime")?
+                .unwrap_or(d.border_size_lerp_time),
+            border_warning_blocks: opt(data, "BorderWarningBlocks")?
+                .unwrap_or(d.border_warning_blocks),
+            border_warning_time: opt(data, "BorderWarningTime")?.unwrap_or(d.border_warning_time),
+            clear_weather_time: opt(data, "clearWeatherTime")?.unwrap_or(d.clear_weather_time),
+            data_version: opt(data, "DataVersion")?.unwrap_or(d.data_version),
+            day_time: opt(data, "DayTime")?.unwrap_or(d.day_time),
+            difficulty: opt(data, "Difficulty")?.unwrap_or(d.difficulty),
+            difficulty_locked: opt(data, "DifficultyLocked")?.unwrap_or(d.difficulty_locked),
+            game_type: opt(data, "GameType")?.unwrap_or(d.game_type),
+            hardcore: opt(data, "hardcore")?.unwrap_or(d.hardcore),
+            initialized: opt(data, "initialized")?.unwrap_or(d.initialized),
+            last_played: opt(data, "LastPlayed")?.unwrap_or(d.last_played),
+            level_name: opt::<&str>(data, "LevelName")?
+                .map(str::to_string)
+                .unwrap_or(d.level_name),
+            raining: opt(data, "raining")?.unwrap_or(d.raining),
+            rain_time: opt(data, "RainTime")?.unwrap_or(d.rain_time),
+            spawn_angle: opt(data, "SpawnAngle")?.unwrap_or(d.spawn_angle),
+            spawn_x: opt(data, "SpawnX")?.unwrap_or(d.spawn_x),
+            spawn_y: opt(data, "SpawnY")?.unwrap_or(d.spawn_y),
+            spawn_z: opt(data, "SpawnZ")?.unwrap_or(d.spawn_z),
+            thundering: opt(data, "thundering")?.unwrap_or(d.thundering),
+            thunder_time: opt(data, "ThunderTime")?.unwrap_or(d.thunder_time),
+            time: opt(data, "Time")?.unwrap_or(d.time),
+            version_id: opt(data, "version")?.unwrap_or(d.version_id),
+            wandering_trader_spawn_chance: opt(data, "WanderingTraderSpawnChance")?
+                .unwrap_or(d.wandering_trader_spawn_chance),
+            wandering_trader_spawn_delay: opt(data, "WanderingTraderSpawnDelay")?
+                .unwrap_or(d.wandering_trader_spawn_delay),
+            was_modded: opt(data, "WasModded")?.unwrap_or(d.was_modded),
+        })
+    }
 }
@@ -1,6 +1,31 @@
+pub mod aquifer;
+pub mod biome_source;
+pub mod carver;
+pub mod caves;
+pub mod chunk;
+pub mod climate;
+pub mod datapack;
+pub mod decoration;
+pub mod density;
+pub mod feature;
+pub mod generator;
 pub mod level;
+mod nbt_util;
+pub mod noise;
+pub mod noise_router;
+pub mod ore_vein;
+pub mod packet;
+pub mod palette;
 pub mod perlin;
+pub mod placement;
 pub mod player;
+pub mod protocol;
+pub mod provider;
+pub mod random;
+pub mod region;
+pub mod registry;
 pub mod superflat;
+pub mod surface;
 #[cfg(test)]
 mod test;
+pub mod tree;
@@ -0,0 +1,121 @@
+//! Shared helpers for reading typed values out of NBT compounds.
+//!
+//! Every lookup reports the offending key, so a broken save file points at the
+//! exact tag that is missing or has the wrong type.
+
+use nbt::{Reader, Tag, Writer};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+pub(crate) fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn missing(key: &str) -> io::Error {
+    invalid_data(format!("missing tag `{key}`"))
+}
+
+fn mistyped(key: &str, expected: &str) -> io::Error {
+    invalid_data(format!("tag `{key}` is not {expected}"))
+}
+
+/// Read a gzip-compressed NBT file such as `level.dat` or player data.
+pub(crate) fn read_gzip_file(path: impl AsRef<Path>) -> io::Result<Tag> {
+    let file = File::open(path)?;
+    Reader::from_gzip(file).read_tag()
+}
+
+/// Decode an uncompressed NBT payload, e.g. a chunk read from a region file.
+pub(crate) fn from_bytes(bytes: &[u8]) -> io::Result<Tag> {
+    Reader::new(bytes).read_tag()
+}
+
+/// Encode a tag as uncompressed NBT.
+pub(crate) fn to_bytes(tag: &Tag) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    Writer::new(&mut out).write_tag(tag)?;
+    Ok(out)
+}
+
+/// Encode a tag as network NBT: since 1.20.2 the root carries no name, only
+/// its type byte followed by the payload.
+pub(crate) fn to_network_bytes(tag: &Tag) -> io::Result<Vec<u8>> {
+    let mut out = to_bytes(tag)?;
+    let name_len = 2 + tag.name().len();
+    if out.len() > name_len {
+        out.drain(1..1 + name_len);
+    }
+    Ok(out)
+}
+
+/// Typed view of a tag's payload, used by [`get`] and [`opt`].
+pub(crate) trait FromTag<'a>: Sized {
+    /// Human-readable type name for error messages.
+    const EXPECTED: &'static str;
+    fn from_tag(tag: &'a Tag) -> Option<Self>;
+}
+
+macro_rules! from_tag {
+    ($($ty:ty, $as:ident, $what:literal;)*) => {
+        $(
+            impl<'a> FromTag<'a> for $ty {
+                const EXPECTED: &'static str = $what;
+                fn from_tag(tag: &'a Tag) -> Option<Self> {
+                    tag.$as()
+                }
+            }
+        )*
+    };
+}
+
+from_tag! {
+    i8, as_byte, "a byte";
+    i16, as_short, "a short";
+    i32, as_int, "an int";
+    i64, as_long, "a long";
+    f32, as_float, "a float";
+    f64, as_double, "a double";
+    &'a str, as_string, "a string";
+    &'a [Tag], as_list, "a list";
+    &'a [i8], as_byte_array, "a byte array";
+    &'a [i32], as_int_array, "an int array";
+    &'a [i64], as_long_array, "a long array";
+}
+
+impl<'a> FromTag<'a> for bool {
+    const EXPECTED: &'static str = "a byte";
+    fn from_tag(tag: &'a Tag) -> Option<Self> {
+        tag.as_byte().map(|b| b != 0)
+    }
+}
+
+/// Required value; errors when the tag is missing or has another type.
+pub(crate) fn get<'a, T: FromTag<'a>>(tag: &'a Tag, key: &str) -> io::Result<T> {
+    let child = tag.get(key).ok_or_else(|| missing(key))?;
+    T::from_tag(child).ok_or_else(|| mistyped(key, T::EXPECTED))
+}
+
+/// Optional value; only errors when the tag exists with another type.
+pub(crate) fn opt<'a, T: FromTag<'a>>(tag: &'a Tag, key: &str) -> io::Result<Option<T>> {
+    match tag.get(key) {
+        None => Ok(None),
+        Some(child) => T::from_tag(child)
+            .map(Some)
+            .ok_or_else(|| mistyped(key, T::EXPECTED)),
+    }
+}
+
+/// Optional compound; errors when the tag exists but is not a compound.
+pub(crate) fn opt_compound<'a>(tag: &'a Tag, key: &str) -> io::Result<Option<&'a Tag>> {
+    match tag.get(key) {
+        None => Ok(None),
+        Some(t) if t.as_compound().is_some() => Ok(Some(t)),
+        Some(_) => Err(mistyped(key, "a compound")),
+    }
+}
+
+/// Required compound.
+pub(crate) fn get_compound<'a>(tag: &'a Tag, key: &str) -> io::Result<&'a Tag> {
+    opt_compound(tag, key)?.ok_or_else(|| missing(key))
+}
@@ -0,0 +1,620 @@
+//! Vanilla's gradient noises: `ImprovedNoise`, the octave sum `PerlinNoise`
+//! and the paired `NormalNoise` behind every climate parameter and density
+//! noise. Seeded from [`crate::random`], they sample the same values as the
+//! game for the same world seed.
+
+use crate::random::RandomSource;
+use std::ops::RangeInclusive;
+use std::sync::OnceLock;
+
+/// The 16 gradient directions shared with `SimplexNoise`: the 12 cube edges
+/// plus four repeats.
+const GRADIENT: [[f64; 3]; 16] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+    [1.0, 1.0, 0.0],
+    [0.0, -1.0, 1.0],
+    [-1.0, 1.0, 0.0],
+    [0.0, -1.0, -1.0],
+];
+
+/// `Mth.floor`.
+#[inline]
+pub fn floor(d: f64) -> i32 {
+    d.floor() as i32
+}
+
+/// `Mth.lfloor`.
+#[inline]
+pub fn lfloor(d: f64) -> i64 {
+    d.floor() as i64
+}
+
+/// `Mth.smoothstep`: 6t⁵ − 15t⁴ + 10t³.
+#[inline]
+pub fn smoothstep(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+/// `Mth.lerp`.
+#[inline]
+pub fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+/// `Mth.lerp2`.
+#[inline]
+pub fn lerp2(tx: f64, ty: f64, a: f64, b: f64, c: f64, d: f64) -> f64 {
+    lerp(ty, lerp(tx, a, b), lerp(tx, c, d))
+}
+
+/// `Mth.lerp3`.
+#[inline]
+#[allow(clippy::too_many_arguments)]
+pub fn lerp3(
+    tx: f64,
+    ty: f64,
+    tz: f64,
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+    e: f64,
+    f: f64,
+    g: f64,
+    h: f64,
+) -> f64 {
+    lerp(tz, lerp2(tx, ty, a, b, c, d), lerp2(tx, ty, e, f, g, h))
+}
+
+/// `Mth`'s 65536-entry sine table. Carvers and features turn with it, so
+/// their shapes depend on its rounding.
+fn sin_table() -> &'static [f32; 65536] {
+    static TABLE: OnceLock<Box<[f32; 65536]>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = Box::new([0.0; 65536]);
+        for (i, v) in table.iter_mut().enumerate() {
+            *v = (i as f64 * std::f64::consts::PI * 2.0 / 65536.0).sin() as f32;
+        }
+        table
+    })
+}
+
+/// `Mth.sin`, looked up in the table.
+#[inline]
+pub fn sin(d: f64) -> f32 {
+    sin_table()[((d * 10430.378350470453) as i64 & 65535) as usize]
+}
+
+/// `Mth.cos`, looked up in the table.
+#[inline]
+pub fn cos(d: f64) -> f32 {
+    sin_table()[((d * 10430.378350470453 + 16384.0) as i64 & 65535) as usize]
+}
+
+#[inline]
+fn grad_dot(hash: u8, x: f64, y: f64, z: f64) -> f64 {
+    let g = GRADIENT[(hash & 15) as usize];
+    g[0] * x + g[1] * y + g[2] * z
+}
+
+/// Ken Perlin's improved noise with a shuffled permutation table and a
+/// random origin offset, both drawn from the seeding source.
+#[derive(Clone, Debug)]
+pub struct ImprovedNoise {
+    p: [u8; 256],
+    pub xo: f64,
+    pub yo: f64,
+    pub zo: f64,
+}
+
+impl ImprovedNoise {
+    pub fn new(random: &mut dyn RandomSource) -> Self {
+        let xo = random.next_double() * 256.0;
+        let yo = random.next_double() * 256.0;
+        let zo = random.next_double() * 256.0;
+        let mut p = [0u8; 256];
+        for (i, v) in p.iter_mut().enumerate() {
+            *v = i as u8;
+        }
+        for i in 0..256 {
+            let j = random.next_int_bounded(256 - i as i32) as usize;
+            p.swap(i, i + j);
+        }
+        Self { p, xo, yo, zo }
+    }
+
+    #[inline]
+    fn p(&self, i: i32) -> i32 {
+        i32::from(self.p[(i & 0xFF) as usize])
+    }
+
+    pub fn noise(&self, x: f64, y: f64, z: f64) -> f64 {
+        self.noise_scaled(x, y, z, 0.0, 0.0)
+    }
+
+    /// Noise with the y input snapped to multiples of `y_scale` (never above
+    /// `y_max`) while the y fade keeps the unsnapped fraction, which gives
+    /// the stepped look of old terrain.
+    pub fn noise_scaled(&self, x: f64, y: f64, z: f64, y_scale: f64, y_max: f64) -> f64 {
+        let (d, e, f) = (x + self.xo, y + self.yo, z + self.zo);
+        let (i, j, k) = (floor(d), floor(e), floor(f));
+        let (g, h, l) = (d - i as f64, e - j as f64, f - k as f64);
+        let o = if y_scale != 0.0 {
+            let m = if y_max >= 0.0 && y_max < h { y_max } else { h };
+            floor(m / y_scale + f64::from(1.0E-7f32)) as f64 * y_scale
+        } else {
+            0.0
+        };
+        self.sample_and_lerp(i, j, k, g, h - o, l, h)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn sample_and_lerp(
+        &self,
+        x: i32,
+        y: i32,
+        z: i32,
+        dx: f64,
+        dy: f64,
+        dz: f64,
+        fade_y: f64,
+    ) -> f64 {
+        let i = self.p(x);
+        let j = self.p(x + 1);
+        let k = self.p(i + y);
+        let l = self.p(i + y + 1);
+        let m = self.p(j + y);
+        let n = self.p(j + y + 1);
+        let hash = |v: i32| self.p(v) as u8;
+        let d = grad_dot(hash(k + z), dx, dy, dz);
+        let e = grad_dot(hash(m + z), dx - 1.0, dy, dz);
+        let f = grad_dot(hash(l + z), dx, dy - 1.0, dz);
+        let g = grad_dot(hash(n + z), dx - 1.0, dy - 1.0, dz);
+        let h = grad_dot(hash(k + z + 1), dx, dy, dz - 1.0);
+        let o = grad_dot(hash(m + z + 1), dx - 1.0, dy, dz - 1.0);
+        let q = grad_dot(hash(l + z + 1), dx, dy - 1.0, dz - 1.0);
+        let r = grad_dot(hash(n + z + 1), dx - 1.0, dy - 1.0, dz - 1.0);
+        lerp3(
+            smoothstep(dx),
+            smoothstep(fade_y),
+            smoothstep(dz),
+            d,
+            e,
+            f,
+            g,
+            h,
+            o,
+            q,
+            r,
+        )
+    }
+}
+
+/// Keep coordinates small enough that the `f64` fraction stays precise, by
+/// wrapping them every 2²⁵ blocks.
+#[inline]
+pub fn wrap(d: f64) -> f64 {
+    const ROUND_OFF: f64 = 33554432.0;
+    d - lfloor(d / ROUND_OFF + 0.5) as f64 * ROUND_OFF
+}
+
+/// Octave settings of a noise, as in `worldgen/noise/*.json`: the lowest
+/// octave (`-7` samples at 1/128 frequency) and one amplitude per octave.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NoiseParameters {
+    pub first_octave: i32,
+    pub amplitudes: Vec<f64>,
+}
+
+impl NoiseParameters {
+    pub fn new(first_octave: i32, amplitudes: Vec<f64>) -> Self {
+        Self {
+            first_octave,
+            amplitudes,
+        }
+    }
+}
+
+/// A sum of [`ImprovedNoise`] octaves, each double the frequency and half
+/// the weight of the previous, scaled by its amplitude. Octaves with a zero
+/// amplitude are skipped entirely.
+#[derive(Clone, Debug)]
+pub struct PerlinNoise {
+    levels: Vec<Option<ImprovedNoise>>,
+    amplitudes: Vec<f64>,
+    first_octave: i32,
+    lowest_freq_input_factor: f64,
+    lowest_freq_value_factor: f64,
+    max_value: f64,
+}
+
+impl PerlinNoise {
+    /// Modern seeding: octave `n` gets its own source from the hash of
+    /// `octave_<n>`.
+    pub fn create(random: &mut dyn RandomSource, first_octave: i32, amplitudes: &[f64]) -> Self {
+        let factory = random.fork_positional();
+        let levels = amplitudes
+            .iter()
+            .enumerate()
+            .map(|(i, &a)| {
+                (a != 0.0).then(|| {
+                    let octave = first_octave + i as i32;
+                    ImprovedNoise::new(&mut *factory.with_hash_of(&format!("octave_{octave}")))
+                })
+            })
+            .collect();
+        Self::with_levels(levels, first_octave, amplitudes.to_vec())
+    }
+
+    /// Legacy seeding used by `BlendedNoise`: all octaves are drawn in
+    /// sequence from one source, highest first, skipping 262 values for
+    /// each unused octave.
+    pub fn create_legacy_for_blended_noise(
+        random: &mut dyn RandomSource,
+        octaves: RangeInclusive<i32>,
+    ) -> Self {
+        let first_octave = *octaves.start();
+        Self::create_legacy(random, first_octave, &vec![1.0; octaves.count()])
+    }
+
+    /// Legacy seeding with arbitrary amplitudes, used by the nether biome
+    /// noises of worlds with `legacy_random_source`.
+    pub fn create_legacy(
+        random: &mut dyn RandomSource,
+        first_octave: i32,
+        amplitudes: &[f64],
+    ) -> Self {
+        let count = amplitudes.len();
+        assert!(
+            first_octave + count as i32 <= 1,
+            "positive octaves are not supported by legacy seeding"
+        );
+        let zero = (-first_octave) as usize;
+
+        let mut levels: Vec<Option<ImprovedNoise>> = vec![None; count];
+        let first = ImprovedNoise::new(random);
+        if zero < count && amplitudes[zero] != 0.0 {
+            levels[zero] = Some(first);
+        }
+        for i in (0..zero).rev() {
+            if i < count && amplitudes[i] != 0.0 {
+                levels[i] = Some(ImprovedNoise::new(random));
+            } else {
+                random.consume_count(262);
+            }
+        }
+        Self::with_levels(levels, first_octave, amplitudes.to_vec())
+    }
+
+    fn with_levels(
+        levels: Vec<Option<ImprovedNoise>>,
+        first_octave: i32,
+        amplitudes: Vec<f64>,
+    ) -> Self {
+        let n = amplitudes.len() as i32;
+        let mut noise = Self {
+            levels,
+            amplitudes,
+            first_octave,
+            lowest_freq_input_factor: 2f64.powi(first_octave),
+            lowest_freq_value_factor: 2f64.powi(n - 1) / (2f64.powi(n) - 1.0),
+            max_value: 0.0,
+        };
+        noise.max_value = noise.edge_value(2.0);
+        noise
+    }
+
+    pub fn first_octave(&self) -> i32 {
+        self.first_octave
+    }
+
+    pub fn amplitudes(&self) -> &[f64] {
+        &self.amplitudes
+    }
+
+    pub fn get_value(&self, x: f64, y: f64, z: f64) -> f64 {
+        self.get_value_scaled(x, y, z, 0.0, 0.0, false)
+    }
+
+    /// Full sampler: `y_scale`/`y_max` are passed to
+    /// [`ImprovedNoise::noise_scaled`], and `use_fixed_y` samples every
+    /// octave at its own origin height, making the noise 2D.
+    pub fn get_value_scaled(
+        &self,
+        x: f64,
+        y: f64,
+        z: f64,
+        y_scale: f64,
+        y_max: f64,
+        use_fixed_y: bool,
+    ) -> f64 {
+        let mut sum = 0.0;
+        let mut input = self.lowest_freq_input_factor;
+        let mut value = self.lowest_freq_value_factor;
+        for (level, amplitude) in self.levels.iter().zip(&self.amplitudes) {
+            if let Some(noise) = level {
+                let y = if use_fixed_y {
+                    -noise.yo
+                } else {
+                    wrap(y * input)
+                };
+                let n = noise.noise_scaled(
+                    wrap(x * input),
+                    y,
+                    wrap(z * input),
+                    y_scale * input,
+                    y_max * input,
+                );
+                sum += amplitude * n * value;
+            }
+            input *= 2.0;
+            value /= 2.0;
+        }
+        sum
+    }
+
+    /// Octave `i` counted from the highest frequency down.
+    pub fn get_octave_noise(&self, i: usize) -> Option<&ImprovedNoise> {
+        self.levels.iter().rev().nth(i)?.as_ref()
+    }
+
+    /// Largest value the sum could reach if every octave returned `d`.
+    pub fn edge_value(&self, d: f64) -> f64 {
+        let mut sum = 0.0;
+        let mut value = self.lowest_freq_value_factor;
+        for (level, amplitude) in self.levels.iter().zip(&self.amplitudes) {
+            if level.is_some() {
+                sum += amplitude * d * value;
+            }
+            value /= 2.0;
+        }
+        sum
+    }
+
+    pub fn max_value(&self) -> f64 {
+        self.max_value
+    }
+
+    pub fn max_broken_value(&self, d: f64) -> f64 {
+        self.edge_value(d + 2.0)
+    }
+}
+
+/// Two [`PerlinNoise`]s with the second sampled at a slightly stretched
+/// position, scaled so the sum has roughly unit spread. Every named noise
+/// in `worldgen/noise` is one of these.
+#[derive(Clone, Debug)]
+pub struct NormalNoise {
+    first: PerlinNoise,
+    second: PerlinNoise,
+    value_factor: f64,
+    max_value: f64,
+    parameters: NoiseParameters,
+}
+
+impl NormalNoise {
+    const INPUT_FACTOR: f64 = 1.0181268882175227;
+    const TARGET_DEVIATION: f64 = 0.3333333333333333;
+
+    pub fn create(random: &mut dyn RandomSource, parameters: &NoiseParameters) -> Self {
+        let first = PerlinNoise::create(random, parameters.first_octave, &parameters.amplitudes);
+        let second = PerlinNoise::create(random, parameters.first_octave, &parameters.amplitudes);
+        Self::from_octaves(first, second, parameters)
+    }
+
+    /// Legacy-seeded variant used for the temperature and vegetation noises
+    /// of dimensions with `legacy_random_source`.
+    pub fn create_legacy_nether_biome(
+        random: &mut dyn RandomSource,
+        parameters: &NoiseParameters,
+    ) -> Self {
+        let (octave, amplitudes) = (parameters.first_octave, &parameters.amplitudes);
+        let first = PerlinNoise::create_legacy(random, octave, amplitudes);
+        let second = PerlinNoise::create_legacy(random, octave, amplitudes);
+        Self::from_octaves(first, second, parameters)
+    }
+
+    fn from_octaves(first: PerlinNoise, second: PerlinNoise, parameters: &NoiseParameters) -> Self {
+        let used: Vec<usize> = parameters
+            .amplitudes
+            .iter()
+            .enumerate()
+            .filter(|(_, a)| **a != 0.0)
+            .map(|(i, _)| i)
+            .collect();
+        let span = match (used.first(), used.last()) {
+            (Some(lo), Some(hi)) => (hi - lo) as i32,
+            // No octaves: vanilla's min/max sentinels overflow to this.
+            _ => i32::MIN.wrapping_sub(i32::MAX),
+        };
+        let value_factor = Self::TARGET_DEVIATION / 2.0 / expected_deviation(span);
+        let max_value = (first.max_value() + second.max_value()) * value_factor;
+        Self {
+            first,
+            second,
+            value_factor,
+            max_value,
+            parameters: parameters.clone(),
+        }
+    }
+
+    pub fn get_value(&self, x: f64, y: f64, z: f64) -> f64 {
+        let f = Self::INPUT_FACTOR;
+        (self.first.get_value(x, y, z) + self.second.get_value(x * f, y * f, z * f))
+            * self.value_factor
+    }
+
+    pub fn max_value(&self) -> f64 {
+        self.max_value
+    }
+
+    pub fn parameters(&self) -> &NoiseParameters {
+        &self.parameters
+    }
+}
+
+fn expected_deviation(octaves: i32) -> f64 {
+    0.1 * (1.0 + 1.0 / f64::from(octaves.wrapping_add(1)))
+}
+
+/// Simplex noise, only used in 2D by the End's island shape.
+#[derive(Clone, Debug)]
+pub struct SimplexNoise {
+    p: [u8; 256],
+    pub xo: f64,
+    pub yo: f64,
+    pub zo: f64,
+}
+
+impl SimplexNoise {
+    pub fn new(random: &mut dyn RandomSource) -> Self {
+        let ImprovedNoise { p, xo, yo, zo } = ImprovedNoise::new(random);
+        Self { p, xo, yo, zo }
+    }
+
+    #[inline]
+    fn p(&self, i: i32) -> i32 {
+        i32::from(self.p[(i & 0xFF) as usize])
+    }
+
+    fn corner_noise(i: i32, x: f64, y: f64) -> f64 {
+        let t = 0.5 - x * x - y * y;
+        if t < 0.0 {
+            0.0
+        } else {
+            let t = t * t;
+            let g = GRADIENT[i as usize];
+            t * t * (g[0] * x + g[1] * y)
+        }
+    }
+
+    pub fn get_value(&self, x: f64, y: f64) -> f64 {
+        let sqrt3 = 3f64.sqrt();
+        let f2 = 0.5 * (sqrt3 - 1.0);
+        let g2 = (3.0 - sqrt3) / 6.0;
+
+        let s = (x + y) * f2;
+        let i = floor(x + s);
+        let j = floor(y + s);
+        let t = f64::from(i + j) * g2;
+        let x0 = x - (f64::from(i) - t);
+        let y0 = y - (f64::from(j) - t);
+        let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+        let x1 = x0 - f64::from(i1) + g2;
+        let y1 = y0 - f64::from(j1) + g2;
+        let x2 = x0 - 1.0 + 2.0 * g2;
+        let y2 = y0 - 1.0 + 2.0 * g2;
+
+        let (ii, jj) = (i & 0xFF, j & 0xFF);
+        let gi0 = self.p(ii + self.p(jj)) % 12;
+        let gi1 = self.p(ii + i1 + self.p(jj + j1)) % 12;
+        let gi2 = self.p(ii + 1 + self.p(jj + 1)) % 12;
+        70.0 * (Self::corner_noise(gi0, x0, y0)
+            + Self::corner_noise(gi1, x1, y1)
+            + Self::corner_noise(gi2, x2, y2))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::XoroshiroRandomSource;
+
+    // Reference values below were produced on the JVM by the vanilla
+    // classes' algorithms for world seed 42.
+
+    const POINTS: [[f64; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [0.5, 64.25, -13.75],
+        [1234.567, -32.1, 98765.4321],
+        [-1e6, 300.5, 7.125],
+    ];
+
+    #[test]
+    fn improved_noise_matches_vanilla() {
+        let noise = ImprovedNoise::new(&mut XoroshiroRandomSource::new(42));
+        assert_eq!(
+            (noise.xo, noise.yo, noise.zo),
+            (190.83062484342904, 101.88674612737026, 151.323544791807)
+        );
+        assert_eq!(&noise.p[..4], [123, 170, 74, 238]);
+
+        let expected = [
+            0.14504255324954013,
+            0.0025984323280949795,
+            0.37997866160612204,
+            0.03848513844367782,
+        ];
+        for ([x, y, z], want) in POINTS.into_iter().zip(expected) {
+            assert_eq!(noise.noise(x, y, z), want);
+        }
+        assert_eq!(
+            noise.noise_scaled(10.3, 20.7, 30.1, 0.5, 0.3),
+            0.1767649601927335
+        );
+    }
+
+    #[test]
+    fn perlin_noise_matches_vanilla() {
+        let noise = PerlinNoise::create(
+            &mut XoroshiroRandomSource::new(42),
+            -3,
+            &[1.0, 1.0, 0.0, 1.0],
+        );
+        assert!(noise.get_octave_noise(1).is_none());
+        let expected = [
+            0.1532256431236129,
+            0.07704154483762225,
+            0.2662299301773345,
+            -0.1690756391741909,
+        ];
+        for ([x, y, z], want) in POINTS.into_iter().zip(expected) {
+            assert_eq!(noise.get_value(x, y, z), want);
+        }
+    }
+
+    #[test]
+    fn legacy_perlin_noise_matches_vanilla() {
+        let noise = PerlinNoise::create_legacy_for_blended_noise(
+            &mut XoroshiroRandomSource::new(42),
+            -7..=0,
+        );
+        assert_eq!(noise.first_octave(), -7);
+        assert_eq!(noise.get_value(0.5, 64.25, -13.75), 0.33846944624231756);
+        assert_eq!(
+            noise.get_value_scaled(512.5, 70.0, -90.25, 0.25, 0.5, false),
+            -0.18927532842549136
+        );
+    }
+
+    #[test]
+    fn normal_noise_matches_vanilla() {
+        // `minecraft:temperature`, seeded the way `RandomState` does it.
+        let params = NoiseParameters::new(-10, vec![1.5, 0.0, 1.0, 0.0, 0.0, 0.0]);
+        let mut random = XoroshiroRandomSource::new(42)
+            .fork_positional()
+            .with_hash_of("minecraft:temperature");
+        let noise = NormalNoise::create(&mut *random, &params);
+        let expected = [
+            0.12429769720028933,
+            0.15066440772703057,
+            0.0019769200213313673,
+            0.323663789715148,
+        ];
+        for ([x, y, z], want) in POINTS.into_iter().zip(expected) {
+            assert_eq!(noise.get_value(x, y, z), want);
+        }
+        assert_eq!(noise.max_value(), 4.444444444444445);
+    }
+}
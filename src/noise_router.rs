@@ -0,0 +1,416 @@
+//! The noise router: the named density functions a dimension's terrain,
+//! climate, aquifers and ore veins are sampled from, and the seeded
+//! [`RandomState`] that gives their noises generators.
+
+use crate::aquifer::Aquifer;
+use crate::caves::NoiseCaves;
+use crate::chunk::{BlockState, Chunk, SECTION_SIZE};
+use crate::density::{
+    BlendedNoise, DensityFn, DensityFunction, DensityVisitor, EndIslands, Memo, NoiseCache,
+    NoiseHolder,
+};
+use crate::noise::{NoiseParameters, NormalNoise};
+use crate::ore_vein::OreVeinifier;
+use crate::random::{LegacyRandom, PositionalRandomFactory, RandomAlgorithm};
+use crate::surface::has_fluid;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Vertical extent and cell size of noise generation, the `noise` object of
+/// a `worldgen/noise_settings` file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NoiseSettings {
+    pub min_y: i32,
+    pub height: i32,
+    /// Cell width in quarter-blocks of 4, i.e. 1 for 4-block cells.
+    pub size_horizontal: i32,
+    /// Cell height in units of 4 blocks.
+    pub size_vertical: i32,
+}
+
+impl NoiseSettings {
+    pub const OVERWORLD: Self = Self {
+        min_y: -64,
+        height: 384,
+        size_horizontal: 1,
+        size_vertical: 2,
+    };
+    pub const NETHER: Self = Self {
+        min_y: 0,
+        height: 128,
+        size_horizontal: 1,
+        size_vertical: 2,
+    };
+    pub const END: Self = Self {
+        min_y: 0,
+        height: 128,
+        size_horizontal: 2,
+        size_vertical: 1,
+    };
+
+    pub fn cell_width(&self) -> i32 {
+        self.size_horizontal * 4
+    }
+
+    pub fn cell_height(&self) -> i32 {
+        self.size_vertical * 4
+    }
+}
+
+/// The density functions of a `noise_router` object.
+#[derive(Clone, Debug)]
+pub struct NoiseRouter {
+    pub barrier: DensityFn,
+    pub fluid_level_floodedness: DensityFn,
+    pub fluid_level_spread: DensityFn,
+    pub lava: DensityFn,
+    pub temperature: DensityFn,
+    pub vegetation: DensityFn,
+    pub continents: DensityFn,
+    pub erosion: DensityFn,
+    pub depth: DensityFn,
+    pub ridges: DensityFn,
+    /// Rough surface height, used by aquifers and surface rules.
+    pub preliminary_surface_level: DensityFn,
+    /// Positive where the terrain is solid.
+    pub final_density: DensityFn,
+    pub vein_toggle: DensityFn,
+    pub vein_ridged: DensityFn,
+    pub vein_gap: DensityFn,
+}
+
+impl Default for NoiseRouter {
+    /// Everything zero: no terrain at all.
+    fn default() -> Self {
+        let zero = DensityFunction::constant(0.0);
+        Self {
+            barrier: zero.clone(),
+            fluid_level_floodedness: zero.clone(),
+            fluid_level_spread: zero.clone(),
+            lava: zero.clone(),
+            temperature: zero.clone(),
+            vegetation: zero.clone(),
+            continents: zero.clone(),
+            erosion: zero.clone(),
+            depth: zero.clone(),
+            ridges: zero.clone(),
+            preliminary_surface_level: zero.clone(),
+            final_density: zero.clone(),
+            vein_toggle: zero.clone(),
+            vein_ridged: zero.clone(),
+            vein_gap: zero,
+        }
+    }
+}
+
+impl NoiseRouter {
+    /// Cut the overworld's noise caves into the terrain, taking the current
+    /// `final_density` as the density before caves.
+    pub fn with_noise_caves(self) -> Self {
+        let final_density = NoiseCaves::new().final_density(&self.final_density);
+        Self {
+            final_density,
+            ..self
+        }
+    }
+
+    /// Map every function through `visitor`, keeping nodes shared between
+    /// functions shared.
+    pub fn map_all(&self, visitor: &mut dyn DensityVisitor) -> Self {
+        let mut memo = Memo::new();
+        let mut map = |f: &DensityFn| f.map_with(visitor, &mut memo);
+        Self {
+            barrier: map(&self.barrier),
+            fluid_level_floodedness: map(&self.fluid_level_floodedness),
+            fluid_level_spread: map(&self.fluid_level_spread),
+            lava: map(&self.lava),
+            temperature: map(&self.temperature),
+            vegetation: map(&self.vegetation),
+            continents: map(&self.continents),
+            erosion: map(&self.erosion),
+            depth: map(&self.depth),
+            ridges: map(&self.ridges),
+            preliminary_surface_level: map(&self.preliminary_surface_level),
+            final_density: map(&self.final_density),
+            vein_toggle: map(&self.vein_toggle),
+            vein_ridged: map(&self.vein_ridged),
+            vein_gap: map(&self.vein_gap),
+        }
+    }
+}
+
+/// Per-world generation state: the router with seeded noises and the
+/// positional random factories of aquifers and ores.
+pub struct RandomState {
+    pub router: NoiseRouter,
+    random: Box<dyn PositionalRandomFactory>,
+    aquifer_random: Box<dyn PositionalRandomFactory>,
+    ore_random: Box<dyn PositionalRandomFactory>,
+    noises: HashMap<String, Arc<NormalNoise>>,
+    seed: i64,
+    legacy_random_source: bool,
+}
+
+impl RandomState {
+    /// Seed `router` for a world. `legacy_random_source` is the setting of
+    /// the same name in the noise settings, true for the Nether and End.
+    pub fn new(router: &NoiseRouter, seed: i64, legacy_random_source: bool) -> Self {
+        let algorithm = if legacy_random_source {
+            RandomAlgorithm::Legacy
+        } else {
+            RandomAlgorithm::Xoroshiro
+        };
+        let random = algorithm.new_instance(seed).fork_positional();
+        let aquifer_random = random.with_hash_of("minecraft:aquifer").fork_positional();
+        let ore_random = random.with_hash_of("minecraft:ore").fork_positional();
+        let mut state = Self {
+            router: NoiseRouter::default(),
+            random,
+            aquifer_random,
+            ore_random,
+            noises: HashMap::new(),
+            seed,
+            legacy_random_source,
+        };
+        state.router = router.map_all(&mut Seeder(&mut state));
+        state
+    }
+
+    pub fn seed(&self) -> i64 {
+        self.seed
+    }
+
+    pub fn random(&self) -> &dyn PositionalRandomFactory {
+        &*self.random
+    }
+
+    pub fn aquifer_random(&self) -> &dyn PositionalRandomFactory {
+        &*self.aquifer_random
+    }
+
+    pub fn ore_random(&self) -> &dyn PositionalRandomFactory {
+        &*self.ore_random
+    }
+
+    /// The generator of a named noise, created on first use from the hash
+    /// of its key.
+    pub fn get_or_create_noise(
+        &mut self,
+        key: &str,
+        parameters: &NoiseParameters,
+    ) -> Arc<NormalNoise> {
+        if let Some(noise) = self.noises.get(key) {
+            return noise.clone();
+        }
+        let mut random = self.random.with_hash_of(key);
+        let noise = Arc::new(NormalNoise::create(&mut *random, parameters));
+        self.noises.insert(key.to_string(), noise.clone());
+        noise
+    }
+}
+
+/// Gives every noise, blended noise and End island function of a tree its
+/// seeded generator.
+struct Seeder<'a>(&'a mut RandomState);
+
+impl DensityVisitor for Seeder<'_> {
+    fn visit_noise(&mut self, holder: &NoiseHolder) -> NoiseHolder {
+        let state = &mut *self.0;
+        if state.legacy_random_source {
+            // Old worlds sampled their nether climate from single legacy
+            // sources and had no shift noise.
+            let legacy = match holder.key.as_str() {
+                "minecraft:temperature" => Some(0),
+                "minecraft:vegetation" => Some(1),
+                _ => None,
+            };
+            if let Some(offset) = legacy {
+                let mut random = LegacyRandom::new(state.seed.wrapping_add(offset));
+                let parameters = NoiseParameters::new(-7, vec![1.0, 1.0]);
+                let noise = NormalNoise::create_legacy_nether_biome(&mut random, &parameters);
+                return holder.with_noise(Arc::new(noise));
+            }
+            if holder.key == "minecraft:offset" {
+                let mut random = state.random.with_hash_of(&holder.key);
+                let noise = NormalNoise::create(&mut *random, &NoiseParameters::new(0, vec![0.0]));
+                return holder.with_noise(Arc::new(noise));
+            }
+        }
+        holder.with_noise(state.get_or_create_noise(&holder.key, &holder.parameters))
+    }
+
+    fn apply(&mut self, function: DensityFunction) -> DensityFunction {
+        let state = &*self.0;
+        match function {
+            DensityFunction::OldBlendedNoise(noise) => {
+                let mut random = if state.legacy_random_source {
+                    Box::new(LegacyRandom::new(state.seed))
+                } else {
+                    state.random.with_hash_of("minecraft:terrain")
+                };
+                DensityFunction::OldBlendedNoise(BlendedNoise::with_random(&noise, &mut *random))
+            }
+            DensityFunction::EndIslands(_) => {
+                DensityFunction::EndIslands(EndIslands::new(state.seed))
+            }
+            other => other,
+        }
+    }
+}
+
+/// Fill a chunk from a seeded router's `final_density`, evaluated cell by
+/// cell like vanilla's noise stage: `default_block` or a block of
+/// `ore_veins` where the density is positive, otherwise what `aquifer` puts
+/// there. Only the part of the chunk inside `noise`'s range is touched.
+pub fn fill_from_noise(
+    chunk: &mut Chunk,
+    router: &NoiseRouter,
+    noise: &NoiseSettings,
+    default_block: &BlockState,
+    aquifer: &mut Aquifer,
+    ore_veins: Option<&OreVeinifier>,
+) {
+    let (w, h) = (noise.cell_width(), noise.cell_height());
+    let min_y = noise.min_y.max(chunk.min_y());
+    let max_y = (noise.min_y + noise.height).min(chunk.max_y());
+    let (base_x, base_z) = (chunk.x * SECTION_SIZE as i32, chunk.z * SECTION_SIZE as i32);
+    let mut cache = NoiseCache::new(w, h);
+
+    let cells = SECTION_SIZE as i32 / w;
+    for cell_x in 0..cells {
+        for cell_z in 0..cells {
+            for cell_y in (min_y.div_euclid(h)..=(max_y - 1).div_euclid(h)).rev() {
+                for y in (cell_y * h..(cell_y + 1) * h).rev() {
+                    if y < min_y || y >= max_y {
+                        continue;
+                    }
+                    for dx in 0..w {
+                        for dz in 0..w {
+                            let (x, z) = (cell_x * w + dx, cell_z * w + dz);
+                            let (block_x, block_z) = (base_x + x, base_z + z);
+                            let density = router
+                                .final_density
+                                .compute_cached(block_x, y, block_z, &mut cache);
+                            let state =
+                                match aquifer.compute_substance(block_x, y, block_z, density) {
+                                    None => ore_veins
+                                        .and_then(|v| v.compute(block_x, y, block_z, &mut cache))
+                                        .unwrap_or_else(|| default_block.clone()),
+                                    Some(state) if state.is_air() => continue,
+                                    Some(state) => state,
+                                };
+                            let fluid = has_fluid(&state);
+                            chunk.set_block(x as usize, y, z as usize, state);
+                            if fluid && aquifer.should_schedule_fluid_update() {
+                                chunk.mark_for_post_processing(x as usize, y, z as usize);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    chunk.compute_heightmaps();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aquifer::FluidPicker;
+    use crate::random::{RandomSource, XoroshiroRandomSource};
+
+    fn holder(key: &str) -> NoiseHolder {
+        NoiseHolder::new(key, NoiseParameters::new(-5, vec![1.0, 0.5]))
+    }
+
+    #[test]
+    fn seeds_noises_from_their_keys() {
+        let router = NoiseRouter {
+            temperature: DensityFunction::noise(holder("minecraft:temperature"), 0.25, 0.0),
+            erosion: DensityFunction::noise(holder("minecraft:erosion"), 0.25, 0.0),
+            ..Default::default()
+        };
+        let state = RandomState::new(&router, 42, false);
+
+        let factory = XoroshiroRandomSource::new(42).fork_positional();
+        let params = NoiseParameters::new(-5, vec![1.0, 0.5]);
+        let erosion = NormalNoise::create(&mut *factory.with_hash_of("minecraft:erosion"), &params);
+        assert_eq!(
+            state.router.erosion.compute(100, 64, -200),
+            erosion.get_value(25.0, 0.0, -50.0)
+        );
+        assert_ne!(
+            state.router.temperature.compute(100, 64, -200),
+            state.router.erosion.compute(100, 64, -200)
+        );
+        // Other worlds get other terrain.
+        let other = RandomState::new(&router, 43, false);
+        assert_ne!(
+            other.router.erosion.compute(100, 64, -200),
+            state.router.erosion.compute(100, 64, -200)
+        );
+    }
+
+    #[test]
+    fn legacy_worlds_have_no_shift() {
+        let router = NoiseRouter {
+            barrier: Arc::new(DensityFunction::ShiftA(holder("minecraft:offset"))),
+            lava: Arc::new(DensityFunction::ShiftA(holder("minecraft:other"))),
+            ..Default::default()
+        };
+        let state = RandomState::new(&router, 7, true);
+        assert_eq!(state.router.barrier.compute(100, 0, 100), 0.0);
+        assert_ne!(state.router.lava.compute(100, 0, 100), 0.0);
+    }
+
+    #[test]
+    fn fills_chunks_from_final_density() {
+        let noise = DensityFunction::noise(holder("minecraft:test"), 1.0, 1.0);
+        let gradient = DensityFunction::y_clamped_gradient(-64, 320, 1.0, -1.0);
+        let router = NoiseRouter {
+            final_density: DensityFunction::interpolated(DensityFunction::binary(
+                crate::density::BinaryOp::Add,
+                gradient,
+                noise,
+            )),
+            ..Default::default()
+        };
+        let state = RandomState::new(&router, 1, false);
+        let settings = NoiseSettings::OVERWORLD;
+        let stone = BlockState::new("minecraft:stone");
+        let water = BlockState::new("minecraft:water");
+
+        let mut chunk = Chunk::new(2, -3);
+        let mut aquifer = Aquifer::Disabled(FluidPicker::new(63, water.clone()));
+        fill_from_noise(
+            &mut chunk,
+            &state.router,
+            &settings,
+            &stone,
+            &mut aquifer,
+            None,
+        );
+
+        let mut cache = NoiseCache::new(settings.cell_width(), settings.cell_height());
+        for (x, y, z) in [(0, -64, 0), (5, 60, 11), (15, 128, 3), (9, 200, 9)] {
+            let density = state
+                .router
+                .final_density
+                .compute_cached(32 + x, y, -48 + z, &mut cache);
+            let want = if density > 0.0 {
+                &stone
+            } else if y < 63 {
+                &water
+            } else {
+                &BlockState::air()
+            };
+            assert_eq!(
+                &chunk.get_block(x as usize, y, z as usize),
+                want,
+                "{x} {y} {z}"
+            );
+        }
+        assert_eq!(chunk.get_block(0, -64, 0), stone);
+        assert!(chunk.get_block(0, 319, 0).is_air());
+    }
+}
@@ -0,0 +1,189 @@
+//! Large ore veins: long bands of copper ore in granite above y = 0 and of
+//! deepslate iron ore in tuff below, placed into solid terrain during the
+//! noise stage from the router's vein noises. A port of vanilla's
+//! `OreVeinifier`.
+
+use crate::chunk::BlockState;
+use crate::density::NoiseCache;
+use crate::noise_router::{NoiseRouter, RandomState};
+use crate::random::PositionalRandomFactory;
+
+/// Veins thin out over this many blocks towards the ends of their range.
+const EDGE_ROUNDOFF_BEGIN: f64 = 20.0;
+const MAX_EDGE_ROUNDOFF: f64 = 0.2;
+const VEININESS_THRESHOLD: f32 = 0.4;
+const VEIN_SOLIDNESS: f32 = 0.7;
+const MIN_RICHNESS: f32 = 0.1;
+const MAX_RICHNESS: f32 = 0.3;
+const MAX_RICHNESS_THRESHOLD: f32 = 0.6;
+const CHANCE_OF_RAW_ORE_BLOCK: f32 = 0.02;
+const SKIP_ORE_IF_GAP_NOISE_IS_BELOW: f32 = -0.3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VeinType {
+    /// Where `vein_toggle` is positive.
+    Copper,
+    Iron,
+}
+
+impl VeinType {
+    pub fn ore(self) -> BlockState {
+        match self {
+            Self::Copper => BlockState::new("minecraft:copper_ore"),
+            Self::Iron => BlockState::new("minecraft:deepslate_iron_ore"),
+        }
+    }
+
+    pub fn raw_ore_block(self) -> BlockState {
+        match self {
+            Self::Copper => BlockState::new("minecraft:raw_copper_block"),
+            Self::Iron => BlockState::new("minecraft:raw_iron_block"),
+        }
+    }
+
+    /// The stone the vein is made of between its ores.
+    pub fn filler(self) -> BlockState {
+        match self {
+            Self::Copper => BlockState::new("minecraft:granite"),
+            Self::Iron => BlockState::new("minecraft:tuff"),
+        }
+    }
+
+    pub fn min_y(self) -> i32 {
+        match self {
+            Self::Copper => 0,
+            Self::Iron => -60,
+        }
+    }
+
+    pub fn max_y(self) -> i32 {
+        match self {
+            Self::Copper => 50,
+            Self::Iron => -8,
+        }
+    }
+}
+
+/// `Mth.clampedMap`: `value` moved from `a..b` onto `c..d`, clamped.
+fn clamped_map(value: f64, a: f64, b: f64, c: f64, d: f64) -> f64 {
+    let t = ((value - a) / (b - a)).clamp(0.0, 1.0);
+    c + t * (d - c)
+}
+
+/// Picks vein blocks for solid terrain.
+pub struct OreVeinifier<'a> {
+    router: &'a NoiseRouter,
+    random: &'a dyn PositionalRandomFactory,
+}
+
+impl<'a> OreVeinifier<'a> {
+    pub fn new(state: &'a RandomState) -> Self {
+        Self {
+            router: &state.router,
+            random: state.ore_random(),
+        }
+    }
+
+    /// The vein block replacing solid terrain at a position, if any. The
+    /// vein noises are sampled through `cache` like the terrain density.
+    pub fn compute(&self, x: i32, y: i32, z: i32, cache: &mut NoiseCache) -> Option<BlockState> {
+        let toggle = self.router.vein_toggle.compute_cached(x, y, z, cache);
+        let vein = if toggle > 0.0 {
+            VeinType::Copper
+        } else {
+            VeinType::Iron
+        };
+        let veininess = toggle.abs();
+        let (above, below) = (vein.max_y() - y, y - vein.min_y());
+        if below < 0 || above < 0 {
+            return None;
+        }
+        let edge = f64::from(above.min(below));
+        let roundoff = clamped_map(edge, 0.0, EDGE_ROUNDOFF_BEGIN, -MAX_EDGE_ROUNDOFF, 0.0);
+        if veininess + roundoff < f64::from(VEININESS_THRESHOLD) {
+            return None;
+        }
+        let mut random = self.random.at(x, y, z);
+        if random.next_float() > VEIN_SOLIDNESS {
+            return None;
+        }
+        if self.router.vein_ridged.compute_cached(x, y, z, cache) >= 0.0 {
+            return None;
+        }
+        let richness = clamped_map(
+            veininess,
+            f64::from(VEININESS_THRESHOLD),
+            f64::from(MAX_RICHNESS_THRESHOLD),
+            f64::from(MIN_RICHNESS),
+            f64::from(MAX_RICHNESS),
+        );
+        if f64::from(random.next_float()) < richness
+            && self.router.vein_gap.compute_cached(x, y, z, cache)
+                > f64::from(SKIP_ORE_IF_GAP_NOISE_IS_BELOW)
+        {
+            if random.next_float() < CHANCE_OF_RAW_ORE_BLOCK {
+                Some(vein.raw_ore_block())
+            } else {
+                Some(vein.ore())
+            }
+        } else {
+            Some(vein.filler())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::density::DensityFunction;
+    use std::collections::HashMap;
+
+    fn count(toggle: f64, ridged: f64, gap: f64, y: i32) -> HashMap<String, usize> {
+        let router = NoiseRouter {
+            vein_toggle: DensityFunction::constant(toggle),
+            vein_ridged: DensityFunction::constant(ridged),
+            vein_gap: DensityFunction::constant(gap),
+            ..NoiseRouter::default()
+        };
+        let state = RandomState::new(&router, 5, false);
+        let veinifier = OreVeinifier::new(&state);
+        let mut cache = NoiseCache::new(4, 8);
+        let mut counts = HashMap::new();
+        for x in 0..64 {
+            for z in 0..64 {
+                if let Some(block) = veinifier.compute(x, y, z, &mut cache) {
+                    *counts.entry(block.name).or_default() += 1;
+                }
+            }
+        }
+        counts
+    }
+
+    #[test]
+    fn copper_above_iron_below() {
+        let copper = count(0.7, -1.0, 0.0, 25);
+        assert_eq!(copper.len(), 3, "{copper:?}");
+        let ore = copper["minecraft:copper_ore"];
+        let granite = copper["minecraft:granite"];
+        // Most of a solid vein is filler, and 30% of it is left out.
+        assert!(granite > ore * 2, "{copper:?}");
+        let total: usize = copper.values().sum();
+        assert!((2700..3000).contains(&total), "{total}");
+
+        let iron = count(-0.7, -1.0, 0.0, -30);
+        assert!(iron.contains_key("minecraft:deepslate_iron_ore"));
+        assert!(iron.contains_key("minecraft:tuff"));
+    }
+
+    #[test]
+    fn no_veins_outside_their_range_or_ridges() {
+        assert!(count(0.7, -1.0, 0.0, 60).is_empty());
+        assert!(count(-0.7, -1.0, 0.0, 0).is_empty());
+        assert!(count(0.7, 0.5, 0.0, 25).is_empty());
+        assert!(count(0.3, -1.0, 0.0, 25).is_empty());
+        // Near the ends of the range veins need a stronger toggle.
+        assert!(count(0.45, -1.0, 0.0, 2).is_empty());
+        // Below the gap threshold they are all filler.
+        assert_eq!(count(0.7, -1.0, -0.5, 25).len(), 1);
+    }
+}
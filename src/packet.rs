@@ -0,0 +1,300 @@
+use crate::chunk::{BlockState, Chunk, HeightmapKind, LIGHT_ARRAY_SIZE};
+use crate::nbt_util;
+use crate::palette::PaletteKind;
+use crate::protocol::write_var_int;
+use nbt::Tag;
+use std::io;
+
+/// Packet id of clientbound "Chunk Data and Update Light" in protocol 772 (1.21.8).
+pub const CHUNK_DATA_PACKET_ID: i32 = 0x27;
+
+/// Heightmaps the client needs; the others only matter to the server.
+const CLIENT_HEIGHTMAPS: [HeightmapKind; 3] = [
+    HeightmapKind::WorldSurface,
+    HeightmapKind::MotionBlocking,
+    HeightmapKind::MotionBlockingNoLeaves,
+];
+
+/// Maps chunk contents to the numeric ids shared with the client: block
+/// states from the block registry, biomes and block entity types from the
+/// registries sent during configuration.
+pub trait RegistryIds {
+    fn block_state_id(&self, state: &BlockState) -> u32;
+    fn biome_id(&self, biome: &str) -> u32;
+    fn block_entity_type_id(&self, id: &str) -> u32;
+    /// Number of block states; decides the width of direct block palettes.
+    fn block_state_count(&self) -> usize;
+    /// Number of biomes; decides the width of direct biome palettes.
+    fn biome_count(&self) -> usize;
+}
+
+/// Encode a full "Chunk Data and Update Light" packet: packet id followed by
+/// the body from [`write_chunk_data`], ready for length framing.
+pub fn chunk_data_packet(chunk: &Chunk, ids: &impl RegistryIds) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    write_var_int(&mut out, CHUNK_DATA_PACKET_ID);
+    write_chunk_data(chunk, ids, &mut out)?;
+    Ok(out)
+}
+
+/// Append the packet body for `chunk`:
+///
+/// - chunk X and Z as big-endian ints,
+/// - heightmaps as a VarInt-prefixed array of `(type, long[])` (since 1.21.5
+///   these are no longer an NBT compound),
+/// - the VarInt-prefixed section buffer: per section the non-air block count,
+///   the block-state container and the biome container,
+/// - block entities with packed XZ, Y, type id and network NBT,
+/// - the sky/block light masks, empty masks and light arrays, covering one
+///   section below and one above the chunk.
+pub fn write_chunk_data(
+    chunk: &Chunk,
+    ids: &impl RegistryIds,
+    out: &mut Vec<u8>,
+) -> io::Result<()> {
+    out.extend_from_slice(&chunk.x.to_be_bytes());
+    out.extend_from_slice(&chunk.z.to_be_bytes());
+
+    let heightmaps: Vec<_> = CLIENT_HEIGHTMAPS
+        .iter()
+        .filter_map(|kind| chunk.heightmaps.get(kind).map(|map| (*kind, map)))
+        .collect();
+    write_var_int(out, heightmaps.len() as i32);
+    for (kind, map) in heightmaps {
+        write_var_int(out, kind as i32);
+        let longs = chunk.pack_heightmap(map);
+        write_var_int(out, longs.len() as i32);
+        for long in longs {
+            out.extend_from_slice(&long.to_be_bytes());
+        }
+    }
+
+    let sections = section_data(chunk, ids);
+    write_var_int(out, sections.len() as i32);
+    out.extend_from_slice(&sections);
+
+    write_var_int(out, chunk.block_entities.len() as i32);
+    for be in &chunk.block_entities {
+        write_block_entity(be, ids, out)?;
+    }
+
+    write_light(chunk, out);
+    Ok(())
+}
+
+fn section_data(chunk: &Chunk, ids: &impl RegistryIds) -> Vec<u8> {
+    let block_bits = crate::palette::ceil_log2(ids.block_state_count());
+    let biome_bits = crate::palette::ceil_log2(ids.biome_count());
+    let mut out = Vec::new();
+    for section in chunk.sections() {
+        out.extend_from_slice(&(section.non_air_blocks() as i16).to_be_bytes());
+        section.block_states().write_network(
+            PaletteKind::BlockStates,
+            block_bits,
+            |s| ids.block_state_id(s),
+            &mut out,
+        );
+        section.biomes().write_network(
+            PaletteKind::Biomes,
+            biome_bits,
+            |b| ids.biome_id(b),
+            &mut out,
+        );
+    }
+    out
+}
+
+fn write_block_entity(be: &Tag, ids: &impl RegistryIds, out: &mut Vec<u8>) -> io::Result<()> {
+    let x = nbt_util::get::<i32>(be, "x")?;
+    let y = nbt_util::get::<i32>(be, "y")?;
+    let z = nbt_util::get::<i32>(be, "z")?;
+    let id = nbt_util::get::<&str>(be, "id")?;
+
+    out.push((((x & 15) << 4) | (z & 15)) as u8);
+    out.extend_from_slice(&(y as i16).to_be_bytes());
+    write_var_int(out, ids.block_entity_type_id(id) as i32);
+
+    // The client gets the update tag: everything except identity and position.
+    let mut data = Tag::new_compound("");
+    for (k, v) in be.as_compound().into_iter().flatten() {
+        if !matches!(k.as_str(), "id" | "x" | "y" | "z" | "keepPacked") {
+            data.insert(k.clone(), v.clone());
+        }
+    }
+    out.extend_from_slice(&nbt_util::to_network_bytes(&data)?);
+    Ok(())
+}
+
+/// Light masks index sections from one below the chunk to one above it.
+fn write_light(chunk: &Chunk, out: &mut Vec<u8>) {
+    let count = chunk.sections().len() + 2;
+    let mut sky = (vec![false; count], vec![false; count], Vec::new());
+    let mut block = (vec![false; count], vec![false; count], Vec::new());
+
+    for (i, section) in chunk.sections().iter().enumerate() {
+        for ((mask, empty, arrays), light) in [
+            (&mut sky, &section.sky_light),
+            (&mut block, &section.block_light),
+        ] {
+            let Some(light) = light else { continue };
+            if light.iter().all(|b| *b == 0) {
+                empty[i + 1] = true;
+            } else {
+                mask[i + 1] = true;
+                arrays.push(light);
+            }
+        }
+    }
+
+    write_bit_set(out, &sky.0);
+    write_bit_set(out, &block.0);
+    write_bit_set(out, &sky.1);
+    write_bit_set(out, &block.1);
+    for arrays in [&sky.2, &block.2] {
+        write_var_int(out, arrays.len() as i32);
+        for light in arrays.iter() {
+            write_var_int(out, LIGHT_ARRAY_SIZE as i32);
+            out.extend_from_slice(light);
+        }
+    }
+}
+
+/// `java.util.BitSet` as sent on the wire: VarInt long count, then the longs,
+/// with trailing zero longs dropped.
+fn write_bit_set(out: &mut Vec<u8>, bits: &[bool]) {
+    let mut longs = vec![0u64; bits.len().div_ceil(64)];
+    for (i, _) in bits.iter().enumerate().filter(|(_, b)| **b) {
+        longs[i / 64] |= 1 << (i % 64);
+    }
+    while longs.last() == Some(&0) {
+        longs.pop();
+    }
+    write_var_int(out, longs.len() as i32);
+    for long in longs {
+        out.extend_from_slice(&long.to_be_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::PalettedContainer;
+    use crate::protocol::{read_i64, read_var_int};
+
+    /// Ids for a tiny fixed registry: air, stone, dirt; plains, desert.
+    struct TestIds;
+
+    impl RegistryIds for TestIds {
+        fn block_state_id(&self, state: &BlockState) -> u32 {
+            match state.name.as_str() {
+                "minecraft:stone" => 1,
+                "minecraft:dirt" => 2,
+                _ => 0,
+            }
+        }
+        fn biome_id(&self, biome: &str) -> u32 {
+            u32::from(biome == "minecraft:desert")
+        }
+        fn block_entity_type_id(&self, _id: &str) -> u32 {
+            7
+        }
+        fn block_state_count(&self) -> usize {
+            3
+        }
+        fn biome_count(&self) -> usize {
+            2
+        }
+    }
+
+    fn read_i32(buf: &mut &[u8]) -> i32 {
+        let (bytes, rest) = buf.split_at(4);
+        *buf = rest;
+        i32::from_be_bytes(bytes.try_into().unwrap())
+    }
+
+    #[test]
+    fn encodes_sections_heightmaps_and_light() {
+        let mut chunk = Chunk::new(2, -3);
+        for x in 0..16 {
+            for z in 0..16 {
+                chunk.set_block(x, -64, z, BlockState::new("minecraft:stone"));
+            }
+        }
+        chunk.set_block(1, 0, 1, BlockState::new("minecraft:dirt"));
+        chunk.set_biome(0, 0, 0, "minecraft:desert");
+        chunk.compute_heightmaps();
+        chunk.sections_mut()[0].sky_light = Some(vec![0x11; LIGHT_ARRAY_SIZE]);
+        chunk.sections_mut()[1].sky_light = Some(vec![0; LIGHT_ARRAY_SIZE]);
+
+        let packet = chunk_data_packet(&chunk, &TestIds).unwrap();
+        let mut buf = &packet[..];
+        assert_eq!(read_var_int(&mut buf).unwrap(), CHUNK_DATA_PACKET_ID);
+        assert_eq!(read_i32(&mut buf), 2);
+        assert_eq!(read_i32(&mut buf), -3);
+
+        assert_eq!(read_var_int(&mut buf).unwrap(), 3);
+        for expected in [1, 4, 5] {
+            assert_eq!(read_var_int(&mut buf).unwrap(), expected);
+            let len = read_var_int(&mut buf).unwrap();
+            assert_eq!(len, 37);
+            for _ in 0..len {
+                read_i64(&mut buf).unwrap();
+            }
+        }
+
+        let size = read_var_int(&mut buf).unwrap() as usize;
+        let (mut sections, rest) = buf.split_at(size);
+        buf = rest;
+        for (i, section) in chunk.sections().iter().enumerate() {
+            let count = i16::from_be_bytes([sections[0], sections[1]]);
+            sections = &sections[2..];
+            assert_eq!(count as u16, section.non_air_blocks());
+            let blocks =
+                PalettedContainer::read_network(PaletteKind::BlockStates, 2, Some, &mut sections)
+                    .unwrap();
+            let biomes =
+                PalettedContainer::read_network(PaletteKind::Biomes, 1, Some, &mut sections)
+                    .unwrap();
+            if i == 0 {
+                assert_eq!(*blocks.get(0), 1);
+                assert_eq!(*blocks.get(256), 0);
+            }
+            if i == 4 {
+                assert_eq!(*blocks.get(16 + 1), 2);
+                assert_eq!(*biomes.get(0), 1);
+            }
+        }
+        assert!(sections.is_empty());
+
+        assert_eq!(read_var_int(&mut buf).unwrap(), 0);
+
+        // Sky mask: section 0 is stored at bit 1; section 1 is empty (bit 2).
+        assert_eq!(read_var_int(&mut buf).unwrap(), 1);
+        assert_eq!(read_i64(&mut buf).unwrap(), 0b10);
+        assert_eq!(read_var_int(&mut buf).unwrap(), 0);
+        assert_eq!(read_var_int(&mut buf).unwrap(), 1);
+        assert_eq!(read_i64(&mut buf).unwrap(), 0b100);
+        assert_eq!(read_var_int(&mut buf).unwrap(), 0);
+        assert_eq!(read_var_int(&mut buf).unwrap(), 1);
+        assert_eq!(read_var_int(&mut buf).unwrap(), 2048);
+        assert_eq!(buf[0], 0x11);
+        buf = &buf[2048..];
+        assert_eq!(read_var_int(&mut buf).unwrap(), 0);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn bit_set_trims_trailing_zeros() {
+        let mut out = Vec::new();
+        let mut bits = vec![false; 130];
+        write_bit_set(&mut out, &bits);
+        assert_eq!(out, [0]);
+
+        bits[65] = true;
+        out.clear();
+        write_bit_set(&mut out, &bits);
+        assert_eq!(out.len(), 1 + 16);
+        assert_eq!(out[0], 2);
+        assert_eq!(out[1 + 8 + 7], 0b10);
+    }
+}
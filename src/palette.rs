@@ -0,0 +1,561 @@
+use crate::protocol::{read_i64, read_u8, read_var_int, write_var_int};
+use std::io;
+
+/// Packed array of fixed-width unsigned entries stored in `i64`s.
+///
+/// Since 1.16 entries never span two longs: each long holds
+/// `64 / bits` entries starting at bit 0, and the leftover high bits are unused.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BitStorage {
+    bits: u8,
+    size: usize,
+    data: Vec<i64>,
+}
+
+impl BitStorage {
+    /// A zeroed storage of `size` entries. `bits == 0` stores nothing.
+    pub fn new(bits: u8, size: usize) -> Self {
+        Self {
+            bits,
+            size,
+            data: vec![0; Self::long_count(bits, size)],
+        }
+    }
+
+    /// Wrap an existing long array, checking its length matches `bits` and `size`.
+    pub fn from_raw(bits: u8, size: usize, data: Vec<i64>) -> io::Result<Self> {
+        let expected = Self::long_count(bits, size);
+        if data.len() != expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "invalid packed length {} for {size} entries of {bits} bits, expected {expected}",
+                    data.len()
+                ),
+            ));
+        }
+        Ok(Self { bits, size, data })
+    }
+
+    /// Number of longs needed for `size` entries of `bits` bits.
+    pub fn long_count(bits: u8, size: usize) -> usize {
+        if bits == 0 {
+            return 0;
+        }
+        let per_long = 64 / bits as usize;
+        size.div_ceil(per_long)
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.bits
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn raw(&self) -> &[i64] {
+        &self.data
+    }
+
+    pub fn into_raw(self) -> Vec<i64> {
+        self.data
+    }
+
+    pub fn get(&self, index: usize) -> u64 {
+        assert!(index < self.size, "index {index} out of {}", self.size);
+        if self.bits == 0 {
+            return 0;
+        }
+        let (cell, shift) = self.position(index);
+        (self.data[cell] as u64 >> shift) & self.mask()
+    }
+
+    pub fn set(&mut self, index: usize, value: u64) {
+        assert!(index < self.size, "index {index} out of {}", self.size);
+        if self.bits == 0 {
+            return;
+        }
+        let mask = self.mask();
+        debug_assert!(value <= mask, "{value} does not fit in {} bits", self.bits);
+        let (cell, shift) = self.position(index);
+        let long = self.data[cell] as u64 & !(mask << shift) | (value & mask) << shift;
+        self.data[cell] = long as i64;
+    }
+
+    #[inline]
+    fn mask(&self) -> u64 {
+        (1u64 << self.bits) - 1
+    }
+
+    #[inline]
+    fn position(&self, index: usize) -> (usize, usize) {
+        let per_long = 64 / self.bits as usize;
+        let cell = index / per_long;
+        (cell, (index - cell * per_long) * self.bits as usize)
+    }
+}
+
+/// `ceil(log2(n))`, with `ceil_log2(0) == ceil_log2(1) == 0`.
+pub fn ceil_log2(n: usize) -> u8 {
+    if n <= 1 {
+        0
+    } else {
+        (usize::BITS - (n - 1).leading_zeros()) as u8
+    }
+}
+
+/// Which kind of data a container holds. This decides the bit widths used when
+/// encoding, matching vanilla's `PalettedContainer.Strategy`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaletteKind {
+    /// 4096 block states; indirect palettes use 4–8 bits.
+    BlockStates,
+    /// 64 biomes; indirect palettes use 1–3 bits.
+    Biomes,
+}
+
+/// How a container is laid out on the wire.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaletteEncoding {
+    /// Every entry is the same value; no data array.
+    SingleValue,
+    /// Entries index into a local palette with the given width.
+    Indirect(u8),
+    /// Entries are global registry ids with the given width.
+    Direct(u8),
+}
+
+impl PaletteKind {
+    pub fn size(self) -> usize {
+        match self {
+            PaletteKind::BlockStates => 4096,
+            PaletteKind::Biomes => 64,
+        }
+    }
+
+    fn indirect_bits(self) -> (u8, u8) {
+        match self {
+            PaletteKind::BlockStates => (4, 8),
+            PaletteKind::Biomes => (1, 3),
+        }
+    }
+
+    /// Network encoding for a palette of `palette_len` values. `global_bits`
+    /// is `ceil_log2` of the registry size, used once the palette is too big.
+    pub fn network_encoding(self, palette_len: usize, global_bits: u8) -> PaletteEncoding {
+        let bits = ceil_log2(palette_len);
+        let (min, max) = self.indirect_bits();
+        match bits {
+            0 => PaletteEncoding::SingleValue,
+            b if b <= max => PaletteEncoding::Indirect(b.max(min)),
+            _ => PaletteEncoding::Direct(global_bits),
+        }
+    }
+
+    /// Width used on disk. Anvil always stores a palette, so large palettes
+    /// keep indirect indices of `ceil_log2(len)` bits instead of going direct.
+    pub fn storage_bits(self, palette_len: usize) -> u8 {
+        match self.network_encoding(palette_len, 0) {
+            PaletteEncoding::SingleValue => 0,
+            PaletteEncoding::Indirect(bits) => bits,
+            PaletteEncoding::Direct(_) => ceil_log2(palette_len),
+        }
+    }
+}
+
+/// A fixed-size container that stores each distinct value once in a palette
+/// and every entry as an index into that palette.
+///
+/// The palette grows automatically when a new value is stored and can be
+/// compacted to drop values that are no longer referenced.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PalettedContainer<T> {
+    palette: Vec<T>,
+    indices: Vec<u16>,
+}
+
+impl<T: Clone + PartialEq> PalettedContainer<T> {
+    /// A container of `size` entries all set to `value`.
+    pub fn new(size: usize, value: T) -> Self {
+        Self {
+            palette: vec![value],
+            indices: vec![0; size],
+        }
+    }
+
+    /// Number of entries in the container.
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn palette(&self) -> &[T] {
+        &self.palette
+    }
+
+    /// True when every entry holds the same value, even if the palette
+    /// still lists values no entry references.
+    pub fn is_single_value(&self) -> bool {
+        self.palette.len() == 1 || self.indices.iter().all(|&i| i == self.indices[0])
+    }
+
+    pub fn get(&self, index: usize) -> &T {
+        &self.palette[self.indices[index] as usize]
+    }
+
+    /// Store `value` at `index` and return the previous value.
+    pub fn set(&mut self, index: usize, value: T) -> T {
+        let id = self.palette_id(value);
+        let old = std::mem::replace(&mut self.indices[index], id);
+        let old = self.palette[old as usize].clone();
+        // Every entry may point at a distinct value, but never more.
+        if self.palette.len() > self.indices.len() {
+            self.compact();
+        }
+        old
+    }
+
+    /// Set every entry to `value`, resetting the palette.
+    pub fn fill(&mut self, value: T) {
+        self.palette.clear();
+        self.palette.push(value);
+        self.indices.fill(0);
+    }
+
+    /// Iterate over every entry in index order.
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.indices.iter().map(|&i| &self.palette[i as usize])
+    }
+
+    /// Count entries whose value matches `pred`.
+    pub fn count(&self, mut pred: impl FnMut(&T) -> bool) -> usize {
+        let matching: Vec<bool> = self.palette.iter().map(&mut pred).collect();
+        self.indices
+            .iter()
+            .filter(|&&i| matching[i as usize])
+            .count()
+    }
+
+    /// Remove palette values that no entry references anymore.
+    pub fn compact(&mut self) {
+        let mut used = vec![false; self.palette.len()];
+        for &i in &self.indices {
+            used[i as usize] = true;
+        }
+        if used.iter().all(|u| *u) {
+            return;
+        }
+
+        let mut remap = vec![0u16; self.palette.len()];
+        let mut palette = Vec::with_capacity(self.palette.len());
+        for (old, value) in self.palette.drain(..).enumerate() {
+            if used[old] {
+                remap[old] = palette.len() as u16;
+                palette.push(value);
+            }
+        }
+        self.palette = palette;
+        for i in &mut self.indices {
+            *i = remap[*i as usize];
+        }
+    }
+
+    /// Encode for Anvil storage: the compacted palette and the packed indices.
+    /// The data array is empty for single-value containers, like vanilla writes it.
+    pub fn to_packed(&self, kind: PaletteKind) -> (Vec<T>, Vec<i64>) {
+        let mut compact = self.clone();
+        compact.compact();
+        let bits = kind.storage_bits(compact.palette.len());
+        let mut storage = BitStorage::new(bits, compact.indices.len());
+        for (i, &id) in compact.indices.iter().enumerate() {
+            storage.set(i, id as u64);
+        }
+        (compact.palette, storage.into_raw())
+    }
+
+    /// Decode the Anvil form produced by [`Self::to_packed`].
+    pub fn from_packed(kind: PaletteKind, palette: Vec<T>, data: Vec<i64>) -> io::Result<Self> {
+        let size = kind.size();
+        if palette.is_empty() {
+            return Err(invalid_data("empty palette"));
+        }
+        if palette.len() == 1 {
+            return Ok(Self {
+                palette,
+                indices: vec![0; size],
+            });
+        }
+        let storage = BitStorage::from_raw(kind.storage_bits(palette.len()), size, data)?;
+        let mut indices = Vec::with_capacity(size);
+        for i in 0..size {
+            let id = storage.get(i);
+            if id as usize >= palette.len() {
+                return Err(invalid_data(format!(
+                    "palette index {id} out of range for {} entries",
+                    palette.len()
+                )));
+            }
+            indices.push(id as u16);
+        }
+        Ok(Self { palette, indices })
+    }
+
+    /// Append the network form: bits-per-entry byte, palette, then the packed
+    /// longs. Since 1.21.5 the long array carries no length prefix.
+    pub fn write_network(
+        &self,
+        kind: PaletteKind,
+        global_bits: u8,
+        id_of: impl Fn(&T) -> u32,
+        out: &mut Vec<u8>,
+    ) {
+        let mut compact = self.clone();
+        compact.compact();
+        let encoding = kind.network_encoding(compact.palette.len(), global_bits);
+        let storage = match encoding {
+            PaletteEncoding::SingleValue => {
+                out.push(0);
+                write_var_int(out, id_of(&compact.palette[0]) as i32);
+                return;
+            }
+            PaletteEncoding::Indirect(bits) => {
+                out.push(bits);
+                write_var_int(out, compact.palette.len() as i32);
+                for value in &compact.palette {
+                    write_var_int(out, id_of(value) as i32);
+                }
+                let mut storage = BitStorage::new(bits, compact.indices.len());
+                for (i, &id) in compact.indices.iter().enumerate() {
+                    storage.set(i, id as u64);
+                }
+                storage
+            }
+            PaletteEncoding::Direct(bits) => {
+                out.push(bits);
+                let ids: Vec<u64> = compact.palette.iter().map(|v| id_of(v) as u64).collect();
+                let mut storage = BitStorage::new(bits, compact.indices.len());
+                for (i, &id) in compact.indices.iter().enumerate() {
+                    storage.set(i, ids[id as usize]);
+                }
+                storage
+            }
+        };
+        for long in storage.raw() {
+            out.extend_from_slice(&long.to_be_bytes());
+        }
+    }
+
+    /// Decode the network form written by [`Self::write_network`].
+    pub fn read_network(
+        kind: PaletteKind,
+        global_bits: u8,
+        value_of: impl Fn(u32) -> Option<T>,
+        buf: &mut &[u8],
+    ) -> io::Result<Self> {
+        let size = kind.size();
+        let lookup = |id: i32| {
+            value_of(id as u32).ok_or_else(|| invalid_data(format!("unknown registry id {id}")))
+        };
+        let bits = read_u8(buf)?;
+        let (min, max) = kind.indirect_bits();
+
+        if bits == 0 {
+            let value = lookup(read_var_int(buf)?)?;
+            return Ok(Self::new(size, value));
+        }
+
+        let indirect = bits <= max;
+        let bits = if indirect { bits.max(min) } else { global_bits };
+        let mut palette = Vec::new();
+        if indirect {
+            let len = read_var_int(buf)?;
+            if len <= 0 || len as usize > size {
+                return Err(invalid_data(format!("invalid palette length {len}")));
+            }
+            for _ in 0..len {
+                palette.push(lookup(read_var_int(buf)?)?);
+            }
+        }
+
+        let mut data = Vec::with_capacity(BitStorage::long_count(bits, size));
+        for _ in 0..BitStorage::long_count(bits, size) {
+            data.push(read_i64(buf)?);
+        }
+        let storage = BitStorage::from_raw(bits, size, data)?;
+
+        if indirect {
+            let mut indices = Vec::with_capacity(size);
+            for i in 0..size {
+                let id = storage.get(i) as usize;
+                if id >= palette.len() {
+                    return Err(invalid_data(format!("palette index {id} out of range")));
+                }
+                indices.push(id as u16);
+            }
+            return Ok(Self { palette, indices });
+        }
+
+        let mut container = Self::new(size, lookup(storage.get(0) as i32)?);
+        for i in 1..size {
+            container.set(i, lookup(storage.get(i) as i32)?);
+        }
+        Ok(container)
+    }
+
+    /// Palette index of `value`, appending it when missing.
+    fn palette_id(&mut self, value: T) -> u16 {
+        if let Some(id) = self.palette.iter().position(|v| *v == value) {
+            return id as u16;
+        }
+        self.palette.push(value);
+        (self.palette.len() - 1) as u16
+    }
+}
+
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grows_and_compacts() {
+        let mut c = PalettedContainer::new(64, "air");
+        assert!(c.is_single_value());
+
+        assert_eq!(c.set(3, "stone"), "air");
+        c.set(4, "dirt");
+        assert_eq!(c.palette(), &["air", "stone", "dirt"]);
+        assert_eq!(*c.get(3), "stone");
+        assert_eq!(c.count(|v| *v != "air"), 2);
+
+        c.set(3, "air");
+        c.compact();
+        assert_eq!(c.palette(), &["air", "dirt"]);
+        assert_eq!(*c.get(4), "dirt");
+
+        c.fill("stone");
+        assert!(c.is_single_value());
+        assert!(c.iter().all(|v| *v == "stone"));
+    }
+
+    #[test]
+    fn single_value_after_setting_every_entry() {
+        let mut c = PalettedContainer::new(16, "air");
+        c.set(0, "dirt");
+        assert!(!c.is_single_value());
+        for i in 0..16 {
+            c.set(i, "stone");
+        }
+        assert_eq!(c.palette(), &["air", "dirt", "stone"]);
+        assert!(c.is_single_value());
+        c.set(5, "dirt");
+        assert!(!c.is_single_value());
+    }
+
+    #[test]
+    fn palette_never_outgrows_entries() {
+        let mut c = PalettedContainer::new(4, 0u32);
+        for round in 0..10u32 {
+            for i in 0..4 {
+                c.set(i, round * 4 + i as u32);
+            }
+        }
+        assert!(c.palette().len() <= 4);
+        assert_eq!(*c.get(3), 39);
+    }
+
+    #[test]
+    fn bit_storage_never_spans_longs() {
+        let mut storage = BitStorage::new(5, 4096);
+        // 12 entries per long, 4 bits of padding at the top.
+        assert_eq!(storage.raw().len(), 342);
+        for i in 0..4096 {
+            storage.set(i, (i % 32) as u64);
+        }
+        for i in 0..4096 {
+            assert_eq!(storage.get(i), (i % 32) as u64);
+        }
+        for long in storage.raw() {
+            assert_eq!(*long as u64 >> 60, 0);
+        }
+        assert_eq!(storage.raw()[0], 0x05A9_2839_8A41_8820);
+    }
+
+    #[test]
+    fn storage_bits_follow_vanilla_strategy() {
+        let blocks = PaletteKind::BlockStates;
+        assert_eq!(blocks.storage_bits(1), 0);
+        assert_eq!(blocks.storage_bits(2), 4);
+        assert_eq!(blocks.storage_bits(17), 5);
+        assert_eq!(blocks.storage_bits(300), 9);
+        assert_eq!(
+            blocks.network_encoding(300, 15),
+            PaletteEncoding::Direct(15)
+        );
+
+        let biomes = PaletteKind::Biomes;
+        assert_eq!(biomes.storage_bits(2), 1);
+        assert_eq!(biomes.storage_bits(5), 3);
+        assert_eq!(biomes.network_encoding(9, 6), PaletteEncoding::Direct(6));
+        assert_eq!(biomes.storage_bits(9), 4);
+    }
+
+    #[test]
+    fn packed_round_trip() {
+        let mut c = PalettedContainer::new(4096, 0u32);
+        for i in 0..4096 {
+            c.set(i, (i % 20) as u32);
+        }
+        let (palette, data) = c.to_packed(PaletteKind::BlockStates);
+        assert_eq!(palette.len(), 20);
+        assert_eq!(data.len(), BitStorage::long_count(5, 4096));
+        let back = PalettedContainer::from_packed(PaletteKind::BlockStates, palette, data).unwrap();
+        assert!((0..4096).all(|i| back.get(i) == c.get(i)));
+
+        let single = PalettedContainer::new(64, "plains");
+        let (palette, data) = single.to_packed(PaletteKind::Biomes);
+        assert_eq!(palette, vec!["plains"]);
+        assert!(data.is_empty());
+
+        assert!(PalettedContainer::from_packed(PaletteKind::Biomes, vec![1, 2], vec![]).is_err());
+    }
+
+    #[test]
+    fn network_round_trip() {
+        let mut c = PalettedContainer::new(4096, 0u32);
+        c.set(7, 33);
+        let mut out = Vec::new();
+        c.write_network(PaletteKind::BlockStates, 15, |v| *v, &mut out);
+        // bits, palette length, two ids, 256 longs.
+        assert_eq!(out[..4], [4, 2, 0, 33]);
+        assert_eq!(out.len(), 4 + 256 * 8);
+        let back =
+            PalettedContainer::read_network(PaletteKind::BlockStates, 15, Some, &mut &out[..])
+                .unwrap();
+        assert_eq!(*back.get(7), 33);
+        assert_eq!(*back.get(8), 0);
+
+        for i in 0..300 {
+            c.set(i, i as u32 * 7);
+        }
+        let mut out = Vec::new();
+        c.write_network(PaletteKind::BlockStates, 15, |v| *v, &mut out);
+        assert_eq!(out[0], 15);
+        assert_eq!(out.len(), 1 + BitStorage::long_count(15, 4096) * 8);
+        let back =
+            PalettedContainer::read_network(PaletteKind::BlockStates, 15, Some, &mut &out[..])
+                .unwrap();
+        assert!((0..4096).all(|i| back.get(i) == c.get(i)));
+
+        let mut out = Vec::new();
+        PalettedContainer::new(64, 3u32).write_network(PaletteKind::Biomes, 6, |v| *v, &mut out);
+        assert_eq!(out, [0, 3]);
+    }
+}
@@ -18,6 +18,13 @@ pub struct Vector {
     y: f32,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vector3 {
+    x: f32,
+    y: f32,
+    z: f32,
+}
+
 pub const MIN_Y: i32 = -64;
 pub const MAX_Y: i32 = 320;
 pub const SEA_LEVEL: i32 = 63;
@@ -43,6 +50,32 @@ fn fbm_seeded(
     (sum / norm).clamp(-1.0, 1.0)
 }
 
+/// 3D counterpart of [`fbm_seeded`]: sums `octaves` layers of [`Noise::get3`],
+/// normalized and clamped to [-1, 1]. Meant for density sampling, where a
+/// positive value is solid ground.
+#[inline]
+#[allow(clippy::too_many_arguments)]
+pub fn fbm3_seeded(
+    seed: u64,
+    scale: f32,
+    x: f32,
+    y: f32,
+    z: f32,
+    octaves: u32,
+    persistence: f32,
+    lacunarity: f32,
+) -> f32 {
+    let base = Noise::new(scale, 1.0, seed);
+    let (mut amp, mut freq, mut sum, mut norm) = (1.0f32, 1.0f32, 0.0f32, 0.0f32);
+    for _ in 0..octaves {
+        sum += amp * base.get3(x * freq, y * freq, z * freq);
+        norm += amp;
+        amp *= persistence;
+        freq *= lacunarity;
+    }
+    (sum / norm).clamp(-1.0, 1.0)
+}
+
 pub fn generate_height_chunk(seed: u64, cx: i32, cz: i32) -> [[i32; CHUNK_SIZE]; CHUNK_SIZE] {
     const SALT_CONT: u64 = 0xC0DEC0DEu64;
     const SALT_MNT: u64 = 0xBEEF1234u64;
@@ -100,6 +133,12 @@ impl Noise {
         self.perlin(xs, zs) * self.amplitude
     }
 
+    /// Query noise at world coordinates (x, y, z), scaled like [`Noise::get`].
+    pub fn get3(&self, x: f32, y: f32, z: f32) -> f32 {
+        let s = self.scale;
+        self.perlin3(x / s, y / s, z / s) * self.amplitude
+    }
+
     /// Standard 2D Perlin noise:
     /// - Pick a unit gradient at each lattice corner via a hash.
     /// - Dot product with displacement vectors from corners to point.
@@ -144,6 +183,44 @@ impl Noise {
         let nx1 = linear_interpolation(n01, n11, u); // top edge
         linear_interpolation(nx0, nx1, v)
     }
+
+    /// Standard 3D Perlin noise: the 2D algorithm with a third axis, using
+    /// the eight corners of the lattice cube and trilinear interpolation.
+    fn perlin3(&self, x: f32, y: f32, z: f32) -> f32 {
+        // Integer lattice cell containing (x, y, z)
+        let xi = x.floor() as i32;
+        let yi = y.floor() as i32;
+        let zi = z.floor() as i32;
+
+        // Local coordinates within the cell in [0, 1)
+        let xf = x - xi as f32;
+        let yf = y - yi as f32;
+        let zf = z - zi as f32;
+
+        // Contribution of the corner at offset (dx, dy, dz)
+        let corner = |dx: i32, dy: i32, dz: i32| {
+            let g = gradient_at3(xi + dx, yi + dy, zi + dz, self.seed);
+            let d = Vector3 {
+                x: xf - dx as f32,
+                y: yf - dy as f32,
+                z: zf - dz as f32,
+            };
+            dot_product3(&g, &d)
+        };
+
+        let u = fade(xf);
+        let v = fade(yf);
+        let w = fade(zf);
+
+        // Interpolate along x on the four edges, then y, then z
+        let nx00 = linear_interpolation(corner(0, 0, 0), corner(1, 0, 0), u);
+        let nx10 = linear_interpolation(corner(0, 1, 0), corner(1, 1, 0), u);
+        let nx01 = linear_interpolation(corner(0, 0, 1), corner(1, 0, 1), u);
+        let nx11 = linear_interpolation(corner(0, 1, 1), corner(1, 1, 1), u);
+        let nxy0 = linear_interpolation(nx00, nx10, v);
+        let nxy1 = linear_interpolation(nx01, nx11, v);
+        linear_interpolation(nxy0, nxy1, w)
+    }
 }
 
 /// 2D integer hash to 64-bit state, mixed with a global seed.
@@ -160,6 +237,20 @@ fn hash2(ix: i32, iy: i32, seed: u64) -> u64 {
     h
 }
 
+/// 3D integer hash, mixed like [`hash2`] with one more odd multiplier.
+fn hash3(ix: i32, iy: i32, iz: i32, seed: u64) -> u64 {
+    let mut h = seed
+        ^ (ix as u64).wrapping_mul(0x9E3779B97F4A7C15)
+        ^ (iy as u64).wrapping_mul(0xC2B2AE3D27D4EB4F)
+        ^ (iz as u64).wrapping_mul(0x165667B19E3779F9);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xFF51AFD7ED558CCD);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xC4CEB9FE1A85EC53);
+    h ^= h >> 33;
+    h
+}
+
 /// Deterministic unit gradient at lattice point (ix, iy) from `seed`.
 #[inline]
 fn gradient_at(ix: i32, iy: i32, seed: u64) -> Vector {
@@ -190,11 +281,93 @@ fn gradient_at(ix: i32, iy: i32, seed: u64) -> Vector {
     G[(h as usize) & 7]
 }
 
+/// Deterministic unit gradient at lattice point (ix, iy, iz) from `seed`.
+/// Uses the 12 cube-edge directions; four of them repeat so a 4-bit mask
+/// picks one without modulo bias.
+#[inline]
+fn gradient_at3(ix: i32, iy: i32, iz: i32, seed: u64) -> Vector3 {
+    const D: f32 = 0.70710677;
+    const G: [Vector3; 16] = [
+        Vector3 { x: D, y: D, z: 0.0 },
+        Vector3 {
+            x: -D,
+            y: D,
+            z: 0.0,
+        },
+        Vector3 {
+            x: D,
+            y: -D,
+            z: 0.0,
+        },
+        Vector3 {
+            x: -D,
+            y: -D,
+            z: 0.0,
+        },
+        Vector3 { x: D, y: 0.0, z: D },
+        Vector3 {
+            x: -D,
+            y: 0.0,
+            z: D,
+        },
+        Vector3 {
+            x: D,
+            y: 0.0,
+            z: -D,
+        },
+        Vector3 {
+            x: -D,
+            y: 0.0,
+            z: -D,
+        },
+        Vector3 { x: 0.0, y: D, z: D },
+        Vector3 {
+            x: 0.0,
+            y: -D,
+            z: D,
+        },
+        Vector3 {
+            x: 0.0,
+            y: D,
+            z: -D,
+        },
+        Vector3 {
+            x: 0.0,
+            y: -D,
+            z: -D,
+        },
+        Vector3 { x: D, y: D, z: 0.0 },
+        Vector3 {
+            x: -D,
+            y: D,
+            z: 0.0,
+        },
+        Vector3 {
+            x: 0.0,
+            y: -D,
+            z: D,
+        },
+        Vector3 {
+            x: 0.0,
+            y: -D,
+            z: -D,
+        },
+    ];
+
+    let h = hash3(ix, iy, iz, seed);
+    G[(h as usize) & 15]
+}
+
 /// Dot product of two 2D vectors.
 fn dot_product(v1: &Vector, v2: &Vector) -> f32 {
     v1.x * v2.x + v1.y * v2.y
 }
 
+/// Dot product of two 3D vectors.
+fn dot_product3(v1: &Vector3, v2: &Vector3) -> f32 {
+    v1.x * v2.x + v1.y * v2.y + v1.z * v2.z
+}
+
 /// Euclidean norm (length) of a 2D vector.
 fn calculate_norm(v1: &Vector) -> f32 {
     v1.x.hypot(v1.y)
@@ -457,4 +630,57 @@ mod perlin_tests {
         assert!((n.get(x + h, y) - c0).abs() < 0.1);
         assert!((n.get(x, y + h) - c0).abs() < 0.1);
     }
+
+    #[test]
+    fn perlin3_deterministic_and_seeded() {
+        let n = Noise::new(32.0, 1.0, 123);
+        let a = n.get3(12.34, 70.5, -5.67);
+        assert_eq!(a, n.get3(12.34, 70.5, -5.67));
+        let other = Noise::new(32.0, 1.0, 124);
+        let differs = (0..16).any(|i| {
+            let p = i as f32 * 7.3;
+            n.get3(p, p * 0.5, -p) != other.get3(p, p * 0.5, -p)
+        });
+        assert!(differs);
+    }
+
+    #[test]
+    fn perlin3_zero_on_lattice_and_bounded() {
+        let n = Noise::new(1.0, 1.0, 7);
+        for i in -3..=3 {
+            assert_eq!(n.get3(i as f32, (i * 2) as f32, -i as f32), 0.0);
+        }
+        for i in -4..=4 {
+            for j in -4..=4 {
+                for k in -4..=4 {
+                    let v = n.get3(i as f32 * 0.37, j as f32 * 0.29, k as f32 * 0.41);
+                    assert!(v.is_finite());
+                    assert!((-1.5..=1.5).contains(&v));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn perlin3_local_continuity() {
+        let n = Noise::new(24.0, 1.0, 42);
+        let (x, y, z) = (3.2f32, 17.9f32, -4.7f32);
+        let h = 1e-3f32;
+        let c0 = n.get3(x, y, z);
+        assert!((n.get3(x + h, y, z) - c0).abs() < 0.1);
+        assert!((n.get3(x, y + h, z) - c0).abs() < 0.1);
+        assert!((n.get3(x, y, z + h) - c0).abs() < 0.1);
+    }
+
+    #[test]
+    fn fbm3_is_normalized() {
+        let mut spread = (f32::MAX, f32::MIN);
+        for i in 0..64 {
+            let p = i as f32 * 13.7;
+            let v = fbm3_seeded(99, 48.0, p, -p * 0.3, p * 0.7, 5, 0.5, 2.0);
+            assert!((-1.0..=1.0).contains(&v));
+            spread = (spread.0.min(v), spread.1.max(v));
+        }
+        assert!(spread.1 - spread.0 > 0.1);
+    }
 }
@@ -0,0 +1,287 @@
+//! Placed features: a configured feature with the placement modifiers
+//! that pick where in a chunk, and how often, it is placed.
+
+use crate::chunk::{BlockState, HeightmapKind};
+use crate::feature::{BlockPredicate, ConfiguredFeature, WorldGenRegion};
+use crate::provider::{HeightProvider, IntProvider};
+use crate::random::{RandomAlgorithm, RandomSource, WorldgenRandom};
+use crate::tree::TreeConfiguration;
+use std::collections::HashSet;
+
+/// One step of a placed feature, turning a position into any number of
+/// positions.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PlacementModifier {
+    /// The position repeated a sampled number of times.
+    Count(IntProvider),
+    /// Kept with chance one in `n`.
+    RarityFilter(i32),
+    /// Moved to a random column of the chunk.
+    InSquare,
+    /// Moved to a sampled height.
+    HeightRange(HeightProvider),
+    /// Moved onto the surface of a heightmap, and dropped in empty columns.
+    Heightmap(HeightmapKind),
+    /// Dropped under more than this many blocks of water.
+    SurfaceWaterDepthFilter(i32),
+    BlockPredicateFilter(BlockPredicate),
+    /// Kept where the biome lists the feature being placed.
+    Biome,
+}
+
+impl PlacementModifier {
+    fn positions(
+        &self,
+        level: &WorldGenRegion,
+        biomes: Option<&HashSet<String>>,
+        random: &mut dyn RandomSource,
+        [x, y, z]: [i32; 3],
+    ) -> Vec<[i32; 3]> {
+        let keep = |kept: bool| if kept { vec![[x, y, z]] } else { Vec::new() };
+        match self {
+            Self::Count(count) => vec![[x, y, z]; count.sample(random).max(0) as usize],
+            Self::RarityFilter(chance) => keep(random.next_float() < 1.0 / *chance as f32),
+            Self::InSquare => {
+                let dx = random.next_int_bounded(16);
+                let dz = random.next_int_bounded(16);
+                vec![[x + dx, y, z + dz]]
+            }
+            Self::HeightRange(height) => {
+                let (min_y, height_range) = (level.min_y(), level.max_y() - level.min_y());
+                vec![[x, height.sample(random, min_y, height_range), z]]
+            }
+            Self::Heightmap(kind) => {
+                let surface = level.height(*kind, x, z);
+                if surface > level.min_y() {
+                    vec![[x, surface, z]]
+                } else {
+                    Vec::new()
+                }
+            }
+            Self::SurfaceWaterDepthFilter(max_depth) => {
+                let floor = level.height(HeightmapKind::OceanFloor, x, z);
+                let surface = level.height(HeightmapKind::WorldSurface, x, z);
+                keep(surface - floor <= *max_depth)
+            }
+            Self::BlockPredicateFilter(predicate) => keep(predicate.test(level, [x, y, z])),
+            // Features placed outside a biome's list, like those of a
+            // patch, aren't restricted to any biome.
+            Self::Biome => keep(biomes.is_none_or(|biomes| {
+                level
+                    .get_biome([x, y, z])
+                    .is_some_and(|biome| biomes.contains(biome))
+            })),
+        }
+    }
+}
+
+/// A `worldgen/placed_feature` entry.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlacedFeature {
+    pub feature: ConfiguredFeature,
+    pub placement: Vec<PlacementModifier>,
+}
+
+impl PlacedFeature {
+    /// Trees with vanilla's `treePlacement`: `count` tries per chunk on dry
+    /// ground where `sapling` could grow.
+    pub fn trees(tree: TreeConfiguration, count: IntProvider, sapling: &str) -> Self {
+        Self {
+            feature: ConfiguredFeature::Tree(Box::new(tree)),
+            placement: vec![
+                PlacementModifier::Count(count),
+                PlacementModifier::InSquare,
+                PlacementModifier::SurfaceWaterDepthFilter(0),
+                PlacementModifier::Heightmap(HeightmapKind::OceanFloor),
+                PlacementModifier::BlockPredicateFilter(BlockPredicate::would_survive(
+                    BlockState::new(sapling),
+                )),
+                PlacementModifier::Biome,
+            ],
+        }
+    }
+
+    /// `count` patches per chunk on the world surface, like vanilla's grass
+    /// patches.
+    pub fn world_surface_squared_with_count(feature: ConfiguredFeature, count: i32) -> Self {
+        Self {
+            feature,
+            placement: vec![
+                PlacementModifier::Count(IntProvider::Constant(count)),
+                PlacementModifier::InSquare,
+                PlacementModifier::Heightmap(HeightmapKind::WorldSurfaceWg),
+                PlacementModifier::Biome,
+            ],
+        }
+    }
+
+    /// A patch in one of `chance` chunks, like vanilla's flowers.
+    pub fn rarity(feature: ConfiguredFeature, chance: i32) -> Self {
+        Self {
+            feature,
+            placement: vec![
+                PlacementModifier::RarityFilter(chance),
+                PlacementModifier::InSquare,
+                PlacementModifier::Heightmap(HeightmapKind::MotionBlocking),
+                PlacementModifier::Biome,
+            ],
+        }
+    }
+
+    /// Seagrass with vanilla's `seagrassPlacement`, tall with chance
+    /// `probability`.
+    pub fn seagrass(count: i32, probability: f32) -> Self {
+        Self {
+            feature: ConfiguredFeature::Seagrass { probability },
+            placement: vec![
+                PlacementModifier::InSquare,
+                PlacementModifier::Heightmap(HeightmapKind::OceanFloorWg),
+                PlacementModifier::Count(IntProvider::Constant(count)),
+                PlacementModifier::Biome,
+            ],
+        }
+    }
+
+    /// `count` kelp columns per chunk on the ocean floor.
+    pub fn kelp(count: i32) -> Self {
+        Self {
+            feature: ConfiguredFeature::Kelp,
+            placement: vec![
+                PlacementModifier::Count(IntProvider::Constant(count)),
+                PlacementModifier::InSquare,
+                PlacementModifier::Heightmap(HeightmapKind::OceanFloorWg),
+                PlacementModifier::Biome,
+            ],
+        }
+    }
+
+    /// Run the modifiers from `origin` and place the feature at every
+    /// position they give, returning whether anything was placed.
+    pub fn place(
+        &self,
+        level: &mut WorldGenRegion,
+        random: &mut dyn RandomSource,
+        origin: [i32; 3],
+    ) -> bool {
+        self.place_from(&self.placement, level, None, random, origin)
+    }
+
+    /// Like [`place`](Self::place), keeping the `biome` modifier to the
+    /// `biomes` listing this feature.
+    pub fn place_with_biome_check(
+        &self,
+        level: &mut WorldGenRegion,
+        biomes: &HashSet<String>,
+        random: &mut dyn RandomSource,
+        origin: [i32; 3],
+    ) -> bool {
+        self.place_from(&self.placement, level, Some(biomes), random, origin)
+    }
+
+    /// Place the feature as feature `index` of decoration step `step` of
+    /// chunk `chunk_x`/`chunk_z`, seeded the way vanilla seeds it.
+    pub fn place_in_chunk(
+        &self,
+        level: &mut WorldGenRegion,
+        level_seed: i64,
+        chunk_x: i32,
+        chunk_z: i32,
+        index: i32,
+        step: i32,
+    ) -> bool {
+        let (x, z) = (chunk_x << 4, chunk_z << 4);
+        let mut random = WorldgenRandom::new(RandomAlgorithm::Xoroshiro, 0);
+        let decoration_seed = random.set_decoration_seed(level_seed, x, z);
+        random.set_feature_seed(decoration_seed, index, step);
+        let origin = [x, level.min_y(), z];
+        self.place(level, &mut random, origin)
+    }
+
+    // Vanilla chains the modifiers as lazy streams, so each position runs
+    // through all later modifiers and the feature before the next one is
+    // made; going depth-first draws from `random` in the same order.
+    fn place_from(
+        &self,
+        modifiers: &[PlacementModifier],
+        level: &mut WorldGenRegion,
+        biomes: Option<&HashSet<String>>,
+        random: &mut dyn RandomSource,
+        pos: [i32; 3],
+    ) -> bool {
+        let Some((modifier, rest)) = modifiers.split_first() else {
+            return self.feature.place(level, random, pos);
+        };
+        let mut placed = false;
+        for next in modifier.positions(level, biomes, random, pos) {
+            placed |= self.place_from(rest, level, biomes, random, next);
+        }
+        placed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::{BlockState, Chunk};
+    use crate::feature::OreConfiguration;
+    use crate::surface::VerticalAnchor;
+    use crate::test::worldgen::find_blocks;
+
+    fn ores(level_seed: i64, height: HeightProvider) -> Vec<[i32; 3]> {
+        let mut chunks = Vec::new();
+        for cx in 1..=3 {
+            for cz in -3..=-1 {
+                let mut chunk = Chunk::with_height(cx, cz, -64, 128);
+                for section in chunk.sections_mut() {
+                    section.fill(BlockState::new("minecraft:stone"));
+                }
+                chunks.push(chunk);
+            }
+        }
+        let feature = PlacedFeature {
+            feature: ConfiguredFeature::Ore(OreConfiguration::stone_and_deepslate(
+                "minecraft:gold_ore",
+                "minecraft:deepslate_gold_ore",
+                9,
+                0.0,
+            )),
+            placement: vec![
+                PlacementModifier::Count(IntProvider::Constant(4)),
+                PlacementModifier::InSquare,
+                PlacementModifier::HeightRange(height),
+            ],
+        };
+        let mut level = WorldGenRegion::new(&mut chunks);
+        assert!(feature.place_in_chunk(&mut level, level_seed, 2, -2, 3, 6));
+
+        find_blocks(&chunks, |_, state| state.name == "minecraft:gold_ore")
+            .into_iter()
+            .map(|(pos, _)| pos)
+            .collect()
+    }
+
+    #[test]
+    fn ores_are_placed_in_their_chunk_and_height_range() {
+        let trapezoid = HeightProvider::Trapezoid {
+            min_inclusive: VerticalAnchor::Absolute(-20),
+            max_inclusive: VerticalAnchor::Absolute(20),
+            plateau: 0,
+        };
+        let found = ores(42, trapezoid.clone());
+        assert!(found.len() > 10, "{}", found.len());
+        for [x, y, z] in &found {
+            // Blobs reach a few blocks past the sampled position.
+            assert!((32 - 4..48 + 4).contains(x), "{x}");
+            assert!((-32 - 4..-16 + 4).contains(z), "{z}");
+            assert!((-20 - 4..=20 + 4).contains(y), "{y}");
+        }
+        assert_eq!(ores(42, trapezoid.clone()), found);
+        assert_ne!(ores(43, trapezoid), found);
+
+        let uniform = HeightProvider::Uniform {
+            min_inclusive: VerticalAnchor::AboveBottom(0),
+            max_inclusive: VerticalAnchor::AboveBottom(8),
+        };
+        assert!(ores(42, uniform).iter().all(|[_, y, _]| *y < -64 + 8 + 4));
+    }
+}
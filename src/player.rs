@@ -1,10 +1,16 @@
 use nbt::{Tag, Writer, write_nbt};
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
+use std::io;
 
-use crate::level::Dimension;
+use crate::level::{Dimension, dim_from_str};
+use crate::nbt_util::{self, get, invalid_data, opt, opt_compound};
 pub struct PlayerData {
     pub inventory: Vec<Item>,
-    pub motion: [f64; 2],
+    pub ender_items: Vec<Item>,
+    /// Armor, offhand and body slots, stored outside `Inventory` since 1.21.5.
+    pub equipment: BTreeMap<EquipmentSlot, Item>,
+    pub motion: [f64; 3],
     pub position: [f64; 3],
     pub rotation: [f32; 2],
     pub absorbtion_amount: f32,
@@ -43,7 +49,9 @@ impl Default for PlayerData {
     fn default() -> Self {
         Self {
             inventory: [].into(),
-            motion: [0.0, 0.0].into(),
+            ender_items: [].into(),
+            equipment: BTreeMap::new(),
+            motion: [0.0, 0.0, 0.0],
             position: [0.0, 0.0, 0.0].into(),
             rotation: [0.0, 0.0].into(),
             absorbtion_amount: 0.0,
@@ -80,43 +88,138 @@ impl Default for PlayerData {
         }
     }
 }
+/// An item stack in the 1.20.5+ format. `slot` is only meaningful for
+/// `Inventory` and `EnderItems`; equipment is keyed by [`EquipmentSlot`].
+#[derive(Clone, Debug)]
 pub struct Item {
-    pub count: i8,
+    pub count: i32,
     pub slot: i8,
-    pub metadata: i16,
     pub id: String,
+    /// Data components such as `minecraft:damage` or `minecraft:enchantments`.
+    pub components: HashMap<String, Tag>,
 }
 
-fn dim_to_str(d: &Dimension) -> &'static str {
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EquipmentSlot {
+    Head,
+    Chest,
+    Legs,
+    Feet,
+    Offhand,
+    Body,
+    Saddle,
+}
+
+impl EquipmentSlot {
+    pub const ALL: [EquipmentSlot; 7] = [
+        EquipmentSlot::Head,
+        EquipmentSlot::Chest,
+        EquipmentSlot::Legs,
+        EquipmentSlot::Feet,
+        EquipmentSlot::Offhand,
+        EquipmentSlot::Body,
+        EquipmentSlot::Saddle,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EquipmentSlot::Head => "head",
+            EquipmentSlot::Chest => "chest",
+            EquipmentSlot::Legs => "legs",
+            EquipmentSlot::Feet => "feet",
+            EquipmentSlot::Offhand => "offhand",
+            EquipmentSlot::Body => "body",
+            EquipmentSlot::Saddle => "saddle",
+        }
+    }
+}
+
+/// TAG_List of doubles, as used by `Pos` and `Motion`.
+fn double_list(name: &str, values: &[f64]) -> Tag {
+    Tag::new_list(
+        name,
+        6,
+        values.iter().map(|d| Tag::new_double("", *d)).collect(),
+    )
+}
+
+/// Item compound without a slot, as used inside `equipment`.
+fn item_to_nbt(item: &Item) -> Tag {
+    let mut tag = Tag::new_compound("");
+    tag.insert("id".into(), Tag::new_string("id", item.id.clone()));
+    tag.insert("count".into(), Tag::new_int("count", item.count));
+    if !item.components.is_empty() {
+        let mut components = Tag::new_compound("components");
+        for (k, v) in &item.components {
+            components.insert(k.clone(), v.clone());
+        }
+        tag.insert("components".into(), components);
+    }
+    tag
+}
+
+/// TAG_List of slotted item compounds, as used by `Inventory` and `EnderItems`.
+fn item_list(name: &str, items: &[Item]) -> Tag {
+    let list: Vec<Tag> = items
+        .iter()
+        .map(|i| {
+            let mut tag = item_to_nbt(i);
+            tag.insert("Slot".into(), Tag::new_byte("Slot", i.slot));
+            tag
+        })
+        .collect();
+    let kind = if list.is_empty() { 0 } else { 10 };
+    Tag::new_list(name, kind, list)
+}
+
+fn dim_to_str(d: &Dimension) -> &str {
     match d {
         Dimension::Overworld => "overworld",
         Dimension::End => "the_end",
         Dimension::Nether => "the_nether",
+        Dimension::Custom(id) => id,
     }
 }
 pub fn create_nbt(uuid: &String, player_data: PlayerData, path: String) -> std::io::Result<()> {
+    let root = to_nbt(uuid, &player_data);
+    let file = File::create(format!("{path}/{uuid}.dat"))?;
+    let mut w = Writer::to_gzip(file);
+    w.write_tag(&root)?;
+    Ok(())
+}
+
+pub fn to_nbt(uuid: &str, player_data: &PlayerData) -> Tag {
     let mut root = Tag::new_compound(uuid);
-    let mut inventory = Tag::new_compound("inventory");
-    let mut position = Tag::new_compound("Position");
-    let mut motion = Tag::new_compound("Motion");
-    for i in player_data.inventory {
-        inventory.insert("Count".into(), Tag::new_byte("Count", i.count));
-        inventory.insert("slot".into(), Tag::new_byte("slot", i.slot));
-        inventory.insert("metadata".into(), Tag::new_short("metadata", i.metadata));
-        inventory.insert("id".into(), Tag::new_string("id", i.id));
+    let mut equipment = Tag::new_compound("equipment");
+    for (slot, item) in &player_data.equipment {
+        equipment.insert(slot.name().into(), item_to_nbt(item));
     }
-    for i in player_data.motion {
-        motion.insert("".into(), Tag::new_double("", i));
-    }
-    for i in player_data.position {
-        position.insert("".into(), Tag::new_double("", i));
-    }
-    root.insert("inventory".into(), inventory);
-    root.insert("motion".into(), motion);
-    root.insert("position".into(), position);
     root.insert(
-        "absorbtionAmount".into(),
-        Tag::new_float("absorbtionAmount", player_data.absorbtion_amount),
+        "Inventory".into(),
+        item_list("Inventory", &player_data.inventory),
+    );
+    root.insert(
+        "EnderItems".into(),
+        item_list("EnderItems", &player_data.ender_items),
+    );
+    root.insert("equipment".into(), equipment);
+    root.insert("Pos".into(), double_list("Pos", &player_data.position));
+    root.insert("Motion".into(), double_list("Motion", &player_data.motion));
+    root.insert(
+        "Rotation".into(),
+        Tag::new_list(
+            "Rotation",
+            5,
+            player_data
+                .rotation
+                .iter()
+                .map(|f| Tag::new_float("", *f))
+                .collect(),
+        ),
+    );
+    root.insert(
+        "AbsorptionAmount".into(),
+        Tag::new_float("AbsorptionAmount", player_data.absorbtion_amount),
     );
     root.insert("Air".into(), Tag::new_short("Air", player_data.air));
     root.insert(
@@ -233,8 +336,141 @@ pub fn create_nbt(uuid: &String, player_data: PlayerData, path: String) -> std::
         Tag::new_int_array("UUID", player_data.uuid.into()),
     );
 
-    let file = File::create(format!("{path}/{uuid}.dat"))?;
-    let mut w = Writer::to_gzip(file);
-    w.write_tag(&root)?;
-    Ok(())
+    root
+}
+
+/// Read exactly `N` numbers from a list tag such as `Pos` or `Rotation`.
+fn number_list<const N: usize, T: Copy + Default>(
+    tag: &Tag,
+    key: &str,
+    as_number: impl Fn(&Tag) -> Option<T>,
+) -> io::Result<[T; N]> {
+    let list = get::<&[Tag]>(tag, key)?;
+    if list.len() != N {
+        return Err(invalid_data(format!(
+            "`{key}` has {} entries, expected {N}",
+            list.len()
+        )));
+    }
+    let mut out = [T::default(); N];
+    for (slot, entry) in out.iter_mut().zip(list) {
+        *slot = as_number(entry)
+            .ok_or_else(|| invalid_data(format!("`{key}` entry has the wrong type")))?;
+    }
+    Ok(out)
+}
+
+fn item_from_nbt(tag: &Tag) -> io::Result<Item> {
+    // 1.20.5+ writes an int `count`, older versions a byte `Count`.
+    let count = match opt::<i32>(tag, "count")? {
+        Some(count) => count,
+        None => opt::<i8>(tag, "Count")?.unwrap_or(1) as i32,
+    };
+    let components = match opt_compound(tag, "components")? {
+        Some(c) => c.as_compound().cloned().unwrap_or_default(),
+        None => HashMap::new(),
+    };
+    Ok(Item {
+        count,
+        slot: opt(tag, "Slot")?.unwrap_or(0),
+        id: get::<&str>(tag, "id")?.to_string(),
+        components,
+    })
+}
+
+fn items_from_nbt(tag: &Tag, key: &str) -> io::Result<Vec<Item>> {
+    opt::<&[Tag]>(tag, key)?
+        .unwrap_or_default()
+        .iter()
+        .map(item_from_nbt)
+        .collect()
+}
+
+impl PlayerData {
+    /// Read `{path}/{uuid}.dat`, the counterpart of [`create_nbt`].
+    pub fn read(uuid: &str, path: &str) -> io::Result<Self> {
+        Self::from_nbt(&nbt_util::read_gzip_file(format!("{path}/{uuid}.dat"))?)
+    }
+
+    /// Decode a vanilla player data compound. Position, rotation, motion,
+    /// inventory, food, XP, dimension and UUID are required; the remaining
+    /// tags fall back to their [`Default`] values.
+    pub fn from_nbt(tag: &Tag) -> io::Result<Self> {
+        let d = PlayerData::default();
+
+        let dimension = get::<&str>(tag, "Dimension")?;
+        let dimension = dim_from_str(dimension)
+            .ok_or_else(|| invalid_data(format!("unknown dimension `{dimension}`")))?;
+
+        let uuid = get::<&[i32]>(tag, "UUID")?;
+        let uuid: [i32; 4] = uuid
+            .try_into()
+            .map_err(|_| invalid_data(format!("`UUID` has {} ints, expected 4", uuid.len())))?;
+
+        let inventory = get::<&[Tag]>(tag, "Inventory")?
+            .iter()
+            .map(item_from_nbt)
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut equipment = BTreeMap::new();
+        if let Some(eq) = opt_compound(tag, "equipment")? {
+            for slot in EquipmentSlot::ALL {
+                if let Some(item) = opt_compound(eq, slot.name())? {
+                    equipment.insert(slot, item_from_nbt(item)?);
+                }
+            }
+        }
+
+        Ok(Self {
+            inventory,
+            ender_items: items_from_nbt(tag, "EnderItems")?,
+            equipment,
+            motion: number_list(tag, "Motion", Tag::as_double)?,
+            position: number_list(tag, "Pos", Tag::as_double)?,
+            rotation: number_list(tag, "Rotation", Tag::as_float)?,
+            // Cactus used to write the key misspelled.
+            absorbtion_amount: opt(tag, "AbsorptionAmount")?
+                .or(opt(tag, "absorbtionAmount")?)
+                .unwrap_or(d.absorbtion_amount),
+            air: opt(tag, "Air")?.unwrap_or(d.air),
+            current_impulse_context_reset_grace_time: opt(
+                tag,
+                "current_impulse_context_reset_grace_time",
+            )?
+            .unwrap_or(d.current_impulse_context_reset_grace_time),
+            data_version: opt(tag, "DataVersion")?.unwrap_or(d.data_version),
+            death_time: opt(tag, "DeathTime")?.unwrap_or(d.death_time),
+            dimension,
+            fall_distance: opt(tag, "fall_distance")?.unwrap_or(d.fall_distance),
+            fall_flying: opt(tag, "FallFlying")?.unwrap_or(d.fall_flying),
+            fire: opt(tag, "Fire")?.unwrap_or(d.fire),
+            food_exhaustion_level: get(tag, "foodExhaustionLevel")?,
+            food_level: get(tag, "foodLevel")?,
+            food_saturation_level: get(tag, "foodSaturationLevel")?,
+            food_tick_timer: get(tag, "foodTickTimer")?,
+            health: opt(tag, "Health")?.unwrap_or(d.health),
+            hurt_by_timestamp: opt(tag, "HurtByTimestamp")?.unwrap_or(d.hurt_by_timestamp),
+            hurt_time: opt(tag, "HurtTime")?.unwrap_or(d.hurt_time),
+            ignore_fall_damage_from_current_explosion: opt(
+                tag,
+                "ignore_fall_damage_from_current_explosion",
+            )?
+            .unwrap_or(d.ignore_fall_damage_from_current_explosion),
+            invulnerable: opt(tag, "Invulnerable")?.unwrap_or(d.invulnerable),
+            on_ground: opt(tag, "OnGround")?.unwrap_or(d.on_ground),
+            player_game_type: opt(tag, "playerGameType")?.unwrap_or(d.player_game_type),
+            portal_cooldown: opt(tag, "PortalCooldown")?.unwrap_or(d.portal_cooldown),
+            score: opt(tag, "Score")?.unwrap_or(d.score),
+            seen_credits: opt(tag, "seenCredits")?.unwrap_or(d.seen_credits),
+            selected_item_slot: opt(tag, "SelectedItemSlot")?.unwrap_or(d.selected_item_slot),
+            sleep_timer: opt(tag, "SleepTimer")?.unwrap_or(d.sleep_timer),
+            spawn_extra_particles_on_fall: opt(tag, "spawn_extra_particles_on_fall")?
+                .unwrap_or(d.spawn_extra_particles_on_fall),
+            xp_level: get(tag, "XpLevel")?,
+            xp_p: get(tag, "XpP")?,
+            xp_seed: opt(tag, "XpSeed")?.unwrap_or(d.xp_seed),
+            xp_total: get(tag, "XpTotal")?,
+            uuid,
+        })
+    }
 }
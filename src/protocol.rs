@@ -0,0 +1,85 @@
+use std::io;
+
+/// Append a protocol VarInt (LEB128 over the two's complement bits).
+pub fn write_var_int(out: &mut Vec<u8>, value: i32) {
+    let mut v = value as u32;
+    loop {
+        if v & !0x7F == 0 {
+            out.push(v as u8);
+            return;
+        }
+        out.push((v as u8 & 0x7F) | 0x80);
+        v >>= 7;
+    }
+}
+
+/// Read a protocol VarInt, advancing `buf` past it.
+pub fn read_var_int(buf: &mut &[u8]) -> io::Result<i32> {
+    let mut value: u32 = 0;
+    for i in 0..5 {
+        let (&byte, rest) = buf
+            .split_first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "truncated VarInt"))?;
+        *buf = rest;
+        value |= ((byte & 0x7F) as u32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(value as i32);
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "VarInt too long",
+    ))
+}
+
+/// Number of bytes `value` takes as a VarInt.
+pub fn var_int_len(value: i32) -> usize {
+    let v = value as u32;
+    (32 - v.leading_zeros() as usize).max(1).div_ceil(7)
+}
+
+pub fn read_u8(buf: &mut &[u8]) -> io::Result<u8> {
+    let (&byte, rest) = buf
+        .split_first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "truncated byte"))?;
+    *buf = rest;
+    Ok(byte)
+}
+
+pub fn read_i64(buf: &mut &[u8]) -> io::Result<i64> {
+    if buf.len() < 8 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated long",
+        ));
+    }
+    let (bytes, rest) = buf.split_at(8);
+    *buf = rest;
+    Ok(i64::from_be_bytes(bytes.try_into().expect("8 bytes")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn var_int_known_values() {
+        let cases: [(i32, &[u8]); 6] = [
+            (0, &[0x00]),
+            (1, &[0x01]),
+            (127, &[0x7F]),
+            (128, &[0x80, 0x01]),
+            (2_147_483_647, &[0xFF, 0xFF, 0xFF, 0xFF, 0x07]),
+            (-1, &[0xFF, 0xFF, 0xFF, 0xFF, 0x0F]),
+        ];
+        for (value, bytes) in cases {
+            let mut out = Vec::new();
+            write_var_int(&mut out, value);
+            assert_eq!(out, bytes);
+            assert_eq!(var_int_len(value), bytes.len());
+            let mut slice = bytes;
+            assert_eq!(read_var_int(&mut slice).unwrap(), value);
+            assert!(slice.is_empty());
+        }
+    }
+}
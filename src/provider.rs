@@ -0,0 +1,285 @@
+//! Randomized values of worldgen configs: vanilla's `IntProvider`,
+//! `FloatProvider` and `HeightProvider`, each sampled from the random
+//! source of the feature or carver using it.
+
+use crate::random::RandomSource;
+use crate::surface::VerticalAnchor;
+
+/// `Mth.randomBetweenInclusive`.
+pub(crate) fn between_inclusive(random: &mut dyn RandomSource, min: i32, max: i32) -> i32 {
+    random.next_int_bounded(max - min + 1) + min
+}
+
+/// `Mth.nextInt`: like [`between_inclusive`], but `min` when the range is
+/// empty or a single value.
+fn next_int(random: &mut dyn RandomSource, min: i32, max: i32) -> i32 {
+    if min >= max {
+        min
+    } else {
+        between_inclusive(random, min, max)
+    }
+}
+
+/// One of `entries`, picked with chance proportional to its weight:
+/// vanilla's `SimpleWeightedRandomList.getRandomValue`.
+pub(crate) fn weighted_pick<'a, T>(
+    entries: &'a [(T, i32)],
+    random: &mut dyn RandomSource,
+) -> &'a T {
+    let total = entries.iter().map(|(_, weight)| weight).sum();
+    let mut pick = random.next_int_bounded(total);
+    for (entry, weight) in entries {
+        pick -= weight;
+        if pick < 0 {
+            return entry;
+        }
+    }
+    unreachable!("a pick below the total weight lands on an entry")
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum IntProvider {
+    Constant(i32),
+    Uniform {
+        min_inclusive: i32,
+        max_inclusive: i32,
+    },
+    /// One of several providers, picked by weight, then sampled.
+    WeightedList(Vec<(IntProvider, i32)>),
+}
+
+impl IntProvider {
+    /// `base` plus, with probability `chance`, `added`: vanilla's
+    /// `PlacementUtils.countExtra`.
+    pub fn count_extra(base: i32, chance: f32, added: i32) -> Self {
+        let weight = (1.0 / chance) as i32;
+        Self::WeightedList(vec![
+            (Self::Constant(base), weight - 1),
+            (Self::Constant(base + added), 1),
+        ])
+    }
+
+    pub fn sample(&self, random: &mut dyn RandomSource) -> i32 {
+        match self {
+            Self::Constant(v) => *v,
+            Self::Uniform {
+                min_inclusive,
+                max_inclusive,
+            } => between_inclusive(random, *min_inclusive, *max_inclusive),
+            Self::WeightedList(entries) => weighted_pick(entries, random).sample(random),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FloatProvider {
+    Constant(f32),
+    /// Uniform in `min_inclusive..max_exclusive`.
+    Uniform {
+        min_inclusive: f32,
+        max_exclusive: f32,
+    },
+    ClampedNormal {
+        mean: f32,
+        deviation: f32,
+        min: f32,
+        max: f32,
+    },
+    /// The sum of two uniform values: flat over `plateau` in the middle of
+    /// `min..max` and falling linearly to either side.
+    Trapezoid {
+        min: f32,
+        max: f32,
+        plateau: f32,
+    },
+}
+
+impl FloatProvider {
+    pub fn sample(&self, random: &mut dyn RandomSource) -> f32 {
+        match *self {
+            Self::Constant(v) => v,
+            Self::Uniform {
+                min_inclusive,
+                max_exclusive,
+            } => random.next_float() * (max_exclusive - min_inclusive) + min_inclusive,
+            Self::ClampedNormal {
+                mean,
+                deviation,
+                min,
+                max,
+            } => (mean + random.next_gaussian() as f32 * deviation).clamp(min, max),
+            Self::Trapezoid { min, max, plateau } => {
+                let range = max - min;
+                let slope = (range - plateau) / 2.0;
+                let rest = range - slope;
+                min + random.next_float() * rest + random.next_float() * slope
+            }
+        }
+    }
+}
+
+/// A y coordinate picked between two anchors, resolved against the world
+/// bottom `min_y` and `height`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum HeightProvider {
+    Constant(VerticalAnchor),
+    Uniform {
+        min_inclusive: VerticalAnchor,
+        max_inclusive: VerticalAnchor,
+    },
+    /// Skewed towards the bottom by picking below a random limit.
+    BiasedToBottom {
+        min_inclusive: VerticalAnchor,
+        max_inclusive: VerticalAnchor,
+        inner: i32,
+    },
+    VeryBiasedToBottom {
+        min_inclusive: VerticalAnchor,
+        max_inclusive: VerticalAnchor,
+        inner: i32,
+    },
+    /// Triangular, or trapezoidal with a flat middle of `plateau` blocks.
+    Trapezoid {
+        min_inclusive: VerticalAnchor,
+        max_inclusive: VerticalAnchor,
+        plateau: i32,
+    },
+}
+
+impl HeightProvider {
+    pub fn sample(&self, random: &mut dyn RandomSource, min_y: i32, height: i32) -> i32 {
+        let resolve = |anchor: VerticalAnchor| anchor.resolve_y(min_y, height);
+        match *self {
+            Self::Constant(anchor) => resolve(anchor),
+            Self::Uniform {
+                min_inclusive,
+                max_inclusive,
+            } => {
+                let (min, max) = (resolve(min_inclusive), resolve(max_inclusive));
+                if min > max {
+                    return min;
+                }
+                between_inclusive(random, min, max)
+            }
+            Self::BiasedToBottom {
+                min_inclusive,
+                max_inclusive,
+                inner,
+            } => {
+                let (min, max) = (resolve(min_inclusive), resolve(max_inclusive));
+                let range = max - min - inner + 1;
+                if range <= 0 {
+                    return min;
+                }
+                let limit = random.next_int_bounded(range);
+                random.next_int_bounded(limit + inner) + min
+            }
+            Self::VeryBiasedToBottom {
+                min_inclusive,
+                max_inclusive,
+                inner,
+            } => {
+                let (min, max) = (resolve(min_inclusive), resolve(max_inclusive));
+                if max - min - inner < 0 {
+                    return min;
+                }
+                let outer = next_int(random, min + inner, max);
+                let limit = next_int(random, min, outer - 1);
+                next_int(random, min, limit - 1 + inner)
+            }
+            Self::Trapezoid {
+                min_inclusive,
+                max_inclusive,
+                plateau,
+            } => {
+                let (min, max) = (resolve(min_inclusive), resolve(max_inclusive));
+                if min > max {
+                    return min;
+                }
+                let range = max - min;
+                if plateau >= range {
+                    return between_inclusive(random, min, max);
+                }
+                let slope = (range - plateau) / 2;
+                let rest = range - slope;
+                min + between_inclusive(random, 0, rest) + between_inclusive(random, 0, slope)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::LegacyRandom;
+
+    #[test]
+    fn heights_stay_in_range() {
+        let mut random = LegacyRandom::new(7);
+        let anchors = (
+            VerticalAnchor::AboveBottom(8),
+            VerticalAnchor::Absolute(180),
+        );
+        let providers = [
+            HeightProvider::Uniform {
+                min_inclusive: anchors.0,
+                max_inclusive: anchors.1,
+            },
+            HeightProvider::BiasedToBottom {
+                min_inclusive: anchors.0,
+                max_inclusive: anchors.1,
+                inner: 1,
+            },
+            HeightProvider::VeryBiasedToBottom {
+                min_inclusive: anchors.0,
+                max_inclusive: anchors.1,
+                inner: 8,
+            },
+            HeightProvider::Trapezoid {
+                min_inclusive: anchors.0,
+                max_inclusive: anchors.1,
+                plateau: 0,
+            },
+        ];
+        let mut means = Vec::new();
+        for provider in &providers {
+            let samples: Vec<i32> = (0..2000)
+                .map(|_| provider.sample(&mut random, -64, 384))
+                .collect();
+            assert!(samples.iter().all(|y| (-56..=180).contains(y)));
+            means.push(samples.iter().sum::<i32>() / samples.len() as i32);
+        }
+        // Uniform and trapezoid centre on 62, the biased ones sit lower.
+        assert!((means[0] - 62).abs() < 8 && (means[3] - 62).abs() < 8);
+        assert!(means[1] < means[0] && means[2] < means[1]);
+
+        let constant = HeightProvider::Constant(VerticalAnchor::BelowTop(10));
+        assert_eq!(constant.sample(&mut random, -64, 384), 309);
+    }
+
+    #[test]
+    fn floats_follow_their_distribution() {
+        let mut random = LegacyRandom::new(7);
+        let trapezoid = FloatProvider::Trapezoid {
+            min: 0.0,
+            max: 6.0,
+            plateau: 2.0,
+        };
+        let normal = FloatProvider::ClampedNormal {
+            mean: 0.5,
+            deviation: 3.0,
+            min: -1.0,
+            max: 1.0,
+        };
+        for _ in 0..1000 {
+            assert!((0.0..6.0).contains(&trapezoid.sample(&mut random)));
+            assert!((-1.0..=1.0).contains(&normal.sample(&mut random)));
+        }
+        let uniform = FloatProvider::Uniform {
+            min_inclusive: 0.1,
+            max_exclusive: 0.9,
+        };
+        let v = uniform.sample(&mut LegacyRandom::new(1));
+        assert_eq!(v, LegacyRandom::new(1).next_float() * (0.9 - 0.1) + 0.1);
+    }
+}
@@ -0,0 +1,734 @@
+//! Random sources that reproduce vanilla's sequences bit for bit.
+//!
+//! World generation only matches the game for the same seed when every noise
+//! and feature draws the exact same numbers, so these follow Mojang's
+//! `RandomSupport`, `LegacyRandomSource`, `Xoroshiro128PlusPlus`,
+//! `XoroshiroRandomSource` and `WorldgenRandom` including their integer
+//! overflow and float rounding.
+
+pub const GOLDEN_RATIO_64: i64 = -7046029254386353131;
+pub const SILVER_RATIO_64: i64 = 7640891576956012809;
+
+/// Stafford's "Mix13" variant of the SplitMix64 finalizer.
+pub fn mix_stafford13(z: i64) -> i64 {
+    let mut z = z as u64;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    (z ^ (z >> 31)) as i64
+}
+
+/// A 128-bit seed as two longs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Seed128 {
+    pub lo: i64,
+    pub hi: i64,
+}
+
+impl Seed128 {
+    pub fn xor(self, lo: i64, hi: i64) -> Self {
+        Self {
+            lo: self.lo ^ lo,
+            hi: self.hi ^ hi,
+        }
+    }
+
+    pub fn mixed(self) -> Self {
+        Self {
+            lo: mix_stafford13(self.lo),
+            hi: mix_stafford13(self.hi),
+        }
+    }
+}
+
+/// Spread a 64-bit world seed over 128 bits without mixing.
+pub fn upgrade_seed_to_128bit_unmixed(seed: i64) -> Seed128 {
+    let lo = seed ^ SILVER_RATIO_64;
+    Seed128 {
+        lo,
+        hi: lo.wrapping_add(GOLDEN_RATIO_64),
+    }
+}
+
+/// `RandomSupport.upgradeSeedTo128bit`: how a world seed becomes a
+/// Xoroshiro state.
+pub fn upgrade_seed_to_128bit(seed: i64) -> Seed128 {
+    upgrade_seed_to_128bit_unmixed(seed).mixed()
+}
+
+/// `RandomSupport.seedFromHashOf`: the MD5 of a name, read as two big-endian
+/// longs. Used to derive independent sources such as `octave_-3` or
+/// `minecraft:temperature` from one positional factory.
+pub fn seed_from_hash_of(name: &str) -> Seed128 {
+    let digest = md5::compute(name.as_bytes()).0;
+    let (lo, hi) = digest.split_at(8);
+    Seed128 {
+        lo: i64::from_be_bytes(lo.try_into().expect("8 bytes")),
+        hi: i64::from_be_bytes(hi.try_into().expect("8 bytes")),
+    }
+}
+
+/// `Mth.getSeed`: a hash of block coordinates. Note the `x` product wraps as
+/// an int before widening, exactly like the Java code.
+pub fn get_seed(x: i32, y: i32, z: i32) -> i64 {
+    let mut l =
+        i64::from(x.wrapping_mul(3129871)) ^ i64::from(z).wrapping_mul(116129781) ^ i64::from(y);
+    l = l
+        .wrapping_mul(l)
+        .wrapping_mul(42317861)
+        .wrapping_add(l.wrapping_mul(11));
+    l >> 16
+}
+
+/// A source of random numbers with vanilla's `RandomSource` interface.
+pub trait RandomSource {
+    /// Split off an independent source seeded from this one.
+    fn fork(&mut self) -> Box<dyn RandomSource>;
+    /// Split off a factory of independent sources keyed by position or name.
+    fn fork_positional(&mut self) -> Box<dyn PositionalRandomFactory>;
+    fn set_seed(&mut self, seed: i64);
+    fn next_int(&mut self) -> i32;
+    /// Uniform in `0..bound`. Panics when `bound` is not positive.
+    fn next_int_bounded(&mut self, bound: i32) -> i32;
+    fn next_long(&mut self) -> i64;
+    fn next_boolean(&mut self) -> bool;
+    fn next_float(&mut self) -> f32;
+    fn next_double(&mut self) -> f64;
+    fn next_gaussian(&mut self) -> f64;
+
+    /// Uniform in `min..=max`.
+    fn next_int_between_inclusive(&mut self, min: i32, max: i32) -> i32 {
+        self.next_int_bounded(max - min + 1) + min
+    }
+
+    /// Advance the sequence by `count` ints.
+    fn consume_count(&mut self, count: usize) {
+        for _ in 0..count {
+            self.next_int();
+        }
+    }
+}
+
+/// Creates sources that depend only on the factory seed and a position or
+/// name, so results don't depend on generation order.
+pub trait PositionalRandomFactory {
+    fn at(&self, x: i32, y: i32, z: i32) -> Box<dyn RandomSource>;
+    /// Vanilla's `fromHashOf`.
+    fn with_hash_of(&self, name: &str) -> Box<dyn RandomSource>;
+    /// Vanilla's `fromSeed`.
+    fn with_seed(&self, seed: i64) -> Box<dyn RandomSource>;
+}
+
+/// `MarsagliaPolarGaussian`: draws normal values in pairs, returning the
+/// first and keeping the second for the next call.
+fn polar_gaussian(next_double: &mut dyn FnMut() -> f64) -> (f64, f64) {
+    loop {
+        let d = 2.0 * next_double() - 1.0;
+        let e = 2.0 * next_double() - 1.0;
+        let f = d * d + e * e;
+        if f < 1.0 && f != 0.0 {
+            let g = (-2.0 * f.ln() / f).sqrt();
+            return (d * g, e * g);
+        }
+    }
+}
+
+/// Sources built on a `next(bits)` primitive, like vanilla's
+/// `BitRandomSource`; the provided methods derive everything else from it
+/// the way `java.util.Random` does.
+trait BitRandomSource {
+    /// The top `bits` bits of the next output, as a (possibly negative) int.
+    fn next(&mut self, bits: u32) -> i32;
+
+    fn bits_next_int_bounded(&mut self, bound: i32) -> i32 {
+        assert!(bound > 0, "bound must be positive");
+        if bound & (bound - 1) == 0 {
+            return ((i64::from(bound) * i64::from(self.next(31))) >> 31) as i32;
+        }
+        loop {
+            let i = self.next(31);
+            let j = i % bound;
+            if i.wrapping_sub(j).wrapping_add(bound - 1) >= 0 {
+                return j;
+            }
+        }
+    }
+
+    fn bits_next_long(&mut self) -> i64 {
+        let hi = i64::from(self.next(32)) << 32;
+        hi.wrapping_add(i64::from(self.next(32)))
+    }
+
+    fn bits_next_float(&mut self) -> f32 {
+        self.next(24) as f32 * 5.9604645E-8
+    }
+
+    fn bits_next_double(&mut self) -> f64 {
+        let bits = (i64::from(self.next(26)) << 27) + i64::from(self.next(27));
+        bits as f64 * f64::from(1.110223E-16f32)
+    }
+}
+
+/// The 48-bit linear congruential generator of `java.util.Random`, as
+/// vanilla's `LegacyRandomSource`. Still used for features, structures and
+/// slime chunks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LegacyRandom {
+    seed: i64,
+    next_gaussian: Option<f64>,
+}
+
+impl LegacyRandom {
+    const MULTIPLIER: i64 = 0x5DEECE66D;
+    const INCREMENT: i64 = 0xB;
+    const MASK: i64 = (1 << 48) - 1;
+
+    pub fn new(seed: i64) -> Self {
+        Self {
+            seed: (seed ^ Self::MULTIPLIER) & Self::MASK,
+            next_gaussian: None,
+        }
+    }
+}
+
+impl BitRandomSource for LegacyRandom {
+    fn next(&mut self, bits: u32) -> i32 {
+        self.seed = self
+            .seed
+            .wrapping_mul(Self::MULTIPLIER)
+            .wrapping_add(Self::INCREMENT)
+            & Self::MASK;
+        (self.seed >> (48 - bits)) as i32
+    }
+}
+
+impl RandomSource for LegacyRandom {
+    fn fork(&mut self) -> Box<dyn RandomSource> {
+        Box::new(Self::new(self.next_long()))
+    }
+
+    fn fork_positional(&mut self) -> Box<dyn PositionalRandomFactory> {
+        Box::new(LegacyPositionalRandomFactory {
+            seed: self.next_long(),
+        })
+    }
+
+    fn set_seed(&mut self, seed: i64) {
+        *self = Self::new(seed);
+    }
+
+    fn next_int(&mut self) -> i32 {
+        self.next(32)
+    }
+
+    fn next_int_bounded(&mut self, bound: i32) -> i32 {
+        self.bits_next_int_bounded(bound)
+    }
+
+    fn next_long(&mut self) -> i64 {
+        self.bits_next_long()
+    }
+
+    fn next_boolean(&mut self) -> bool {
+        self.next(1) != 0
+    }
+
+    fn next_float(&mut self) -> f32 {
+        self.bits_next_float()
+    }
+
+    fn next_double(&mut self) -> f64 {
+        self.bits_next_double()
+    }
+
+    fn next_gaussian(&mut self) -> f64 {
+        if let Some(g) = self.next_gaussian.take() {
+            return g;
+        }
+        let (g, next) = polar_gaussian(&mut || self.bits_next_double());
+        self.next_gaussian = Some(next);
+        g
+    }
+}
+
+/// `LegacyRandomSource.LegacyPositionalRandomFactory`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LegacyPositionalRandomFactory {
+    seed: i64,
+}
+
+impl PositionalRandomFactory for LegacyPositionalRandomFactory {
+    fn at(&self, x: i32, y: i32, z: i32) -> Box<dyn RandomSource> {
+        Box::new(LegacyRandom::new(get_seed(x, y, z) ^ self.seed))
+    }
+
+    fn with_hash_of(&self, name: &str) -> Box<dyn RandomSource> {
+        Box::new(LegacyRandom::new(
+            i64::from(java_string_hash(name)) ^ self.seed,
+        ))
+    }
+
+    fn with_seed(&self, seed: i64) -> Box<dyn RandomSource> {
+        Box::new(LegacyRandom::new(seed))
+    }
+}
+
+/// `String.hashCode` over UTF-16 code units.
+fn java_string_hash(s: &str) -> i32 {
+    s.encode_utf16()
+        .fold(0i32, |h, c| h.wrapping_mul(31).wrapping_add(i32::from(c)))
+}
+
+/// The Xoroshiro128++ generator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Xoroshiro128PlusPlus {
+    lo: i64,
+    hi: i64,
+}
+
+impl Xoroshiro128PlusPlus {
+    /// An all-zero state would only ever produce zeros, so it is replaced
+    /// by the golden and silver ratios.
+    pub fn new(lo: i64, hi: i64) -> Self {
+        if lo | hi == 0 {
+            Self {
+                lo: GOLDEN_RATIO_64,
+                hi: SILVER_RATIO_64,
+            }
+        } else {
+            Self { lo, hi }
+        }
+    }
+
+    pub fn from_seed128(seed: Seed128) -> Self {
+        Self::new(seed.lo, seed.hi)
+    }
+
+    pub fn next_long(&mut self) -> i64 {
+        let l = self.lo as u64;
+        let mut m = self.hi as u64;
+        let n = l.wrapping_add(m).rotate_left(17).wrapping_add(l);
+        m ^= l;
+        self.lo = (l.rotate_left(49) ^ m ^ (m << 21)) as i64;
+        self.hi = m.rotate_left(28) as i64;
+        n as i64
+    }
+}
+
+/// `XoroshiroRandomSource`, the random source of modern world generation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct XoroshiroRandomSource {
+    rng: Xoroshiro128PlusPlus,
+    next_gaussian: Option<f64>,
+}
+
+impl XoroshiroRandomSource {
+    pub fn new(seed: i64) -> Self {
+        Self::from_seed128(upgrade_seed_to_128bit(seed))
+    }
+
+    pub fn from_seed128(seed: Seed128) -> Self {
+        Self {
+            rng: Xoroshiro128PlusPlus::from_seed128(seed),
+            next_gaussian: None,
+        }
+    }
+
+    fn next_bits(&mut self, bits: u32) -> u64 {
+        (self.rng.next_long() as u64) >> (64 - bits)
+    }
+}
+
+impl RandomSource for XoroshiroRandomSource {
+    fn fork(&mut self) -> Box<dyn RandomSource> {
+        let lo = self.rng.next_long();
+        let hi = self.rng.next_long();
+        Box::new(Self::from_seed128(Seed128 { lo, hi }))
+    }
+
+    fn fork_positional(&mut self) -> Box<dyn PositionalRandomFactory> {
+        let lo = self.rng.next_long();
+        let hi = self.rng.next_long();
+        Box::new(XoroshiroPositionalRandomFactory { lo, hi })
+    }
+
+    fn set_seed(&mut self, seed: i64) {
+        *self = Self::new(seed);
+    }
+
+    fn next_int(&mut self) -> i32 {
+        self.rng.next_long() as i32
+    }
+
+    /// Lemire's multiply-shift with rejection, as in vanilla.
+    fn next_int_bounded(&mut self, bound: i32) -> i32 {
+        assert!(bound > 0, "bound must be positive");
+        let bound = bound as u64;
+        let mut m = u64::from(self.next_int() as u32) * bound;
+        if m & 0xFFFF_FFFF < bound {
+            let threshold = (bound as u32).wrapping_neg() % bound as u32;
+            while ((m & 0xFFFF_FFFF) as u32) < threshold {
+                m = u64::from(self.next_int() as u32) * bound;
+            }
+        }
+        (m >> 32) as i32
+    }
+
+    fn next_long(&mut self) -> i64 {
+        self.rng.next_long()
+    }
+
+    fn next_boolean(&mut self) -> bool {
+        self.rng.next_long() & 1 != 0
+    }
+
+    fn next_float(&mut self) -> f32 {
+        self.next_bits(24) as f32 * 5.9604645E-8
+    }
+
+    fn next_double(&mut self) -> f64 {
+        // Vanilla multiplies by the float literal 1.110223E-16F, which is
+        // exactly 2^-53.
+        self.next_bits(53) as f64 * (1.110223E-16f32 as f64)
+    }
+
+    fn next_gaussian(&mut self) -> f64 {
+        if let Some(g) = self.next_gaussian.take() {
+            return g;
+        }
+        let (g, next) = polar_gaussian(&mut || self.next_double());
+        self.next_gaussian = Some(next);
+        g
+    }
+
+    fn consume_count(&mut self, count: usize) {
+        for _ in 0..count {
+            self.rng.next_long();
+        }
+    }
+}
+
+/// `XoroshiroRandomSource.XoroshiroPositionalRandomFactory`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct XoroshiroPositionalRandomFactory {
+    lo: i64,
+    hi: i64,
+}
+
+impl PositionalRandomFactory for XoroshiroPositionalRandomFactory {
+    fn at(&self, x: i32, y: i32, z: i32) -> Box<dyn RandomSource> {
+        let seed = Seed128 {
+            lo: get_seed(x, y, z) ^ self.lo,
+            hi: self.hi,
+        };
+        Box::new(XoroshiroRandomSource::from_seed128(seed))
+    }
+
+    fn with_hash_of(&self, name: &str) -> Box<dyn RandomSource> {
+        let seed = seed_from_hash_of(name).xor(self.lo, self.hi);
+        Box::new(XoroshiroRandomSource::from_seed128(seed))
+    }
+
+    fn with_seed(&self, seed: i64) -> Box<dyn RandomSource> {
+        let seed = Seed128 { lo: seed, hi: seed }.xor(self.lo, self.hi);
+        Box::new(XoroshiroRandomSource::from_seed128(seed))
+    }
+}
+
+/// Which generator a dimension's noise settings ask for
+/// (`legacy_random_source`), vanilla's `WorldgenRandom.Algorithm`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RandomAlgorithm {
+    Legacy,
+    Xoroshiro,
+}
+
+impl RandomAlgorithm {
+    pub fn new_instance(self, seed: i64) -> Box<dyn RandomSource> {
+        match self {
+            Self::Legacy => Box::new(LegacyRandom::new(seed)),
+            Self::Xoroshiro => Box::new(XoroshiroRandomSource::new(seed)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum WorldgenSource {
+    Legacy(LegacyRandom),
+    Xoroshiro(XoroshiroRandomSource),
+}
+
+/// `WorldgenRandom`: the `java.util.Random` interface on top of either
+/// algorithm, plus the seeding schemes of decoration and structures. Every
+/// value goes through `next(bits)`, so a Xoroshiro-backed instance draws
+/// differently from a plain [`XoroshiroRandomSource`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WorldgenRandom {
+    source: WorldgenSource,
+    count: u32,
+    next_gaussian: Option<f64>,
+}
+
+impl WorldgenRandom {
+    pub fn new(algorithm: RandomAlgorithm, seed: i64) -> Self {
+        let source = match algorithm {
+            RandomAlgorithm::Legacy => WorldgenSource::Legacy(LegacyRandom::new(seed)),
+            RandomAlgorithm::Xoroshiro => {
+                WorldgenSource::Xoroshiro(XoroshiroRandomSource::new(seed))
+            }
+        };
+        Self {
+            source,
+            count: 0,
+            next_gaussian: None,
+        }
+    }
+
+    /// Number of `next(bits)` calls so far.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Seed for decorating the chunk whose minimum block is `x`/`z`;
+    /// returned so each feature can be seeded from it.
+    pub fn set_decoration_seed(&mut self, level_seed: i64, x: i32, z: i32) -> i64 {
+        self.set_seed(level_seed);
+        let l = self.next_long() | 1;
+        let m = self.next_long() | 1;
+        let n = i64::from(x)
+            .wrapping_mul(l)
+            .wrapping_add(i64::from(z).wrapping_mul(m))
+            ^ level_seed;
+        self.set_seed(n);
+        n
+    }
+
+    /// Seed for feature `index` of generation step `step`.
+    pub fn set_feature_seed(&mut self, decoration_seed: i64, index: i32, step: i32) {
+        let seed = decoration_seed
+            .wrapping_add(i64::from(index))
+            .wrapping_add(i64::from(10000i32.wrapping_mul(step)));
+        self.set_seed(seed);
+    }
+
+    /// Seed for carvers and structure starts of the chunk at `x`/`z`.
+    pub fn set_large_feature_seed(&mut self, seed: i64, x: i32, z: i32) {
+        self.set_seed(seed);
+        let l = self.next_long();
+        let m = self.next_long();
+        let n = i64::from(x).wrapping_mul(l) ^ i64::from(z).wrapping_mul(m) ^ seed;
+        self.set_seed(n);
+    }
+
+    /// Seed for structure placement in region `x`/`z`.
+    pub fn set_large_feature_with_salt(&mut self, seed: i64, x: i32, z: i32, salt: i32) {
+        let n = i64::from(x)
+            .wrapping_mul(341873128712)
+            .wrapping_add(i64::from(z).wrapping_mul(132897987541))
+            .wrapping_add(seed)
+            .wrapping_add(i64::from(salt));
+        self.set_seed(n);
+    }
+
+    /// The source deciding whether chunk `x`/`z` is a slime chunk: it is
+    /// when `next_int_bounded(10)` gives 0 with salt `987234911`.
+    pub fn seed_slime_chunk(x: i32, z: i32, seed: i64, salt: i64) -> LegacyRandom {
+        LegacyRandom::new(
+            seed.wrapping_add(i64::from(x.wrapping_mul(x).wrapping_mul(4987142)))
+                .wrapping_add(i64::from(x.wrapping_mul(5947611)))
+                .wrapping_add(i64::from(z.wrapping_mul(z)).wrapping_mul(4392871))
+                .wrapping_add(i64::from(z.wrapping_mul(406811)))
+                ^ salt,
+        )
+    }
+}
+
+/// Whether slimes spawn underground in chunk `x`/`z` of a world.
+pub fn is_slime_chunk(seed: i64, x: i32, z: i32) -> bool {
+    WorldgenRandom::seed_slime_chunk(x, z, seed, 987234911).next_int_bounded(10) == 0
+}
+
+impl BitRandomSource for WorldgenRandom {
+    fn next(&mut self, bits: u32) -> i32 {
+        self.count += 1;
+        match &mut self.source {
+            WorldgenSource::Legacy(r) => r.next(bits),
+            WorldgenSource::Xoroshiro(r) => ((r.next_long() as u64) >> (64 - bits)) as i32,
+        }
+    }
+}
+
+impl RandomSource for WorldgenRandom {
+    fn fork(&mut self) -> Box<dyn RandomSource> {
+        match &mut self.source {
+            WorldgenSource::Legacy(r) => r.fork(),
+            WorldgenSource::Xoroshiro(r) => r.fork(),
+        }
+    }
+
+    fn fork_positional(&mut self) -> Box<dyn PositionalRandomFactory> {
+        match &mut self.source {
+            WorldgenSource::Legacy(r) => r.fork_positional(),
+            WorldgenSource::Xoroshiro(r) => r.fork_positional(),
+        }
+    }
+
+    fn set_seed(&mut self, seed: i64) {
+        match &mut self.source {
+            WorldgenSource::Legacy(r) => r.set_seed(seed),
+            WorldgenSource::Xoroshiro(r) => r.set_seed(seed),
+        }
+    }
+
+    fn next_int(&mut self) -> i32 {
+        self.next(32)
+    }
+
+    fn next_int_bounded(&mut self, bound: i32) -> i32 {
+        self.bits_next_int_bounded(bound)
+    }
+
+    fn next_long(&mut self) -> i64 {
+        self.bits_next_long()
+    }
+
+    fn next_boolean(&mut self) -> bool {
+        self.next(1) != 0
+    }
+
+    fn next_float(&mut self) -> f32 {
+        self.bits_next_float()
+    }
+
+    fn next_double(&mut self) -> f64 {
+        self.bits_next_double()
+    }
+
+    fn next_gaussian(&mut self) -> f64 {
+        if let Some(g) = self.next_gaussian.take() {
+            return g;
+        }
+        let (g, next) = polar_gaussian(&mut || self.bits_next_double());
+        self.next_gaussian = Some(next);
+        g
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reference values below were produced on the JVM by the vanilla
+    // algorithms, including Java's int/long overflow and float literals.
+
+    #[test]
+    fn xoroshiro_matches_vanilla() {
+        let mut r = XoroshiroRandomSource::new(42);
+        assert_eq!(
+            [r.next_long(), r.next_long(), r.next_long()],
+            [
+                -4695948378737616609,
+                7341713790291473579,
+                -7542733514721318211
+            ]
+        );
+
+        let mut r = XoroshiroRandomSource::new(42);
+        assert_eq!(r.next_int(), 1774573855);
+        assert_eq!(r.next_int_bounded(100), 31);
+        assert_eq!(r.next_double(), 0.5911075968429961);
+        assert_eq!(r.next_float(), 0.26502723);
+    }
+
+    #[test]
+    fn seed_mixing_matches_vanilla() {
+        assert_eq!(
+            upgrade_seed_to_128bit(0),
+            Seed128 {
+                lo: 3847398142028685078,
+                hi: 7192185014346937746
+            }
+        );
+        assert_eq!(
+            seed_from_hash_of("minecraft:offset"),
+            Seed128 {
+                lo: 577895406318539652,
+                hi: 4557074653038767061
+            }
+        );
+        assert_eq!(get_seed(1, 2, 3), -33674130277896);
+        assert_eq!(get_seed(-100000, 64, 2500000), 1492461844983);
+    }
+
+    #[test]
+    fn positional_factory_matches_vanilla() {
+        let factory = XoroshiroRandomSource::new(42).fork_positional();
+        assert_eq!(factory.at(1, 2, 3).next_long(), -3901958205717205245);
+        assert_eq!(
+            factory.with_hash_of("octave_-3").next_long(),
+            -777879536903086661
+        );
+    }
+
+    #[test]
+    fn legacy_random_matches_java_util_random() {
+        let mut r = LegacyRandom::new(42);
+        assert_eq!(r.next_int(), -1170105035);
+        assert_eq!(r.next_int_bounded(10), 3);
+        assert_eq!(r.next_int_bounded(16), 10);
+        assert_eq!(r.next_int_bounded(1000000007), 102948884);
+        assert_eq!(r.next_long(), 5694868678511409995);
+        assert!(!r.next_boolean());
+        assert_eq!(r.next_float(), 0.70771056);
+        assert_eq!(r.next_double(), 0.6655489517945736);
+        assert_eq!(r.next_gaussian(), 0.7712775326519722);
+        assert_eq!(r.next_gaussian(), -0.25089675133752143);
+        assert_eq!(r.next_int(), 1184328952);
+        assert_eq!(
+            LegacyRandom::new(-123456789).next_long(),
+            6194098178591492762
+        );
+    }
+
+    #[test]
+    fn forks_match_vanilla() {
+        let mut r = XoroshiroRandomSource::new(42);
+        assert_eq!(r.fork().next_long(), 726233142542344846);
+
+        let factory = LegacyPositionalRandomFactory { seed: 42 };
+        assert_eq!(factory.at(1, 2, 3).next_long(), -3705372445285275952);
+        assert_eq!(
+            factory.with_hash_of("minecraft:ore").next_long(),
+            7056204845629078676
+        );
+    }
+
+    #[test]
+    fn worldgen_seeds_match_vanilla() {
+        let mut r = WorldgenRandom::new(RandomAlgorithm::Legacy, 0);
+        let decoration = r.set_decoration_seed(12345, 32, -48);
+        assert_eq!(decoration, -3799801871930699959);
+        assert_eq!(r.next_int(), 1427232687);
+        r.set_feature_seed(decoration, 3, 9);
+        assert_eq!(r.next_int_bounded(100), 53);
+        r.set_large_feature_seed(12345, 7, -3);
+        assert_eq!(r.next_int(), -1055283679);
+        r.set_large_feature_with_salt(12345, 7, -3, 10387312);
+        assert_eq!(r.next_int(), -1298127080);
+        assert_eq!(r.count(), 12);
+
+        let mut r = WorldgenRandom::new(RandomAlgorithm::Xoroshiro, 0);
+        assert_eq!(r.set_decoration_seed(12345, 32, -48), 2476831614839651209);
+        assert_eq!(r.next_int_bounded(100), 58);
+        assert_eq!(r.next_long(), 5048926763059840922);
+    }
+
+    #[test]
+    fn slime_chunks_match_vanilla() {
+        let slime: Vec<(i32, i32)> = (-8..8)
+            .flat_map(|x| (-8..8).map(move |z| (x, z)))
+            .filter(|&(x, z)| is_slime_chunk(12345, x, z))
+            .collect();
+        assert_eq!(slime.len(), 32);
+        assert_eq!(&slime[..4], [(-7, 2), (-6, -6), (-6, -5), (-6, -3)]);
+    }
+}
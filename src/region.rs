@@ -0,0 +1,590 @@
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Size of one region sector in bytes. Headers and chunk payloads are sector aligned.
+pub const SECTOR_SIZE: usize = 4096;
+/// Number of chunks along one side of a region (32 × 32 chunks per file).
+pub const REGION_SIZE: i32 = 32;
+const CHUNK_COUNT: usize = (REGION_SIZE * REGION_SIZE) as usize;
+/// Location table + timestamp table.
+const HEADER_SECTORS: u32 = 2;
+/// Largest sector count representable in the location table. Bigger chunks
+/// are stored in an external `c.X.Z.mcc` file next to the region.
+pub const MAX_CHUNK_SECTORS: u32 = 255;
+/// Flag on the compression byte marking a chunk stored in a `.mcc` file.
+const EXTERNAL_FLAG: u8 = 0x80;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Gzip = 1,
+    Zlib = 2,
+    None = 3,
+}
+
+impl Compression {
+    pub fn from_id(id: u8) -> io::Result<Self> {
+        match id {
+            1 => Ok(Compression::Gzip),
+            2 => Ok(Compression::Zlib),
+            3 => Ok(Compression::None),
+            other => Err(invalid_data(format!(
+                "unsupported chunk compression type {other}"
+            ))),
+        }
+    }
+
+    /// Decompress a chunk payload into raw NBT bytes.
+    pub fn decompress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        match self {
+            Compression::Gzip => {
+                GzDecoder::new(data).read_to_end(&mut out)?;
+            }
+            Compression::Zlib => {
+                ZlibDecoder::new(data).read_to_end(&mut out)?;
+            }
+            Compression::None => out.extend_from_slice(data),
+        }
+        Ok(out)
+    }
+
+    /// Compress raw NBT bytes for storage.
+    pub fn compress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::Gzip => {
+                let mut enc = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                enc.write_all(data)?;
+                enc.finish()
+            }
+            Compression::Zlib => {
+                let mut enc = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                enc.write_all(data)?;
+                enc.finish()
+            }
+            Compression::None => Ok(data.to_vec()),
+        }
+    }
+}
+
+/// An Anvil region file (`r.X.Z.mca`) holding up to 32 × 32 chunks.
+///
+/// Layout: a 4 KiB location table (3-byte sector offset + 1-byte sector count
+/// per chunk), a 4 KiB table of last-modified timestamps, then the chunk
+/// payloads, each starting on a sector boundary with a 4-byte big-endian
+/// length and a 1-byte compression type.
+///
+/// Chunk coordinates passed to the accessors are absolute; they are wrapped
+/// into the region for the header lookup and used as-is to name `.mcc` files.
+pub struct RegionFile {
+    file: File,
+    path: PathBuf,
+    locations: [u32; CHUNK_COUNT],
+    timestamps: [u32; CHUNK_COUNT],
+    /// Occupancy of every sector in the file, header included.
+    used_sectors: Vec<bool>,
+}
+
+/// Path of the region file containing region `(region_x, region_z)` inside `dir`.
+pub fn region_path(dir: &Path, region_x: i32, region_z: i32) -> PathBuf {
+    dir.join(format!("r.{region_x}.{region_z}.mca"))
+}
+
+/// Region coordinates containing the chunk `(chunk_x, chunk_z)`.
+pub fn region_coords(chunk_x: i32, chunk_z: i32) -> (i32, i32) {
+    (chunk_x >> 5, chunk_z >> 5)
+}
+
+/// Path of the external file holding the oversized chunk `(chunk_x, chunk_z)`.
+pub fn external_chunk_path(dir: &Path, chunk_x: i32, chunk_z: i32) -> PathBuf {
+    dir.join(format!("c.{chunk_x}.{chunk_z}.mcc"))
+}
+
+/// Index of a chunk in the header tables. Absolute chunk coordinates are
+/// accepted and wrapped into the region, like vanilla does.
+#[inline]
+fn chunk_index(chunk_x: i32, chunk_z: i32) -> usize {
+    ((chunk_x & (REGION_SIZE - 1)) + (chunk_z & (REGION_SIZE - 1)) * REGION_SIZE) as usize
+}
+
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+impl RegionFile {
+    /// Open a region file for reading and writing, creating it when missing.
+    /// A file shorter than the header is reset to an empty region, and a
+    /// trailing partial sector is padded out to the sector boundary.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        let mut locations = [0u32; CHUNK_COUNT];
+        let mut timestamps = [0u32; CHUNK_COUNT];
+
+        let header_len = (HEADER_SECTORS as usize * SECTOR_SIZE) as u64;
+        let mut len = file.metadata()?.len();
+        if len < header_len {
+            file.set_len(0)?;
+            file.set_len(header_len)?;
+            len = header_len;
+        } else {
+            let mut header = vec![0u8; HEADER_SECTORS as usize * SECTOR_SIZE];
+            file.seek(SeekFrom::Start(0))?;
+            file.read_exact(&mut header)?;
+            for i in 0..CHUNK_COUNT {
+                let loc = &header[i * 4..i * 4 + 4];
+                locations[i] = u32::from_be_bytes([loc[0], loc[1], loc[2], loc[3]]);
+                let ts = &header[SECTOR_SIZE + i * 4..SECTOR_SIZE + i * 4 + 4];
+                timestamps[i] = u32::from_be_bytes([ts[0], ts[1], ts[2], ts[3]]);
+            }
+        }
+        if len % SECTOR_SIZE as u64 != 0 {
+            len = len.next_multiple_of(SECTOR_SIZE as u64);
+            file.set_len(len)?;
+        }
+
+        let total_sectors = (len / SECTOR_SIZE as u64) as usize;
+        let mut used_sectors = vec![false; total_sectors];
+        used_sectors[..HEADER_SECTORS as usize].fill(true);
+        for loc in locations.iter_mut() {
+            let (offset, count) = ((*loc >> 8) as usize, (*loc & 0xFF) as usize);
+            if *loc == 0 {
+                continue;
+            }
+            // Drop entries pointing into the header or past the end of the file.
+            if offset < HEADER_SECTORS as usize || count == 0 || offset + count > total_sectors {
+                *loc = 0;
+                continue;
+            }
+            used_sectors[offset..offset + count].fill(true);
+        }
+
+        Ok(Self {
+            file,
+            path,
+            locations,
+            timestamps,
+            used_sectors,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// First sector and sector count of a chunk, `(0, 0)` when absent.
+    pub fn sectors(&self, chunk_x: i32, chunk_z: i32) -> (u32, u32) {
+        let loc = self.locations[chunk_index(chunk_x, chunk_z)];
+        (loc >> 8, loc & 0xFF)
+    }
+
+    pub fn has_chunk(&self, chunk_x: i32, chunk_z: i32) -> bool {
+        self.locations[chunk_index(chunk_x, chunk_z)] != 0
+    }
+
+    /// Last modification time of a chunk, in seconds since the epoch.
+    pub fn timestamp(&self, chunk_x: i32, chunk_z: i32) -> u32 {
+        self.timestamps[chunk_index(chunk_x, chunk_z)]
+    }
+
+    /// Read and decompress the NBT payload of a chunk.
+    /// Returns `Ok(None)` when the chunk has never been saved.
+    pub fn read_chunk(&mut self, chunk_x: i32, chunk_z: i32) -> io::Result<Option<Vec<u8>>> {
+        let (offset, count) = self.sectors(chunk_x, chunk_z);
+        if offset == 0 || count == 0 {
+            return Ok(None);
+        }
+        if offset < HEADER_SECTORS {
+            return Err(invalid_data(format!(
+                "chunk ({chunk_x}, {chunk_z}) points into the region header"
+            )));
+        }
+
+        let mut buf = vec![0u8; count as usize * SECTOR_SIZE];
+        self.file
+            .seek(SeekFrom::Start(offset as u64 * SECTOR_SIZE as u64))?;
+        self.file.read_exact(&mut buf)?;
+
+        let length = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+        if length == 0 || length + 4 > buf.len() {
+            return Err(invalid_data(format!(
+                "chunk ({chunk_x}, {chunk_z}) has invalid length {length}"
+            )));
+        }
+        let compression_byte = buf[4];
+        let compression = Compression::from_id(compression_byte & !EXTERNAL_FLAG)?;
+        if compression_byte & EXTERNAL_FLAG != 0 {
+            let external = std::fs::read(self.external_path(chunk_x, chunk_z))?;
+            return compression.decompress(&external).map(Some);
+        }
+        // `length` counts the compression byte as well.
+        compression.decompress(&buf[5..4 + length]).map(Some)
+    }
+
+    /// Compress and store the NBT payload of a chunk, updating both header
+    /// tables. The chunk is rewritten in place when it still fits. Otherwise
+    /// the first free run large enough is used, growing the file only when no
+    /// such run exists, and the old sectors are only freed once the header
+    /// points at the new ones.
+    ///
+    /// Payloads over [`MAX_CHUNK_SECTORS`] go to a `c.X.Z.mcc` file and the
+    /// region only keeps a one-sector stub with the external flag set.
+    pub fn write_chunk(
+        &mut self,
+        chunk_x: i32,
+        chunk_z: i32,
+        nbt: &[u8],
+        compression: Compression,
+    ) -> io::Result<()> {
+        let payload = compression.compress(nbt)?;
+        let mut data = Vec::with_capacity(payload.len() + 5);
+        data.extend_from_slice(&(payload.len() as u32 + 1).to_be_bytes());
+        data.push(compression as u8);
+        data.extend_from_slice(&payload);
+
+        let mut needed = data.len().div_ceil(SECTOR_SIZE) as u32;
+        let external_path = self.external_path(chunk_x, chunk_z);
+        let external = needed > MAX_CHUNK_SECTORS;
+        if external {
+            std::fs::write(&external_path, &payload)?;
+            data.clear();
+            data.extend_from_slice(&1u32.to_be_bytes());
+            data.push(compression as u8 | EXTERNAL_FLAG);
+            needed = 1;
+        }
+        data.resize(needed as usize * SECTOR_SIZE, 0);
+
+        let (old_offset, old_count) = self.sectors(chunk_x, chunk_z);
+        let in_place = old_offset >= HEADER_SECTORS && needed <= old_count;
+        let offset = if in_place {
+            old_offset
+        } else {
+            self.allocate(needed)
+        };
+
+        self.file
+            .seek(SeekFrom::Start(offset as u64 * SECTOR_SIZE as u64))?;
+        self.file.write_all(&data)?;
+
+        let index = chunk_index(chunk_x, chunk_z);
+        self.locations[index] = (offset << 8) | needed;
+        self.timestamps[index] = now_seconds();
+        self.write_header_entry(index)?;
+
+        if in_place {
+            self.free_sectors(old_offset + needed, old_count - needed);
+        } else {
+            self.free_sectors(old_offset, old_count);
+        }
+        if !external {
+            remove_if_exists(&external_path)?;
+        }
+        Ok(())
+    }
+
+    /// Remove a chunk from the region and release its sectors.
+    pub fn delete_chunk(&mut self, chunk_x: i32, chunk_z: i32) -> io::Result<()> {
+        let (offset, count) = self.sectors(chunk_x, chunk_z);
+        self.free_sectors(offset, count);
+        let index = chunk_index(chunk_x, chunk_z);
+        self.locations[index] = 0;
+        self.timestamps[index] = 0;
+        remove_if_exists(&self.external_path(chunk_x, chunk_z))?;
+        self.write_header_entry(index)
+    }
+
+    /// Flush pending writes to disk.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_all()
+    }
+
+    fn external_path(&self, chunk_x: i32, chunk_z: i32) -> PathBuf {
+        let dir = self.path.parent().unwrap_or(Path::new(""));
+        external_chunk_path(dir, chunk_x, chunk_z)
+    }
+
+    fn free_sectors(&mut self, offset: u32, count: u32) {
+        if offset < HEADER_SECTORS {
+            return;
+        }
+        let end = (offset + count) as usize;
+        self.used_sectors[offset as usize..end].fill(false);
+    }
+
+    /// First-fit allocation over the sector bitmap; appends past the end of
+    /// the file when no free run is long enough.
+    fn allocate(&mut self, count: u32) -> u32 {
+        let count = count as usize;
+        let mut run = 0;
+        let mut start = None;
+        for (i, used) in self.used_sectors.iter().enumerate() {
+            if *used {
+                run = 0;
+                continue;
+            }
+            run += 1;
+            if run == count {
+                start = Some(i + 1 - count);
+                break;
+            }
+        }
+        let start = start.unwrap_or_else(|| {
+            // Extend a free run at the end of the file if there is one.
+            let tail = self.used_sectors.iter().rev().take_while(|u| !**u).count();
+            let start = self.used_sectors.len() - tail;
+            self.used_sectors.resize(start + count, false);
+            start
+        });
+        self.used_sectors[start..start + count].fill(true);
+        start as u32
+    }
+
+    fn write_header_entry(&mut self, index: usize) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(index as u64 * 4))?;
+        self.file.write_all(&self.locations[index].to_be_bytes())?;
+        self.file
+            .seek(SeekFrom::Start((SECTOR_SIZE + index * 4) as u64))?;
+        self.file.write_all(&self.timestamps[index].to_be_bytes())
+    }
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        other => other,
+    }
+}
+
+fn now_seconds() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_region(path: &Path, chunks: &[(i32, i32, u8, Vec<u8>)]) {
+        let mut header = vec![0u8; SECTOR_SIZE * 2];
+        let mut body = Vec::new();
+        let mut sector = HEADER_SECTORS;
+        for (x, z, compression, payload) in chunks {
+            let mut data = Vec::new();
+            data.extend_from_slice(&(payload.len() as u32 + 1).to_be_bytes());
+            data.push(*compression);
+            data.extend_from_slice(payload);
+            let count = data.len().div_ceil(SECTOR_SIZE) as u32;
+            data.resize(count as usize * SECTOR_SIZE, 0);
+
+            let i = chunk_index(*x, *z);
+            header[i * 4..i * 4 + 4].copy_from_slice(&((sector << 8) | count).to_be_bytes());
+            header[SECTOR_SIZE + i * 4..SECTOR_SIZE + i * 4 + 4]
+                .copy_from_slice(&1_700_000_000u32.to_be_bytes());
+            body.extend_from_slice(&data);
+            sector += count;
+        }
+        let mut f = File::create(path).unwrap();
+        f.write_all(&header).unwrap();
+        f.write_all(&body).unwrap();
+    }
+
+    #[test]
+    fn reads_all_compression_types() {
+        let raw = b"chunk nbt bytes".repeat(500);
+
+        let gz = Compression::Gzip.compress(&raw).unwrap();
+        let zl = Compression::Zlib.compress(&raw).unwrap();
+
+        std::fs::create_dir_all("target").ok();
+        let path = Path::new("target/r.test_read.mca");
+        write_region(
+            path,
+            &[(0, 0, 1, gz), (5, 7, 2, zl), (31, 31, 3, raw.clone())],
+        );
+
+        let mut region = RegionFile::open(path).unwrap();
+        assert_eq!(region.read_chunk(0, 0).unwrap().unwrap(), raw);
+        assert_eq!(region.read_chunk(5, 7).unwrap().unwrap(), raw);
+        // Absolute coordinates wrap into the region.
+        assert_eq!(region.read_chunk(-1, -1).unwrap().unwrap(), raw);
+        assert_eq!(region.timestamp(5, 7), 1_700_000_000);
+        assert!(region.read_chunk(1, 0).unwrap().is_none());
+        assert!(!region.has_chunk(1, 0));
+    }
+
+    #[test]
+    fn rejects_unknown_compression() {
+        std::fs::create_dir_all("target").ok();
+        let path = Path::new("target/r.test_bad.mca");
+        write_region(path, &[(0, 0, 9, vec![1, 2, 3])]);
+        let mut region = RegionFile::open(path).unwrap();
+        assert!(region.read_chunk(0, 0).is_err());
+    }
+
+    #[test]
+    fn region_naming() {
+        assert_eq!(region_coords(-1, 33), (-1, 1));
+        assert_eq!(
+            region_path(Path::new("region"), -1, 1),
+            Path::new("region/r.-1.1.mca")
+        );
+    }
+
+    /// Deterministic, poorly compressible bytes so sector counts are predictable.
+    fn noise_bytes(len: usize, seed: u32) -> Vec<u8> {
+        let mut x = seed.wrapping_mul(2654435761) | 1;
+        (0..len)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 17;
+                x ^= x << 5;
+                x as u8
+            })
+            .collect()
+    }
+
+    fn fresh(path: &str) -> RegionFile {
+        std::fs::create_dir_all("target").ok();
+        std::fs::remove_file(path).ok();
+        RegionFile::open(path).unwrap()
+    }
+
+    #[test]
+    fn write_then_read_back() {
+        let path = "target/r.test_write.mca";
+        let a = noise_bytes(10_000, 1);
+        let b = noise_bytes(100, 2);
+        {
+            let mut region = fresh(path);
+            region.write_chunk(3, 4, &a, Compression::Zlib).unwrap();
+            region.write_chunk(-1, 0, &b, Compression::Gzip).unwrap();
+            assert!(region.timestamp(3, 4) > 0);
+        }
+        let mut region = RegionFile::open(path).unwrap();
+        assert_eq!(region.read_chunk(3, 4).unwrap().unwrap(), a);
+        assert_eq!(region.read_chunk(31, 0).unwrap().unwrap(), b);
+        assert_eq!(
+            std::fs::metadata(path).unwrap().len() % SECTOR_SIZE as u64,
+            0
+        );
+    }
+
+    #[test]
+    fn reuses_freed_sectors() {
+        let path = "target/r.test_reuse.mca";
+        let mut region = fresh(path);
+        let big = noise_bytes(3 * SECTOR_SIZE, 3);
+        let small = noise_bytes(SECTOR_SIZE / 2, 4);
+
+        region.write_chunk(0, 0, &big, Compression::None).unwrap();
+        region.write_chunk(1, 0, &small, Compression::None).unwrap();
+        let (first, count) = region.sectors(0, 0);
+        assert_eq!((first, count), (2, 4));
+        let len = std::fs::metadata(path).unwrap().len();
+
+        // Shrinking stays in place and frees the tail.
+        region.write_chunk(0, 0, &small, Compression::None).unwrap();
+        assert_eq!(region.sectors(0, 0), (2, 1));
+
+        // A new chunk fits into the freed gap instead of growing the file.
+        region
+            .write_chunk(2, 0, &noise_bytes(2 * SECTOR_SIZE, 5), Compression::None)
+            .unwrap();
+        assert_eq!(region.sectors(2, 0), (3, 3));
+        assert_eq!(std::fs::metadata(path).unwrap().len(), len);
+
+        region.delete_chunk(1, 0).unwrap();
+        assert!(region.read_chunk(1, 0).unwrap().is_none());
+        assert_eq!(region.read_chunk(0, 0).unwrap().unwrap(), small);
+    }
+
+    #[test]
+    fn growing_chunks_move_before_freeing() {
+        let path = "target/r.test_grow.mca";
+        let mut region = fresh(path);
+        let small = noise_bytes(SECTOR_SIZE / 2, 7);
+        let big = noise_bytes(2 * SECTOR_SIZE, 8);
+        region.write_chunk(0, 0, &small, Compression::None).unwrap();
+        region.write_chunk(1, 0, &small, Compression::None).unwrap();
+
+        // Too big for its old sector, so it moves past the neighbour rather
+        // than taking its own old sector back.
+        region.write_chunk(0, 0, &big, Compression::None).unwrap();
+        assert_eq!(region.sectors(0, 0), (4, 3));
+        region.write_chunk(2, 0, &small, Compression::None).unwrap();
+        assert_eq!(region.sectors(2, 0), (2, 1));
+        assert_eq!(region.read_chunk(0, 0).unwrap().unwrap(), big);
+        assert_eq!(region.read_chunk(1, 0).unwrap().unwrap(), small);
+    }
+
+    #[test]
+    fn pads_truncated_files() {
+        let path = "target/r.test_pad.mca";
+        let mut region = fresh(path);
+        region
+            .write_chunk(0, 0, &noise_bytes(100, 6), Compression::None)
+            .unwrap();
+        drop(region);
+
+        let f = OpenOptions::new().write(true).open(path).unwrap();
+        f.set_len(2 * SECTOR_SIZE as u64 + 500).unwrap();
+        drop(f);
+
+        let _ = RegionFile::open(path).unwrap();
+        assert_eq!(
+            std::fs::metadata(path).unwrap().len(),
+            3 * SECTOR_SIZE as u64
+        );
+    }
+
+    #[test]
+    fn oversized_chunks_go_external() {
+        std::fs::create_dir_all("target/external").ok();
+        let path = "target/external/r.0.0.mca";
+        std::fs::remove_file(path).ok();
+        let mcc = Path::new("target/external/c.2.3.mcc");
+
+        let mut region = RegionFile::open(path).unwrap();
+        let huge = noise_bytes(256 * SECTOR_SIZE, 7);
+        region.write_chunk(2, 3, &huge, Compression::Zlib).unwrap();
+        assert!(mcc.exists());
+        assert_eq!(region.sectors(2, 3).1, 1);
+        drop(region);
+
+        let mut region = RegionFile::open(path).unwrap();
+        assert_eq!(region.read_chunk(2, 3).unwrap().unwrap(), huge);
+
+        // Shrinking back under the limit moves the chunk into the region.
+        let small = noise_bytes(100, 8);
+        region.write_chunk(2, 3, &small, Compression::Zlib).unwrap();
+        assert!(!mcc.exists());
+        assert_eq!(region.read_chunk(2, 3).unwrap().unwrap(), small);
+    }
+
+    #[test]
+    fn reads_vanilla_external_stub() {
+        std::fs::create_dir_all("target/external_stub").ok();
+        let path = Path::new("target/external_stub/r.-1.0.mca");
+        let raw = b"external".repeat(100);
+        std::fs::write(
+            "target/external_stub/c.-3.4.mcc",
+            Compression::Gzip.compress(&raw).unwrap(),
+        )
+        .unwrap();
+        write_region(path, &[(-3, 4, 1 | EXTERNAL_FLAG, Vec::new())]);
+
+        let mut region = RegionFile::open(path).unwrap();
+        assert_eq!(region.read_chunk(-3, 4).unwrap().unwrap(), raw);
+    }
+}
@@ -0,0 +1,311 @@
+use crate::chunk::BlockState;
+use crate::level::VersionInfo;
+use crate::nbt_util::invalid_data;
+use crate::palette::ceil_log2;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+/// One block from the `blocks.json` report.
+#[derive(Clone, Debug)]
+pub struct BlockInfo {
+    pub name: String,
+    /// Property names with their allowed values, sorted by name.
+    pub properties: Vec<(String, Vec<String>)>,
+    /// Global ids of all states of this block; vanilla keeps them contiguous.
+    pub states: Range<u32>,
+    pub default_state: u32,
+}
+
+/// Global block state ids, loaded from the `blocks.json` report of the data
+/// generator (`java -DbundlerMainClass=net.minecraft.data.Main -jar
+/// server.jar --reports`). The ids are only valid for the version the report
+/// was generated from.
+#[derive(Clone, Debug, Default)]
+pub struct BlockRegistry {
+    blocks: Vec<BlockInfo>,
+    by_name: HashMap<String, usize>,
+    /// Indexed by global state id.
+    states: Vec<BlockState>,
+    ids: HashMap<BlockState, u32>,
+}
+
+fn json_error(e: serde_json::Error) -> io::Error {
+    invalid_data(format!("invalid blocks.json: {e}"))
+}
+
+impl BlockRegistry {
+    /// Load a `blocks.json` report.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// Load `<dir>/<version name>/blocks.json`, so each protocol version
+    /// gets the ids of its own report.
+    pub fn load_for_version(dir: impl AsRef<Path>, version: &VersionInfo) -> io::Result<Self> {
+        Self::load(dir.as_ref().join(&version.name).join("blocks.json"))
+    }
+
+    /// Parse the report contents.
+    pub fn from_json(json: &str) -> io::Result<Self> {
+        let root: Value = serde_json::from_str(json).map_err(json_error)?;
+        let root = root
+            .as_object()
+            .ok_or_else(|| invalid_data("blocks.json root is not an object"))?;
+
+        let mut registry = Self::default();
+        let mut states: Vec<Option<BlockState>> = Vec::new();
+        for (name, block) in root {
+            let properties = match block.get("properties").and_then(Value::as_object) {
+                Some(props) => props
+                    .iter()
+                    .map(|(key, values)| {
+                        let values = values
+                            .as_array()
+                            .into_iter()
+                            .flatten()
+                            .map(|v| v.as_str().map(str::to_string))
+                            .collect::<Option<Vec<_>>>()
+                            .ok_or_else(|| {
+                                invalid_data(format!("bad values of `{name}` property `{key}`"))
+                            })?;
+                        Ok((key.clone(), values))
+                    })
+                    .collect::<io::Result<Vec<_>>>()?,
+                None => Vec::new(),
+            };
+
+            let block_states = block
+                .get("states")
+                .and_then(Value::as_array)
+                .filter(|s| !s.is_empty())
+                .ok_or_else(|| invalid_data(format!("block `{name}` has no states")))?;
+            let mut default_state = None;
+            let (mut min, mut max) = (u32::MAX, 0);
+            for state in block_states {
+                let id = state
+                    .get("id")
+                    .and_then(Value::as_u64)
+                    .and_then(|id| u32::try_from(id).ok())
+                    .ok_or_else(|| invalid_data(format!("state of `{name}` has no id")))?;
+                let mut block_state = BlockState::new(name.clone());
+                for (k, v) in state
+                    .get("properties")
+                    .and_then(Value::as_object)
+                    .into_iter()
+                    .flatten()
+                {
+                    let v = v.as_str().ok_or_else(|| {
+                        invalid_data(format!("property `{k}` of `{name}` is not a string"))
+                    })?;
+                    block_state.set_property(k.clone(), v);
+                }
+                if state.get("default").and_then(Value::as_bool) == Some(true) {
+                    default_state = Some(id);
+                }
+                min = min.min(id);
+                max = max.max(id);
+
+                let slot = id as usize;
+                if states.len() <= slot {
+                    states.resize(slot + 1, None);
+                }
+                if states[slot].is_some() {
+                    return Err(invalid_data(format!("duplicate block state id {id}")));
+                }
+                registry.ids.insert(block_state.clone(), id);
+                states[slot] = Some(block_state);
+            }
+            if (max - min + 1) as usize != block_states.len() {
+                return Err(invalid_data(format!(
+                    "states of `{name}` are not contiguous"
+                )));
+            }
+
+            registry.blocks.push(BlockInfo {
+                name: name.clone(),
+                properties,
+                states: min..max + 1,
+                default_state: default_state.unwrap_or(min),
+            });
+        }
+
+        registry.states = states
+            .into_iter()
+            .enumerate()
+            .map(|(id, s)| s.ok_or_else(|| invalid_data(format!("missing block state id {id}"))))
+            .collect::<io::Result<_>>()?;
+        registry.blocks.sort_by_key(|b| b.states.start);
+        for (i, block) in registry.blocks.iter().enumerate() {
+            registry.by_name.insert(block.name.clone(), i);
+        }
+        Ok(registry)
+    }
+
+    /// Number of block states, i.e. one past the highest global id.
+    pub fn state_count(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Bits per entry of a direct (global) block palette.
+    pub fn global_bits(&self) -> u8 {
+        ceil_log2(self.state_count())
+    }
+
+    /// All blocks, ordered by their first state id.
+    pub fn blocks(&self) -> &[BlockInfo] {
+        &self.blocks
+    }
+
+    pub fn block(&self, name: &str) -> Option<&BlockInfo> {
+        self.by_name.get(name).map(|&i| &self.blocks[i])
+    }
+
+    /// The block state with a global id.
+    pub fn state(&self, id: u32) -> Option<&BlockState> {
+        self.states.get(id as usize)
+    }
+
+    pub fn default_state(&self, name: &str) -> Option<&BlockState> {
+        self.state(self.block(name)?.default_state)
+    }
+
+    /// Global id of a state. Properties the state leaves out take the block's
+    /// default values; unknown properties or values give `None`.
+    pub fn state_id(&self, state: &BlockState) -> Option<u32> {
+        if let Some(&id) = self.ids.get(state) {
+            return Some(id);
+        }
+        let mut full = self.default_state(&state.name)?.clone();
+        for (k, v) in &state.properties {
+            full.property(k)?;
+            full.set_property(k.clone(), v.clone());
+        }
+        self.ids.get(&full).copied()
+    }
+
+    /// Parse `minecraft:oak_stairs[facing=north,half=bottom]` into a full
+    /// state, filling omitted properties with the block's defaults.
+    pub fn parse_state(&self, s: &str) -> io::Result<BlockState> {
+        let state: BlockState = s.parse()?;
+        let id = self
+            .state_id(&state)
+            .ok_or_else(|| invalid_data(format!("unknown block state `{s}`")))?;
+        Ok(self.states[id as usize].clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Excerpt in the shape of the 1.21.8 report.
+    const BLOCKS: &str = r#"{
+        "minecraft:air": {
+            "definition": { "type": "minecraft:air", "properties": {} },
+            "states": [ { "default": true, "id": 0 } ]
+        },
+        "minecraft:stone": {
+            "definition": { "type": "minecraft:block", "properties": {} },
+            "states": [ { "default": true, "id": 1 } ]
+        },
+        "minecraft:grass_block": {
+            "properties": { "snowy": ["true", "false"] },
+            "states": [
+                { "id": 8, "properties": { "snowy": "true" } },
+                { "default": true, "id": 9, "properties": { "snowy": "false" } }
+            ]
+        },
+        "minecraft:granite": {
+            "states": [ { "default": true, "id": 2 } ]
+        },
+        "minecraft:polished_granite": {
+            "states": [ { "default": true, "id": 3 } ]
+        },
+        "minecraft:diorite": {
+            "states": [ { "default": true, "id": 4 } ]
+        },
+        "minecraft:polished_diorite": {
+            "states": [ { "default": true, "id": 5 } ]
+        },
+        "minecraft:andesite": {
+            "states": [ { "default": true, "id": 6 } ]
+        },
+        "minecraft:polished_andesite": {
+            "states": [ { "default": true, "id": 7 } ]
+        },
+        "minecraft:dirt": {
+            "states": [ { "default": true, "id": 10 } ]
+        },
+        "minecraft:oak_log": {
+            "properties": { "axis": ["x", "y", "z"] },
+            "states": [
+                { "id": 11, "properties": { "axis": "x" } },
+                { "default": true, "id": 12, "properties": { "axis": "y" } },
+                { "id": 13, "properties": { "axis": "z" } }
+            ]
+        }
+    }"#;
+
+    #[test]
+    fn maps_states_to_ids_and_back() {
+        let registry = BlockRegistry::from_json(BLOCKS).unwrap();
+        assert_eq!(registry.state_count(), 14);
+        assert_eq!(registry.global_bits(), 4);
+
+        let snowy = BlockState::new("minecraft:grass_block").with("snowy", "true");
+        assert_eq!(registry.state_id(&snowy), Some(8));
+        assert_eq!(registry.state(8), Some(&snowy));
+        assert_eq!(
+            registry.state_id(&BlockState::new("minecraft:grass_block")),
+            Some(9)
+        );
+        assert_eq!(registry.state_id(&BlockState::air()), Some(0));
+        assert_eq!(
+            registry.state_id(&BlockState::new("minecraft:oak_log").with("axis", "w")),
+            None
+        );
+        assert_eq!(
+            registry.state_id(&BlockState::new("minecraft:dirt").with("snowy", "true")),
+            None
+        );
+        assert_eq!(registry.state_id(&BlockState::new("minecraft:nope")), None);
+    }
+
+    #[test]
+    fn exposes_blocks_and_parses_states() {
+        let registry = BlockRegistry::from_json(BLOCKS).unwrap();
+        let log = registry.block("minecraft:oak_log").unwrap();
+        assert_eq!(log.states, 11..14);
+        assert_eq!(
+            log.properties,
+            [("axis".to_string(), vec!["x".into(), "y".into(), "z".into()])]
+        );
+        assert_eq!(
+            registry
+                .default_state("minecraft:oak_log")
+                .unwrap()
+                .to_string(),
+            "minecraft:oak_log[axis=y]"
+        );
+        assert_eq!(registry.blocks()[2].name, "minecraft:granite");
+
+        let state = registry.parse_state("oak_log[axis=z]").unwrap();
+        assert_eq!(registry.state_id(&state), Some(13));
+        assert_eq!(
+            registry.parse_state("minecraft:grass_block").unwrap(),
+            BlockState::new("minecraft:grass_block").with("snowy", "false")
+        );
+        assert!(registry.parse_state("minecraft:oak_log[axis=q]").is_err());
+        assert!(registry.parse_state("minecraft:oak_log[axis=x").is_err());
+    }
+
+    #[test]
+    fn rejects_gaps_in_ids() {
+        let json = r#"{ "minecraft:air": { "states": [ { "default": true, "id": 1 } ] } }"#;
+        let err = BlockRegistry::from_json(json).unwrap_err();
+        assert_eq!(err.to_string(), "missing block state id 0");
+    }
+}
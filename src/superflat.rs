@@ -1,3 +1,9 @@
+use crate::chunk::{BlockState, Chunk, DEFAULT_BIOME};
+use crate::nbt_util::{self, get, invalid_data, opt, opt_compound};
+use crate::packet::{RegistryIds, chunk_data_packet};
+use nbt::Tag;
+use std::io;
+
 pub const CHUNK_SIZE: usize = 16;
 enum BlockId {
     Air = 0,
@@ -5,27 +11,270 @@ enum BlockId {
     Dirt = 2,
     Grass = 3,
 }
+
+impl BlockId {
+    /// The one-byte id of a block in the legacy packet, air for blocks it
+    /// has no id for.
+    fn from_name(name: &str) -> Self {
+        match name {
+            "minecraft:bedrock" => Self::Bedrock,
+            "minecraft:dirt" => Self::Dirt,
+            "minecraft:grass_block" => Self::Grass,
+            _ => Self::Air,
+        }
+    }
+}
+
+/// Vanilla's `DimensionType.Y_SIZE`, the thickest a layer may be.
+pub const MAX_LAYER_HEIGHT: u32 = 4064;
+
+/// One layer of a flat world: `height` blocks of `block`, stacked from the
+/// bottom of the world upwards.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlatLayer {
+    pub block: String,
+    pub height: u32,
+}
+
+impl FlatLayer {
+    pub fn new(block: impl Into<String>, height: u32) -> Self {
+        Self {
+            block: block.into(),
+            height,
+        }
+    }
+}
+
+/// Settings of the `minecraft:flat` generator, as stored in `level.dat`
+/// under the overworld's `generator.settings`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlatGeneratorSettings {
+    pub layers: Vec<FlatLayer>,
+    pub biome: String,
+    /// Run the biome's decoration features (trees, flowers, ores...).
+    pub features: bool,
+    /// Place water and lava lakes.
+    pub lakes: bool,
+}
+
+impl Default for FlatGeneratorSettings {
+    /// The "Classic Flat" preset.
+    fn default() -> Self {
+        Self {
+            layers: vec![
+                FlatLayer::new("minecraft:bedrock", 1),
+                FlatLayer::new("minecraft:dirt", 2),
+                FlatLayer::new("minecraft:grass_block", 1),
+            ],
+            biome: DEFAULT_BIOME.into(),
+            features: false,
+            lakes: false,
+        }
+    }
+}
+
+/// Add the `minecraft:` namespace to bare ids, as the game does.
+fn namespaced(id: &str) -> String {
+    if id.contains(':') {
+        id.to_string()
+    } else {
+        format!("minecraft:{id}")
+    }
+}
+
+impl FlatGeneratorSettings {
+    /// Parse a preset string such as
+    /// `minecraft:bedrock,2*minecraft:dirt,minecraft:grass_block;minecraft:plains`:
+    /// comma-separated `[count*]block` layers from the bottom up, then an
+    /// optional biome. Anything after the biome is ignored.
+    pub fn from_preset(preset: &str) -> io::Result<Self> {
+        let mut parts = preset.trim().split(';');
+        let layers = parts
+            .next()
+            .unwrap_or_default()
+            .split(',')
+            .filter(|l| !l.trim().is_empty())
+            .map(|layer| {
+                let layer = layer.trim();
+                let (height, block) = match layer.split_once('*') {
+                    Some((count, block)) => {
+                        let count = count
+                            .trim()
+                            .parse::<u32>()
+                            .map_err(|_| invalid_data(format!("bad layer count in `{layer}`")))?;
+                        (count, block.trim())
+                    }
+                    None => (1, layer),
+                };
+                if height == 0 || block.is_empty() {
+                    return Err(invalid_data(format!("bad flat layer `{layer}`")));
+                }
+                if height > MAX_LAYER_HEIGHT {
+                    return Err(invalid_data(format!(
+                        "layer `{layer}` is over {MAX_LAYER_HEIGHT} blocks"
+                    )));
+                }
+                Ok(FlatLayer::new(namespaced(block), height))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let biome = match parts.next().map(str::trim) {
+            Some(b) if !b.is_empty() => namespaced(b),
+            _ => DEFAULT_BIOME.into(),
+        };
+
+        Ok(Self {
+            layers,
+            biome,
+            ..Default::default()
+        })
+    }
+
+    /// Format the layers and biome back into a preset string.
+    pub fn to_preset(&self) -> String {
+        let layers: Vec<String> = self
+            .layers
+            .iter()
+            .map(|l| match l.height {
+                1 => l.block.clone(),
+                n => format!("{n}*{}", l.block),
+            })
+            .collect();
+        format!("{};{}", layers.join(","), self.biome)
+    }
+
+    /// Total thickness of all layers.
+    pub fn height(&self) -> u32 {
+        self.layers
+            .iter()
+            .fold(0, |height, l| height.saturating_add(l.height))
+    }
+
+    /// Decode a flat `settings` compound.
+    pub fn from_nbt(tag: &Tag) -> io::Result<Self> {
+        let d = Self::default();
+        let layers = match opt::<&[Tag]>(tag, "layers")? {
+            Some(list) => list
+                .iter()
+                .map(|l| {
+                    let height = get::<i32>(l, "height")?;
+                    let height = u32::try_from(height)
+                        .ok()
+                        .filter(|h| *h <= MAX_LAYER_HEIGHT)
+                        .ok_or_else(|| invalid_data(format!("bad layer height {height}")))?;
+                    Ok(FlatLayer::new(get::<&str>(l, "block")?, height))
+                })
+                .collect::<io::Result<_>>()?,
+            None => d.layers,
+        };
+        Ok(Self {
+            layers,
+            biome: opt::<&str>(tag, "biome")?
+                .map(str::to_string)
+                .unwrap_or(d.biome),
+            features: opt(tag, "features")?.unwrap_or(d.features),
+            lakes: opt(tag, "lakes")?.unwrap_or(d.lakes),
+        })
+    }
+
+    /// Encode as a flat `settings` compound.
+    pub fn to_nbt(&self) -> Tag {
+        let layers = self
+            .layers
+            .iter()
+            .map(|l| {
+                let mut layer = Tag::new_compound("");
+                layer.insert("block".into(), Tag::new_string("block", l.block.clone()));
+                layer.insert(
+                    "height".into(),
+                    Tag::new_int("height", i32::try_from(l.height).unwrap_or(i32::MAX)),
+                );
+                layer
+            })
+            .collect();
+        let mut tag = Tag::new_compound("settings");
+        tag.insert("layers".into(), Tag::new_list("layers", 10, layers));
+        tag.insert("biome".into(), Tag::new_string("biome", self.biome.clone()));
+        tag.insert(
+            "features".into(),
+            Tag::new_byte("features", i8::from(self.features)),
+        );
+        tag.insert("lakes".into(), Tag::new_byte("lakes", i8::from(self.lakes)));
+        tag
+    }
+
+    /// Settings of the overworld generator in a `level.dat` tree, or `None`
+    /// when the overworld is not a flat world.
+    pub fn from_level_nbt(tag: &Tag) -> io::Result<Option<Self>> {
+        let data = opt_compound(tag, "Data")?.unwrap_or(tag);
+        let Some(dims) = opt_compound(data, "WorldGenSettings")?
+            .map(|wgs| opt_compound(wgs, "dimensions"))
+            .transpose()?
+            .flatten()
+        else {
+            return Ok(None);
+        };
+        let Some(generator) = opt_compound(dims, "minecraft:overworld")?
+            .map(|dim| opt_compound(dim, "generator"))
+            .transpose()?
+            .flatten()
+        else {
+            return Ok(None);
+        };
+        if opt::<&str>(generator, "type")? != Some("minecraft:flat") {
+            return Ok(None);
+        }
+        match opt_compound(generator, "settings")? {
+            Some(settings) => Self::from_nbt(settings).map(Some),
+            None => Ok(Some(Self::default())),
+        }
+    }
+
+    /// Read the flat generator settings from a gzip-compressed `level.dat`.
+    pub fn read_level_dat(path: &str) -> io::Result<Option<Self>> {
+        Self::from_level_nbt(&nbt_util::read_gzip_file(path)?)
+    }
+
+    /// Build the chunk at `chunk_x`/`chunk_z`: layers stacked from the bottom
+    /// of the world, cut off at the top, and the whole chunk in one biome.
+    pub fn generate_chunk(&self, chunk_x: i32, chunk_z: i32) -> Chunk {
+        let mut chunk = Chunk::new(chunk_x, chunk_z);
+        for section in chunk.sections_mut() {
+            section.fill_biome(self.biome.clone());
+        }
+
+        let mut y = chunk.min_y();
+        for layer in &self.layers {
+            let state = BlockState::new(layer.block.clone());
+            let height = i32::try_from(layer.height).unwrap_or(i32::MAX);
+            let top = y.saturating_add(height).min(chunk.max_y());
+            for y in y..top {
+                for z in 0..CHUNK_SIZE {
+                    for x in 0..CHUNK_SIZE {
+                        chunk.set_block(x, y, z, state.clone());
+                    }
+                }
+            }
+            y = top;
+        }
+        chunk.compute_heightmaps();
+        chunk
+    }
+}
+
+/// The legacy one-byte-per-block packet of a "Classic Flat" chunk, `height`
+/// blocks tall from the bottom of the world.
 pub fn generate_superflat_chunk_packet(chunk_x: i32, chunk_z: i32, height: u16) -> Vec<u8> {
     let bytes_per_block: u8 = 1;
     let voxel_count = (CHUNK_SIZE * CHUNK_SIZE) as u32 * height as u32;
 
+    let chunk = FlatGeneratorSettings::default().generate_chunk(chunk_x, chunk_z);
     let mut payload = vec![BlockId::Air as u8; voxel_count as usize];
-
-    for y in 0..height as usize {
-        let id = if y == 0 {
-            BlockId::Bedrock
-        } else if (1..=2).contains(&y) {
-            BlockId::Dirt
-        } else if y == 3 {
-            BlockId::Grass
-        } else {
-            BlockId::Air
-        } as u8;
-
+    for y in 0..(height as usize).min((chunk.max_y() - chunk.min_y()) as usize) {
         for z in 0..CHUNK_SIZE {
             for x in 0..CHUNK_SIZE {
-                let idx = linear_index(x, y, z);
-                payload[idx] = id;
+                let block = chunk.get_block(x, chunk.min_y() + y as i32, z);
+                payload[linear_index(x, y, z)] = BlockId::from_name(&block.name) as u8;
             }
         }
     }
@@ -60,6 +309,25 @@ pub fn generate_view_radius_packets(
     }
     out
 }
+
+/// Chunk data packets for every chunk within `radius` of the center chunk.
+pub fn generate_view_radius_chunk_packets(
+    settings: &FlatGeneratorSettings,
+    ids: &impl RegistryIds,
+    center_cx: i32,
+    center_cz: i32,
+    radius: i32,
+) -> io::Result<Vec<Vec<u8>>> {
+    let mut out = Vec::new();
+    for dz in -radius..=radius {
+        for dx in -radius..=radius {
+            let chunk = settings.generate_chunk(center_cx + dx, center_cz + dz);
+            out.push(chunk_data_packet(&chunk, ids)?);
+        }
+    }
+    Ok(out)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -116,4 +384,136 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn parses_presets() {
+        let classic = FlatGeneratorSettings::from_preset(
+            "minecraft:bedrock,2*minecraft:dirt,minecraft:grass_block;minecraft:plains",
+        )
+        .unwrap();
+        assert_eq!(classic, FlatGeneratorSettings::default());
+        assert_eq!(
+            classic.to_preset(),
+            "minecraft:bedrock,2*minecraft:dirt,minecraft:grass_block;minecraft:plains"
+        );
+
+        let water = FlatGeneratorSettings::from_preset(
+            "bedrock, 5*stone ,5*dirt,90*water;minecraft:deep_ocean",
+        )
+        .unwrap();
+        assert_eq!(water.layers[1], FlatLayer::new("minecraft:stone", 5));
+        assert_eq!(water.height(), 101);
+        assert_eq!(water.biome, "minecraft:deep_ocean");
+
+        let no_biome = FlatGeneratorSettings::from_preset("minecraft:sandstone").unwrap();
+        assert_eq!(no_biome.biome, DEFAULT_BIOME);
+
+        for bad in [
+            "x*minecraft:stone",
+            "0*minecraft:stone",
+            "3*;minecraft:plains",
+        ] {
+            assert!(FlatGeneratorSettings::from_preset(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn preset_layers_ok() {
+        let settings =
+            FlatGeneratorSettings::from_preset("bedrock,3*stone,2*dirt,grass_block;desert")
+                .unwrap();
+        let chunk = settings.generate_chunk(3, -7);
+        let column: Vec<String> = (-64..-56).map(|y| chunk.get_block(5, y, 9).name).collect();
+        assert_eq!(
+            column,
+            [
+                "minecraft:bedrock",
+                "minecraft:stone",
+                "minecraft:stone",
+                "minecraft:stone",
+                "minecraft:dirt",
+                "minecraft:dirt",
+                "minecraft:grass_block",
+                "minecraft:air",
+            ]
+        );
+        assert_eq!(chunk.get_biome(0, 200, 15), "minecraft:desert");
+        assert_eq!(chunk.sections()[0].non_air_blocks(), 7 * 256);
+        assert!(chunk.sections()[1].is_empty());
+    }
+
+    #[test]
+    fn layer_heights_are_limited() {
+        let tallest = FlatGeneratorSettings::from_preset("4064*minecraft:stone").unwrap();
+        assert_eq!(tallest.height(), MAX_LAYER_HEIGHT);
+        for bad in ["4065*minecraft:stone", "4294967295*minecraft:stone"] {
+            assert!(FlatGeneratorSettings::from_preset(bad).is_err(), "{bad}");
+        }
+
+        let mut layer = Tag::new_compound("");
+        layer.insert("block".into(), Tag::new_string("block", "minecraft:stone"));
+        layer.insert("height".into(), Tag::new_int("height", 5000));
+        let mut tag = Tag::new_compound("settings");
+        tag.insert("layers".into(), Tag::new_list("layers", 10, vec![layer]));
+        assert!(FlatGeneratorSettings::from_nbt(&tag).is_err());
+
+        // Built by hand past the limit, layers still stack in order.
+        let settings = FlatGeneratorSettings {
+            layers: vec![
+                FlatLayer::new("minecraft:stone", u32::MAX),
+                FlatLayer::new("minecraft:dirt", u32::MAX),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(settings.height(), u32::MAX);
+        let chunk = settings.generate_chunk(0, 0);
+        assert_eq!(
+            chunk.get_block(0, chunk.max_y() - 1, 0).name,
+            "minecraft:stone"
+        );
+        let read = FlatGeneratorSettings::from_nbt(&settings.to_nbt());
+        assert!(read.is_err());
+    }
+
+    #[test]
+    fn layers_are_cut_at_world_top() {
+        let settings = FlatGeneratorSettings {
+            layers: vec![FlatLayer::new("minecraft:stone", 1000)],
+            ..Default::default()
+        };
+        let chunk = settings.generate_chunk(0, 0);
+        assert!(chunk.sections().iter().all(|s| s.non_air_blocks() == 4096));
+    }
+
+    #[test]
+    fn reads_flat_generator_from_level_dat() {
+        let settings = FlatGeneratorSettings {
+            layers: vec![
+                FlatLayer::new("minecraft:bedrock", 1),
+                FlatLayer::new("minecraft:sand", 12),
+            ],
+            biome: "minecraft:desert".into(),
+            features: true,
+            lakes: false,
+        };
+        let mut generator = Tag::new_compound("generator");
+        generator.insert("type".into(), Tag::new_string("type", "minecraft:flat"));
+        generator.insert("settings".into(), settings.to_nbt());
+        let mut overworld = Tag::new_compound("minecraft:overworld");
+        overworld.insert("generator".into(), generator);
+        let mut dims = Tag::new_compound("dimensions");
+        dims.insert("minecraft:overworld".into(), overworld);
+        let mut wgs = Tag::new_compound("WorldGenSettings");
+        wgs.insert("dimensions".into(), dims);
+        let mut data = Tag::new_compound("Data");
+        data.insert("WorldGenSettings".into(), wgs);
+        let mut root = Tag::new_compound("");
+        root.insert("Data".into(), data);
+
+        let read = FlatGeneratorSettings::from_level_nbt(&root).unwrap();
+        assert_eq!(read, Some(settings));
+
+        let noise = Tag::new_compound("");
+        assert_eq!(FlatGeneratorSettings::from_level_nbt(&noise).unwrap(), None);
+    }
 }
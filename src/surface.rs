@@ -0,0 +1,938 @@
+//! Surface rules: the stage after noise that turns the top of the default
+//! block into grass, sand, snow, terracotta bands, deepslate and the like,
+//! per biome. A port of vanilla's `SurfaceRules` and `SurfaceSystem`.
+
+use crate::biome_source::{BiomeManager, BiomeSource};
+use crate::chunk::{BlockState, Chunk, SECTION_SIZE};
+use crate::density::NoiseHolder;
+use crate::noise::{NoiseParameters, NormalNoise, floor, lerp2};
+use crate::noise_router::RandomState;
+use crate::random::PositionalRandomFactory;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Vanilla's `DimensionType.WAY_BELOW_MIN_Y`, below every world: the floor
+/// of a solid run that reaches the bottom of the world, and the level of
+/// aquifers with no fluid at all.
+pub(crate) const WAY_BELOW_MIN_Y: i32 = -2032 << 4;
+
+/// A height relative to the bottom or top of the world, or absolute.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerticalAnchor {
+    Absolute(i32),
+    AboveBottom(i32),
+    BelowTop(i32),
+}
+
+impl VerticalAnchor {
+    pub fn resolve_y(self, min_y: i32, height: i32) -> i32 {
+        match self {
+            Self::Absolute(y) => y,
+            Self::AboveBottom(n) => min_y + n,
+            Self::BelowTop(n) => min_y + height - 1 - n,
+        }
+    }
+}
+
+/// The side of a solid run that `stone_depth` counts from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaveSurface {
+    Floor,
+    Ceiling,
+}
+
+#[derive(Clone, Debug)]
+pub enum SurfaceCondition {
+    /// The block's biome is one of these.
+    Biome(Vec<String>),
+    /// The 2D noise value of the column lies in `min_threshold..=max_threshold`.
+    NoiseThreshold {
+        noise: NoiseHolder,
+        min_threshold: f64,
+        max_threshold: f64,
+    },
+    /// True at and below one height, false at and above another, and in
+    /// between randomly with a chance falling linearly from 1 to 0.
+    VerticalGradient {
+        random_name: String,
+        true_at_and_below: VerticalAnchor,
+        false_at_and_above: VerticalAnchor,
+    },
+    YAbove {
+        anchor: VerticalAnchor,
+        surface_depth_multiplier: i32,
+        add_stone_depth: bool,
+    },
+    /// No fluid above the solid run, or the block is above the fluid
+    /// surface plus `offset`.
+    Water {
+        offset: i32,
+        surface_depth_multiplier: i32,
+        add_stone_depth: bool,
+    },
+    /// The block is at most `1 + offset` blocks into its solid run, counted
+    /// from the floor or the ceiling.
+    StoneDepth {
+        offset: i32,
+        add_surface_depth: bool,
+        secondary_depth_range: i32,
+        surface_type: CaveSurface,
+    },
+    /// Cold enough to snow. Biome temperatures are not modelled, so this
+    /// never holds.
+    Temperature,
+    /// The terrain climbs at least four blocks between the neighbouring
+    /// columns, north to south or east to west.
+    Steep,
+    /// The surface depth of the column is not positive.
+    Hole,
+    AbovePreliminarySurface,
+    Not(Box<SurfaceCondition>),
+}
+
+#[derive(Clone, Debug)]
+pub enum SurfaceRule {
+    Block(BlockState),
+    /// The result of the first rule that yields a block.
+    Sequence(Vec<SurfaceRule>),
+    Condition {
+        if_true: SurfaceCondition,
+        then_run: Box<SurfaceRule>,
+    },
+    /// The terracotta bands of badlands.
+    Bandlands,
+}
+
+/// The seeded surface stage of one world.
+pub struct SurfaceSystem {
+    rule: SurfaceRule,
+    default_block: BlockState,
+    sea_level: i32,
+    biome_manager: BiomeManager,
+    clay_bands: Vec<BlockState>,
+    clay_bands_offset: Arc<NormalNoise>,
+    surface: Arc<NormalNoise>,
+    surface_secondary: Arc<NormalNoise>,
+    badlands_pillar: Arc<NormalNoise>,
+    badlands_pillar_roof: Arc<NormalNoise>,
+    badlands_surface: Arc<NormalNoise>,
+    iceberg_pillar: Arc<NormalNoise>,
+    iceberg_pillar_roof: Arc<NormalNoise>,
+    iceberg_surface: Arc<NormalNoise>,
+    /// Random factories of the `vertical_gradient` conditions, by name.
+    gradients: HashMap<String, Box<dyn PositionalRandomFactory>>,
+}
+
+impl SurfaceSystem {
+    /// Seed `rule` and the surface noises from `state`.
+    pub fn new(
+        rule: &SurfaceRule,
+        default_block: BlockState,
+        sea_level: i32,
+        state: &mut RandomState,
+    ) -> Self {
+        // The vanilla `worldgen/noise` parameters of the noises sampled by
+        // the system itself rather than by a rule.
+        let mut noise = |key: &str, first_octave: i32, amplitudes: &[f64]| {
+            let parameters = NoiseParameters::new(first_octave, amplitudes.to_vec());
+            state.get_or_create_noise(key, &parameters)
+        };
+        let clay_bands_offset = noise("minecraft:clay_bands_offset", -8, &[1.0]);
+        let surface = noise("minecraft:surface", -6, &[1.0, 1.0, 1.0]);
+        let surface_secondary = noise("minecraft:surface_secondary", -6, &[1.0, 1.0, 0.0, 1.0]);
+        let badlands_pillar = noise("minecraft:badlands_pillar", -2, &[1.0, 1.0, 1.0, 1.0]);
+        let badlands_pillar_roof = noise("minecraft:badlands_pillar_roof", -8, &[1.0]);
+        let badlands_surface = noise("minecraft:badlands_surface", -6, &[1.0, 1.0, 1.0]);
+        let iceberg_pillar = noise("minecraft:iceberg_pillar", -6, &[1.0, 1.0, 1.0, 1.0]);
+        let iceberg_pillar_roof = noise("minecraft:iceberg_pillar_roof", -3, &[1.0]);
+        let iceberg_surface = noise("minecraft:iceberg_surface", -6, &[1.0, 1.0, 1.0]);
+
+        let clay_bands = generate_bands(&mut *state.random().with_hash_of("minecraft:clay_bands"));
+        let mut gradients = HashMap::new();
+        let rule = seed_rule(rule, state, &mut gradients);
+        Self {
+            rule,
+            default_block,
+            sea_level,
+            biome_manager: BiomeManager::new(state.seed()),
+            clay_bands,
+            clay_bands_offset,
+            surface,
+            surface_secondary,
+            badlands_pillar,
+            badlands_pillar_roof,
+            badlands_surface,
+            iceberg_pillar,
+            iceberg_pillar_roof,
+            iceberg_surface,
+            gradients,
+        }
+    }
+
+    /// Apply the rules to every default block of `chunk`, whose biomes and
+    /// noise must already be filled. Biomes come from the chunk, or from
+    /// `biome_source` next to it. `use_legacy_random` is the noise settings'
+    /// flag; with it the column extensions pick their biome at y = 0.
+    pub fn build_surface(
+        &self,
+        state: &RandomState,
+        biome_source: &BiomeSource,
+        chunk: &mut Chunk,
+        use_legacy_random: bool,
+    ) {
+        let size = SECTION_SIZE as i32;
+        let (min_x, min_z) = (chunk.x * size, chunk.z * size);
+        let min_y = chunk.min_y();
+        let mut ctx = Context::new(self, state, biome_source, chunk);
+        ctx.heights(chunk);
+
+        for x in 0..SECTION_SIZE {
+            for z in 0..SECTION_SIZE {
+                let column = z * SECTION_SIZE + x;
+                let (block_x, block_z) = (min_x + x as i32, min_z + z as i32);
+                let top = ctx.heights[column] + 1;
+                let biome_y = if use_legacy_random { 0 } else { top };
+                let biome = ctx.biome_at(chunk, block_x, biome_y, block_z);
+                if biome == "minecraft:eroded_badlands" {
+                    self.eroded_badlands_extension(chunk, x, z, top);
+                    ctx.heights[column] = top_block(chunk, x, z);
+                }
+
+                let start = ctx.heights[column] + 1;
+                ctx.update_xz(block_x, block_z);
+                let mut stone_depth_above = 0;
+                let mut water_height = i32::MIN;
+                let mut floor_y = i32::MAX;
+                for y in (min_y..=start).rev() {
+                    let block = chunk.get_block(x, y, z);
+                    if block.is_air() {
+                        stone_depth_above = 0;
+                        water_height = i32::MIN;
+                    } else if has_fluid(&block) {
+                        if water_height == i32::MIN {
+                            water_height = y + 1;
+                        }
+                    } else {
+                        if floor_y >= y {
+                            floor_y = WAY_BELOW_MIN_Y;
+                            for below in (min_y - 1..y).rev() {
+                                if !is_stone(&chunk.get_block(x, below, z)) {
+                                    floor_y = below + 1;
+                                    break;
+                                }
+                            }
+                        }
+                        stone_depth_above += 1;
+                        ctx.update_y(stone_depth_above, y - floor_y + 1, water_height, y);
+                        if block == self.default_block
+                            && let Some(state) = ctx.try_apply(&self.rule, chunk)
+                        {
+                            chunk.set_block(x, y, z, state);
+                        }
+                    }
+                }
+
+                if biome == "minecraft:frozen_ocean" || biome == "minecraft:deep_frozen_ocean" {
+                    let min_surface_level = ctx.min_surface_level();
+                    self.frozen_ocean_extension(state, chunk, x, z, top, min_surface_level, &biome);
+                    ctx.heights[column] = top_block(chunk, x, z);
+                }
+            }
+        }
+    }
+
+    /// The block the rules give a floor at world position `(x, y, z)` of
+    /// `chunk`, as carvers ask for the dirt they uncover. `has_fluid` puts
+    /// the fluid surface right above it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn top_material(
+        &self,
+        state: &RandomState,
+        biome_source: &BiomeSource,
+        chunk: &Chunk,
+        x: i32,
+        y: i32,
+        z: i32,
+        has_fluid: bool,
+    ) -> Option<BlockState> {
+        let mut ctx = Context::new(self, state, biome_source, chunk);
+        ctx.update_xz(x, z);
+        ctx.update_y(1, 1, if has_fluid { y + 1 } else { i32::MIN }, y);
+        ctx.try_apply(&self.rule, chunk)
+    }
+
+    fn surface_depth(&self, state: &RandomState, x: i32, z: i32) -> i32 {
+        let noise = self.surface.get_value(f64::from(x), 0.0, f64::from(z));
+        let jitter = state.random().at(x, 0, z).next_double() * 0.25;
+        (noise * 2.75 + 3.0 + jitter) as i32
+    }
+
+    fn band(&self, x: i32, y: i32, z: i32) -> &BlockState {
+        let offset = self
+            .clay_bands_offset
+            .get_value(f64::from(x), 0.0, f64::from(z));
+        // `Math.round`, which rounds halves up.
+        let offset = (offset * 4.0 + 0.5).floor() as i32;
+        let len = self.clay_bands.len() as i32;
+        &self.clay_bands[(y + offset + len).rem_euclid(len) as usize]
+    }
+
+    /// Raise the hoodoo pillars of eroded badlands out of the air above the
+    /// terrain.
+    fn eroded_badlands_extension(&self, chunk: &mut Chunk, x: usize, z: usize, top: i32) {
+        let (bx, bz) = (
+            f64::from(chunk.x * SECTION_SIZE as i32 + x as i32),
+            f64::from(chunk.z * SECTION_SIZE as i32 + z as i32),
+        );
+        let surface = (self.badlands_surface.get_value(bx, 0.0, bz) * 8.25)
+            .abs()
+            .min(self.badlands_pillar.get_value(bx * 0.2, 0.0, bz * 0.2) * 15.0);
+        if surface <= 0.0 {
+            return;
+        }
+        let roof = (self
+            .badlands_pillar_roof
+            .get_value(bx * 0.75, 0.0, bz * 0.75)
+            * 1.5)
+            .abs();
+        let pillar_top = floor(64.0 + (surface * surface * 2.5).min((roof * 50.0).ceil() + 24.0));
+        if top > pillar_top {
+            return;
+        }
+        let min_y = chunk.min_y();
+        for y in (min_y..=pillar_top).rev() {
+            let block = chunk.get_block(x, y, z);
+            if block.name == self.default_block.name {
+                break;
+            }
+            if block.name == "minecraft:water" {
+                return;
+            }
+        }
+        let mut y = pillar_top;
+        while y >= min_y && chunk.get_block(x, y, z).is_air() {
+            chunk.set_block(x, y, z, self.default_block.clone());
+            y -= 1;
+        }
+    }
+
+    /// Grow icebergs of packed ice and snow out of frozen oceans.
+    #[allow(clippy::too_many_arguments)]
+    fn frozen_ocean_extension(
+        &self,
+        state: &RandomState,
+        chunk: &mut Chunk,
+        x: usize,
+        z: usize,
+        top: i32,
+        min_surface_level: i32,
+        biome: &str,
+    ) {
+        let block_x = chunk.x * SECTION_SIZE as i32 + x as i32;
+        let block_z = chunk.z * SECTION_SIZE as i32 + z as i32;
+        let (bx, bz) = (f64::from(block_x), f64::from(block_z));
+        let surface = (self.iceberg_surface.get_value(bx, 0.0, bz) * 8.25)
+            .abs()
+            .min(self.iceberg_pillar.get_value(bx * 1.28, 0.0, bz * 1.28) * 15.0);
+        if surface <= 1.8 {
+            return;
+        }
+        let roof = (self
+            .iceberg_pillar_roof
+            .get_value(bx * 1.17, 0.0, bz * 1.17)
+            * 1.5)
+            .abs();
+        let mut height = (surface * surface * 1.2).min((roof * 40.0).ceil() + 14.0);
+        // Vanilla shaves icebergs where the biome is warmer than 0.1 at sea
+        // level. Deep frozen oceans always are; frozen oceans only where
+        // their temperature noise says so, which is not modelled.
+        if biome == "minecraft:deep_frozen_ocean" {
+            height -= 2.0;
+        }
+        let sea_level = f64::from(self.sea_level);
+        let (iceberg_top, iceberg_bottom) = if height > 2.0 {
+            (height + sea_level, sea_level - height - 7.0)
+        } else {
+            (0.0, 0.0)
+        };
+
+        let mut random = state.random().at(block_x, 0, block_z);
+        let snow_depth = 2 + random.next_int_bounded(4);
+        let snow_min_y = self.sea_level + 18 + random.next_int_bounded(10);
+        let mut snow = 0;
+        let packed_ice = BlockState::new("minecraft:packed_ice");
+        let snow_block = BlockState::new("minecraft:snow_block");
+        for y in (min_surface_level..=top.max(iceberg_top as i32 + 1)).rev() {
+            let block = chunk.get_block(x, y, z);
+            if block.is_air() && y < iceberg_top as i32 && random.next_double() > 0.01
+                || block.name == "minecraft:water"
+                    && y > iceberg_bottom as i32
+                    && y < self.sea_level
+                    && iceberg_bottom != 0.0
+                    && random.next_double() > 0.15
+            {
+                if snow <= snow_depth && y > snow_min_y {
+                    chunk.set_block(x, y, z, snow_block.clone());
+                    snow += 1;
+                } else {
+                    chunk.set_block(x, y, z, packed_ice.clone());
+                }
+            }
+        }
+    }
+}
+
+/// The 192 terracotta bands of badlands, shuffled by the world seed.
+fn generate_bands(random: &mut dyn crate::random::RandomSource) -> Vec<BlockState> {
+    let terracotta = |color: &str| BlockState::new(format!("minecraft:{color}terracotta"));
+    let mut bands = vec![terracotta(""); 192];
+    let mut i = 0;
+    while i < bands.len() {
+        i += random.next_int_bounded(5) as usize + 1;
+        if i < bands.len() {
+            bands[i] = terracotta("orange_");
+        }
+        i += 1;
+    }
+    make_bands(random, &mut bands, 1, terracotta("yellow_"));
+    make_bands(random, &mut bands, 2, terracotta("brown_"));
+    make_bands(random, &mut bands, 1, terracotta("red_"));
+    let count = random.next_int_between_inclusive(9, 15);
+    let mut placed = 0;
+    let mut i = 0;
+    while placed < count && i < bands.len() {
+        bands[i] = terracotta("white_");
+        if i > 1 && random.next_boolean() {
+            bands[i - 1] = terracotta("light_gray_");
+        }
+        if i + 1 < bands.len() && random.next_boolean() {
+            bands[i + 1] = terracotta("light_gray_");
+        }
+        placed += 1;
+        i += random.next_int_bounded(16) as usize + 4;
+    }
+    bands
+}
+
+fn make_bands(
+    random: &mut dyn crate::random::RandomSource,
+    bands: &mut [BlockState],
+    min_width: i32,
+    state: BlockState,
+) {
+    let count = random.next_int_between_inclusive(6, 15);
+    for _ in 0..count {
+        let width = (min_width + random.next_int_bounded(3)) as usize;
+        let start = random.next_int_bounded(bands.len() as i32) as usize;
+        for band in bands.iter_mut().skip(start).take(width) {
+            *band = state.clone();
+        }
+    }
+}
+
+/// Give the rule's noises their generators and collect the random
+/// factories of its gradients.
+fn seed_rule(
+    rule: &SurfaceRule,
+    state: &mut RandomState,
+    gradients: &mut HashMap<String, Box<dyn PositionalRandomFactory>>,
+) -> SurfaceRule {
+    match rule {
+        SurfaceRule::Sequence(rules) => SurfaceRule::Sequence(
+            rules
+                .iter()
+                .map(|rule| seed_rule(rule, state, gradients))
+                .collect(),
+        ),
+        SurfaceRule::Condition { if_true, then_run } => SurfaceRule::Condition {
+            if_true: seed_condition(if_true, state, gradients),
+            then_run: Box::new(seed_rule(then_run, state, gradients)),
+        },
+        other => other.clone(),
+    }
+}
+
+fn seed_condition(
+    condition: &SurfaceCondition,
+    state: &mut RandomState,
+    gradients: &mut HashMap<String, Box<dyn PositionalRandomFactory>>,
+) -> SurfaceCondition {
+    match condition {
+        SurfaceCondition::NoiseThreshold {
+            noise,
+            min_threshold,
+            max_threshold,
+        } => SurfaceCondition::NoiseThreshold {
+            noise: noise.with_noise(state.get_or_create_noise(&noise.key, &noise.parameters)),
+            min_threshold: *min_threshold,
+            max_threshold: *max_threshold,
+        },
+        SurfaceCondition::VerticalGradient { random_name, .. } => {
+            if !gradients.contains_key(random_name) {
+                let factory = state.random().with_hash_of(random_name).fork_positional();
+                gradients.insert(random_name.clone(), factory);
+            }
+            condition.clone()
+        }
+        SurfaceCondition::Not(inner) => {
+            SurfaceCondition::Not(Box::new(seed_condition(inner, state, gradients)))
+        }
+        other => other.clone(),
+    }
+}
+
+/// Whether a block holds a fluid, judged by name as collision data is not
+/// available.
+pub(crate) fn has_fluid(state: &BlockState) -> bool {
+    matches!(
+        state.name.as_str(),
+        "minecraft:water" | "minecraft:lava" | "minecraft:bubble_column"
+    ) || state.property("waterlogged") == Some("true")
+}
+
+fn is_stone(state: &BlockState) -> bool {
+    !state.is_air() && !has_fluid(state)
+}
+
+/// Height of the highest non-air block of a column, or one below the chunk
+/// when it is empty; the `WORLD_SURFACE_WG` heightmap minus one.
+fn top_block(chunk: &Chunk, x: usize, z: usize) -> i32 {
+    (chunk.min_y()..chunk.max_y())
+        .rev()
+        .find(|&y| !chunk.get_block(x, y, z).is_air())
+        .unwrap_or(chunk.min_y() - 1)
+}
+
+/// `Mth.map`: `value` moved from the range `a..b` onto `c..d`.
+fn map(value: f64, a: f64, b: f64, c: f64, d: f64) -> f64 {
+    c + (value - a) / (b - a) * (d - c)
+}
+
+/// The position and column state rules are evaluated against, with the
+/// lazily computed values vanilla caches per column or block.
+struct Context<'a> {
+    system: &'a SurfaceSystem,
+    state: &'a RandomState,
+    biome_source: &'a BiomeSource,
+    min_y: i32,
+    height: i32,
+    /// Top block of each column, `z * 16 + x`, scanned on first use.
+    heights: Vec<i32>,
+    /// Preliminary surface levels at the four chunk corners.
+    preliminary_surface: Option<[i32; 4]>,
+    block_x: i32,
+    block_y: i32,
+    block_z: i32,
+    surface_depth: i32,
+    surface_secondary: Option<f64>,
+    min_surface_level: Option<i32>,
+    stone_depth_above: i32,
+    stone_depth_below: i32,
+    water_height: i32,
+    biome: Option<String>,
+}
+
+impl<'a> Context<'a> {
+    fn new(
+        system: &'a SurfaceSystem,
+        state: &'a RandomState,
+        biome_source: &'a BiomeSource,
+        chunk: &Chunk,
+    ) -> Self {
+        Self {
+            system,
+            state,
+            biome_source,
+            min_y: chunk.min_y(),
+            height: chunk.height(),
+            heights: Vec::new(),
+            preliminary_surface: None,
+            block_x: 0,
+            block_y: 0,
+            block_z: 0,
+            surface_depth: 0,
+            surface_secondary: None,
+            min_surface_level: None,
+            stone_depth_above: 0,
+            stone_depth_below: 0,
+            water_height: 0,
+            biome: None,
+        }
+    }
+
+    fn heights(&mut self, chunk: &Chunk) -> &mut [i32] {
+        if self.heights.is_empty() {
+            self.heights = (0..SECTION_SIZE * SECTION_SIZE)
+                .map(|i| top_block(chunk, i % SECTION_SIZE, i / SECTION_SIZE))
+                .collect();
+        }
+        &mut self.heights
+    }
+
+    fn update_xz(&mut self, x: i32, z: i32) {
+        self.block_x = x;
+        self.block_z = z;
+        self.surface_depth = self.system.surface_depth(self.state, x, z);
+        self.surface_secondary = None;
+        self.min_surface_level = None;
+        self.biome = None;
+    }
+
+    fn update_y(
+        &mut self,
+        stone_depth_above: i32,
+        stone_depth_below: i32,
+        water_height: i32,
+        y: i32,
+    ) {
+        self.block_y = y;
+        self.stone_depth_above = stone_depth_above;
+        self.stone_depth_below = stone_depth_below;
+        self.water_height = water_height;
+        self.biome = None;
+    }
+
+    /// Biome at block coordinates through the biome zoom. Cells of other
+    /// chunks are sampled from the source.
+    fn biome_at(&self, chunk: &Chunk, x: i32, y: i32, z: i32) -> String {
+        let min_qy = chunk.min_y() >> 2;
+        let max_qy = (chunk.max_y() >> 2) - 1;
+        self.system.biome_manager.get_biome(x, y, z, |qx, qy, qz| {
+            let qy = qy.clamp(min_qy, max_qy);
+            if qx >> 2 == chunk.x && qz >> 2 == chunk.z {
+                let (cx, cz) = ((qx & 3) as usize * 4, (qz & 3) as usize * 4);
+                chunk.get_biome(cx, qy << 2, cz).to_string()
+            } else {
+                self.biome_source
+                    .get_noise_biome(&self.state.router, qx, qy, qz)
+                    .to_string()
+            }
+        })
+    }
+
+    fn surface_secondary(&mut self) -> f64 {
+        let (x, z) = (f64::from(self.block_x), f64::from(self.block_z));
+        *self
+            .surface_secondary
+            .get_or_insert_with(|| self.system.surface_secondary.get_value(x, 0.0, z))
+    }
+
+    /// The preliminary surface interpolated across the chunk, lowered by
+    /// eight blocks less the surface depth.
+    fn min_surface_level(&mut self) -> i32 {
+        if let Some(level) = self.min_surface_level {
+            return level;
+        }
+        let corners = match self.preliminary_surface {
+            Some(corners) => corners,
+            None => {
+                let size = SECTION_SIZE as i32;
+                let (cx, cz) = (self.block_x.div_euclid(size), self.block_z.div_euclid(size));
+                let level = &self.state.router.preliminary_surface_level;
+                let corners = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                    .map(|(dx, dz)| floor(level.compute((cx + dx) * size, 0, (cz + dz) * size)));
+                self.preliminary_surface = Some(corners);
+                corners
+            }
+        };
+        let [a, b, c, d] = corners.map(f64::from);
+        let tx = f64::from((self.block_x & 15) as f32 / 16.0);
+        let tz = f64::from((self.block_z & 15) as f32 / 16.0);
+        let level = floor(lerp2(tx, tz, a, b, c, d)) + self.surface_depth - 8;
+        self.min_surface_level = Some(level);
+        level
+    }
+
+    fn try_apply(&mut self, rule: &SurfaceRule, chunk: &Chunk) -> Option<BlockState> {
+        match rule {
+            SurfaceRule::Block(state) => Some(state.clone()),
+            SurfaceRule::Sequence(rules) => {
+                rules.iter().find_map(|rule| self.try_apply(rule, chunk))
+            }
+            SurfaceRule::Condition { if_true, then_run } => {
+                if self.test(if_true, chunk) {
+                    self.try_apply(then_run, chunk)
+                } else {
+                    None
+                }
+            }
+            SurfaceRule::Bandlands => Some(
+                self.system
+                    .band(self.block_x, self.block_y, self.block_z)
+                    .clone(),
+            ),
+        }
+    }
+
+    fn test(&mut self, condition: &SurfaceCondition, chunk: &Chunk) -> bool {
+        match condition {
+            SurfaceCondition::Biome(biomes) => {
+                if self.biome.is_none() {
+                    self.biome =
+                        Some(self.biome_at(chunk, self.block_x, self.block_y, self.block_z));
+                }
+                biomes.iter().any(|b| Some(b) == self.biome.as_ref())
+            }
+            SurfaceCondition::NoiseThreshold {
+                noise,
+                min_threshold,
+                max_threshold,
+            } => {
+                let v = noise.get_value(f64::from(self.block_x), 0.0, f64::from(self.block_z));
+                v >= *min_threshold && v <= *max_threshold
+            }
+            SurfaceCondition::VerticalGradient {
+                random_name,
+                true_at_and_below,
+                false_at_and_above,
+            } => {
+                let true_y = true_at_and_below.resolve_y(self.min_y, self.height);
+                let false_y = false_at_and_above.resolve_y(self.min_y, self.height);
+                let y = self.block_y;
+                if y <= true_y {
+                    return true;
+                }
+                if y >= false_y {
+                    return false;
+                }
+                let chance = map(
+                    f64::from(y),
+                    f64::from(true_y),
+                    f64::from(false_y),
+                    1.0,
+                    0.0,
+                );
+                let mut random =
+                    self.system.gradients[random_name].at(self.block_x, y, self.block_z);
+                f64::from(random.next_float()) < chance
+            }
+            SurfaceCondition::YAbove {
+                anchor,
+                surface_depth_multiplier,
+                add_stone_depth,
+            } => {
+                let depth = if *add_stone_depth {
+                    self.stone_depth_above
+                } else {
+                    0
+                };
+                self.block_y + depth
+                    >= anchor.resolve_y(self.min_y, self.height)
+                        + self.surface_depth * surface_depth_multiplier
+            }
+            SurfaceCondition::Water {
+                offset,
+                surface_depth_multiplier,
+                add_stone_depth,
+            } => {
+                let depth = if *add_stone_depth {
+                    self.stone_depth_above
+                } else {
+                    0
+                };
+                self.water_height == i32::MIN
+                    || self.block_y + depth
+                        >= self.water_height
+                            + offset
+                            + self.surface_depth * surface_depth_multiplier
+            }
+            SurfaceCondition::StoneDepth {
+                offset,
+                add_surface_depth,
+                secondary_depth_range,
+                surface_type,
+            } => {
+                let depth = match surface_type {
+                    CaveSurface::Floor => self.stone_depth_above,
+                    CaveSurface::Ceiling => self.stone_depth_below,
+                };
+                let surface = if *add_surface_depth {
+                    self.surface_depth
+                } else {
+                    0
+                };
+                let secondary = if *secondary_depth_range == 0 {
+                    0
+                } else {
+                    let range = f64::from(*secondary_depth_range);
+                    map(self.surface_secondary(), -1.0, 1.0, 0.0, range) as i32
+                };
+                depth <= 1 + offset + surface + secondary
+            }
+            SurfaceCondition::Temperature => false,
+            SurfaceCondition::Steep => {
+                let (x, z) = ((self.block_x & 15) as usize, (self.block_z & 15) as usize);
+                let heights = self.heights(chunk);
+                let height = |x: usize, z: usize| heights[z * SECTION_SIZE + x];
+                if height(x, (z + 1).min(15)) >= height(x, z.saturating_sub(1)) + 4 {
+                    return true;
+                }
+                height(x.saturating_sub(1), z) >= height((x + 1).min(15), z) + 4
+            }
+            SurfaceCondition::Hole => self.surface_depth <= 0,
+            SurfaceCondition::AbovePreliminarySurface => self.block_y >= self.min_surface_level(),
+            SurfaceCondition::Not(inner) => !self.test(inner, chunk),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::density::DensityFunction;
+    use crate::generator::{NoiseBasedGenerator, NoiseGeneratorSettings};
+    use crate::noise_router::NoiseRouter;
+
+    fn block(name: &str) -> SurfaceRule {
+        SurfaceRule::Block(BlockState::new(name))
+    }
+
+    fn when(condition: SurfaceCondition, rule: SurfaceRule) -> SurfaceRule {
+        SurfaceRule::Condition {
+            if_true: condition,
+            then_run: Box::new(rule),
+        }
+    }
+
+    fn floor_depth(offset: i32, add_surface_depth: bool) -> SurfaceCondition {
+        SurfaceCondition::StoneDepth {
+            offset,
+            add_surface_depth,
+            secondary_depth_range: 0,
+            surface_type: CaveSurface::Floor,
+        }
+    }
+
+    /// Flat terrain with its top block at y = 69, under a sea at y = 63
+    /// where `sea_level` is above that.
+    fn generator(rule: SurfaceRule, sea_level: i32, biome: &str) -> NoiseBasedGenerator {
+        let settings = NoiseGeneratorSettings {
+            noise_router: NoiseRouter {
+                final_density: DensityFunction::y_clamped_gradient(0, 140, 1.0, -1.0),
+                ..Default::default()
+            },
+            sea_level,
+            surface_rule: rule,
+            ..Default::default()
+        };
+        NoiseBasedGenerator::new(settings, BiomeSource::Fixed(biome.into()), 99)
+    }
+
+    fn plains_rule() -> SurfaceRule {
+        let bedrock = SurfaceCondition::VerticalGradient {
+            random_name: "minecraft:bedrock_floor".into(),
+            true_at_and_below: VerticalAnchor::AboveBottom(0),
+            false_at_and_above: VerticalAnchor::AboveBottom(5),
+        };
+        let dry = SurfaceCondition::Water {
+            offset: -1,
+            surface_depth_multiplier: 0,
+            add_stone_depth: false,
+        };
+        SurfaceRule::Sequence(vec![
+            when(bedrock, block("minecraft:bedrock")),
+            when(
+                SurfaceCondition::Biome(vec!["minecraft:plains".into()]),
+                SurfaceRule::Sequence(vec![
+                    when(
+                        floor_depth(0, false),
+                        SurfaceRule::Sequence(vec![
+                            when(dry, block("minecraft:grass_block")),
+                            block("minecraft:sand"),
+                        ]),
+                    ),
+                    when(floor_depth(0, true), block("minecraft:dirt")),
+                ]),
+            ),
+            when(
+                SurfaceCondition::Not(Box::new(SurfaceCondition::YAbove {
+                    anchor: VerticalAnchor::Absolute(8),
+                    surface_depth_multiplier: 0,
+                    add_stone_depth: false,
+                })),
+                block("minecraft:deepslate"),
+            ),
+        ])
+    }
+
+    #[test]
+    fn layers_grass_dirt_and_bedrock() {
+        let chunk = generator(plains_rule(), 63, "minecraft:plains").generate_chunk(2, -3);
+        for (x, z) in [(0, 0), (7, 12), (15, 15)] {
+            assert_eq!(chunk.get_block(x, 69, z).name, "minecraft:grass_block");
+            assert_eq!(chunk.get_block(x, 68, z).name, "minecraft:dirt");
+            assert_eq!(chunk.get_block(x, 40, z).name, "minecraft:stone");
+            assert_eq!(chunk.get_block(x, 0, z).name, "minecraft:deepslate");
+            assert_eq!(chunk.get_block(x, -64, z).name, "minecraft:bedrock");
+            assert_eq!(chunk.get_block(x, -59, z).name, "minecraft:deepslate");
+            assert!(chunk.get_block(x, 70, z).is_air());
+        }
+        // The gradient between the two anchors is patchy.
+        let layer = |y| {
+            (0..16)
+                .flat_map(|x| (0..16).map(move |z| (x, z)))
+                .filter(|&(x, z)| chunk.get_block(x, y, z).name == "minecraft:bedrock")
+                .count()
+        };
+        assert!(layer(-63) > layer(-60) && layer(-60) > 0 && layer(-63) < 256);
+
+        // Under water the top becomes sand, and other biomes keep stone.
+        let chunk = generator(plains_rule(), 80, "minecraft:plains").generate_chunk(0, 0);
+        assert_eq!(chunk.get_block(4, 69, 4).name, "minecraft:sand");
+        assert_eq!(chunk.get_block(4, 70, 4).name, "minecraft:water");
+        let chunk = generator(plains_rule(), 63, "minecraft:desert").generate_chunk(0, 0);
+        assert_eq!(chunk.get_block(4, 69, 4).name, "minecraft:stone");
+    }
+
+    #[test]
+    fn bands_repeat_every_192_blocks() {
+        let generator = generator(SurfaceRule::Bandlands, 63, "minecraft:badlands");
+        let chunk = generator.generate_chunk(0, 0);
+        let column: Vec<_> = (-64..70).map(|y| chunk.get_block(3, y, 9)).collect();
+        assert!(column.iter().all(|b| b.name.ends_with("terracotta")));
+        assert!(
+            column
+                .iter()
+                .any(|b| b.name == "minecraft:orange_terracotta")
+        );
+        assert!(column.iter().any(|b| b.name == "minecraft:terracotta"));
+
+        let system = &generator.surface_system;
+        assert_eq!(system.clay_bands.len(), 192);
+        assert_eq!(system.band(5, 10, 5), system.band(5, 10 + 192, 5));
+    }
+
+    #[test]
+    fn steep_follows_neighbouring_columns() {
+        let steep = when(SurfaceCondition::Steep, block("minecraft:gravel"));
+        let generator = generator(steep, 63, "minecraft:plains");
+        let mut chunk = Chunk::new(0, 0);
+        for x in 0..16 {
+            for z in 0..16 {
+                // A cliff rising five blocks per column southwards.
+                for y in 0..=60 + 5 * z as i32 {
+                    chunk.set_block(x, y, z, BlockState::new("minecraft:stone"));
+                }
+            }
+        }
+        generator.surface_system.build_surface(
+            &generator.random_state,
+            &generator.biome_source,
+            &mut chunk,
+            false,
+        );
+        assert_eq!(chunk.get_block(3, 70, 2).name, "minecraft:gravel");
+        assert_eq!(chunk.get_block(3, 20, 2).name, "minecraft:gravel");
+        // Flat east to west, and the edge clamps to the column itself.
+        assert_eq!(chunk.get_block(3, 135, 15).name, "minecraft:gravel");
+        let mut flat = Chunk::new(0, 0);
+        flat.set_block(3, 60, 3, BlockState::new("minecraft:stone"));
+        generator.surface_system.build_surface(
+            &generator.random_state,
+            &generator.biome_source,
+            &mut flat,
+            false,
+        );
+        assert_eq!(flat.get_block(3, 60, 3).name, "minecraft:stone");
+    }
+}
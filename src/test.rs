@@ -49,7 +49,8 @@ mod perlin_test {
 #[cfg(test)]
 mod level_file_test {
 
-    use crate::level::{LevelDat, create_nbt};
+    use crate::level::{CustomBossEvent, Dimension, LevelDat, ScheduledEvent, create_nbt, to_nbt};
+    use nbt::Tag;
 
     #[test]
     fn test_creation_of_file() {
@@ -60,12 +61,208 @@ mod level_file_test {
         let result = create_nbt(&level, "target/level.dat");
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_level_round_trip() {
+        let mut level = LevelDat {
+            day_time: 12345,
+            raining: true,
+            spawn_x: -40,
+            spawn_y: 70,
+            spawn_z: 1200,
+            level_name: "Cactus".into(),
+            ..Default::default()
+        };
+        level.world_gen_settings.seed = -987654321;
+        level
+            .game_rules
+            .insert("doDaylightCycle".into(), "false".into());
+        level.dragon_fight.gateways = vec![3, 9];
+        level.data_packs.enabled.push("file/arena".into());
+
+        let read = LevelDat::from_nbt(&to_nbt(&level)).unwrap();
+        assert_eq!(read.day_time, 12345);
+        assert!(read.raining);
+        assert_eq!((read.spawn_x, read.spawn_y, read.spawn_z), (-40, 70, 1200));
+        assert_eq!(read.level_name, "Cactus");
+        assert_eq!(read.world_gen_settings.seed, -987654321);
+        assert_eq!(read.game_rules["doDaylightCycle"], "false");
+        assert_eq!(read.dragon_fight.gateways, vec![3, 9]);
+        assert_eq!(read.data_packs.enabled, vec!["vanilla", "file/arena"]);
+        assert!(matches!(
+            read.world_gen_settings.dimensions["Nether"],
+            Dimension::Nether
+        ));
+    }
+
+    #[test]
+    fn test_level_defaults_and_vanilla_layout() {
+        let mut data = Tag::new_compound("Data");
+        data.insert("SpawnY".into(), Tag::new_int("SpawnY", 100));
+        let mut root = Tag::new_compound("");
+        root.insert("Data".into(), data);
+
+        let read = LevelDat::from_nbt(&root).unwrap();
+        assert_eq!(read.spawn_y, 100);
+        assert_eq!(read.time, LevelDat::default().time);
+
+        let mut bad = Tag::new_compound("Data");
+        bad.insert("SpawnY".into(), Tag::new_string("SpawnY", "high"));
+        let err = LevelDat::from_nbt(&bad).err().unwrap();
+        assert!(err.to_string().contains("SpawnY"));
+    }
+
+    #[test]
+    fn test_level_custom_dimensions() {
+        let dimension = |kind: Tag| {
+            let mut dim = Tag::new_compound("");
+            dim.insert("type".into(), kind);
+            dim
+        };
+        let mut dims = Tag::new_compound("dimensions");
+        dims.insert(
+            "minecraft:overworld".into(),
+            dimension(Tag::new_string("type", "minecraft:overworld")),
+        );
+        dims.insert(
+            "arena:lobby".into(),
+            dimension(Tag::new_string("type", "arena:flat_type")),
+        );
+        let mut inline = Tag::new_compound("type");
+        inline.insert("height".into(), Tag::new_int("height", 256));
+        dims.insert("arena:void".into(), dimension(inline));
+        let mut wgs = Tag::new_compound("WorldGenSettings");
+        wgs.insert("seed".into(), Tag::new_long("seed", 77));
+        wgs.insert("dimensions".into(), dims);
+        let mut data = Tag::new_compound("Data");
+        data.insert("WorldGenSettings".into(), wgs);
+
+        let read = LevelDat::from_nbt(&data).unwrap();
+        let dims = &read.world_gen_settings.dimensions;
+        assert_eq!(read.world_gen_settings.seed, 77);
+        assert!(matches!(dims["minecraft:overworld"], Dimension::Overworld));
+        assert!(matches!(&dims["arena:lobby"], Dimension::Custom(id) if id == "arena:flat_type"));
+        assert!(matches!(&dims["arena:void"], Dimension::Custom(id) if id == "arena:void"));
+
+        let again = LevelDat::from_nbt(&to_nbt(&read)).unwrap();
+        let dims = &again.world_gen_settings.dimensions;
+        assert!(matches!(&dims["arena:lobby"], Dimension::Custom(id) if id == "arena:flat_type"));
+    }
+
+    #[test]
+    fn test_level_boss_bars_and_schedules_round_trip() {
+        let mut level = LevelDat::default();
+        level.custom_boss_events.events.insert(
+            "arena:timer".into(),
+            CustomBossEvent {
+                name: Tag::new_string("Name", "Time left"),
+                color: "red".into(),
+                overlay: "notched_10".into(),
+                value: 40,
+                max: 60,
+                visible: true,
+                players: vec![[1, 2, 3, 4]],
+                create_world_fog: false,
+                darken_screen: true,
+                play_boss_music: false,
+            },
+        );
+        level.scheduled_events.push(ScheduledEvent {
+            name: "arena:reset".into(),
+            trigger_time: 120000,
+            callback_type: "function".into(),
+            callback_name: "arena:reset".into(),
+        });
+
+        let read = LevelDat::from_nbt(&to_nbt(&level)).unwrap();
+        let boss = &read.custom_boss_events.events["arena:timer"];
+        assert_eq!(boss.name.as_string(), Some("Time left"));
+        assert_eq!((boss.color.as_str(), boss.value, boss.max), ("red", 40, 60));
+        assert_eq!(boss.players, vec![[1, 2, 3, 4]]);
+        assert!(boss.darken_screen && !boss.play_boss_music);
+        let event = &read.scheduled_events[0];
+        assert_eq!(read.scheduled_events.len(), 1);
+        assert_eq!(event.trigger_time, 120000);
+        assert_eq!(event.callback_name, "arena:reset");
+
+        let mut bad = to_nbt(&level);
+        bad.insert(
+            "ScheduledEvents".into(),
+            Tag::new_list("ScheduledEvents", 10, vec![Tag::new_compound("")]),
+        );
+        assert!(LevelDat::from_nbt(&bad).is_err());
+    }
 }
 
 /// Test for player.rs
 #[cfg(test)]
 mod player_data_test {
-    use crate::player::{PlayerData, create_nbt};
+    use crate::level::Dimension;
+    use crate::player::{EquipmentSlot, Item, PlayerData, create_nbt, to_nbt};
+    use nbt::Tag;
+    use std::collections::HashMap;
+
+    fn item(slot: i8, id: &str, count: i32) -> Item {
+        Item {
+            count,
+            slot,
+            id: id.into(),
+            components: HashMap::new(),
+        }
+    }
+
+    fn vanilla_player() -> Tag {
+        let mut root = Tag::new_compound("");
+        let doubles = |name: &str, v: [f64; 3]| {
+            Tag::new_list(name, 6, v.iter().map(|d| Tag::new_double("", *d)).collect())
+        };
+        root.insert("Pos".into(), doubles("Pos", [10.5, 64.0, -3.25]));
+        root.insert("Motion".into(), doubles("Motion", [0.1, -0.08, 0.2]));
+        root.insert(
+            "Rotation".into(),
+            Tag::new_list(
+                "Rotation",
+                5,
+                vec![Tag::new_float("", 90.0), Tag::new_float("", -12.5)],
+            ),
+        );
+        let mut item = Tag::new_compound("");
+        item.insert("Slot".into(), Tag::new_byte("Slot", 3));
+        item.insert(
+            "id".into(),
+            Tag::new_string("id", "minecraft:diamond_pickaxe"),
+        );
+        item.insert("count".into(), Tag::new_int("count", 1));
+        root.insert(
+            "Inventory".into(),
+            Tag::new_list("Inventory", 10, vec![item]),
+        );
+        root.insert("foodLevel".into(), Tag::new_int("foodLevel", 17));
+        root.insert(
+            "foodSaturationLevel".into(),
+            Tag::new_float("foodSaturationLevel", 2.5),
+        );
+        root.insert(
+            "foodExhaustionLevel".into(),
+            Tag::new_float("foodExhaustionLevel", 0.75),
+        );
+        root.insert("foodTickTimer".into(), Tag::new_int("foodTickTimer", 0));
+        root.insert("XpLevel".into(), Tag::new_int("XpLevel", 30));
+        root.insert("XpP".into(), Tag::new_float("XpP", 0.5));
+        root.insert("XpTotal".into(), Tag::new_int("XpTotal", 1395));
+        root.insert(
+            "Dimension".into(),
+            Tag::new_string("Dimension", "minecraft:the_nether"),
+        );
+        root.insert(
+            "UUID".into(),
+            Tag::new_int_array(
+                "UUID",
+                vec![-1938810203, -480034083, -1435396649, 1803224535],
+            ),
+        );
+        root
+    }
 
     #[test]
     fn test_playerdata_creation() {
@@ -79,4 +276,184 @@ mod player_data_test {
         );
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_playerdata_read_vanilla() {
+        let data = PlayerData::from_nbt(&vanilla_player()).unwrap();
+        assert_eq!(data.position, [10.5, 64.0, -3.25]);
+        assert_eq!(data.rotation, [90.0, -12.5]);
+        assert_eq!(data.inventory.len(), 1);
+        assert_eq!(data.inventory[0].id, "minecraft:diamond_pickaxe");
+        assert_eq!(data.inventory[0].slot, 3);
+        assert_eq!(data.food_level, 17);
+        assert_eq!(data.xp_total, 1395);
+        assert!(matches!(data.dimension, Dimension::Nether));
+        assert_eq!(data.uuid[3], 1803224535);
+        assert_eq!(data.air, PlayerData::default().air);
+    }
+
+    #[test]
+    fn test_playerdata_read_errors() {
+        let mut tag = vanilla_player();
+        tag.insert("foodLevel".into(), Tag::new_string("foodLevel", "full"));
+        let err = PlayerData::from_nbt(&tag).err().unwrap();
+        assert_eq!(err.to_string(), "tag `foodLevel` is not an int");
+
+        let mut tag = Tag::new_compound("");
+        tag.insert(
+            "Dimension".into(),
+            Tag::new_string("Dimension", "minecraft:overworld"),
+        );
+        let err = PlayerData::from_nbt(&tag).err().unwrap();
+        assert_eq!(err.to_string(), "missing tag `UUID`");
+    }
+
+    #[test]
+    fn test_playerdata_items_round_trip() {
+        let mut inventory: Vec<Item> = (0..9)
+            .map(|slot| item(slot, "minecraft:cobblestone", 64 - slot as i32))
+            .collect();
+        inventory[4].components.insert(
+            "minecraft:damage".into(),
+            Tag::new_int("minecraft:damage", 12),
+        );
+        let equipment = [
+            (EquipmentSlot::Head, "minecraft:diamond_helmet"),
+            (EquipmentSlot::Chest, "minecraft:diamond_chestplate"),
+            (EquipmentSlot::Legs, "minecraft:diamond_leggings"),
+            (EquipmentSlot::Feet, "minecraft:diamond_boots"),
+            (EquipmentSlot::Offhand, "minecraft:shield"),
+        ]
+        .into_iter()
+        .map(|(slot, id)| (slot, item(0, id, 1)))
+        .collect();
+        let data = PlayerData {
+            inventory,
+            ender_items: vec![item(26, "minecraft:elytra", 1)],
+            equipment,
+            ..Default::default()
+        };
+
+        let written = to_nbt("8c701aa5-e353-42dd-aa71-95d76b63a5d7", &data);
+        assert_eq!(
+            written
+                .get("Inventory")
+                .and_then(Tag::as_list)
+                .unwrap()
+                .len(),
+            9
+        );
+
+        let read = PlayerData::from_nbt(&written).unwrap();
+        assert_eq!(read.inventory.len(), 9);
+        assert_eq!(read.inventory[8].slot, 8);
+        assert_eq!(read.inventory[8].count, 56);
+        assert_eq!(
+            read.inventory[4].components["minecraft:damage"].as_int(),
+            Some(12)
+        );
+        assert_eq!(read.ender_items[0].id, "minecraft:elytra");
+        assert_eq!(read.equipment.len(), 5);
+        assert_eq!(
+            read.equipment[&EquipmentSlot::Offhand].id,
+            "minecraft:shield"
+        );
+    }
+
+    #[test]
+    fn test_playerdata_position_round_trip() {
+        let data = PlayerData {
+            position: [-120.5, 72.0, 3000.25],
+            motion: [0.25, -0.0784, -0.5],
+            rotation: [-45.0, 30.0],
+            ..Default::default()
+        };
+        let tag = to_nbt("8c701aa5-e353-42dd-aa71-95d76b63a5d7", &data);
+        for (key, len) in [("Pos", 3), ("Motion", 3), ("Rotation", 2)] {
+            assert_eq!(tag.get(key).and_then(Tag::as_list).unwrap().len(), len);
+        }
+        assert_eq!(
+            tag.get("Rotation").and_then(Tag::as_list).unwrap()[0].as_float(),
+            Some(-45.0)
+        );
+
+        let read = PlayerData::from_nbt(&tag).unwrap();
+        assert_eq!(read.position, data.position);
+        assert_eq!(read.motion, data.motion);
+        assert_eq!(read.rotation, data.rotation);
+    }
+
+    #[test]
+    fn test_playerdata_absorption_key() {
+        let data = PlayerData {
+            absorbtion_amount: 4.0,
+            ..Default::default()
+        };
+        let tag = to_nbt("8c701aa5-e353-42dd-aa71-95d76b63a5d7", &data);
+        assert_eq!(
+            tag.get("AbsorptionAmount").and_then(Tag::as_float),
+            Some(4.0)
+        );
+        assert_eq!(PlayerData::from_nbt(&tag).unwrap().absorbtion_amount, 4.0);
+    }
+
+    #[test]
+    fn test_playerdata_position_needs_three_entries() {
+        let tag = to_nbt(
+            "8c701aa5-e353-42dd-aa71-95d76b63a5d7",
+            &PlayerData::default(),
+        );
+        for len in [2, 5] {
+            let mut bad = tag.clone();
+            let pos = (0..len).map(|_| Tag::new_double("", 1.0)).collect();
+            bad.insert("Pos".into(), Tag::new_list("Pos", 6, pos));
+            let err = PlayerData::from_nbt(&bad).err().unwrap();
+            assert_eq!(
+                err.to_string(),
+                format!("`Pos` has {len} entries, expected 3")
+            );
+        }
+    }
+}
+/// Fixtures for the worldgen tests
+#[cfg(test)]
+pub(crate) mod worldgen {
+    use crate::chunk::{BlockState, Chunk};
+
+    /// 3x3 chunks around the origin, `height` blocks tall from `min_y`,
+    /// each filled in by `fill`.
+    pub(crate) fn region(min_y: i32, height: i32, mut fill: impl FnMut(&mut Chunk)) -> Vec<Chunk> {
+        let mut chunks = Vec::new();
+        for cx in -1..=1 {
+            for cz in -1..=1 {
+                let mut chunk = Chunk::with_height(cx, cz, min_y, height);
+                fill(&mut chunk);
+                chunks.push(chunk);
+            }
+        }
+        chunks
+    }
+
+    /// The world position and state of every block of `chunks` matching
+    /// `predicate`.
+    pub(crate) fn find_blocks(
+        chunks: &[Chunk],
+        mut predicate: impl FnMut([i32; 3], &BlockState) -> bool,
+    ) -> Vec<([i32; 3], BlockState)> {
+        let mut found = Vec::new();
+        for chunk in chunks {
+            for y in chunk.min_y()..chunk.max_y() {
+                for z in 0..16 {
+                    for x in 0..16 {
+                        let pos = [chunk.x * 16 + x as i32, y, chunk.z * 16 + z as i32];
+                        let state = chunk.get_block(x, y, z);
+                        if predicate(pos, &state) {
+                            found.push((pos, state));
+                        }
+                    }
+                }
+            }
+        }
+        found
+    }
 }
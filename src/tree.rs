@@ -0,0 +1,1050 @@
+//! Trees: a trunk placer grows the logs and leaves attachment points at
+//! which a foliage placer grows the leaves. A port of vanilla's
+//! `TreeFeature`, without roots or decorators.
+
+use crate::chunk::BlockState;
+use crate::feature::{WorldGenRegion, is_dirt};
+use crate::provider::{IntProvider, between_inclusive};
+use crate::random::RandomSource;
+use std::collections::HashSet;
+
+/// Leaves farther than this from a log decay.
+const MAX_LEAF_DISTANCE: i32 = 7;
+
+/// Vanilla's `#replaceable_by_trees` block tag, besides leaves.
+const REPLACEABLE_BY_TREES: &[&str] = &[
+    "minecraft:short_grass",
+    "minecraft:fern",
+    "minecraft:dead_bush",
+    "minecraft:vine",
+    "minecraft:glow_lichen",
+    "minecraft:sunflower",
+    "minecraft:lilac",
+    "minecraft:rose_bush",
+    "minecraft:peony",
+    "minecraft:tall_grass",
+    "minecraft:large_fern",
+    "minecraft:hanging_roots",
+    "minecraft:pitcher_plant",
+    "minecraft:water",
+    "minecraft:seagrass",
+    "minecraft:tall_seagrass",
+    "minecraft:warped_roots",
+    "minecraft:nether_sprouts",
+    "minecraft:crimson_roots",
+];
+
+/// North, east, south and west, as `Direction.Plane.HORIZONTAL`.
+const HORIZONTAL: [[i32; 3]; 4] = [[0, 0, -1], [1, 0, 0], [0, 0, 1], [-1, 0, 0]];
+
+fn offset([x, y, z]: [i32; 3], [dx, dy, dz]: [i32; 3], n: i32) -> [i32; 3] {
+    [x + dx * n, y + dy * n, z + dz * n]
+}
+
+fn random_direction(random: &mut dyn RandomSource) -> [i32; 3] {
+    HORIZONTAL[random.next_int_bounded(4) as usize]
+}
+
+fn is_leaves(state: &BlockState) -> bool {
+    state.name.ends_with("_leaves")
+}
+
+fn is_log(state: &BlockState) -> bool {
+    state.name.ends_with("_log") || state.name.ends_with("_wood")
+}
+
+fn is_air_or_leaves(state: &BlockState) -> bool {
+    state.is_air() || is_leaves(state)
+}
+
+/// Whether a tree may put a log or leaves in place of `state`.
+fn valid_tree_pos(state: &BlockState) -> bool {
+    is_air_or_leaves(state) || REPLACEABLE_BY_TREES.contains(&state.name.as_str())
+}
+
+/// How much room a tree needs around its trunk at each height.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FeatureSize {
+    TwoLayers {
+        limit: i32,
+        lower_size: i32,
+        upper_size: i32,
+    },
+    ThreeLayers {
+        limit: i32,
+        upper_limit: i32,
+        lower_size: i32,
+        middle_size: i32,
+        upper_size: i32,
+        /// A tree short of room may still grow down to this height.
+        min_clipped_height: Option<i32>,
+    },
+}
+
+impl FeatureSize {
+    fn size_at_height(&self, trunk_height: i32, y: i32) -> i32 {
+        match *self {
+            Self::TwoLayers {
+                limit,
+                lower_size,
+                upper_size,
+            } => {
+                if y < limit {
+                    lower_size
+                } else {
+                    upper_size
+                }
+            }
+            Self::ThreeLayers {
+                limit,
+                upper_limit,
+                lower_size,
+                middle_size,
+                upper_size,
+                ..
+            } => {
+                if y < limit {
+                    lower_size
+                } else if y >= trunk_height - upper_limit {
+                    upper_size
+                } else {
+                    middle_size
+                }
+            }
+        }
+    }
+
+    fn min_clipped_height(&self) -> Option<i32> {
+        match *self {
+            Self::TwoLayers { .. } => None,
+            Self::ThreeLayers {
+                min_clipped_height, ..
+            } => min_clipped_height,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TrunkKind {
+    Straight,
+    /// Bends to one side near the top and may grow a second branch.
+    Forking,
+    /// Two by two logs with short stubs around the top.
+    DarkOak,
+    /// A short trunk with one to three branches ending in leaves.
+    Cherry {
+        branch_count: IntProvider,
+        branch_horizontal_length: IntProvider,
+        /// Uniform range of where the first branch starts, from the top.
+        branch_start_offset_from_top: (i32, i32),
+        branch_end_offset_from_top: IntProvider,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrunkPlacer {
+    pub base_height: i32,
+    pub height_rand_a: i32,
+    pub height_rand_b: i32,
+    pub kind: TrunkKind,
+}
+
+impl TrunkPlacer {
+    pub fn new(base_height: i32, height_rand_a: i32, height_rand_b: i32, kind: TrunkKind) -> Self {
+        Self {
+            base_height,
+            height_rand_a,
+            height_rand_b,
+            kind,
+        }
+    }
+
+    fn tree_height(&self, random: &mut dyn RandomSource) -> i32 {
+        self.base_height
+            + random.next_int_bounded(self.height_rand_a + 1)
+            + random.next_int_bounded(self.height_rand_b + 1)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FoliageKind {
+    /// Layers shrinking towards the top, as on oak and birch.
+    Blob {
+        height: i32,
+    },
+    /// Alternating wide and narrow rings down the trunk.
+    Spruce {
+        trunk_height: IntProvider,
+    },
+    /// A flat canopy.
+    Acacia,
+    DarkOak,
+    Cherry {
+        height: IntProvider,
+        wide_bottom_layer_hole_chance: f32,
+        corner_hole_chance: f32,
+        hanging_leaves_chance: f32,
+        hanging_leaves_extension_chance: f32,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FoliagePlacer {
+    pub radius: IntProvider,
+    pub offset: IntProvider,
+    pub kind: FoliageKind,
+}
+
+impl FoliagePlacer {
+    pub fn new(radius: IntProvider, offset: IntProvider, kind: FoliageKind) -> Self {
+        Self {
+            radius,
+            offset,
+            kind,
+        }
+    }
+
+    fn foliage_height(&self, random: &mut dyn RandomSource, tree_height: i32) -> i32 {
+        match &self.kind {
+            FoliageKind::Blob { height } => *height,
+            FoliageKind::Spruce { trunk_height } => {
+                (tree_height - trunk_height.sample(random)).max(4)
+            }
+            FoliageKind::Acacia => 0,
+            FoliageKind::DarkOak => 4,
+            FoliageKind::Cherry { height, .. } => height.sample(random),
+        }
+    }
+
+    fn should_skip_location_signed(
+        &self,
+        random: &mut dyn RandomSource,
+        x: i32,
+        y: i32,
+        z: i32,
+        range: i32,
+        large: bool,
+    ) -> bool {
+        if matches!(self.kind, FoliageKind::DarkOak)
+            && y == 0
+            && large
+            && (x == -range || x >= range)
+            && (z == -range || z >= range)
+        {
+            return true;
+        }
+        let (x, z) = if large {
+            (x.abs().min((x - 1).abs()), z.abs().min((z - 1).abs()))
+        } else {
+            (x.abs(), z.abs())
+        };
+        self.should_skip_location(random, x, y, z, range, large)
+    }
+
+    fn should_skip_location(
+        &self,
+        random: &mut dyn RandomSource,
+        x: i32,
+        y: i32,
+        z: i32,
+        range: i32,
+        large: bool,
+    ) -> bool {
+        let corner = x == range && z == range;
+        match self.kind {
+            FoliageKind::Blob { .. } => corner && (random.next_int_bounded(2) == 0 || y == 0),
+            FoliageKind::Spruce { .. } => corner && range > 0,
+            FoliageKind::Acacia if y == 0 => (x > 1 || z > 1) && x != 0 && z != 0,
+            FoliageKind::Acacia => corner && range > 0,
+            FoliageKind::DarkOak if y == -1 && !large => corner,
+            FoliageKind::DarkOak if y == 1 => x + z > range * 2 - 2,
+            FoliageKind::DarkOak => false,
+            FoliageKind::Cherry {
+                wide_bottom_layer_hole_chance,
+                corner_hole_chance,
+                ..
+            } => {
+                if y == -1
+                    && (x == range || z == range)
+                    && random.next_float() < wide_bottom_layer_hole_chance
+                {
+                    return true;
+                }
+                if range > 2 {
+                    corner || (x + z > range * 2 - 2 && random.next_float() < corner_hole_chance)
+                } else {
+                    corner && random.next_float() < corner_hole_chance
+                }
+            }
+        }
+    }
+}
+
+/// Where a foliage placer grows leaves, relative to the trunk top.
+struct FoliageAttachment {
+    pos: [i32; 3],
+    radius_offset: i32,
+    double_trunk: bool,
+}
+
+impl FoliageAttachment {
+    fn new(pos: [i32; 3], radius_offset: i32, double_trunk: bool) -> Self {
+        Self {
+            pos,
+            radius_offset,
+            double_trunk,
+        }
+    }
+}
+
+/// A `minecraft:tree` configured feature.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TreeConfiguration {
+    pub trunk: BlockState,
+    pub trunk_placer: TrunkPlacer,
+    pub foliage: BlockState,
+    pub foliage_placer: FoliagePlacer,
+    /// Put under the trunk in place of grass and non-dirt blocks.
+    pub dirt: BlockState,
+    pub minimum_size: FeatureSize,
+    pub ignore_vines: bool,
+    pub force_dirt: bool,
+}
+
+impl TreeConfiguration {
+    fn new(
+        trunk: &str,
+        trunk_placer: TrunkPlacer,
+        foliage: &str,
+        foliage_placer: FoliagePlacer,
+        minimum_size: FeatureSize,
+    ) -> Self {
+        Self {
+            trunk: BlockState::new(trunk),
+            trunk_placer,
+            foliage: BlockState::new(foliage),
+            foliage_placer,
+            dirt: BlockState::new("minecraft:dirt"),
+            minimum_size,
+            ignore_vines: true,
+            force_dirt: false,
+        }
+    }
+
+    /// `TreeFeatures.createStraightBlobTree`.
+    fn straight_blob(log: &str, leaves: &str, base_height: i32, rand_a: i32, radius: i32) -> Self {
+        Self::new(
+            log,
+            TrunkPlacer::new(base_height, rand_a, 0, TrunkKind::Straight),
+            leaves,
+            FoliagePlacer::new(
+                IntProvider::Constant(radius),
+                IntProvider::Constant(0),
+                FoliageKind::Blob { height: 3 },
+            ),
+            FeatureSize::TwoLayers {
+                limit: 1,
+                lower_size: 0,
+                upper_size: 1,
+            },
+        )
+    }
+
+    /// `minecraft:oak`.
+    pub fn oak() -> Self {
+        Self::straight_blob("minecraft:oak_log", "minecraft:oak_leaves", 4, 2, 2)
+    }
+
+    /// `minecraft:birch`.
+    pub fn birch() -> Self {
+        Self::straight_blob("minecraft:birch_log", "minecraft:birch_leaves", 5, 2, 2)
+    }
+
+    /// `minecraft:jungle_tree_no_vine`.
+    pub fn jungle_tree() -> Self {
+        Self::straight_blob("minecraft:jungle_log", "minecraft:jungle_leaves", 4, 8, 2)
+    }
+
+    /// `minecraft:spruce`.
+    pub fn spruce() -> Self {
+        Self::new(
+            "minecraft:spruce_log",
+            TrunkPlacer::new(5, 2, 1, TrunkKind::Straight),
+            "minecraft:spruce_leaves",
+            FoliagePlacer::new(
+                IntProvider::Uniform {
+                    min_inclusive: 2,
+                    max_inclusive: 3,
+                },
+                IntProvider::Uniform {
+                    min_inclusive: 0,
+                    max_inclusive: 2,
+                },
+                FoliageKind::Spruce {
+                    trunk_height: IntProvider::Uniform {
+                        min_inclusive: 1,
+                        max_inclusive: 2,
+                    },
+                },
+            ),
+            FeatureSize::TwoLayers {
+                limit: 2,
+                lower_size: 0,
+                upper_size: 2,
+            },
+        )
+    }
+
+    /// `minecraft:acacia`.
+    pub fn acacia() -> Self {
+        Self::new(
+            "minecraft:acacia_log",
+            TrunkPlacer::new(5, 2, 2, TrunkKind::Forking),
+            "minecraft:acacia_leaves",
+            FoliagePlacer::new(
+                IntProvider::Constant(2),
+                IntProvider::Constant(0),
+                FoliageKind::Acacia,
+            ),
+            FeatureSize::TwoLayers {
+                limit: 1,
+                lower_size: 0,
+                upper_size: 2,
+            },
+        )
+    }
+
+    /// `minecraft:dark_oak`.
+    pub fn dark_oak() -> Self {
+        Self::new(
+            "minecraft:dark_oak_log",
+            TrunkPlacer::new(6, 2, 1, TrunkKind::DarkOak),
+            "minecraft:dark_oak_leaves",
+            FoliagePlacer::new(
+                IntProvider::Constant(0),
+                IntProvider::Constant(0),
+                FoliageKind::DarkOak,
+            ),
+            FeatureSize::ThreeLayers {
+                limit: 1,
+                upper_limit: 1,
+                lower_size: 0,
+                middle_size: 1,
+                upper_size: 2,
+                min_clipped_height: None,
+            },
+        )
+    }
+
+    /// `minecraft:cherry`.
+    pub fn cherry() -> Self {
+        let branch_count = (1..=3).map(|n| (IntProvider::Constant(n), 1)).collect();
+        Self::new(
+            "minecraft:cherry_log",
+            TrunkPlacer::new(
+                7,
+                1,
+                0,
+                TrunkKind::Cherry {
+                    branch_count: IntProvider::WeightedList(branch_count),
+                    branch_horizontal_length: IntProvider::Uniform {
+                        min_inclusive: 2,
+                        max_inclusive: 4,
+                    },
+                    branch_start_offset_from_top: (-4, -3),
+                    branch_end_offset_from_top: IntProvider::Uniform {
+                        min_inclusive: -1,
+                        max_inclusive: 0,
+                    },
+                },
+            ),
+            "minecraft:cherry_leaves",
+            FoliagePlacer::new(
+                IntProvider::Constant(4),
+                IntProvider::Constant(0),
+                FoliageKind::Cherry {
+                    height: IntProvider::Constant(5),
+                    wide_bottom_layer_hole_chance: 0.25,
+                    corner_hole_chance: 0.5,
+                    hanging_leaves_chance: 1.0 / 6.0,
+                    hanging_leaves_extension_chance: 1.0 / 3.0,
+                },
+            ),
+            FeatureSize::TwoLayers {
+                limit: 1,
+                lower_size: 0,
+                upper_size: 2,
+            },
+        )
+    }
+
+    /// Grow a tree from `origin`, the block above the ground, returning
+    /// whether it fit.
+    pub fn place(
+        &self,
+        level: &mut WorldGenRegion,
+        random: &mut dyn RandomSource,
+        origin: [i32; 3],
+    ) -> bool {
+        let mut tree = Tree {
+            level,
+            config: self,
+            trunk: HashSet::new(),
+            foliage: HashSet::new(),
+        };
+        if !tree.grow(random, origin) || (tree.trunk.is_empty() && tree.foliage.is_empty()) {
+            return false;
+        }
+        tree.update_leaves();
+        true
+    }
+}
+
+/// A tree being placed, with the blocks it has set so far.
+struct Tree<'l, 'r> {
+    level: &'l mut WorldGenRegion<'r>,
+    config: &'l TreeConfiguration,
+    trunk: HashSet<[i32; 3]>,
+    foliage: HashSet<[i32; 3]>,
+}
+
+impl Tree<'_, '_> {
+    fn grow(&mut self, random: &mut dyn RandomSource, origin: [i32; 3]) -> bool {
+        let config = self.config;
+        let height = config.trunk_placer.tree_height(random);
+        let foliage_height = config.foliage_placer.foliage_height(random, height);
+        let foliage_radius = config.foliage_placer.radius.sample(random);
+        let y = origin[1];
+        if y < self.level.min_y() + 1 || y + height + 1 > self.level.max_y() {
+            return false;
+        }
+        let free_height = self.max_free_tree_height(height, origin);
+        if free_height < height
+            && config
+                .minimum_size
+                .min_clipped_height()
+                .is_none_or(|min| free_height < min)
+        {
+            return false;
+        }
+        for attachment in self.place_trunk(random, free_height, origin) {
+            let offset = config.foliage_placer.offset.sample(random);
+            self.create_foliage(random, &attachment, foliage_height, foliage_radius, offset);
+        }
+        true
+    }
+
+    /// The height the tree has room for, or `height` if it fits whole.
+    fn max_free_tree_height(&self, height: i32, [x, y, z]: [i32; 3]) -> i32 {
+        for dy in 0..=height + 1 {
+            let size = self.config.minimum_size.size_at_height(height, dy);
+            for dx in -size..=size {
+                for dz in -size..=size {
+                    let state = self.level.get_block([x + dx, y + dy, z + dz]);
+                    let free = valid_tree_pos(&state) || is_log(&state);
+                    if !free || (!self.config.ignore_vines && state.name == "minecraft:vine") {
+                        return dy - 2;
+                    }
+                }
+            }
+        }
+        height
+    }
+
+    fn set_dirt_at(&mut self, pos: [i32; 3]) {
+        let state = self.level.get_block(pos);
+        let dirt = is_dirt(&state)
+            && state.name != "minecraft:grass_block"
+            && state.name != "minecraft:mycelium";
+        if self.config.force_dirt || !dirt {
+            self.level.set_block(pos, self.config.dirt.clone());
+            self.trunk.insert(pos);
+        }
+    }
+
+    /// Put a log with the given `axis`, or the default upright one.
+    fn place_log(&mut self, pos: [i32; 3], axis: Option<&str>) -> bool {
+        if !valid_tree_pos(&self.level.get_block(pos)) {
+            return false;
+        }
+        let mut state = self.config.trunk.clone();
+        if let Some(axis) = axis {
+            state.set_property("axis", axis);
+        }
+        self.level.set_block(pos, state);
+        self.trunk.insert(pos);
+        true
+    }
+
+    fn try_place_leaf(&mut self, pos: [i32; 3]) -> bool {
+        let state = self.level.get_block(pos);
+        if state.property("persistent") == Some("true") || !valid_tree_pos(&state) {
+            return false;
+        }
+        let mut leaves = self.config.foliage.clone();
+        if state.name == "minecraft:water" && state.property("level").is_none_or(|l| l == "0") {
+            leaves.set_property("waterlogged", "true");
+        }
+        self.level.set_block(pos, leaves);
+        self.foliage.insert(pos);
+        true
+    }
+
+    fn place_trunk(
+        &mut self,
+        random: &mut dyn RandomSource,
+        free_height: i32,
+        pos: [i32; 3],
+    ) -> Vec<FoliageAttachment> {
+        let [x, y, z] = pos;
+        let config = self.config;
+        match &config.trunk_placer.kind {
+            TrunkKind::Straight => {
+                self.set_dirt_at([x, y - 1, z]);
+                for i in 0..free_height {
+                    self.place_log([x, y + i, z], None);
+                }
+                vec![FoliageAttachment::new([x, y + free_height, z], 0, false)]
+            }
+            TrunkKind::Forking => self.place_forking_trunk(random, free_height, pos),
+            TrunkKind::DarkOak => self.place_dark_oak_trunk(random, free_height, pos),
+            TrunkKind::Cherry {
+                branch_count,
+                branch_horizontal_length,
+                branch_start_offset_from_top,
+                branch_end_offset_from_top,
+            } => {
+                self.set_dirt_at([x, y - 1, z]);
+                let (start_min, start_max) = *branch_start_offset_from_top;
+                let first =
+                    (free_height - 1 + between_inclusive(random, start_min, start_max)).max(0);
+                let mut second =
+                    (free_height - 1 + between_inclusive(random, start_min, start_max - 1)).max(0);
+                if second >= first {
+                    second += 1;
+                }
+                let count = branch_count.sample(random);
+                let (three, two) = (count == 3, count >= 2);
+                let trunk_height = if three {
+                    free_height
+                } else if two {
+                    first.max(second) + 1
+                } else {
+                    first + 1
+                };
+                for i in 0..trunk_height {
+                    self.place_log([x, y + i, z], None);
+                }
+                let mut attachments = Vec::new();
+                if three {
+                    attachments.push(FoliageAttachment::new([x, y + trunk_height, z], 0, false));
+                }
+                let direction = random_direction(random);
+                let axis = if direction[0] != 0 { "x" } else { "z" };
+                let branch = |tree: &mut Self, random: &mut dyn RandomSource, dir, start: i32| {
+                    let end = free_height - 1 + branch_end_offset_from_top.sample(random);
+                    let continues = start < trunk_height - 1 || end < start;
+                    let length = branch_horizontal_length.sample(random) + i32::from(continues);
+                    let target = offset([x, y + end, z], dir, length);
+                    let mut m = [x, y + start, z];
+                    for _ in 0..if continues { 2 } else { 1 } {
+                        m = offset(m, dir, 1);
+                        tree.place_log(m, Some(axis));
+                    }
+                    let vertical = if target[1] > m[1] { 1 } else { -1 };
+                    loop {
+                        let distance = (0..3).map(|i| (target[i] - m[i]).abs()).sum::<i32>();
+                        if distance == 0 {
+                            break;
+                        }
+                        let up_chance = (target[1] - m[1]).abs() as f32 / distance as f32;
+                        if random.next_float() < up_chance {
+                            m[1] += vertical;
+                            tree.place_log(m, None);
+                        } else {
+                            m = offset(m, dir, 1);
+                            tree.place_log(m, Some(axis));
+                        }
+                    }
+                    FoliageAttachment::new([target[0], target[1] + 1, target[2]], 0, false)
+                };
+                attachments.push(branch(self, random, direction, first));
+                if two {
+                    let opposite = direction.map(|d| -d);
+                    attachments.push(branch(self, random, opposite, second));
+                }
+                attachments
+            }
+        }
+    }
+
+    fn place_forking_trunk(
+        &mut self,
+        random: &mut dyn RandomSource,
+        free_height: i32,
+        [x, y, z]: [i32; 3],
+    ) -> Vec<FoliageAttachment> {
+        self.set_dirt_at([x, y - 1, z]);
+        let mut attachments = Vec::new();
+        let direction = random_direction(random);
+        let bend_at = free_height - random.next_int_bounded(4) - 1;
+        let mut bend = 3 - random.next_int_bounded(3);
+        let (mut bx, mut bz) = (x, z);
+        let mut top = None;
+        for i in 0..free_height {
+            if i >= bend_at && bend > 0 {
+                bx += direction[0];
+                bz += direction[2];
+                bend -= 1;
+            }
+            if self.place_log([bx, y + i, bz], None) {
+                top = Some(y + i + 1);
+            }
+        }
+        if let Some(top) = top {
+            attachments.push(FoliageAttachment::new([bx, top, bz], 1, false));
+        }
+        let (mut bx, mut bz) = (x, z);
+        let branch = random_direction(random);
+        if branch != direction {
+            let start = bend_at - random.next_int_bounded(2) - 1;
+            let mut length = 1 + random.next_int_bounded(3);
+            let mut top = None;
+            let mut i = start;
+            while i < free_height && length > 0 {
+                if i >= 1 {
+                    bx += branch[0];
+                    bz += branch[2];
+                    if self.place_log([bx, y + i, bz], None) {
+                        top = Some(y + i + 1);
+                    }
+                }
+                i += 1;
+                length -= 1;
+            }
+            if let Some(top) = top {
+                attachments.push(FoliageAttachment::new([bx, top, bz], 0, false));
+            }
+        }
+        attachments
+    }
+
+    fn place_dark_oak_trunk(
+        &mut self,
+        random: &mut dyn RandomSource,
+        free_height: i32,
+        [x, y, z]: [i32; 3],
+    ) -> Vec<FoliageAttachment> {
+        for [dx, dz] in [[0, 0], [1, 0], [0, 1], [1, 1]] {
+            self.set_dirt_at([x + dx, y - 1, z + dz]);
+        }
+        let direction = random_direction(random);
+        let bend_at = free_height - random.next_int_bounded(4);
+        let mut bend = 2 - random.next_int_bounded(3);
+        let (mut bx, mut bz) = (x, z);
+        let top = y + free_height - 1;
+        for i in 0..free_height {
+            if i >= bend_at && bend > 0 {
+                bx += direction[0];
+                bz += direction[2];
+                bend -= 1;
+            }
+            if !is_air_or_leaves(&self.level.get_block([bx, y + i, bz])) {
+                continue;
+            }
+            for [dx, dz] in [[0, 0], [1, 0], [0, 1], [1, 1]] {
+                self.place_log([bx + dx, y + i, bz + dz], None);
+            }
+        }
+        let mut attachments = vec![FoliageAttachment::new([bx, top, bz], 0, true)];
+        for dx in -1..=2 {
+            for dz in -1..=2 {
+                let core = (0..=1).contains(&dx) && (0..=1).contains(&dz);
+                if core || random.next_int_bounded(3) > 0 {
+                    continue;
+                }
+                let length = random.next_int_bounded(3) + 2;
+                for i in 0..length {
+                    self.place_log([x + dx, top - i - 1, z + dz], None);
+                }
+                attachments.push(FoliageAttachment::new([x + dx, top, z + dz], 0, false));
+            }
+        }
+        attachments
+    }
+
+    fn create_foliage(
+        &mut self,
+        random: &mut dyn RandomSource,
+        attachment: &FoliageAttachment,
+        foliage_height: i32,
+        foliage_radius: i32,
+        offset: i32,
+    ) {
+        let config = self.config;
+        let placer = &config.foliage_placer;
+        let large = attachment.double_trunk;
+        let pos = attachment.pos;
+        let above = [pos[0], pos[1] + offset, pos[2]];
+        let radius = foliage_radius + attachment.radius_offset;
+        match placer.kind {
+            FoliageKind::Blob { .. } => {
+                for y in (offset - foliage_height..=offset).rev() {
+                    let range = (radius - 1 - y / 2).max(0);
+                    self.place_leaves_row(random, pos, range, y, large);
+                }
+            }
+            FoliageKind::Spruce { .. } => {
+                let mut range = random.next_int_bounded(2);
+                let (mut max_range, mut min_range) = (1, 0);
+                for y in (-foliage_height..=offset).rev() {
+                    self.place_leaves_row(random, pos, range, y, large);
+                    if range >= max_range {
+                        range = min_range;
+                        min_range = 1;
+                        max_range = (max_range + 1).min(radius);
+                    } else {
+                        range += 1;
+                    }
+                }
+            }
+            FoliageKind::Acacia => {
+                self.place_leaves_row(random, above, radius, -1 - foliage_height, large);
+                self.place_leaves_row(random, above, foliage_radius - 1, -foliage_height, large);
+                self.place_leaves_row(random, above, radius - 1, 0, large);
+            }
+            FoliageKind::DarkOak if large => {
+                self.place_leaves_row(random, above, foliage_radius + 2, -1, large);
+                self.place_leaves_row(random, above, foliage_radius + 3, 0, large);
+                self.place_leaves_row(random, above, foliage_radius + 2, 1, large);
+                if random.next_boolean() {
+                    self.place_leaves_row(random, above, foliage_radius, 2, large);
+                }
+            }
+            FoliageKind::DarkOak => {
+                self.place_leaves_row(random, above, foliage_radius + 2, -1, large);
+                self.place_leaves_row(random, above, foliage_radius + 1, 0, large);
+            }
+            FoliageKind::Cherry {
+                hanging_leaves_chance,
+                hanging_leaves_extension_chance,
+                ..
+            } => {
+                let range = radius - 1;
+                self.place_leaves_row(random, above, range - 2, foliage_height - 3, large);
+                self.place_leaves_row(random, above, range - 1, foliage_height - 4, large);
+                for y in (0..=foliage_height - 5).rev() {
+                    self.place_leaves_row(random, above, range, y, large);
+                }
+                let chances = (hanging_leaves_chance, hanging_leaves_extension_chance);
+                self.place_hanging_leaves_row(random, above, range, -1, large, chances);
+                self.place_hanging_leaves_row(random, above, range - 1, -2, large, chances);
+            }
+        }
+    }
+
+    fn place_leaves_row(
+        &mut self,
+        random: &mut dyn RandomSource,
+        [x, y, z]: [i32; 3],
+        range: i32,
+        local_y: i32,
+        large: bool,
+    ) {
+        let config = self.config;
+        let extra = i32::from(large);
+        for dx in -range..=range + extra {
+            for dz in -range..=range + extra {
+                let placer = &config.foliage_placer;
+                if !placer.should_skip_location_signed(random, dx, local_y, dz, range, large) {
+                    self.try_place_leaf([x + dx, y + local_y, z + dz]);
+                }
+            }
+        }
+    }
+
+    /// A leaves row with leaves hanging from its rim, one or two blocks
+    /// long.
+    fn place_hanging_leaves_row(
+        &mut self,
+        random: &mut dyn RandomSource,
+        pos: [i32; 3],
+        range: i32,
+        local_y: i32,
+        large: bool,
+        (chance, extension_chance): (f32, f32),
+    ) {
+        self.place_leaves_row(random, pos, range, local_y, large);
+        let extra = i32::from(large);
+        let log = [pos[0], pos[1] - 1, pos[2]];
+        for (i, direction) in HORIZONTAL.iter().enumerate() {
+            let clockwise = HORIZONTAL[(i + 1) % 4];
+            let positive = clockwise[0] + clockwise[2] > 0;
+            let side = if positive { range + extra } else { range };
+            let start = [pos[0], pos[1] + local_y - 1, pos[2]];
+            let mut m = offset(offset(start, clockwise, side), *direction, -range);
+            for _ in -range..range + extra {
+                let above = [m[0], m[1] + 1, m[2]];
+                if self.foliage.contains(&above) && self.try_place_extension(random, chance, log, m)
+                {
+                    let below = [m[0], m[1] - 1, m[2]];
+                    self.try_place_extension(random, extension_chance, log, below);
+                }
+                m = offset(m, *direction, 1);
+            }
+        }
+    }
+
+    fn try_place_extension(
+        &mut self,
+        random: &mut dyn RandomSource,
+        chance: f32,
+        log: [i32; 3],
+        pos: [i32; 3],
+    ) -> bool {
+        let distance = (0..3).map(|i| (pos[i] - log[i]).abs()).sum::<i32>();
+        if distance >= 7 || random.next_float() > chance {
+            return false;
+        }
+        self.try_place_leaf(pos)
+    }
+
+    /// Give the leaves around the tree their distance to the nearest log,
+    /// within the box spanning the tree, so they don't decay.
+    fn update_leaves(&mut self) {
+        let mut min = [i32::MAX; 3];
+        let mut max = [i32::MIN; 3];
+        for pos in self.trunk.iter().chain(&self.foliage) {
+            for i in 0..3 {
+                min[i] = min[i].min(pos[i]);
+                max[i] = max[i].max(pos[i]);
+            }
+        }
+        let inside = |pos: [i32; 3]| (0..3).all(|i| (min[i]..=max[i]).contains(&pos[i]));
+        let mut layers: Vec<Vec<[i32; 3]>> = vec![Vec::new(); MAX_LEAF_DISTANCE as usize];
+        layers[0].extend(self.trunk.iter().copied());
+        let mut done = HashSet::new();
+        let mut distance = 0;
+        while distance < MAX_LEAF_DISTANCE {
+            let Some(pos) = layers[distance as usize].pop() else {
+                distance += 1;
+                continue;
+            };
+            if !done.insert(pos) {
+                continue;
+            }
+            if distance != 0 {
+                let leaves = self.level.get_block(pos);
+                self.level
+                    .set_block(pos, leaves.with("distance", distance.to_string()));
+            }
+            for direction in [[0, -1, 0], [0, 1, 0]].iter().chain(&HORIZONTAL) {
+                let next = offset(pos, *direction, 1);
+                if !inside(next) || done.contains(&next) {
+                    continue;
+                }
+                let state = self.level.get_block(next);
+                let current = if is_log(&state) {
+                    0
+                } else if is_leaves(&state) {
+                    state
+                        .property("distance")
+                        .and_then(|d| d.parse().ok())
+                        .unwrap_or(MAX_LEAF_DISTANCE)
+                } else {
+                    continue;
+                };
+                let next_distance = current.min(distance + 1);
+                if next_distance < MAX_LEAF_DISTANCE {
+                    layers[next_distance as usize].push(next);
+                    distance = distance.min(next_distance);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::random::XoroshiroRandomSource;
+    use crate::test::worldgen::{find_blocks, region};
+
+    /// 3x3 chunks of dirt topped with grass at y = 0.
+    fn meadow() -> Vec<Chunk> {
+        region(-16, 64, |chunk| {
+            chunk.sections_mut()[0].fill(BlockState::new("minecraft:dirt"));
+            for x in 0..16 {
+                for z in 0..16 {
+                    chunk.set_block(x, 0, z, BlockState::new("minecraft:grass_block"));
+                }
+            }
+        })
+    }
+
+    fn blocks(chunks: &[Chunk], suffix: &str) -> Vec<([i32; 3], BlockState)> {
+        find_blocks(chunks, |_, state| state.name.ends_with(suffix))
+    }
+
+    #[test]
+    fn grows_every_tree() {
+        let trees = [
+            TreeConfiguration::oak(),
+            TreeConfiguration::birch(),
+            TreeConfiguration::spruce(),
+            TreeConfiguration::jungle_tree(),
+            TreeConfiguration::acacia(),
+            TreeConfiguration::dark_oak(),
+            TreeConfiguration::cherry(),
+        ];
+        for tree in trees {
+            for seed in 0..4 {
+                let mut chunks = meadow();
+                let mut level = WorldGenRegion::new(&mut chunks);
+                let mut random = XoroshiroRandomSource::new(seed);
+                assert!(tree.place(&mut level, &mut random, [0, 1, 0]));
+
+                let logs = blocks(&chunks, "_log");
+                let leaves = blocks(&chunks, "_leaves");
+                let name = &tree.trunk.name;
+                assert!(logs.len() >= 4, "{name}: {} logs", logs.len());
+                assert!(leaves.len() >= 10, "{name}: {} leaves", leaves.len());
+                assert!(logs.iter().all(|(pos, _)| pos[1] >= 1));
+                // Grass under the trunk turns to dirt.
+                assert_eq!(
+                    chunks[4].get_block(0, 0, 0).name,
+                    "minecraft:dirt",
+                    "{name}"
+                );
+                // Leaves near the logs get their distance to them.
+                let near = leaves
+                    .iter()
+                    .filter(|(_, state)| state.property("distance") == Some("1"))
+                    .count();
+                assert!(near > 0, "{name}");
+            }
+        }
+    }
+
+    #[test]
+    fn trees_need_room_to_grow() {
+        let mut chunks = meadow();
+        for chunk in &mut chunks {
+            for x in 0..16 {
+                for z in 0..16 {
+                    chunk.set_block(x, 4, z, BlockState::new("minecraft:stone"));
+                }
+            }
+        }
+        let mut level = WorldGenRegion::new(&mut chunks);
+        let mut random = XoroshiroRandomSource::new(1);
+        assert!(!TreeConfiguration::oak().place(&mut level, &mut random, [0, 1, 0]));
+        assert!(blocks(&chunks, "_log").is_empty());
+
+        // Nor do they grow out of the top of the world.
+        let mut chunks = meadow();
+        let mut level = WorldGenRegion::new(&mut chunks);
+        assert!(!TreeConfiguration::oak().place(&mut level, &mut random, [0, 45, 0]));
+    }
+}